@@ -0,0 +1,207 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use candid::CandidType;
+use serde::Deserialize;
+
+/// Number of status changes kept per node. Older changes are dropped, and the
+/// time they covered no longer counts towards uptime.
+pub const HISTORY_LENGTH: usize = 1000;
+
+/// Number of status changes kept over all nodes, so that the whole history
+/// can still be saved to stable memory on upgrades. The oldest changes are
+/// dropped first, whatever node they belong to.
+pub const MAX_TOTAL_CHANGES: usize = 200_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, CandidType, Deserialize)]
+pub struct StatusChange {
+    /// Nanoseconds since the epoch, as given by the canister's clock
+    pub timestamp: u64,
+    pub status: bool,
+}
+
+/// The last status changes of a node, oldest first. A deque, since the
+/// oldest change is dropped whenever a new one is recorded to a full history.
+#[derive(Clone, Debug, Default, PartialEq, CandidType, Deserialize)]
+pub struct NodeHistory {
+    changes: VecDeque<StatusChange>,
+    /// Whether older changes were dropped, in which case the oldest change
+    /// kept is a flip too
+    truncated: bool,
+}
+
+impl NodeHistory {
+    /// Records the status if it differs from the last one recorded
+    pub fn record(&mut self, timestamp: u64, status: bool) {
+        if self.changes.back().is_some_and(|c| c.status == status) {
+            return;
+        }
+        if self.changes.len() == HISTORY_LENGTH {
+            self.drop_oldest();
+        }
+        self.changes.push_back(StatusChange { timestamp, status });
+    }
+
+    fn drop_oldest(&mut self) {
+        self.changes.pop_front();
+        self.truncated = true;
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Status changes with a timestamp in `[from, to]`
+    pub fn between(&self, from: u64, to: u64) -> Vec<StatusChange> {
+        self.changes
+            .iter()
+            .filter(|c| c.timestamp >= from && c.timestamp <= to)
+            .cloned()
+            .collect()
+    }
+
+    /// Number of times the status flipped in `[from, to]`. The first status
+    /// recorded for a node is not a flip.
+    pub fn flips_between(&self, from: u64, to: u64) -> usize {
+        let first_status = usize::from(!self.truncated);
+        self.changes
+            .iter()
+            .skip(first_status)
+            .filter(|c| c.timestamp >= from && c.timestamp <= to)
+            .count()
+    }
+
+    /// Time the node was up, and time its status was known, in `[from, to)`
+    pub fn time_up(&self, from: u64, to: u64) -> (u64, u64) {
+        let mut up = 0;
+        let mut known = 0;
+        for (i, change) in self.changes.iter().enumerate() {
+            let end = self.changes.get(i + 1).map_or(to, |next| next.timestamp).min(to);
+            let start = change.timestamp.max(from);
+            if end <= start {
+                continue;
+            }
+            known += end - start;
+            if change.status {
+                up += end - start;
+            }
+        }
+        (up, known)
+    }
+}
+
+/// Drops the oldest changes over all the histories until at most `max_changes`
+/// are left, and the histories that end up empty
+pub fn limit_total_changes<K: Ord + Clone>(histories: &mut BTreeMap<K, NodeHistory>, max_changes: usize) {
+    let mut total: usize = histories.values().map(NodeHistory::len).sum();
+    while total > max_changes {
+        let oldest = histories
+            .iter()
+            .filter_map(|(node, history)| history.changes.front().map(|c| (c.timestamp, node)))
+            .min_by_key(|(timestamp, _)| *timestamp)
+            .map(|(_, node)| node.clone());
+        let Some(oldest) = oldest else {
+            break;
+        };
+        if let Some(history) = histories.get_mut(&oldest) {
+            history.drop_oldest();
+        }
+        total -= 1;
+    }
+    histories.retain(|_, history| !history.is_empty());
+}
+
+/// Uptime in percent, or `None` if the status was not known at any time
+pub fn uptime_percentage((up, known): (u64, u64)) -> Option<f64> {
+    if known == 0 {
+        None
+    } else {
+        Some(up as f64 * 100. / known as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(changes: &[(u64, bool)]) -> NodeHistory {
+        let mut history = NodeHistory::default();
+        for (timestamp, status) in changes {
+            history.record(*timestamp, *status);
+        }
+        history
+    }
+
+    #[test]
+    fn records_only_changes() {
+        let history = history(&[(10, true), (20, true), (30, false), (40, true)]);
+        assert_eq!(
+            history.between(0, 100),
+            vec![
+                StatusChange { timestamp: 10, status: true },
+                StatusChange {
+                    timestamp: 30,
+                    status: false
+                },
+                StatusChange { timestamp: 40, status: true },
+            ]
+        );
+        assert_eq!(
+            history.between(15, 35),
+            vec![StatusChange {
+                timestamp: 30,
+                status: false
+            }]
+        );
+        assert_eq!(history.flips_between(0, 100), 2);
+        assert_eq!(history.flips_between(35, 100), 1);
+    }
+
+    #[test]
+    fn drops_oldest_changes() {
+        let mut history = NodeHistory::default();
+        for i in 0..(HISTORY_LENGTH as u64 + 10) {
+            history.record(i, i % 2 == 0);
+        }
+        let changes = history.between(0, u64::MAX);
+        assert_eq!(changes.len(), HISTORY_LENGTH);
+        assert_eq!(changes[0].timestamp, 10);
+        // Every change kept is a flip of the change before it
+        assert_eq!(history.flips_between(0, u64::MAX), HISTORY_LENGTH);
+    }
+
+    #[test]
+    fn limits_total_changes() {
+        let mut histories = BTreeMap::from([
+            (1, history(&[(10, true), (30, false), (50, true)])),
+            (2, history(&[(20, true), (40, false)])),
+        ]);
+        limit_total_changes(&mut histories, 2);
+        assert_eq!(histories[&1].between(0, 100), vec![StatusChange { timestamp: 50, status: true }]);
+        assert_eq!(
+            histories[&2].between(0, 100),
+            vec![StatusChange {
+                timestamp: 40,
+                status: false
+            }]
+        );
+        assert_eq!(histories[&1].flips_between(0, 100), 1);
+
+        limit_total_changes(&mut histories, 1);
+        assert_eq!(histories.keys().collect::<Vec<_>>(), vec![&1]);
+    }
+
+    #[test]
+    fn uptime() {
+        let history = history(&[(100, true), (200, false), (250, true)]);
+        // Up 100..200 and 250..300, down 200..250
+        assert_eq!(history.time_up(0, 300), (150, 200));
+        assert_eq!(uptime_percentage(history.time_up(0, 300)), Some(75.));
+        // Up 150..200, down 200..250
+        assert_eq!(uptime_percentage(history.time_up(150, 250)), Some(50.));
+        assert_eq!(uptime_percentage(history.time_up(0, 100)), None);
+    }
+}
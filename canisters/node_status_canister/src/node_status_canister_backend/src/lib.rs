@@ -1,17 +1,115 @@
-use std::{cell::RefCell, collections::BTreeMap};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+};
 
 use candid::{CandidType, Principal};
+use history::{limit_total_changes, uptime_percentage, NodeHistory, StatusChange, MAX_TOTAL_CHANGES};
 use serde::Deserialize;
 
+mod history;
+
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub struct NodeStatus {
     pub node_id: Principal,
     pub subnet_id: Option<Principal>,
+    /// Whether the node is up, i.e. its health is not `Down`
     pub status: bool,
+    /// Missing for statuses written by updaters that only reported `status`
+    pub health: Option<NodeHealth>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, CandidType, Deserialize)]
+pub enum HealthLevel {
+    Healthy,
+    Degraded,
+    Down,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, CandidType, Deserialize)]
+pub enum HealthReason {
+    NodeExporterDown,
+    ReplicaDown,
+    FinalizationStalled,
+    OrchestratorDown,
+    MetricsProxyUnreachable,
+    /// None of the health checks returned anything for the node
+    NoMetrics,
+}
+
+#[derive(Clone, Debug, PartialEq, CandidType, Deserialize)]
+pub struct NodeHealth {
+    pub level: HealthLevel,
+    /// Why the node is not healthy
+    pub reasons: Vec<HealthReason>,
+}
+
+#[derive(Clone, Debug, PartialEq, CandidType, Deserialize)]
+pub enum NodeStatusError {
+    /// The caller is not on the list of writers
+    Unauthorized(Principal),
+    /// The caller is not a controller of the canister
+    NotController(Principal),
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct NodeFlaps {
+    pub node_id: Principal,
+    pub flaps: u64,
 }
 
 thread_local! {
     pub static STATUSES: RefCell<BTreeMap<Principal, NodeStatus>>  = RefCell::new(BTreeMap::new());
+    /// Principals allowed to update node statuses, managed by the controllers
+    pub static WRITERS: RefCell<BTreeSet<Principal>> = RefCell::new(BTreeSet::new());
+    pub static HISTORY: RefCell<BTreeMap<Principal, NodeHistory>> = RefCell::new(BTreeMap::new());
+}
+
+fn check_controller() -> Result<(), NodeStatusError> {
+    let caller = ic_cdk::caller();
+    if ic_cdk::api::is_controller(&caller) {
+        Ok(())
+    } else {
+        Err(NodeStatusError::NotController(caller))
+    }
+}
+
+fn check_writer() -> Result<(), NodeStatusError> {
+    let caller = ic_cdk::caller();
+    if WRITERS.with(|w| w.borrow().contains(&caller)) {
+        Ok(())
+    } else {
+        Err(NodeStatusError::Unauthorized(caller))
+    }
+}
+
+#[ic_cdk::pre_upgrade]
+fn pre_upgrade() {
+    let statuses = STATUSES.with(|s| s.take());
+    let writers = WRITERS.with(|w| w.take());
+    let history = HISTORY.with(|h| h.take());
+    ic_cdk::storage::stable_save((statuses, writers, Some(history))).expect("failed to save state to stable memory");
+}
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    // Versions before the writers were introduced did not save anything, so
+    // there is nothing to restore when upgrading from them
+    if ic_cdk::api::stable::stable_size() == 0 {
+        return;
+    }
+    // Versions before the history did not save one, which decodes as `None`.
+    // Any other state that doesn't decode traps, so that the upgrade fails
+    // instead of silently dropping the state.
+    type State = (
+        BTreeMap<Principal, NodeStatus>,
+        BTreeSet<Principal>,
+        Option<BTreeMap<Principal, NodeHistory>>,
+    );
+    let (statuses, writers, history) = ic_cdk::storage::stable_restore::<State>().expect("failed to restore state from stable memory");
+    STATUSES.with(|s| *s.borrow_mut() = statuses);
+    WRITERS.with(|w| *w.borrow_mut() = writers);
+    HISTORY.with(|h| *h.borrow_mut() = history.unwrap_or_default());
 }
 
 #[ic_cdk::query]
@@ -25,12 +123,111 @@ fn get_node_status() -> Vec<NodeStatus> {
 }
 
 #[ic_cdk::update]
-fn update_node_status(new_statuses: Vec<NodeStatus>) -> bool {
+fn update_node_status(new_statuses: Vec<NodeStatus>) -> Result<bool, NodeStatusError> {
+    check_writer()?;
+    let now = ic_cdk::api::time();
+    HISTORY.with(|h| {
+        let mut history = h.borrow_mut();
+        for new_status in new_statuses.iter() {
+            history.entry(new_status.node_id).or_default().record(now, new_status.status);
+        }
+        limit_total_changes(&mut history, MAX_TOTAL_CHANGES);
+    });
     STATUSES.with(|f: &RefCell<BTreeMap<Principal, NodeStatus>>| {
         let mut statuses = f.borrow_mut();
         for new_status in new_statuses {
             statuses.insert(new_status.node_id, new_status);
         }
-        true
+        Ok(true)
+    })
+}
+
+/// Removes nodes that are no longer in the registry, along with their history
+#[ic_cdk::update]
+fn remove_nodes(node_ids: Vec<Principal>) -> Result<bool, NodeStatusError> {
+    check_writer()?;
+    HISTORY.with(|h| {
+        let mut history = h.borrow_mut();
+        for node_id in node_ids.iter() {
+            history.remove(node_id);
+        }
+    });
+    STATUSES.with(|f| {
+        let mut statuses = f.borrow_mut();
+        for node_id in node_ids {
+            statuses.remove(&node_id);
+        }
+        Ok(true)
+    })
+}
+
+/// Status changes of a node with a timestamp in `[from, to]`, in nanoseconds
+/// since the epoch
+#[ic_cdk::query]
+fn get_node_status_history(node_id: Principal, from: u64, to: u64) -> Vec<StatusChange> {
+    HISTORY.with(|h| h.borrow().get(&node_id).map(|history| history.between(from, to)).unwrap_or_default())
+}
+
+/// Percentage of `[from, to)` in which the node was up, out of the time its
+/// status was known
+#[ic_cdk::query]
+fn get_node_uptime(node_id: Principal, from: u64, to: u64) -> Option<f64> {
+    let to = to.min(ic_cdk::api::time());
+    HISTORY.with(|h| h.borrow().get(&node_id).and_then(|history| uptime_percentage(history.time_up(from, to))))
+}
+
+/// Uptime over `[from, to)` of all the nodes currently in the subnet
+#[ic_cdk::query]
+fn get_subnet_uptime(subnet_id: Principal, from: u64, to: u64) -> Option<f64> {
+    let to = to.min(ic_cdk::api::time());
+    let nodes: Vec<Principal> = STATUSES.with(|s| {
+        s.borrow()
+            .values()
+            .filter(|status| status.subnet_id == Some(subnet_id))
+            .map(|status| status.node_id)
+            .collect()
+    });
+    HISTORY.with(|h| {
+        let history = h.borrow();
+        let total = nodes
+            .iter()
+            .filter_map(|node| history.get(node))
+            .map(|history| history.time_up(from, to))
+            .fold((0, 0), |(up, known), (node_up, node_known)| (up + node_up, known + node_known));
+        uptime_percentage(total)
     })
 }
+
+/// Nodes whose status flipped more than `min_flaps` times in `[from, to]`
+#[ic_cdk::query]
+fn get_flapping_nodes(from: u64, to: u64, min_flaps: u64) -> Vec<NodeFlaps> {
+    HISTORY.with(|h| {
+        h.borrow()
+            .iter()
+            .map(|(node_id, history)| NodeFlaps {
+                node_id: *node_id,
+                flaps: history.flips_between(from, to) as u64,
+            })
+            .filter(|node| node.flaps > min_flaps)
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_writers() -> Vec<Principal> {
+    WRITERS.with(|w| w.borrow().iter().cloned().collect())
+}
+
+#[ic_cdk::update]
+fn add_writer(writer: Principal) -> Result<(), NodeStatusError> {
+    check_controller()?;
+    WRITERS.with(|w| w.borrow_mut().insert(writer));
+    Ok(())
+}
+
+#[ic_cdk::update]
+fn remove_writer(writer: Principal) -> Result<(), NodeStatusError> {
+    check_controller()?;
+    WRITERS.with(|w| w.borrow_mut().remove(&writer));
+    Ok(())
+}
@@ -2,9 +2,9 @@ use clap::{Parser, Subcommand};
 use clap_num::maybe_hex;
 use humantime::parse_duration;
 use ic_base_types::PrincipalId;
-use ic_management_types::Artifact;
+use ic_management_types::{Artifact, OptimizationStrategy};
 use ic_registry_keys::FirewallRulesScope;
-use std::{path::PathBuf, time::Duration};
+use std::{path::PathBuf, str::FromStr, time::Duration};
 use url::Url;
 
 // For more info about the version setup, look at https://docs.rs/clap/latest/clap/struct.Command.html#method.version
@@ -47,10 +47,30 @@ pub struct Opts {
     #[clap(long, env = "NNS_URLS", aliases = &["registry-url", "nns-url"], value_delimiter = ',')]
     pub nns_urls: Vec<Url>,
 
+    // Path to a YAML file with the decentralization business rules for subnets.
+    // The built-in policy is used if not provided
+    #[clap(long, env = "BUSINESS_RULES_POLICY", global = true)]
+    pub business_rules_policy: Option<PathBuf>,
+
+    // Path to a YAML file with additional node features, e.g. ASN or legal
+    // jurisdiction, and their values per data center, node provider or node.
+    // The features can be used in the business rules policy and in filters
+    #[clap(long, env = "NODE_FEATURES", global = true)]
+    pub node_features: Option<PathBuf>,
+
     #[clap(subcommand)]
     pub subcommand: Commands,
 }
 
+fn parse_subnet_max_swaps(value: &str) -> Result<(PrincipalId, usize), String> {
+    let (subnet, max_swaps) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected <subnet-id>=<count>, got '{}'", value))?;
+    let subnet = PrincipalId::from_str(subnet).map_err(|e| format!("invalid subnet id '{}': {}", subnet, e))?;
+    let max_swaps = max_swaps.parse().map_err(|e| format!("invalid count '{}': {}", max_swaps, e))?;
+    Ok((subnet, max_swaps))
+}
+
 #[derive(Subcommand, Clone)]
 pub enum Commands {
     // Convert a DER file to a Principal
@@ -65,6 +85,22 @@ pub enum Commands {
         /// and minimizing the number of replaced nodes per subnet
         #[clap(short, long)]
         max_replaceable_nodes_per_sub: Option<usize>,
+
+        /// Only print a plan of node replacements across all subnets, without
+        /// submitting proposals. Unhealthy nodes are replaced first, and the
+        /// rest of the budget is used to improve the least decentralized
+        /// subnets
+        #[clap(long)]
+        plan_only: bool,
+
+        /// Max number of node replacements in the whole plan
+        #[clap(long, requires = "plan_only")]
+        max_swaps: Option<usize>,
+
+        /// Max number of node replacements for specific subnets, as
+        /// <subnet-id>=<count>, overriding --max-replaceable-nodes-per-sub
+        #[clap(long, num_args(1..), value_parser = parse_subnet_max_swaps, requires = "plan_only")]
+        subnet_max_swaps: Vec<(PrincipalId, usize)>,
     },
 
     /// Manage an existing subnet
@@ -152,10 +188,15 @@ pub enum Commands {
         #[clap(long, default_value = "-1")]
         version: i64,
 
-        /// Output file (default is stdout)
+        /// Output file (default is stdout). For CSV and Parquet this is the directory
+        /// that gets one file per entity, for SQLite the database file
         #[clap(short = 'o', long)]
         output: Option<PathBuf>,
 
+        /// Format of the dump
+        #[clap(long, value_enum, default_value = "json")]
+        format: registry::DumpFormat,
+
         /// Output only information related to the node operator records with incorrect rewards
         #[clap(long)]
         incorrect_rewards: bool,
@@ -163,6 +204,9 @@ pub enum Commands {
         /// Optional path to cached registry, can be used to inspect an arbitrary path
         #[clap(long, env = "LOCAL_REGISTRY_PATH")]
         local_registry_path: Option<PathBuf>,
+
+        #[clap(subcommand)]
+        subcommand: Option<registry::Commands>,
     },
 
     /// Firewall rules
@@ -179,6 +223,9 @@ pub enum Commands {
     /// Proposal Listing
     Proposals(proposals::Cmd),
 
+    /// Network-wide decentralization analysis
+    Analyze(analyze::Cmd),
+
     /// Self upgrade
     Upgrade,
 }
@@ -224,7 +271,9 @@ pub mod subnet {
             #[clap(short, long, aliases = ["summary"])]
             motivation: Option<String>,
 
-            /// Minimum Nakamoto coefficients after the replacement
+            /// Minimum Nakamoto coefficients after the replacement, e.g.
+            /// `node_provider=5 average=3`, or `node_provider+country=3` for a
+            /// joint coefficient across features
             #[clap(long, num_args(1..))]
             min_nakamoto_coefficients: Vec<String>,
 
@@ -240,6 +289,11 @@ pub mod subnet {
             /// regardless of the decentralization score
             #[clap(long, num_args(1..))]
             include: Vec<PrincipalId>,
+
+            /// Strategy for picking the nodes: greedy, beam-search,
+            /// simulated-annealing or exhaustive
+            #[clap(long, default_value_t = OptimizationStrategy::Greedy)]
+            optimizer: OptimizationStrategy,
         },
 
         /// Resize the subnet
@@ -268,6 +322,11 @@ pub mod subnet {
             /// Motivation for resizing the subnet
             #[clap(short, long, aliases = ["summary"])]
             motivation: Option<String>,
+
+            /// Strategy for picking the nodes: greedy, beam-search,
+            /// simulated-annealing or exhaustive
+            #[clap(long, default_value_t = OptimizationStrategy::Greedy)]
+            optimizer: OptimizationStrategy,
         },
 
         /// Create a new subnet
@@ -455,6 +514,50 @@ pub mod hostos {
     }
 }
 
+pub mod registry {
+    use super::*;
+    use clap::ValueEnum;
+
+    #[derive(ValueEnum, Copy, Clone, Debug, Eq, PartialEq, Default)]
+    pub enum DumpFormat {
+        /// The whole dump as a single JSON document
+        #[default]
+        Json,
+        /// One CSV file per entity: nodes, subnets, node operators, node providers and data centers
+        Csv,
+        /// One Parquet file per entity, needs dre to be built with the `parquet` feature
+        Parquet,
+        /// A SQLite database with a table per entity, and foreign keys between them, needs dre
+        /// to be built with the `sqlite` feature
+        Sqlite,
+    }
+
+    #[derive(Subcommand, Clone)]
+    pub enum Commands {
+        /// Show what changed in the registry between two versions: nodes added, removed or
+        /// moved between subnets, subnet configuration, node operator allowances, elected
+        /// versions and firewall rules
+        Diff {
+            /// Version to compare from
+            #[clap(long)]
+            from: u64,
+
+            /// Version to compare to. If value is less than 0 will compare to the latest version
+            #[clap(long, default_value = "-1")]
+            to: i64,
+
+            /// Print the diff as JSON
+            #[clap(long)]
+            json: bool,
+
+            /// Instead of the diff, find the version at which the value at this JSON pointer
+            /// into the registry snapshot changed, e.g. `/subnets/<subnet id>/replica_version_id`
+            #[clap(long)]
+            bisect: Option<String>,
+        },
+    }
+}
+
 pub mod nodes {
     use super::*;
 
@@ -545,6 +648,32 @@ pub mod api_boundary_nodes {
     }
 }
 
+pub mod analyze {
+    use super::*;
+
+    #[derive(Parser, Clone)]
+    pub struct Cmd {
+        #[clap(subcommand)]
+        pub subcommand: Commands,
+    }
+
+    #[derive(Subcommand, Clone)]
+    pub enum Commands {
+        /// Rank the single points of failure of the network: for every node
+        /// provider, data center, owner, city, country and continent, list the
+        /// subnets that would lose consensus if all of its nodes went offline
+        Outage {
+            /// Only analyze these features, e.g. `node_provider country`
+            #[clap(long, num_args(1..))]
+            features: Vec<String>,
+
+            /// Max number of failure domains to show
+            #[clap(long, default_value = "20")]
+            limit: usize,
+        },
+    }
+}
+
 pub mod proposals {
     use std::fmt::Display;
 
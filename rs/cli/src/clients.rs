@@ -1,9 +1,14 @@
 use async_trait::async_trait;
+use decentralization::outage::OutageReport;
+use decentralization::planner::NetworkRebalancePlan;
 use decentralization::HealResponse;
 use decentralization::SubnetChangeResponse;
 use ic_base_types::PrincipalId;
 use ic_management_types::{
-    requests::{HealRequest, MembershipReplaceRequest, NodesRemoveRequest, NodesRemoveResponse, SubnetCreateRequest, SubnetResizeRequest},
+    requests::{
+        HealPlanRequest, HealRequest, MembershipReplaceRequest, NodesRemoveRequest, NodesRemoveResponse, OutageAnalysisRequest, SubnetCreateRequest,
+        SubnetResizeRequest,
+    },
     Artifact, Network, NetworkError, Release, TopologyChangeProposal,
 };
 use log::error;
@@ -106,6 +111,22 @@ impl DashboardBackendClient {
             .rest_send()
             .await
     }
+
+    pub(crate) async fn network_heal_plan(&self, request: HealPlanRequest) -> anyhow::Result<NetworkRebalancePlan> {
+        reqwest::Client::new()
+            .post(self.url.join("network/heal/plan").map_err(|e| anyhow::anyhow!(e))?)
+            .json(&request)
+            .rest_send()
+            .await
+    }
+
+    pub(crate) async fn outage_analysis(&self, request: OutageAnalysisRequest) -> anyhow::Result<OutageReport> {
+        reqwest::Client::new()
+            .post(self.url.join("network/outage").map_err(|e| anyhow::anyhow!(e))?)
+            .json(&request)
+            .rest_send()
+            .await
+    }
 }
 
 #[async_trait]
@@ -5,13 +5,13 @@ use dre::cli::proposals::ProposalStatus;
 use dre::detect_neuron::Auth;
 use dre::general::{filter_proposals, get_node_metrics_history, vote_on_proposals};
 use dre::operations::hostos_rollout::{NodeGroupUpdate, NumberOfNodes};
-use dre::{cli, ic_admin, registry_dump, runner};
+use dre::{cli, ic_admin, registry_diff, registry_dump, runner};
 use ic_base_types::CanisterId;
 use ic_canisters::governance::{governance_canister_version, GovernanceCanisterWrapper};
 use ic_canisters::CanisterClient;
 use ic_management_types::filter_map_nns_function_proposals;
 use ic_management_types::requests::NodesRemoveRequest;
-use ic_management_types::{Artifact, MinNakamotoCoefficients, NodeFeature};
+use ic_management_types::{Artifact, FeatureGroup, MinNakamotoCoefficients, NodeFeature};
 
 use ic_nns_common::pb::v1::ProposalId;
 use ic_nns_governance::pb::v1::ListProposalInfo;
@@ -47,6 +47,14 @@ async fn main() -> Result<(), anyhow::Error> {
 
     let handle = tokio::task::spawn_blocking(move || check_latest_release(version, false));
 
+    // Custom node features have to be known before the policy that uses them is loaded
+    if let Some(path) = &cli_opts.node_features {
+        decentralization::features::CustomNodeFeatures::load(path)?.activate()?;
+    }
+    if let Some(path) = &cli_opts.business_rules_policy {
+        decentralization::policy::BusinessRulesPolicy::load(path)?.activate();
+    }
+
     let target_network = ic_management_types::Network::new(cli_opts.network.clone(), &cli_opts.nns_urls)
         .await
         .expect("Failed to create network");
@@ -91,6 +99,23 @@ async fn main() -> Result<(), anyhow::Error> {
 
             cli::Commands::Heal {
                 max_replaceable_nodes_per_sub,
+                plan_only: true,
+                max_swaps,
+                subnet_max_swaps,
+            } => {
+                runner_instance
+                    .network_heal_plan(ic_management_types::requests::HealPlanRequest {
+                        max_swaps: *max_swaps,
+                        max_swaps_per_subnet: *max_replaceable_nodes_per_sub,
+                        max_swaps_by_subnet: subnet_max_swaps.iter().cloned().collect(),
+                    })
+                    .await
+            }
+
+            cli::Commands::Heal {
+                max_replaceable_nodes_per_sub,
+                plan_only: false,
+                ..
             } => {
                 runner_instance
                     .network_heal(
@@ -141,6 +166,7 @@ async fn main() -> Result<(), anyhow::Error> {
                         only,
                         include,
                         min_nakamoto_coefficients,
+                        optimizer,
                     } => {
                         let min_nakamoto_coefficients = parse_min_nakamoto_coefficients(&mut cmd, min_nakamoto_coefficients);
                         runner_instance
@@ -166,6 +192,7 @@ async fn main() -> Result<(), anyhow::Error> {
                                     only: only.clone(),
                                     include: include.clone().into(),
                                     min_nakamoto_coefficients,
+                                    optimizer: *optimizer,
                                 },
                                 cli_opts.verbose,
                                 dry_run,
@@ -179,6 +206,7 @@ async fn main() -> Result<(), anyhow::Error> {
                         only,
                         exclude,
                         motivation,
+                        optimizer,
                     } => {
                         if let Some(motivation) = motivation.clone() {
                             runner_instance
@@ -190,6 +218,7 @@ async fn main() -> Result<(), anyhow::Error> {
                                         only: only.clone().into(),
                                         exclude: exclude.clone().into(),
                                         include: include.clone().into(),
+                                        optimizer: *optimizer,
                                     },
                                     motivation,
                                     cli_opts.verbose,
@@ -227,6 +256,7 @@ async fn main() -> Result<(), anyhow::Error> {
                                         only: only.clone().into(),
                                         exclude: exclude.clone().into(),
                                         include: include.clone().into(),
+                                        optimizer: *optimizer,
                                     },
                                     motivation,
                                     cli_opts.verbose,
@@ -454,7 +484,14 @@ async fn main() -> Result<(), anyhow::Error> {
                 output,
                 local_registry_path,
                 incorrect_rewards,
-            } => registry_dump::dump_registry(local_registry_path, &target_network, version, output, *incorrect_rewards).await,
+                format,
+                subcommand,
+            } => match subcommand {
+                Some(cli::registry::Commands::Diff { from, to, json, bisect }) => {
+                    registry_diff::diff_registry(local_registry_path, &target_network, *from, *to, *json, bisect).await
+                }
+                None => registry_dump::dump_registry(local_registry_path, &target_network, version, output, *incorrect_rewards, format).await,
+            },
 
             cli::Commands::Firewall { title, summary, rules_scope } => {
                 runner_instance
@@ -471,6 +508,21 @@ async fn main() -> Result<(), anyhow::Error> {
                     )
                     .await
             }
+            cli::Commands::Analyze(analyze) => match &analyze.subcommand {
+                cli::analyze::Commands::Outage { features, limit } => {
+                    runner_instance
+                        .outage_analysis(ic_management_types::requests::OutageAnalysisRequest {
+                            // Parsed only now, once the custom node features are registered
+                            features: features
+                                .iter()
+                                .map(|f| NodeFeature::from_str(f).map_err(|e| anyhow::anyhow!(e)))
+                                .collect::<anyhow::Result<_>>()?,
+                            limit: Some(*limit),
+                        })
+                        .await
+                }
+            },
+
             cli::Commands::Proposals(p) => match &p.subcommand {
                 cli::proposals::Commands::Pending => {
                     let nns_url = target_network.get_nns_urls().first().expect("Should have at least one NNS URL");
@@ -596,6 +648,11 @@ async fn main() -> Result<(), anyhow::Error> {
 //           -> "data_centers" NC >= 4.0
 //           -> "node_provider" NC >= 5.0 (default)
 //           -> average NC >= 3.0 (default)
+//
+// ["node_provider+country=3"] => require a joint NC across node providers and
+// countries
+//           -> node providers and countries together need >= 3 actors
+//           -> average NC >= 3.0 (default)
 fn parse_min_nakamoto_coefficients(cmd: &mut clap::Command, min_nakamoto_coefficients: &[String]) -> Option<MinNakamotoCoefficients> {
     let min_nakamoto_coefficients: Vec<String> = if min_nakamoto_coefficients.is_empty() {
         ["node_provider=5", "average=3"].iter().map(|s| String::from(*s)).collect()
@@ -604,6 +661,7 @@ fn parse_min_nakamoto_coefficients(cmd: &mut clap::Command, min_nakamoto_coeffic
     };
 
     let mut average = 3.0;
+    let mut joint_coefficients = BTreeMap::new();
     let min_nakamoto_coefficients = min_nakamoto_coefficients
         .iter()
         .filter_map(|s| {
@@ -611,7 +669,18 @@ fn parse_min_nakamoto_coefficients(cmd: &mut clap::Command, min_nakamoto_coeffic
                 Some(s) => s,
                 None => cmd.error(ErrorKind::ValueValidation, "Value requires exactly one '=' symbol").exit(),
             };
-            if key.to_lowercase() == "average" {
+            if key.contains('+') {
+                let group = match FeatureGroup::from_str(key) {
+                    Ok(v) => v,
+                    Err(e) => cmd.error(ErrorKind::ValueValidation, e).exit(),
+                };
+                let val: f64 = val
+                    .parse::<f64>()
+                    .map_err(|_| cmd.error(ErrorKind::ValueValidation, "Failed to parse feature from string").exit())
+                    .unwrap();
+                joint_coefficients.insert(group, val);
+                None
+            } else if key.to_lowercase() == "average" {
                 average = val
                     .parse::<f64>()
                     .map_err(|_| cmd.error(ErrorKind::ValueValidation, "Failed to parse feature from string").exit())
@@ -619,7 +688,8 @@ fn parse_min_nakamoto_coefficients(cmd: &mut clap::Command, min_nakamoto_coeffic
                 None
             } else {
                 let feature = match NodeFeature::from_str(key) {
-                    Ok(v) => v,
+                    Ok(v) if NodeFeature::variants().contains(&v) => v,
+                    Ok(v) => cmd.error(ErrorKind::ValueValidation, format!("Unknown node feature '{}'", v)).exit(),
                     Err(_) => cmd.error(ErrorKind::ValueValidation, "Failed to parse feature from string").exit(),
                 };
                 let val: f64 = val
@@ -634,6 +704,7 @@ fn parse_min_nakamoto_coefficients(cmd: &mut clap::Command, min_nakamoto_coeffic
     Some(MinNakamotoCoefficients {
         coefficients: min_nakamoto_coefficients,
         average,
+        joint_coefficients,
     })
 }
 
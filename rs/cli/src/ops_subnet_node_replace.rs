@@ -1,4 +1,5 @@
 use crate::ic_admin;
+use decentralization::explanation::NodeChange;
 use decentralization::SubnetChangeResponse;
 
 #[cfg(test)]
@@ -16,7 +17,32 @@ pub fn replace_proposal_options(change: &SubnetChangeResponse) -> anyhow::Result
 
     Ok(ic_admin::ProposeOptions {
         title: format!("Replace {replace_target} in subnet {subnet_id_short}",).into(),
-        summary: format!("# Replace {replace_target} in subnet {subnet_id_short}",).into(),
+        summary: format!("# Replace {replace_target} in subnet {subnet_id_short}{}", node_selection_summary(change)).into(),
         motivation: change.motivation.clone(),
     })
 }
+
+/// One line per added or removed node, with the reason it was picked and the
+/// runner-up among the candidates
+fn node_selection_summary(change: &SubnetChangeResponse) -> String {
+    if change.explanations.is_empty() {
+        return String::new();
+    }
+    let lines = change
+        .explanations
+        .iter()
+        .map(|e| {
+            let action = match e.change {
+                NodeChange::Added => "Adding",
+                NodeChange::Removed => "Removing",
+            };
+            let runner_up = e
+                .rejected
+                .first()
+                .map(|r| format!(" Runner-up `{}`: {}.", r.node_id, r.reason))
+                .unwrap_or_default();
+            format!("- {} `{}`: {}.{}", action, e.node_id, e.reason, runner_up)
+        })
+        .collect::<Vec<_>>();
+    format!("\n\n## Node selection\n\n{}", lines.join("\n"))
+}
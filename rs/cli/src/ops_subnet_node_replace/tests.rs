@@ -1,5 +1,6 @@
 use std::str::FromStr;
 
+use decentralization::explanation::{NodeChange, NodeChangeExplanation, RejectedCandidate};
 use decentralization::SubnetChangeResponse;
 use ic_base_types::PrincipalId;
 
@@ -46,3 +47,38 @@ fn replace_proposal_options_2_nodes() {
     assert_eq!(result.summary.unwrap(), "# Replace nodes in subnet tdb26");
     assert_eq!(result.motivation.unwrap(), "For testing purposes");
 }
+
+#[test]
+fn replace_proposal_options_with_explanations() {
+    let added = PrincipalId::from_str("afx6y-22h67-ct72t-etddn-t2jaz-gfsrz-u3yxw-oocjp-gj3za-de3ot-2ae").unwrap();
+    let removed = PrincipalId::from_str("z3tum-w7bue-lt6ca-qgynf-us6oq-nc3qc-7miiq-34rbp-ekuoa-g6cqr-wqe").unwrap();
+    let runner_up = PrincipalId::from_str("dsthq-itfw5-zkibk-chtl5-u7afl-xvxva-7swke-tvqif-vq3t2-wvp7x-mae").unwrap();
+    let change = SubnetChangeResponse {
+        subnet_id: PrincipalId::from_str("tdb26-jop6k-aogll-7ltgs-eruif-6kk7m-qpktf-gdiqx-mxtrf-vb5e6-eqe")
+            .unwrap()
+            .into(),
+        added: vec![added],
+        removed: vec![removed],
+        explanations: vec![
+            NodeChangeExplanation::new(removed, NodeChange::Removed, "requested removal".to_string()),
+            NodeChangeExplanation {
+                rejected: vec![RejectedCandidate {
+                    node_id: runner_up,
+                    reason: "higher penalty 100 > 0 (non_decentralized_nodes)".to_string(),
+                }],
+                ..NodeChangeExplanation::new(added, NodeChange::Added, "best of 2 candidates".to_string())
+            },
+        ],
+        ..Default::default()
+    };
+
+    let result = ops_subnet_node_replace::replace_proposal_options(&change).unwrap();
+
+    assert_eq!(
+        result.summary.unwrap(),
+        format!(
+            "# Replace a node in subnet tdb26\n\n## Node selection\n\n- Removing `{}`: requested removal.\n- Adding `{}`: best of 2 candidates. Runner-up `{}`: higher penalty 100 > 0 (non_decentralized_nodes).",
+            removed, added, runner_up
+        )
+    );
+}
@@ -0,0 +1,463 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use ic_base_types::{PrincipalId, RegistryVersion};
+use ic_interfaces_registry::RegistryClient;
+use ic_management_backend::registry::RegistryFamilyEntries;
+use ic_management_types::Network;
+use ic_protobuf::registry::{
+    firewall::v1::FirewallRuleSet, hostos_version::v1::HostosVersionRecord, node::v1::NodeRecord, node_operator::v1::NodeOperatorRecord,
+    replica_version::v1::ReplicaVersionRecord,
+};
+use ic_registry_keys::FIREWALL_RULES_RECORD_KEY_PREFIX;
+use ic_registry_local_registry::LocalRegistry;
+use prost::Message;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::registry_dump::{get_subnets, local_registry};
+
+/// The parts of the registry at a version that are compared by the diff
+#[derive(Debug, Default, Serialize, Clone, PartialEq)]
+pub struct RegistrySnapshot {
+    pub version: u64,
+    pub nodes: BTreeMap<PrincipalId, NodeSnapshot>,
+    /// Configuration of the subnets, by field, without their membership
+    pub subnets: BTreeMap<PrincipalId, BTreeMap<String, Value>>,
+    pub node_operator_allowances: BTreeMap<PrincipalId, u64>,
+    pub elected_guestos_versions: BTreeSet<String>,
+    pub elected_hostos_versions: BTreeSet<String>,
+    /// Firewall rules by scope
+    pub firewall_rules: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct NodeSnapshot {
+    pub subnet_id: Option<PrincipalId>,
+    pub node_operator_id: PrincipalId,
+}
+
+impl RegistrySnapshot {
+    pub fn load(local_registry: &LocalRegistry, version: RegistryVersion) -> anyhow::Result<Self> {
+        let subnets = get_subnets(local_registry, version)?;
+        let subnet_of_node: BTreeMap<&String, PrincipalId> = subnets
+            .iter()
+            .flat_map(|subnet| subnet.membership.iter().map(|node| (node, subnet.subnet_id)))
+            .collect();
+
+        let nodes = local_registry
+            .get_family_entries_of_version::<NodeRecord>(version)
+            .map_err(|e| anyhow::anyhow!("Couldn't get nodes: {:?}", e))?
+            .into_iter()
+            .map(|(k, (_, record))| {
+                let node = NodeSnapshot {
+                    subnet_id: subnet_of_node.get(&k).cloned(),
+                    node_operator_id: PrincipalId::try_from(&record.node_operator_id).expect("Couldn't parse principal id"),
+                };
+                (PrincipalId::from_str(&k).expect("Couldn't parse principal id"), node)
+            })
+            .collect();
+
+        let subnets = subnets
+            .iter()
+            .map(|subnet| -> anyhow::Result<(PrincipalId, BTreeMap<String, Value>)> {
+                let mut config = match serde_json::to_value(subnet)? {
+                    Value::Object(config) => config.into_iter().collect::<BTreeMap<_, _>>(),
+                    _ => unreachable!("subnet records serialize to objects"),
+                };
+                // Membership changes are reported as node moves
+                for field in ["subnet_id", "membership", "nodes"] {
+                    config.remove(field);
+                }
+                Ok((subnet.subnet_id, config))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let node_operator_allowances = local_registry
+            .get_family_entries_of_version::<NodeOperatorRecord>(version)
+            .map_err(|e| anyhow::anyhow!("Couldn't get node operators: {:?}", e))?
+            .into_iter()
+            .map(|(k, (_, record))| (PrincipalId::from_str(&k).expect("Couldn't parse principal id"), record.node_allowance))
+            .collect();
+
+        let elected_guestos_versions = local_registry
+            .get_family_entries_of_version::<ReplicaVersionRecord>(version)
+            .map_err(|e| anyhow::anyhow!("Couldn't get elected versions: {:?}", e))?
+            .into_keys()
+            .collect();
+        let elected_hostos_versions = local_registry
+            .get_family_entries_of_version::<HostosVersionRecord>(version)
+            .map_err(|e| anyhow::anyhow!("Couldn't get elected versions: {:?}", e))?
+            .into_keys()
+            .collect();
+
+        let mut firewall_rules = BTreeMap::new();
+        for key in local_registry.get_key_family(FIREWALL_RULES_RECORD_KEY_PREFIX, version)? {
+            if let Some(value) = local_registry.get_value(&key, version)? {
+                let rules =
+                    FirewallRuleSet::decode(value.as_slice()).map_err(|e| anyhow::anyhow!("Failed to deserialize firewall ruleset: {:?}", e))?;
+                firewall_rules.insert(
+                    key[FIREWALL_RULES_RECORD_KEY_PREFIX.len()..].to_string(),
+                    serde_json::to_value(rules.entries)?,
+                );
+            }
+        }
+
+        Ok(Self {
+            version: version.get(),
+            nodes,
+            subnets,
+            node_operator_allowances,
+            elected_guestos_versions,
+            elected_hostos_versions,
+            firewall_rules,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct NodeMove {
+    pub node_id: PrincipalId,
+    pub from: Option<PrincipalId>,
+    pub to: Option<PrincipalId>,
+}
+
+/// A change of a value, which is `None` on the side where it did not exist
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Change<K, V> {
+    pub key: K,
+    pub from: Option<V>,
+    pub to: Option<V>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct SubnetFieldChange {
+    pub subnet_id: PrincipalId,
+    pub field: String,
+    pub from: Option<Value>,
+    pub to: Option<Value>,
+}
+
+#[derive(Debug, Default, Serialize, Clone, PartialEq)]
+pub struct RegistryDiff {
+    pub from: u64,
+    pub to: u64,
+    pub nodes_added: Vec<PrincipalId>,
+    pub nodes_removed: Vec<PrincipalId>,
+    /// Nodes that were added to, removed from, or moved between subnets
+    pub nodes_moved: Vec<NodeMove>,
+    pub subnets_added: Vec<PrincipalId>,
+    pub subnets_removed: Vec<PrincipalId>,
+    pub subnet_changes: Vec<SubnetFieldChange>,
+    pub node_operator_allowance_changes: Vec<Change<PrincipalId, u64>>,
+    pub guestos_versions_elected: Vec<String>,
+    pub guestos_versions_unelected: Vec<String>,
+    pub hostos_versions_elected: Vec<String>,
+    pub hostos_versions_unelected: Vec<String>,
+    /// Changes of the firewall rules, by scope
+    pub firewall_rule_changes: Vec<Change<String, Value>>,
+}
+
+fn map_changes<K: Ord + Clone, V: PartialEq + Clone>(from: &BTreeMap<K, V>, to: &BTreeMap<K, V>) -> Vec<Change<K, V>> {
+    from.keys()
+        .chain(to.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|key| from.get(*key) != to.get(*key))
+        .map(|key| Change {
+            key: key.clone(),
+            from: from.get(key).cloned(),
+            to: to.get(key).cloned(),
+        })
+        .collect()
+}
+
+impl RegistryDiff {
+    pub fn new(from: &RegistrySnapshot, to: &RegistrySnapshot) -> Self {
+        let added = |from: &BTreeSet<String>, to: &BTreeSet<String>| to.difference(from).cloned().collect::<Vec<_>>();
+        Self {
+            from: from.version,
+            to: to.version,
+            nodes_added: to.nodes.keys().filter(|n| !from.nodes.contains_key(n)).cloned().collect(),
+            nodes_removed: from.nodes.keys().filter(|n| !to.nodes.contains_key(n)).cloned().collect(),
+            nodes_moved: from
+                .nodes
+                .iter()
+                .filter_map(|(node_id, before)| {
+                    let after = to.nodes.get(node_id)?;
+                    (before.subnet_id != after.subnet_id).then_some(NodeMove {
+                        node_id: *node_id,
+                        from: before.subnet_id,
+                        to: after.subnet_id,
+                    })
+                })
+                .collect(),
+            subnets_added: to.subnets.keys().filter(|s| !from.subnets.contains_key(s)).cloned().collect(),
+            subnets_removed: from.subnets.keys().filter(|s| !to.subnets.contains_key(s)).cloned().collect(),
+            subnet_changes: from
+                .subnets
+                .iter()
+                .filter_map(|(subnet_id, before)| Some((subnet_id, before, to.subnets.get(subnet_id)?)))
+                .flat_map(|(subnet_id, before, after)| {
+                    map_changes(before, after).into_iter().map(|change| SubnetFieldChange {
+                        subnet_id: *subnet_id,
+                        field: change.key,
+                        from: change.from,
+                        to: change.to,
+                    })
+                })
+                .collect(),
+            node_operator_allowance_changes: map_changes(&from.node_operator_allowances, &to.node_operator_allowances),
+            guestos_versions_elected: added(&from.elected_guestos_versions, &to.elected_guestos_versions),
+            guestos_versions_unelected: added(&to.elected_guestos_versions, &from.elected_guestos_versions),
+            hostos_versions_elected: added(&from.elected_hostos_versions, &to.elected_hostos_versions),
+            hostos_versions_unelected: added(&to.elected_hostos_versions, &from.elected_hostos_versions),
+            firewall_rule_changes: map_changes(&from.firewall_rules, &to.firewall_rules),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self
+            == Self {
+                from: self.from,
+                to: self.to,
+                ..Default::default()
+            }
+    }
+}
+
+fn or_none<T: Display>(value: &Option<T>) -> String {
+    value.as_ref().map(|v| v.to_string()).unwrap_or_else(|| "none".to_string())
+}
+
+impl Display for RegistryDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Registry changes from version {} to {}", self.from, self.to)?;
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        let list = |f: &mut std::fmt::Formatter<'_>, title: &str, items: Vec<String>| -> std::fmt::Result {
+            if items.is_empty() {
+                return Ok(());
+            }
+            writeln!(f, "\n{}:", title)?;
+            items.iter().try_for_each(|item| writeln!(f, "  {}", item))
+        };
+        let ids = |ids: &[PrincipalId]| ids.iter().map(|id| id.to_string()).collect();
+        list(f, "Nodes added", ids(&self.nodes_added))?;
+        list(f, "Nodes removed", ids(&self.nodes_removed))?;
+        list(
+            f,
+            "Nodes moved",
+            self.nodes_moved
+                .iter()
+                .map(|m| format!("{}: {} -> {}", m.node_id, or_none(&m.from), or_none(&m.to)))
+                .collect(),
+        )?;
+        list(f, "Subnets added", ids(&self.subnets_added))?;
+        list(f, "Subnets removed", ids(&self.subnets_removed))?;
+        list(
+            f,
+            "Subnet changes",
+            self.subnet_changes
+                .iter()
+                .map(|c| format!("{} {}: {} -> {}", c.subnet_id, c.field, or_none(&c.from), or_none(&c.to)))
+                .collect(),
+        )?;
+        list(
+            f,
+            "Node operator allowance changes",
+            self.node_operator_allowance_changes
+                .iter()
+                .map(|c| format!("{}: {} -> {}", c.key, or_none(&c.from), or_none(&c.to)))
+                .collect(),
+        )?;
+        list(f, "GuestOS versions elected", self.guestos_versions_elected.clone())?;
+        list(f, "GuestOS versions unelected", self.guestos_versions_unelected.clone())?;
+        list(f, "HostOS versions elected", self.hostos_versions_elected.clone())?;
+        list(f, "HostOS versions unelected", self.hostos_versions_unelected.clone())?;
+        list(
+            f,
+            "Firewall rule changes",
+            self.firewall_rule_changes
+                .iter()
+                .map(|c| format!("{}: {} -> {}", c.key, or_none(&c.from), or_none(&c.to)))
+                .collect(),
+        )
+    }
+}
+
+/// Finds the first version in `(from, to]` at which `value_at` differs from
+/// its value at `from`. If the value changed more than once in the range, the
+/// version found is one of those at which it changed.
+pub fn bisect<F>(from: u64, to: u64, value_at: F) -> anyhow::Result<Option<u64>>
+where
+    F: Fn(u64) -> anyhow::Result<Option<Value>>,
+{
+    let initial = value_at(from)?;
+    if value_at(to)? == initial {
+        return Ok(None);
+    }
+    let (mut low, mut high) = (from, to);
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if value_at(mid)? == initial {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Ok(Some(high))
+}
+
+pub async fn diff_registry(
+    path: &Option<PathBuf>,
+    network: &Network,
+    from: u64,
+    to: i64,
+    json: bool,
+    bisect_pointer: &Option<String>,
+) -> anyhow::Result<()> {
+    let local_registry = local_registry(path, network).await?;
+    let to = if to >= 0 {
+        RegistryVersion::new(to as u64)
+    } else {
+        local_registry.get_latest_version()
+    };
+    if to.get() < from {
+        anyhow::bail!("Version to compare to ({}) is older than the version to compare from ({})", to, from);
+    }
+
+    if let Some(pointer) = bisect_pointer {
+        let value_at = |version: u64| -> anyhow::Result<Option<Value>> {
+            let snapshot = RegistrySnapshot::load(&local_registry, RegistryVersion::new(version))?;
+            Ok(serde_json::to_value(snapshot)?.pointer(pointer).cloned())
+        };
+        match bisect(from, to.get(), value_at)? {
+            Some(version) => {
+                let before = value_at(version - 1)?;
+                let after = value_at(version)?;
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&serde_json::json!({ "pointer": pointer, "version": version, "from": before, "to": after }))?
+                    );
+                } else {
+                    println!("{} changed at version {}: {} -> {}", pointer, version, or_none(&before), or_none(&after));
+                }
+            }
+            None => println!("{} did not change between versions {} and {}", pointer, from, to),
+        }
+        return Ok(());
+    }
+
+    let diff = RegistryDiff::new(
+        &RegistrySnapshot::load(&local_registry, RegistryVersion::new(from))?,
+        &RegistrySnapshot::load(&local_registry, to)?,
+    );
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        print!("{}", diff);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn diff_of_snapshots() {
+        let subnet = |id: u64| PrincipalId::new_subnet_test_id(id);
+        let node = |id: u64| PrincipalId::new_node_test_id(id);
+        let operator = PrincipalId::new_user_test_id(1);
+        let in_subnet = |subnet_id: Option<PrincipalId>| NodeSnapshot {
+            subnet_id,
+            node_operator_id: operator,
+        };
+        let from = RegistrySnapshot {
+            version: 10,
+            nodes: BTreeMap::from([
+                (node(1), in_subnet(Some(subnet(1)))),
+                (node(2), in_subnet(None)),
+                (node(3), in_subnet(None)),
+            ]),
+            subnets: BTreeMap::from([(subnet(1), BTreeMap::from([("replica_version_id".to_string(), json!("a"))]))]),
+            node_operator_allowances: BTreeMap::from([(operator, 3)]),
+            elected_guestos_versions: BTreeSet::from(["a".to_string()]),
+            ..Default::default()
+        };
+        let to = RegistrySnapshot {
+            version: 12,
+            nodes: BTreeMap::from([
+                (node(1), in_subnet(None)),
+                (node(2), in_subnet(Some(subnet(1)))),
+                (node(4), in_subnet(None)),
+            ]),
+            subnets: BTreeMap::from([(subnet(1), BTreeMap::from([("replica_version_id".to_string(), json!("b"))]))]),
+            node_operator_allowances: BTreeMap::from([(operator, 2)]),
+            elected_guestos_versions: BTreeSet::from(["b".to_string()]),
+            firewall_rules: BTreeMap::from([("global".to_string(), json!([]))]),
+            ..Default::default()
+        };
+
+        let diff = RegistryDiff::new(&from, &to);
+        assert_eq!(
+            diff,
+            RegistryDiff {
+                from: 10,
+                to: 12,
+                nodes_added: vec![node(4)],
+                nodes_removed: vec![node(3)],
+                nodes_moved: vec![
+                    NodeMove {
+                        node_id: node(1),
+                        from: Some(subnet(1)),
+                        to: None
+                    },
+                    NodeMove {
+                        node_id: node(2),
+                        from: None,
+                        to: Some(subnet(1))
+                    },
+                ],
+                subnet_changes: vec![SubnetFieldChange {
+                    subnet_id: subnet(1),
+                    field: "replica_version_id".to_string(),
+                    from: Some(json!("a")),
+                    to: Some(json!("b")),
+                }],
+                node_operator_allowance_changes: vec![Change {
+                    key: operator,
+                    from: Some(3),
+                    to: Some(2)
+                }],
+                guestos_versions_elected: vec!["b".to_string()],
+                guestos_versions_unelected: vec!["a".to_string()],
+                firewall_rule_changes: vec![Change {
+                    key: "global".to_string(),
+                    from: None,
+                    to: Some(json!([]))
+                }],
+                ..Default::default()
+            }
+        );
+        assert!(!diff.is_empty());
+        assert!(RegistryDiff::new(&from, &from).is_empty());
+    }
+
+    #[test]
+    fn bisect_finds_change() {
+        let value_at = |version: u64| Ok(Some(json!(if version >= 37 { "b" } else { "a" })));
+        assert_eq!(bisect(1, 100, value_at).unwrap(), Some(37));
+        assert_eq!(bisect(37, 100, value_at).unwrap(), None);
+        assert_eq!(bisect(36, 37, value_at).unwrap(), Some(37));
+    }
+}
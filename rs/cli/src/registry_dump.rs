@@ -5,6 +5,7 @@ use std::{
     time::Duration,
 };
 
+use decentralization::features::CustomNodeFeatures;
 use ic_base_types::{PrincipalId, RegistryVersion};
 use ic_interfaces_registry::RegistryClient;
 use ic_management_backend::{
@@ -12,7 +13,7 @@ use ic_management_backend::{
     public_dashboard::query_ic_dashboard_list,
     registry::{local_registry_path, sync_local_store, RegistryFamilyEntries},
 };
-use ic_management_types::{Network, NodeProvidersResponse, Status};
+use ic_management_types::{Network, NodeFeature, NodeProvidersResponse, Status};
 use ic_protobuf::registry::{
     api_boundary_node::v1::ApiBoundaryNodeRecord,
     dc::v1::DataCenterRecord,
@@ -31,6 +32,10 @@ use log::{info, warn};
 use registry_canister::mutations::common::decode_registry_value;
 use serde::Serialize;
 
+use crate::cli::registry::DumpFormat;
+
+mod export;
+
 #[derive(Debug, Serialize)]
 struct RegistryDump {
     elected_guest_os_versions: Vec<ReplicaVersionRecord>,
@@ -44,13 +49,18 @@ struct RegistryDump {
     api_bns: Vec<ApiBoundaryNodeDetails>,
 }
 
-async fn get_registry(path: &Option<PathBuf>, network: &Network, version: &i64) -> Result<RegistryDump, anyhow::Error> {
+/// Syncs the local registry of the network, or the one at `path`, and opens it
+pub(crate) async fn local_registry(path: &Option<PathBuf>, network: &Network) -> Result<LocalRegistry, anyhow::Error> {
     if let Some(path) = path {
         std::env::set_var("LOCAL_REGISTRY_PATH", path)
     }
     sync_local_store(network).await?;
 
-    let local_registry = LocalRegistry::new(local_registry_path(network), Duration::from_secs(10))?;
+    Ok(LocalRegistry::new(local_registry_path(network), Duration::from_secs(10))?)
+}
+
+async fn get_registry(path: &Option<PathBuf>, network: &Network, version: &i64) -> Result<RegistryDump, anyhow::Error> {
+    let local_registry = local_registry(path, network).await?;
 
     // determine desired version
     let version = {
@@ -128,7 +138,24 @@ pub async fn dump_registry(
     version: &i64,
     output: &Option<PathBuf>,
     incorrect_rewards_info_only: bool,
+    format: &DumpFormat,
 ) -> Result<(), anyhow::Error> {
+    if *format != DumpFormat::Json {
+        if incorrect_rewards_info_only {
+            anyhow::bail!("--incorrect-rewards is only supported for the JSON format");
+        }
+        let output = output
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("--output is required for the {:?} format", format))?;
+        let dump = get_registry(path, network, version).await?;
+        return match format {
+            DumpFormat::Csv => export::to_csv(&dump, output),
+            DumpFormat::Parquet => export::to_parquet(&dump, output),
+            DumpFormat::Sqlite => export::to_sqlite(&dump, output),
+            DumpFormat::Json => unreachable!(),
+        };
+    }
+
     let writer: Box<dyn std::io::Write> = match output {
         Some(path) => {
             let path = path.with_extension("json").canonicalize()?;
@@ -178,6 +205,7 @@ async fn get_nodes(
 ) -> Result<Vec<NodeDetails>, RegistryDumpError> {
     let health_client = HealthClient::new(network.clone());
     let nodes_health = health_client.nodes().await?;
+    let custom_features = CustomNodeFeatures::active();
 
     let nodes = local_registry
         .get_family_entries_of_version::<NodeRecord>(version)
@@ -186,6 +214,15 @@ async fn get_nodes(
         .map(|(k, (_, record))| {
             let node_operator_id = PrincipalId::try_from(&record.node_operator_id).expect("Couldn't parse principal id");
             let node_id = PrincipalId::from_str(&k).expect("Couldn't parse principal id");
+            let node_provider_id = match node_operators.get(&node_operator_id) {
+                Some(no) => no.node_provider_principal_id,
+                None => PrincipalId::new_anonymous(),
+            };
+            let dc_id = match node_operators.get(&node_operator_id) {
+                Some(no) => no.dc_id.clone(),
+                None => "".to_string(),
+            };
+            let node_custom_features = custom_features.values(&node_id, &node_provider_id, Some(&dc_id));
             NodeDetails {
                 node_id,
                 xnet: record.xnet,
@@ -194,26 +231,21 @@ async fn get_nodes(
                 chip_id: record.chip_id,
                 hostos_version_id: record.hostos_version_id,
                 public_ipv4_config: record.public_ipv4_config,
-                node_provider_id: match node_operators.get(&node_operator_id) {
-                    Some(no) => no.node_provider_principal_id,
-                    None => PrincipalId::new_anonymous(),
-                },
+                node_provider_id,
                 subnet_id: subnets
                     .iter()
                     .find(|subnet| subnet.membership.contains(&k))
                     .map(|subnet| subnet.subnet_id),
-                dc_id: match node_operators.get(&node_operator_id) {
-                    Some(no) => no.dc_id.clone(),
-                    None => "".to_string(),
-                },
+                dc_id,
                 status: nodes_health.get(&node_id).unwrap_or(&ic_management_types::Status::Unknown).clone(),
+                custom_features: node_custom_features,
             }
         })
         .collect::<Vec<_>>();
     Ok(nodes)
 }
 
-fn get_subnets(local_registry: &LocalRegistry, version: RegistryVersion) -> Result<Vec<SubnetRecord>, RegistryDumpError> {
+pub(crate) fn get_subnets(local_registry: &LocalRegistry, version: RegistryVersion) -> Result<Vec<SubnetRecord>, RegistryDumpError> {
     Ok(local_registry
         .get_family_entries_of_version::<SubnetRecordProto>(version)
         .map_err(|e| anyhow::anyhow!("Couldn't get subnets: {:?}", e))?
@@ -385,14 +417,17 @@ struct NodeDetails {
     dc_id: String,
     node_provider_id: PrincipalId,
     status: Status,
+    /// Values of the custom node features, see [CustomNodeFeatures]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    custom_features: BTreeMap<NodeFeature, String>,
 }
 
 /// User-friendly representation of a SubnetRecord. For instance,
 /// the `membership` field is a `Vec<String>` to pretty-print the node IDs.
 #[derive(Debug, Default, Serialize, Clone)]
-struct SubnetRecord {
-    subnet_id: PrincipalId,
-    membership: Vec<String>,
+pub(crate) struct SubnetRecord {
+    pub(crate) subnet_id: PrincipalId,
+    pub(crate) membership: Vec<String>,
     nodes: BTreeMap<PrincipalId, NodeDetails>,
     max_ingress_bytes_per_message: u64,
     max_ingress_messages_per_block: u64,
@@ -458,7 +493,7 @@ pub struct NodeRewardsTableFlattened {
     pub table: BTreeMap<String, NodeRewardRatesFlattened>,
 }
 
-enum RegistryDumpError {
+pub(crate) enum RegistryDumpError {
     RegistryClientError(ic_types::registry::RegistryClientError),
     LocalRegistryError(ic_registry_local_registry::LocalRegistryError),
     IoError(std::io::Error),
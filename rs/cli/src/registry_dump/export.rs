@@ -0,0 +1,301 @@
+//! Flat, per-entity views of a [RegistryDump], written as CSV, Parquet or a
+//! SQLite database for analysis.
+//!
+//! Parquet and SQLite pull in large dependencies, so they are only built with
+//! the `parquet` and `sqlite` features of the crate.
+
+use std::{collections::BTreeMap, path::Path};
+
+use log::info;
+use serde::Serialize;
+
+use super::RegistryDump;
+
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "parquet")]
+pub(super) use self::parquet::to_parquet;
+#[cfg(feature = "sqlite")]
+pub(super) use self::sqlite::to_sqlite;
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("registry records serialize to JSON")
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct NodeRow {
+    node_id: String,
+    subnet_id: Option<String>,
+    node_operator_id: String,
+    node_provider_id: String,
+    dc_id: String,
+    hostos_version_id: Option<String>,
+    status: String,
+    http: Option<String>,
+    xnet: Option<String>,
+    public_ipv4: Option<String>,
+    chip_id: Option<String>,
+    /// JSON object of the custom node features
+    custom_features: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct SubnetRow {
+    subnet_id: String,
+    subnet_type: String,
+    replica_version_id: String,
+    node_count: u64,
+    max_ingress_bytes_per_message: u64,
+    max_ingress_messages_per_block: u64,
+    max_block_payload_size: u64,
+    unit_delay_millis: u64,
+    initial_notary_delay_millis: u64,
+    dkg_interval_length: u64,
+    start_as_nns: bool,
+    max_instructions_per_message: u64,
+    max_instructions_per_round: u64,
+    max_instructions_per_install_code: u64,
+    max_number_of_canisters: u64,
+    /// JSON object of the subnet features
+    features: String,
+    /// JSON object of the ECDSA config, if any
+    ecdsa_config: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct NodeOperatorRow {
+    node_operator_id: String,
+    node_provider_id: String,
+    dc_id: String,
+    node_allowance_remaining: u64,
+    node_allowance_total: u64,
+    total_up_nodes: u32,
+    rewards_correct: bool,
+    ipv6: Option<String>,
+    /// JSON object of the number of rewardable nodes by node type
+    rewardable_nodes: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct NodeProviderRow {
+    node_provider_id: String,
+    name: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct DataCenterRow {
+    dc_id: String,
+    region: String,
+    owner: String,
+    latitude: Option<f32>,
+    longitude: Option<f32>,
+}
+
+/// The rows of every entity of the dump
+struct Tables {
+    nodes: Vec<NodeRow>,
+    subnets: Vec<SubnetRow>,
+    node_operators: Vec<NodeOperatorRow>,
+    node_providers: Vec<NodeProviderRow>,
+    data_centers: Vec<DataCenterRow>,
+}
+
+impl From<&RegistryDump> for Tables {
+    fn from(dump: &RegistryDump) -> Self {
+        let nodes = dump
+            .nodes
+            .iter()
+            .map(|n| NodeRow {
+                node_id: n.node_id.to_string(),
+                subnet_id: n.subnet_id.map(|s| s.to_string()),
+                node_operator_id: n.node_operator_id.to_string(),
+                node_provider_id: n.node_provider_id.to_string(),
+                dc_id: n.dc_id.clone(),
+                hostos_version_id: n.hostos_version_id.clone(),
+                status: n.status.to_string(),
+                http: n.http.as_ref().map(|e| format!("[{}]:{}", e.ip_addr, e.port)),
+                xnet: n.xnet.as_ref().map(|e| format!("[{}]:{}", e.ip_addr, e.port)),
+                public_ipv4: n.public_ipv4_config.as_ref().map(|c| format!("{}/{}", c.ip_addr, c.prefix_length)),
+                chip_id: n.chip_id.as_ref().map(|c| c.iter().map(|b| format!("{:02x}", b)).collect()),
+                custom_features: to_json(&n.custom_features),
+            })
+            .collect();
+        let subnets = dump
+            .subnets
+            .iter()
+            .map(|s| SubnetRow {
+                subnet_id: s.subnet_id.to_string(),
+                subnet_type: to_json(&s.subnet_type).trim_matches('"').to_string(),
+                replica_version_id: s.replica_version_id.clone(),
+                node_count: s.membership.len() as u64,
+                max_ingress_bytes_per_message: s.max_ingress_bytes_per_message,
+                max_ingress_messages_per_block: s.max_ingress_messages_per_block,
+                max_block_payload_size: s.max_block_payload_size,
+                unit_delay_millis: s.unit_delay_millis,
+                initial_notary_delay_millis: s.initial_notary_delay_millis,
+                dkg_interval_length: s.dkg_interval_length,
+                start_as_nns: s.start_as_nns,
+                max_instructions_per_message: s.max_instructions_per_message,
+                max_instructions_per_round: s.max_instructions_per_round,
+                max_instructions_per_install_code: s.max_instructions_per_install_code,
+                max_number_of_canisters: s.max_number_of_canisters,
+                features: to_json(&s.features),
+                ecdsa_config: s.ecdsa_config.as_ref().map(to_json),
+            })
+            .collect();
+        let node_operators = dump
+            .node_operators
+            .iter()
+            .map(|o| NodeOperatorRow {
+                node_operator_id: o.node_operator_principal_id.to_string(),
+                node_provider_id: o.node_provider_principal_id.to_string(),
+                dc_id: o.dc_id.clone(),
+                node_allowance_remaining: o.node_allowance_remaining,
+                node_allowance_total: o.node_allowance_total,
+                total_up_nodes: o.total_up_nodes,
+                rewards_correct: o.rewards_correct,
+                ipv6: o.ipv6.clone(),
+                rewardable_nodes: to_json(&o.rewardable_nodes),
+            })
+            .collect();
+        let node_providers = dump
+            .node_operators
+            .iter()
+            .map(|o| (o.node_provider_principal_id, o.node_provider_name.clone()))
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .map(|(id, name)| NodeProviderRow {
+                node_provider_id: id.to_string(),
+                name,
+            })
+            .collect();
+        let data_centers = dump
+            .dcs
+            .iter()
+            .map(|dc| DataCenterRow {
+                dc_id: dc.id.clone(),
+                region: dc.region.clone(),
+                owner: dc.owner.clone(),
+                latitude: dc.gps.as_ref().map(|g| g.latitude),
+                longitude: dc.gps.as_ref().map(|g| g.longitude),
+            })
+            .collect();
+        Self {
+            nodes,
+            subnets,
+            node_operators,
+            node_providers,
+            data_centers,
+        }
+    }
+}
+
+fn write_csv<T: Serialize>(dir: &Path, name: &str, rows: &[T]) -> anyhow::Result<()> {
+    let path = dir.join(name).with_extension("csv");
+    let mut writer = csv::Writer::from_path(&path)?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    info!("Wrote {} rows to {:?}", rows.len(), path);
+    Ok(())
+}
+
+/// Writes one CSV file per entity into `dir`
+pub(super) fn to_csv(dump: &RegistryDump, dir: &Path) -> anyhow::Result<()> {
+    let tables = Tables::from(dump);
+    fs_err::create_dir_all(dir)?;
+    write_csv(dir, "nodes", &tables.nodes)?;
+    write_csv(dir, "subnets", &tables.subnets)?;
+    write_csv(dir, "node_operators", &tables.node_operators)?;
+    write_csv(dir, "node_providers", &tables.node_providers)?;
+    write_csv(dir, "data_centers", &tables.data_centers)
+}
+
+#[cfg(not(feature = "parquet"))]
+pub(super) fn to_parquet(_dump: &RegistryDump, _dir: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("dre was built without Parquet support, build it with `--features parquet`")
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub(super) fn to_sqlite(_dump: &RegistryDump, _path: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("dre was built without SQLite support, build it with `--features sqlite`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) fn tables() -> Tables {
+        Tables {
+            nodes: vec![NodeRow {
+                node_id: "node-1".to_string(),
+                subnet_id: Some("subnet-1".to_string()),
+                node_operator_id: "operator-1".to_string(),
+                node_provider_id: "provider-1".to_string(),
+                dc_id: "dc1".to_string(),
+                hostos_version_id: None,
+                status: "Healthy".to_string(),
+                http: Some("[::1]:8080".to_string()),
+                xnet: None,
+                public_ipv4: None,
+                chip_id: None,
+                custom_features: "{}".to_string(),
+            }],
+            subnets: vec![SubnetRow {
+                subnet_id: "subnet-1".to_string(),
+                subnet_type: "application".to_string(),
+                replica_version_id: "a".to_string(),
+                node_count: 1,
+                max_ingress_bytes_per_message: 1,
+                max_ingress_messages_per_block: 1,
+                max_block_payload_size: 1,
+                unit_delay_millis: 1,
+                initial_notary_delay_millis: 1,
+                dkg_interval_length: 1,
+                start_as_nns: false,
+                max_instructions_per_message: 1,
+                max_instructions_per_round: 1,
+                max_instructions_per_install_code: 1,
+                max_number_of_canisters: 1,
+                features: "{}".to_string(),
+                ecdsa_config: None,
+            }],
+            node_operators: vec![NodeOperatorRow {
+                node_operator_id: "operator-1".to_string(),
+                node_provider_id: "provider-1".to_string(),
+                dc_id: "dc1".to_string(),
+                node_allowance_remaining: 0,
+                node_allowance_total: 1,
+                total_up_nodes: 1,
+                rewards_correct: true,
+                ipv6: None,
+                rewardable_nodes: "{}".to_string(),
+            }],
+            node_providers: vec![NodeProviderRow {
+                node_provider_id: "provider-1".to_string(),
+                name: "Provider".to_string(),
+            }],
+            data_centers: vec![DataCenterRow {
+                dc_id: "dc1".to_string(),
+                region: "Europe".to_string(),
+                owner: "Owner".to_string(),
+                latitude: None,
+                longitude: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn csv_files() {
+        let dir = tempfile::tempdir().unwrap();
+        write_csv(dir.path(), "nodes", &tables().nodes).unwrap();
+
+        let csv = fs_err::read_to_string(dir.path().join("nodes.csv")).unwrap();
+        assert_eq!(csv.lines().count(), 2);
+        assert!(csv.starts_with("node_id,subnet_id,node_operator_id"));
+    }
+}
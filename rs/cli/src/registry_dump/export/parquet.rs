@@ -0,0 +1,54 @@
+use std::{path::Path, sync::Arc};
+
+use arrow_json::reader::{infer_json_schema_from_iterator, ReaderBuilder};
+use log::{info, warn};
+use parquet::arrow::ArrowWriter;
+use serde::Serialize;
+
+use super::{RegistryDump, Tables};
+
+fn write_parquet<T: Serialize>(dir: &Path, name: &str, rows: &[T]) -> anyhow::Result<()> {
+    let path = dir.join(name).with_extension("parquet");
+    if rows.is_empty() {
+        warn!("No {} in the registry, not writing {:?}", name, path);
+        return Ok(());
+    }
+    let values = rows.iter().map(serde_json::to_value).collect::<Result<Vec<_>, _>>()?;
+    let schema = Arc::new(infer_json_schema_from_iterator(values.iter().map(Ok))?);
+    let mut decoder = ReaderBuilder::new(schema.clone()).with_batch_size(rows.len()).build_decoder()?;
+    decoder.serialize(rows)?;
+    let batch = decoder.flush()?.ok_or_else(|| anyhow::anyhow!("No rows decoded for {}", name))?;
+
+    let mut writer = ArrowWriter::try_new(fs_err::File::create(&path)?, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    info!("Wrote {} rows to {:?}", rows.len(), path);
+    Ok(())
+}
+
+/// Writes one Parquet file per entity into `dir`
+pub(crate) fn to_parquet(dump: &RegistryDump, dir: &Path) -> anyhow::Result<()> {
+    let tables = Tables::from(dump);
+    fs_err::create_dir_all(dir)?;
+    write_parquet(dir, "nodes", &tables.nodes)?;
+    write_parquet(dir, "subnets", &tables.subnets)?;
+    write_parquet(dir, "node_operators", &tables.node_operators)?;
+    write_parquet(dir, "node_providers", &tables.node_providers)?;
+    write_parquet(dir, "data_centers", &tables.data_centers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{tests::tables, NodeRow};
+    use super::*;
+
+    #[test]
+    fn parquet_files() {
+        let dir = tempfile::tempdir().unwrap();
+        write_parquet(dir.path(), "nodes", &tables().nodes).unwrap();
+        write_parquet(dir.path(), "empty", &Vec::<NodeRow>::new()).unwrap();
+
+        assert!(dir.path().join("nodes.parquet").exists());
+        assert!(!dir.path().join("empty.parquet").exists());
+    }
+}
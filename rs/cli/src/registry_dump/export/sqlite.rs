@@ -0,0 +1,196 @@
+use std::path::Path;
+
+use itertools::Itertools;
+use log::info;
+use rusqlite::{params, Connection};
+
+use super::{RegistryDump, Tables};
+
+// The foreign keys are declared so that tools can follow them, but are not
+// enforced: the registry can reference records that don't exist, e.g. nodes
+// of a node operator that was removed.
+const SCHEMA: &str = r#"
+CREATE TABLE node_providers (
+    node_provider_id TEXT PRIMARY KEY,
+    name TEXT NOT NULL
+);
+CREATE TABLE data_centers (
+    dc_id TEXT PRIMARY KEY,
+    region TEXT NOT NULL,
+    owner TEXT NOT NULL,
+    latitude REAL,
+    longitude REAL
+);
+CREATE TABLE subnets (
+    subnet_id TEXT PRIMARY KEY,
+    subnet_type TEXT NOT NULL,
+    replica_version_id TEXT NOT NULL,
+    node_count INTEGER NOT NULL,
+    max_ingress_bytes_per_message INTEGER NOT NULL,
+    max_ingress_messages_per_block INTEGER NOT NULL,
+    max_block_payload_size INTEGER NOT NULL,
+    unit_delay_millis INTEGER NOT NULL,
+    initial_notary_delay_millis INTEGER NOT NULL,
+    dkg_interval_length INTEGER NOT NULL,
+    start_as_nns INTEGER NOT NULL,
+    max_instructions_per_message INTEGER NOT NULL,
+    max_instructions_per_round INTEGER NOT NULL,
+    max_instructions_per_install_code INTEGER NOT NULL,
+    max_number_of_canisters INTEGER NOT NULL,
+    features TEXT NOT NULL,
+    ecdsa_config TEXT
+);
+CREATE TABLE node_operators (
+    node_operator_id TEXT PRIMARY KEY,
+    node_provider_id TEXT NOT NULL REFERENCES node_providers (node_provider_id),
+    dc_id TEXT NOT NULL REFERENCES data_centers (dc_id),
+    node_allowance_remaining INTEGER NOT NULL,
+    node_allowance_total INTEGER NOT NULL,
+    total_up_nodes INTEGER NOT NULL,
+    rewards_correct INTEGER NOT NULL,
+    ipv6 TEXT,
+    rewardable_nodes TEXT NOT NULL
+);
+CREATE TABLE nodes (
+    node_id TEXT PRIMARY KEY,
+    subnet_id TEXT REFERENCES subnets (subnet_id),
+    node_operator_id TEXT NOT NULL REFERENCES node_operators (node_operator_id),
+    node_provider_id TEXT NOT NULL REFERENCES node_providers (node_provider_id),
+    dc_id TEXT NOT NULL,
+    hostos_version_id TEXT,
+    status TEXT NOT NULL,
+    http TEXT,
+    xnet TEXT,
+    public_ipv4 TEXT,
+    chip_id TEXT,
+    custom_features TEXT NOT NULL
+);
+"#;
+
+fn insert_tables(conn: &mut Connection, tables: &Tables) -> anyhow::Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare("INSERT INTO node_providers VALUES (?1, ?2)")?;
+        for p in tables.node_providers.iter() {
+            stmt.execute(params![p.node_provider_id, p.name])?;
+        }
+        let mut stmt = tx.prepare("INSERT INTO data_centers VALUES (?1, ?2, ?3, ?4, ?5)")?;
+        for dc in tables.data_centers.iter() {
+            stmt.execute(params![
+                dc.dc_id,
+                dc.region,
+                dc.owner,
+                dc.latitude.map(f64::from),
+                dc.longitude.map(f64::from)
+            ])?;
+        }
+        let mut stmt = tx.prepare(&format!(
+            "INSERT INTO subnets VALUES ({})",
+            (1..=17).map(|i| format!("?{}", i)).join(", ")
+        ))?;
+        for s in tables.subnets.iter() {
+            stmt.execute(params![
+                s.subnet_id,
+                s.subnet_type,
+                s.replica_version_id,
+                s.node_count as i64,
+                s.max_ingress_bytes_per_message as i64,
+                s.max_ingress_messages_per_block as i64,
+                s.max_block_payload_size as i64,
+                s.unit_delay_millis as i64,
+                s.initial_notary_delay_millis as i64,
+                s.dkg_interval_length as i64,
+                s.start_as_nns,
+                s.max_instructions_per_message as i64,
+                s.max_instructions_per_round as i64,
+                s.max_instructions_per_install_code as i64,
+                s.max_number_of_canisters as i64,
+                s.features,
+                s.ecdsa_config,
+            ])?;
+        }
+        let mut stmt = tx.prepare(&format!(
+            "INSERT INTO node_operators VALUES ({})",
+            (1..=9).map(|i| format!("?{}", i)).join(", ")
+        ))?;
+        for o in tables.node_operators.iter() {
+            stmt.execute(params![
+                o.node_operator_id,
+                o.node_provider_id,
+                o.dc_id,
+                o.node_allowance_remaining as i64,
+                o.node_allowance_total as i64,
+                o.total_up_nodes,
+                o.rewards_correct,
+                o.ipv6,
+                o.rewardable_nodes,
+            ])?;
+        }
+        let mut stmt = tx.prepare(&format!("INSERT INTO nodes VALUES ({})", (1..=12).map(|i| format!("?{}", i)).join(", ")))?;
+        for n in tables.nodes.iter() {
+            stmt.execute(params![
+                n.node_id,
+                n.subnet_id,
+                n.node_operator_id,
+                n.node_provider_id,
+                n.dc_id,
+                n.hostos_version_id,
+                n.status,
+                n.http,
+                n.xnet,
+                n.public_ipv4,
+                n.chip_id,
+                n.custom_features,
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Writes the dump into a new SQLite database at `path`
+pub(crate) fn to_sqlite(dump: &RegistryDump, path: &Path) -> anyhow::Result<()> {
+    if path.exists() {
+        anyhow::bail!("Database {:?} already exists", path);
+    }
+    let tables = Tables::from(dump);
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+    insert_tables(&mut conn, &tables)?;
+    info!(
+        "Wrote {} nodes, {} subnets and {} node operators to {:?}",
+        tables.nodes.len(),
+        tables.subnets.len(),
+        tables.node_operators.len(),
+        path
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::tables;
+    use super::*;
+
+    #[test]
+    fn sqlite_joins() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        insert_tables(&mut conn, &tables()).unwrap();
+
+        let (node, subnet_type, provider): (String, String, String) = conn
+            .query_row(
+                "SELECT n.node_id, s.subnet_type, p.name FROM nodes n
+                 JOIN subnets s ON n.subnet_id = s.subnet_id
+                 JOIN node_operators o ON n.node_operator_id = o.node_operator_id
+                 JOIN node_providers p ON o.node_provider_id = p.node_provider_id",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (node.as_str(), subnet_type.as_str(), provider.as_str()),
+            ("node-1", "application", "Provider")
+        );
+    }
+}
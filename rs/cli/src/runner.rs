@@ -545,6 +545,18 @@ impl Runner {
         Ok(())
     }
 
+    pub async fn network_heal_plan(&self, request: ic_management_types::requests::HealPlanRequest) -> Result<(), anyhow::Error> {
+        let plan = self.get_backend_client().await?.network_heal_plan(request).await?;
+        println!("{}", plan);
+        Ok(())
+    }
+
+    pub async fn outage_analysis(&self, request: ic_management_types::requests::OutageAnalysisRequest) -> Result<(), anyhow::Error> {
+        let report = self.get_backend_client().await?.outage_analysis(request).await?;
+        println!("{}", report);
+        Ok(())
+    }
+
     pub async fn decentralization_change(&self, change: &ChangeSubnetMembershipPayload) -> Result<(), anyhow::Error> {
         if let Some(id) = change.get_subnet() {
             let subnet_before = self
@@ -0,0 +1,282 @@
+use crate::nakamoto::NakamotoScore;
+use crate::network::ReplacementCandidate;
+use crate::policy::BusinessRule;
+use ic_base_types::PrincipalId;
+use ic_management_types::NodeFeature;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Number of best ranked candidates kept in an explanation
+const RANKED_CANDIDATES_MAX: usize = 5;
+/// Number of candidates that failed the business rules kept in an explanation
+const INELIGIBLE_CANDIDATES_MAX: usize = 5;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, strum_macros::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum NodeChange {
+    Added,
+    Removed,
+}
+
+/// How a candidate node would change the subnet if it was picked.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CandidateScore {
+    pub node_id: PrincipalId,
+    /// Total penalty of the subnet with this candidate
+    pub penalty: usize,
+    /// Penalty of the subnet with this candidate, by violated business rule
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub penalties: BTreeMap<BusinessRule, usize>,
+    /// Average Nakamoto coefficient of the subnet with this candidate
+    pub score: f64,
+    /// Change of the Nakamoto coefficient of every feature that this candidate
+    /// causes
+    pub contributions: BTreeMap<NodeFeature, f64>,
+}
+
+impl CandidateScore {
+    fn new(candidate: &ReplacementCandidate, score_before: &NakamotoScore) -> Self {
+        let before = score_before.scores_individual();
+        Self {
+            node_id: candidate.node.id,
+            penalty: candidate.penalty,
+            penalties: candidate.violations.iter().fold(BTreeMap::new(), |mut acc, v| {
+                *acc.entry(v.rule).or_default() += v.penalty;
+                acc
+            }),
+            score: candidate.score.score_avg_linear(),
+            contributions: candidate
+                .score
+                .scores_individual()
+                .into_iter()
+                .map(|(feature, score)| {
+                    let change = score - before.get(&feature).copied().unwrap_or_default();
+                    (feature, change)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Eq for CandidateScore {}
+
+/// A candidate node that was not picked, and why.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RejectedCandidate {
+    pub node_id: PrincipalId,
+    pub reason: String,
+}
+
+/// Why a node was added to or removed from a subnet.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NodeChangeExplanation {
+    pub node_id: PrincipalId,
+    pub change: NodeChange,
+    pub reason: String,
+    /// Number of nodes that were considered
+    pub candidates_total: usize,
+    /// The best candidates, with the picked node first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ranking: Vec<CandidateScore>,
+    /// Reasons why the other best candidates, and some of the candidates that
+    /// failed the business rules, were not picked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<RejectedCandidate>,
+}
+
+impl NodeChangeExplanation {
+    /// Explanation for a node that was not picked among ranked candidates,
+    /// e.g. a node that was removed because it is unhealthy
+    pub fn new(node_id: PrincipalId, change: NodeChange, reason: String) -> Self {
+        Self {
+            node_id,
+            change,
+            reason,
+            candidates_total: 1,
+            ranking: Vec::new(),
+            rejected: Vec::new(),
+        }
+    }
+
+    /// Explain the choice of `chosen` among the `ranked` candidates, which are
+    /// sorted with the best candidate at the end.
+    pub(crate) fn picked(
+        change: NodeChange,
+        score_before: &NakamotoScore,
+        ranked: &[ReplacementCandidate],
+        chosen: &ReplacementCandidate,
+        ineligible: Vec<RejectedCandidate>,
+    ) -> Self {
+        let alternatives = ranked
+            .iter()
+            .rev()
+            .filter(|c| c.node.id != chosen.node.id)
+            .take(RANKED_CANDIDATES_MAX - 1)
+            .collect::<Vec<_>>();
+        let candidates_total = ranked.len() + ineligible.len();
+        Self {
+            node_id: chosen.node.id,
+            change,
+            reason: format!(
+                "best of {} candidates: penalty {}, Nakamoto score {:.2} -> {:.2}",
+                candidates_total,
+                chosen.penalty,
+                score_before.score_avg_linear(),
+                chosen.score.score_avg_linear()
+            ),
+            candidates_total,
+            ranking: std::iter::once(chosen)
+                .chain(alternatives.iter().copied())
+                .map(|c| CandidateScore::new(c, score_before))
+                .collect(),
+            rejected: alternatives
+                .iter()
+                .map(|c| RejectedCandidate {
+                    node_id: c.node.id,
+                    reason: Self::rejection_reason(c, chosen),
+                })
+                .chain(ineligible.into_iter().take(INELIGIBLE_CANDIDATES_MAX))
+                .collect(),
+        }
+    }
+
+    fn rejection_reason(candidate: &ReplacementCandidate, chosen: &ReplacementCandidate) -> String {
+        if candidate.penalty > chosen.penalty {
+            let rules = candidate.violations.iter().map(|v| v.rule.to_string()).unique().join(", ");
+            return format!("higher penalty {} > {} ({})", candidate.penalty, chosen.penalty, rules);
+        }
+        if candidate.score == chosen.score {
+            return "as good as the picked node, lost the deterministic tie-break".to_string();
+        }
+        let chosen_scores = chosen.score.scores_individual();
+        let worse_features = candidate
+            .score
+            .scores_individual()
+            .into_iter()
+            .filter_map(|(feature, score)| {
+                let chosen_score = chosen_scores.get(&feature).copied().unwrap_or_default();
+                (score < chosen_score).then(|| format!("{} {} < {}", feature, score, chosen_score))
+            })
+            .join(", ");
+        if worse_features.is_empty() {
+            format!(
+                "lower Nakamoto score {:.2} < {:.2}",
+                candidate.score.score_avg_linear(),
+                chosen.score.score_avg_linear()
+            )
+        } else {
+            format!("lower Nakamoto coefficients: {}", worse_features)
+        }
+    }
+}
+
+impl Display for NodeChangeExplanation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} {}: {}", self.change, self.node_id, self.reason)?;
+        if self.ranking.is_empty() {
+            return Ok(());
+        }
+        let mut table = tabular::Table::new("    {:>}  {:<}  {:>}  {:<}  {:>}  {:<}  {:<}");
+        table.add_row(
+            tabular::Row::new()
+                .with_cell("#")
+                .with_cell("Node")
+                .with_cell("Penalty")
+                .with_cell("Penalty by rule")
+                .with_cell("Score")
+                .with_cell("Score change by feature")
+                .with_cell("Outcome"),
+        );
+        for (i, candidate) in self.ranking.iter().enumerate() {
+            let outcome = if candidate.node_id == self.node_id {
+                "picked".to_string()
+            } else {
+                self.rejected
+                    .iter()
+                    .find(|r| r.node_id == candidate.node_id)
+                    .map(|r| r.reason.clone())
+                    .unwrap_or_default()
+            };
+            table.add_row(
+                tabular::Row::new()
+                    .with_cell(i + 1)
+                    .with_cell(candidate.node_id)
+                    .with_cell(candidate.penalty)
+                    .with_cell(
+                        candidate
+                            .penalties
+                            .iter()
+                            .map(|(rule, penalty)| format!("{}={}", rule, penalty))
+                            .join(", "),
+                    )
+                    .with_cell(format!("{:.2}", candidate.score))
+                    .with_cell(
+                        candidate
+                            .contributions
+                            .iter()
+                            .filter(|(_, change)| **change != 0.)
+                            .map(|(feature, change)| format!("{} {:+}", feature, change))
+                            .join(", "),
+                    )
+                    .with_cell(outcome),
+            );
+        }
+        write!(f, "{}", table)?;
+        for rejected in self.rejected.iter().filter(|r| !self.ranking.iter().any(|c| c.node_id == r.node_id)) {
+            writeln!(f, "    not eligible {}: {}", rejected.node_id, rejected.reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{DecentralizedSubnet, Node};
+    use crate::test_utils::new_test_node;
+
+    #[test]
+    fn explains_added_node() {
+        let subnet = DecentralizedSubnet::default().with_nodes(
+            (0..6)
+                .map(|i| new_test_node(i, &format!("NP{}", i), &format!("country {}", i), i == 0))
+                .collect(),
+        );
+        let available = vec![
+            new_test_node(10, "NP0", "country 10", false),
+            new_test_node(11, "NP11", "country 11", false),
+            Node {
+                decentralized: false,
+                ..new_test_node(12, "NP12", "country 12", false)
+            },
+        ];
+
+        let extended = subnet.subnet_with_more_nodes(1, &available).unwrap();
+        let explanation = extended.explanations.last().unwrap();
+
+        assert_eq!(explanation.change, NodeChange::Added);
+        assert_eq!(explanation.node_id, available[1].id);
+        assert_eq!(explanation.candidates_total, 3);
+        assert_eq!(explanation.ranking[0].node_id, available[1].id);
+        assert_eq!(explanation.ranking.len(), 3);
+        let non_decentralized = explanation.ranking.iter().find(|c| c.node_id == available[2].id).unwrap();
+        assert_eq!(
+            non_decentralized.penalties.get(&BusinessRule::NonDecentralizedNodes),
+            Some(&BusinessRule::NonDecentralizedNodes.default_penalty())
+        );
+        let same_provider = explanation.ranking.iter().find(|c| c.node_id == available[0].id).unwrap();
+        assert!(same_provider.contributions[&NodeFeature::NodeProvider] < 0.);
+        assert_eq!(explanation.rejected.len(), 2);
+        assert!(explanation
+            .rejected
+            .iter()
+            .any(|r| r.node_id == available[2].id && r.reason.starts_with("higher penalty")));
+        assert!(explanation
+            .rejected
+            .iter()
+            .any(|r| r.node_id == available[0].id && r.reason.contains("node_provider")));
+    }
+}
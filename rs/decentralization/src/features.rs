@@ -0,0 +1,151 @@
+use ic_base_types::PrincipalId;
+use ic_management_types::NodeFeature;
+use itertools::Itertools;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+static ACTIVE_CUSTOM_FEATURES: RwLock<Option<Arc<CustomNodeFeatures>>> = RwLock::new(None);
+
+/// Additional node features, e.g. the ASN or the legal jurisdiction, whose
+/// values are not in the registry but come from an external data file.
+///
+/// Values can be set per data center, per node provider and per node. A more
+/// specific value wins, so a value set for a node replaces the value of its
+/// node provider, which in turn replaces the value of its data center.
+///
+/// Nodes that the data file does not cover have no value for the feature, and
+/// are left out of its Nakamoto coefficient instead of being counted as a
+/// single actor.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CustomNodeFeatures {
+    /// Names of the custom features, in snake_case
+    pub features: Vec<String>,
+    /// Feature values by data center id, e.g. `zh1`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub data_centers: BTreeMap<String, BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub node_providers: BTreeMap<PrincipalId, BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub nodes: BTreeMap<PrincipalId, BTreeMap<String, String>>,
+}
+
+impl CustomNodeFeatures {
+    /// Load the custom features from a YAML (or JSON) file
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read node features file {}: {}", path.display(), e))?;
+        let features: Self =
+            serde_yaml::from_str(&contents).map_err(|e| anyhow::anyhow!("Failed to parse node features file {}: {}", path.display(), e))?;
+        features.validate()?;
+        Ok(features)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(duplicate) = self.features.iter().duplicates().next() {
+            return Err(anyhow::anyhow!("Node feature '{}' is listed more than once", duplicate));
+        }
+        for name in &self.features {
+            NodeFeature::new_custom(name).map_err(|e| anyhow::anyhow!(e))?;
+        }
+        let entries = self
+            .data_centers
+            .iter()
+            .map(|(dc, values)| (format!("data center {}", dc), values))
+            .chain(self.node_providers.iter().map(|(np, values)| (format!("node provider {}", np), values)))
+            .chain(self.nodes.iter().map(|(node, values)| (format!("node {}", node), values)));
+        for (entry, values) in entries {
+            if let Some(name) = values.keys().find(|name| !self.features.contains(name)) {
+                return Err(anyhow::anyhow!("Undeclared node feature '{}' used for {}", name, entry));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn node_features(&self) -> Vec<NodeFeature> {
+        self.features.iter().cloned().map(NodeFeature::Custom).collect()
+    }
+
+    /// Values of the custom features for a node, without the features that
+    /// have no value for it
+    pub fn values(&self, node_id: &PrincipalId, node_provider_id: &PrincipalId, data_center: Option<&str>) -> BTreeMap<NodeFeature, String> {
+        let sources = [
+            self.nodes.get(node_id),
+            self.node_providers.get(node_provider_id),
+            data_center.and_then(|dc| self.data_centers.get(dc)),
+        ];
+        self.features
+            .iter()
+            .filter_map(|name| {
+                let value = sources.iter().flatten().find_map(|values| values.get(name))?;
+                Some((NodeFeature::Custom(name.clone()), value.clone()))
+            })
+            .collect()
+    }
+
+    /// Register the custom features with [NodeFeature] and use their values
+    /// for all nodes in this process
+    pub fn activate(self) -> anyhow::Result<()> {
+        NodeFeature::register_custom(self.features.clone()).map_err(|e| anyhow::anyhow!(e))?;
+        info!("Activating {} custom node features: {}", self.features.len(), self.features.join(", "));
+        *ACTIVE_CUSTOM_FEATURES.write().expect("custom node features lock poisoned") = Some(Arc::new(self));
+        Ok(())
+    }
+
+    /// The custom features currently in use, if any
+    pub fn active() -> Arc<CustomNodeFeatures> {
+        ACTIVE_CUSTOM_FEATURES
+            .read()
+            .expect("custom node features lock poisoned")
+            .clone()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_specific_value_wins() {
+        let node_provider = PrincipalId::new_user_test_id(1);
+        let node = PrincipalId::new_node_test_id(2);
+        let features: CustomNodeFeatures = serde_yaml::from_str(&format!(
+            r#"
+features: [asn, jurisdiction]
+data_centers:
+  zh1:
+    asn: AS1
+    jurisdiction: CH
+node_providers:
+  {}:
+    asn: AS2
+nodes:
+  {}:
+    asn: AS3
+"#,
+            node_provider, node
+        ))
+        .unwrap();
+        features.validate().unwrap();
+        let asn = NodeFeature::Custom("asn".to_string());
+        let jurisdiction = NodeFeature::Custom("jurisdiction".to_string());
+
+        let values = features.values(&node, &node_provider, Some("zh1"));
+        assert_eq!((values[&asn].as_str(), values[&jurisdiction].as_str()), ("AS3", "CH"));
+        let values = features.values(&PrincipalId::new_node_test_id(3), &node_provider, Some("zh1"));
+        assert_eq!(values[&asn], "AS2");
+        let values = features.values(&PrincipalId::new_node_test_id(3), &PrincipalId::new_user_test_id(4), Some("fr1"));
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn rejects_undeclared_and_builtin_features() {
+        let features: CustomNodeFeatures = serde_yaml::from_str("features: [asn]\ndata_centers: {zh1: {jurisdiction: CH}}").unwrap();
+        assert!(features.validate().is_err());
+        let features: CustomNodeFeatures = serde_yaml::from_str("features: [country]").unwrap();
+        assert!(features.validate().is_err());
+    }
+}
@@ -1,12 +1,20 @@
+pub mod explanation;
+pub mod features;
 pub mod nakamoto;
 pub mod network;
+pub mod optimizer;
+pub mod outage;
+pub mod planner;
+pub mod policy;
+#[cfg(test)]
+mod test_utils;
 use colored::Colorize;
 use itertools::{EitherOrBoth::*, Itertools};
 use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
 
 use ic_base_types::PrincipalId;
-use ic_management_types::NodeFeature;
+use ic_management_types::{FeatureGroup, NodeFeature};
 use serde::{self, Deserialize, Serialize};
 
 #[derive(Clone, Debug, Deserialize, Serialize, Default)]
@@ -17,11 +25,22 @@ pub struct SubnetChangeResponse {
     pub subnet_id: Option<PrincipalId>,
     pub score_before: nakamoto::NakamotoScore,
     pub score_after: nakamoto::NakamotoScore,
+    /// Joint Nakamoto coefficients before and after the change, for the
+    /// feature groups of the active policy
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub joint_scores_before: BTreeMap<FeatureGroup, f64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub joint_scores_after: BTreeMap<FeatureGroup, f64>,
     pub motivation: Option<String>,
     pub comment: Option<String>,
     pub run_log: Option<Vec<String>>,
     pub feature_diff: BTreeMap<NodeFeature, FeatureDiff>,
     pub proposal_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub optimization: Option<optimizer::OptimizationReport>,
+    /// Why each of the added and removed nodes was picked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub explanations: Vec<explanation::NodeChangeExplanation>,
 }
 
 pub type FeatureDiff = BTreeMap<String, (usize, usize)>;
@@ -37,12 +56,32 @@ impl SubnetChangeResponse {
 
 impl From<&network::SubnetChange> for SubnetChangeResponse {
     fn from(change: &network::SubnetChange) -> Self {
+        let added = change.added().iter().map(|n| n.id).collect::<Vec<_>>();
+        let removed = change.removed().iter().map(|n| n.id).collect::<Vec<_>>();
+        // Nodes can be picked more than once while the change is computed, e.g.
+        // added and then removed again, so only the last explanation of the
+        // final change of every node is kept
+        let explanations = added
+            .iter()
+            .map(|id| (id, explanation::NodeChange::Added))
+            .chain(removed.iter().map(|id| (id, explanation::NodeChange::Removed)))
+            .filter_map(|(id, node_change)| {
+                change
+                    .explanations
+                    .iter()
+                    .rev()
+                    .find(|e| &e.node_id == id && e.change == node_change)
+                    .cloned()
+            })
+            .collect();
         Self {
-            added: change.added().iter().map(|n| n.id).collect(),
-            removed: change.removed().iter().map(|n| n.id).collect(),
+            added,
+            removed,
             subnet_id: if change.id == Default::default() { None } else { Some(change.id) },
             score_before: nakamoto::NakamotoScore::new_from_nodes(&change.old_nodes),
             score_after: nakamoto::NakamotoScore::new_from_nodes(&change.new_nodes),
+            joint_scores_before: nakamoto::NakamotoScore::joint_scores(&change.id, &change.old_nodes),
+            joint_scores_after: nakamoto::NakamotoScore::joint_scores(&change.id, &change.new_nodes),
             motivation: None,
             comment: change.comment.clone(),
             run_log: Some(change.run_log.clone()),
@@ -67,6 +106,8 @@ impl From<&network::SubnetChange> for SubnetChangeResponse {
                 },
             ),
             proposal_id: None,
+            optimization: change.optimization.clone(),
+            explanations,
         }
     }
 }
@@ -100,6 +141,19 @@ impl Display for SubnetChangeResponse {
             })
             .for_each(|s| writeln!(f, "{: >40}", s).expect("write failed"));
 
+        for (group, before) in &self.joint_scores_before {
+            let after = self.joint_scores_after.get(group).copied().unwrap_or_default();
+            let output = format!("joint {}: {:.2} -> {:.2}", group, before, after);
+            let output = if *before > after {
+                output.bright_red()
+            } else if after > *before {
+                output.bright_green()
+            } else {
+                output.dimmed()
+            };
+            writeln!(f, "{: >40}", output)?;
+        }
+
         let total_before = self.score_before.score_avg_linear();
         let total_after = self.score_after.score_avg_linear();
         let output = format!(
@@ -176,6 +230,17 @@ impl Display for SubnetChangeResponse {
         }
         writeln!(f)?;
 
+        if let Some(optimization) = &self.optimization {
+            writeln!(f, "{}\n", optimization.to_string().bold())?;
+        }
+
+        if !self.explanations.is_empty() {
+            writeln!(f, "{}", "Why these nodes:".bold())?;
+            for explanation in &self.explanations {
+                writeln!(f, "{}", explanation)?;
+            }
+        }
+
         if let Some(comment) = &self.comment {
             writeln!(f, "{}", format!("*** Note ***\n{}", comment).red())?;
         }
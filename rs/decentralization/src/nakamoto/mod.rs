@@ -1,5 +1,7 @@
 use crate::network::Node;
+use crate::policy::BusinessRulesPolicy;
 use ahash::{AHashMap, AHasher};
+use ic_base_types::PrincipalId;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
@@ -9,7 +11,7 @@ use std::fmt::{self, Debug, Display, Formatter};
 use std::hash::Hasher;
 use std::iter::{FromIterator, IntoIterator};
 
-use ic_management_types::NodeFeature;
+use ic_management_types::{FeatureGroup, NodeFeature};
 
 #[derive(Eq, PartialEq, Clone, Serialize, Deserialize, Debug)]
 pub struct NodeFeatures {
@@ -69,6 +71,7 @@ thread_local! {
     pub static MEMOIZE_REQ: RefCell<u32> = RefCell::new(0);
     pub static MEMOIZE_HIT: RefCell<u32> = RefCell::new(0);
     pub static MEMOIZE_HIT_RATES: RefCell<VecDeque<u32>> = RefCell::new(VecDeque::new());
+    pub static JOINT_NAKAMOTO_CACHE: RefCell<AHashMap<u64, (usize, usize)>> = RefCell::new(AHashMap::new());
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
@@ -98,6 +101,10 @@ pub struct NakamotoScore {
     min: f64,
 }
 
+/// Upper bound on the number of coalitions evaluated by
+/// [NakamotoScore::joint_nakamoto], after which the greedy result is used.
+const JOINT_NAKAMOTO_SEARCH_LIMIT: usize = 1_000_000;
+
 impl NakamotoScore {
     /// Build a new NakamotoScore object from a slice of [NodeFeatures].
     pub fn new_from_slice_node_features(slice_node_features: &[NodeFeatures]) -> Self {
@@ -116,33 +123,12 @@ impl NakamotoScore {
             }
         }
 
-        let nakamoto_calc = features_to_nodes_map.iter().map(|value| {
-            // Turns a Vec<Features> into a Vec<(NodeFeature, Number)>
-            // where "Number" is the count of objects with the feature
-            let counters: Vec<(String, usize)> = value
-                .1
-                .iter()
-                // AHashMap is a very fast HashMap implementation https://github.com/tkaitchuck/aHash
-                // We use it here to count the number of times each value appears in the input vector
-                // Doing this with a fold instead of using https://github.com/coriolinus/counter-rs is faster
-                .fold(AHashMap::new(), |mut acc: AHashMap<String, u32>, s| {
-                    if let Some(s) = s {
-                        acc.entry(s.to_string()).and_modify(|v| *v += 1).or_insert(1);
-                    }
-                    acc
-                })
-                .into_iter()
-                .map(|(feat, cnt)| (feat, cnt as usize))
-                .collect::<Vec<_>>();
-
-            // We only care about the counts to calculate the Nakamoto Coefficient, so we
-            // discard the feature names
-            let only_counter = counters.iter().map(|(_feat, cnt)| *cnt).collect::<Vec<_>>();
-            // But for deeper understanding (logging and debugging) we also keep track of
-            // all strings and their counts
-            let value_counts = counters.into_iter().sorted_by_key(|(_feat, cnt)| -(*cnt as isize)).collect::<Vec<_>>();
-
-            (value.0.clone(), Self::nakamoto(&only_counter), value_counts)
+        // A custom feature without any value is left out of the score altogether
+        features_to_nodes_map.retain(|feature, values| !feature.is_custom() || values.iter().any(Option::is_some));
+
+        let nakamoto_calc = features_to_nodes_map.iter().map(|(feature, values)| {
+            let (nakamoto, value_counts) = Self::feature_nakamoto(values);
+            (feature.clone(), nakamoto, value_counts)
         });
 
         let scores = nakamoto_calc
@@ -175,9 +161,53 @@ impl NakamotoScore {
         }
     }
 
+    /// The Nakamoto coefficient of a single feature, given the value of the
+    /// feature for each node, and the count of each value sorted from the most
+    /// to the least common.
+    /// Nodes without a value (for custom features that only cover some nodes)
+    /// still count towards the 1/3 of the subnet that has to be controlled,
+    /// each as an actor of its own, since nothing is known that ties them to
+    /// other nodes.
+    fn feature_nakamoto(values: &[Option<String>]) -> ((usize, usize), Vec<(String, usize)>) {
+        // Turns a Vec<Features> into a Vec<(NodeFeature, Number)>
+        // where "Number" is the count of objects with the feature
+        let counters: Vec<(String, usize)> = values
+            .iter()
+            // AHashMap is a very fast HashMap implementation https://github.com/tkaitchuck/aHash
+            // We use it here to count the number of times each value appears in the input vector
+            // Doing this with a fold instead of using https://github.com/coriolinus/counter-rs is faster
+            .fold(AHashMap::new(), |mut acc: AHashMap<String, u32>, s| {
+                if let Some(s) = s {
+                    acc.entry(s.to_string()).and_modify(|v| *v += 1).or_insert(1);
+                }
+                acc
+            })
+            .into_iter()
+            .map(|(feat, cnt)| (feat, cnt as usize))
+            .collect::<Vec<_>>();
+
+        // We only care about the counts to calculate the Nakamoto Coefficient, so we
+        // discard the feature names
+        let missing = values.iter().filter(|v| v.is_none()).count();
+        let only_counter = counters
+            .iter()
+            .map(|(_feat, cnt)| *cnt)
+            .chain(std::iter::repeat(1).take(missing))
+            .collect::<Vec<_>>();
+        // But for deeper understanding (logging and debugging) we also keep track of
+        // all strings and their counts
+        let value_counts = counters.into_iter().sorted_by_key(|(_feat, cnt)| -(*cnt as isize)).collect::<Vec<_>>();
+
+        (Self::nakamoto(&only_counter), value_counts)
+    }
+
     /// Build a new NakamotoScore object from a slice of [Node]s.
     pub fn new_from_nodes(nodes: &[Node]) -> Self {
         let mut memoize_key = AHasher::default();
+        // The custom features are part of the key, since they are part of every score
+        for feature in NodeFeature::custom_variants() {
+            memoize_key.write(feature.to_string().as_bytes());
+        }
         for node in nodes.iter().sorted_by_cached_key(|n| n.id) {
             for byte in node.id.0.as_slice() {
                 memoize_key.write_u8(*byte);
@@ -262,6 +292,132 @@ impl NakamotoScore {
         (sum_actors, sum_nodes)
     }
 
+    /// The joint Nakamoto Coefficient is the smallest number of actors that
+    /// would have to collude to control more than 1/3 of the nodes, where an
+    /// actor is any value of any feature in the group. For instance, for
+    /// `node_provider+country` a coalition may combine node providers and
+    /// countries, and controls every node that has one of their values.
+    /// Returns the coefficient and the number of nodes that the coalition
+    /// controls.
+    ///
+    /// Finding the smallest coalition is a set cover problem, so the search
+    /// falls back to the greedy result if it becomes too expensive.
+    pub fn joint_nakamoto(slice_node_features: &[NodeFeatures], group: &FeatureGroup) -> (usize, usize) {
+        let total_nodes = slice_node_features.len();
+        let words = total_nodes.div_ceil(64);
+        let mut actors: AHashMap<(NodeFeature, String), Vec<u64>> = AHashMap::new();
+        for (i, node_features) in slice_node_features.iter().enumerate() {
+            for feature in group.features() {
+                if let Some(value) = node_features.get(feature) {
+                    actors.entry((feature.clone(), value)).or_insert_with(|| vec![0; words])[i / 64] |= 1 << (i % 64);
+                }
+            }
+        }
+        // Actors controlling exactly the same nodes are interchangeable
+        let actors = actors
+            .into_values()
+            .unique()
+            .map(|nodes| (nodes.iter().map(|w| w.count_ones() as usize).sum::<usize>(), nodes))
+            .sorted_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)))
+            .collect::<Vec<_>>();
+        let max_malicious_nodes = total_nodes / 3;
+
+        let union = |a: &[u64], b: &[u64]| a.iter().zip(b).map(|(a, b)| a | b).collect::<Vec<_>>();
+        let count = |a: &[u64]| a.iter().map(|w| w.count_ones() as usize).sum::<usize>();
+
+        // Greedy upper bound: keep adding the actor that controls the most new nodes
+        let mut greedy = (0, 0);
+        let mut controlled = vec![0; words];
+        let mut remaining = actors.iter().map(|(_, nodes)| nodes.clone()).collect::<Vec<_>>();
+        while !remaining.is_empty() {
+            let (best, _) = remaining
+                .iter()
+                .enumerate()
+                .max_by_key(|(i, nodes)| (count(&union(&controlled, nodes)), std::cmp::Reverse(*i)))
+                .expect("remaining actors are not empty");
+            controlled = union(&controlled, &remaining.swap_remove(best));
+            greedy = (greedy.0 + 1, count(&controlled));
+            if greedy.1 > max_malicious_nodes {
+                break;
+            }
+        }
+
+        // Look for smaller coalitions, pruning branches that cannot exceed the
+        // threshold even if they took the largest remaining actors.
+        fn search(
+            actors: &[(usize, Vec<u64>)],
+            start: usize,
+            left: usize,
+            controlled: &[u64],
+            threshold: usize,
+            budget: &mut usize,
+        ) -> Option<usize> {
+            let count = controlled.iter().map(|w| w.count_ones() as usize).sum::<usize>();
+            if count > threshold {
+                return Some(count);
+            }
+            if left == 0 || *budget == 0 {
+                return None;
+            }
+            for i in start..actors.len() {
+                if count + actors[i..].iter().take(left).map(|(size, _)| size).sum::<usize>() <= threshold {
+                    return None;
+                }
+                *budget = budget.saturating_sub(1);
+                let next = controlled.iter().zip(&actors[i].1).map(|(a, b)| a | b).collect::<Vec<_>>();
+                if let Some(found) = search(actors, i + 1, left - 1, &next, threshold, budget) {
+                    return Some(found);
+                }
+            }
+            None
+        }
+
+        let mut budget = JOINT_NAKAMOTO_SEARCH_LIMIT;
+        for coalition_size in 1..greedy.0 {
+            if let Some(controlled) = search(&actors, 0, coalition_size, &vec![0; words], max_malicious_nodes, &mut budget) {
+                return (coalition_size, controlled);
+            }
+            if budget == 0 {
+                break;
+            }
+        }
+        greedy
+    }
+
+    /// Memoized [NakamotoScore::joint_nakamoto] of a slice of [Node]s, since
+    /// the same node sets are evaluated over and over while searching.
+    pub fn joint_nakamoto_from_nodes(nodes: &[Node], group: &FeatureGroup) -> (usize, usize) {
+        let mut memoize_key = AHasher::default();
+        memoize_key.write(group.to_string().as_bytes());
+        for node in nodes.iter().sorted_by_cached_key(|n| n.id) {
+            for byte in node.id.0.as_slice() {
+                memoize_key.write_u8(*byte);
+            }
+        }
+        let memoize_key = memoize_key.finish();
+        if let Some(result) = JOINT_NAKAMOTO_CACHE.with(|cache| cache.borrow().get(&memoize_key).copied()) {
+            return result;
+        }
+        let result = Self::joint_nakamoto(&nodes.iter().map(|n| n.features.clone()).collect::<Vec<_>>(), group);
+        JOINT_NAKAMOTO_CACHE.with(|cache| cache.borrow_mut().insert(memoize_key, result));
+        result
+    }
+
+    /// Joint Nakamoto coefficients of the subnet nodes for the feature groups
+    /// of the active [BusinessRulesPolicy], and for the groups with a minimum
+    /// joint coefficient in the policy of the subnet.
+    pub fn joint_scores(subnet_id: &PrincipalId, nodes: &[Node]) -> BTreeMap<FeatureGroup, f64> {
+        let policy = BusinessRulesPolicy::active();
+        let subnet_policy = policy.for_subnet(subnet_id);
+        policy
+            .joint_features
+            .iter()
+            .chain(subnet_policy.min_joint_nakamoto_coefficients.keys())
+            .unique()
+            .map(|group| (group.clone(), Self::joint_nakamoto_from_nodes(nodes, group).0 as f64))
+            .collect()
+    }
+
     /// An average of the linear nakamoto scores over all features
     pub fn score_avg_linear(&self) -> f64 {
         self.avg_linear
@@ -435,7 +591,6 @@ mod tests {
     use std::str::FromStr;
 
     use crate::network::{DecentralizedSubnet, NetworkHealRequest, NetworkHealSubnets, SubnetChangeRequest};
-    use ic_base_types::PrincipalId;
     use itertools::Itertools;
     use regex::Regex;
 
@@ -466,6 +621,29 @@ mod tests {
         assert_eq!((2, 8), NakamotoScore::nakamoto(&[1, 1, 2, 3, 5, 1, 2])); // two top actors control 8/15 nodes
     }
 
+    #[test]
+    fn partially_covered_feature_uses_the_full_subnet_size() {
+        let values = |known: &[&str], missing: usize| {
+            known
+                .iter()
+                .map(|v| Some(v.to_string()))
+                .chain(std::iter::repeat(None).take(missing))
+                .collect::<Vec<_>>()
+        };
+        // AS1 has 3 of the 4 known nodes, which is over 1/3 of the known nodes
+        // but not over 1/3 of the 13 subnet nodes
+        let (nakamoto, value_counts) = NakamotoScore::feature_nakamoto(&values(&["AS1", "AS1", "AS1", "AS2"], 9));
+        assert_eq!(nakamoto, (3, 5));
+        assert_eq!(value_counts, vec![("AS1".to_string(), 3), ("AS2".to_string(), 1)]);
+        // With only a few known nodes, nodes without a value are needed to get
+        // over 1/3 of the subnet
+        let (nakamoto, _) = NakamotoScore::feature_nakamoto(&values(&["AS1", "AS2"], 11));
+        assert_eq!(nakamoto, (5, 5));
+        // A fully covered feature is not affected
+        let (nakamoto, _) = NakamotoScore::feature_nakamoto(&values(&["AS1", "AS1", "AS2", "AS3"], 0));
+        assert_eq!(nakamoto, (1, 2));
+    }
+
     #[test]
     fn score_from_features() {
         let features = vec![NodeFeatures::new_test_feature_set("foo")];
@@ -496,6 +674,80 @@ mod tests {
         assert_eq!(score, score_expected);
     }
 
+    #[test]
+    fn computes_joint_nakamoto_scores() {
+        let group = FeatureGroup::from_str("node_provider+country").unwrap();
+        // 13 nodes with distinct node providers, but np-0..np-2 share a country
+        // with the nodes of np-3 and np-4
+        let features = (0..13)
+            .map(|i| {
+                NodeFeatures::from_iter([
+                    (NodeFeature::NodeProvider, format!("np-{}", i)),
+                    (NodeFeature::Country, if i < 5 { "CH".to_string() } else { format!("country-{}", i) }),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let score = NakamotoScore::new_from_slice_node_features(&features);
+        assert_eq!(score.score_feature(&NodeFeature::NodeProvider), Some(5.));
+        assert_eq!(score.score_feature(&NodeFeature::Country), Some(1.));
+        assert_eq!(NakamotoScore::joint_nakamoto(&features, &group), (1, 5));
+
+        // Nodes of the two largest node providers are in the same countries,
+        // so the joint coefficient is lower than for each feature alone
+        let features = (0..13)
+            .map(|i| {
+                let (np, country) = match i {
+                    0..=1 => ("np-a".to_string(), "country-x".to_string()),
+                    2..=3 => ("np-b".to_string(), "country-y".to_string()),
+                    4 => ("np-c".to_string(), "country-x".to_string()),
+                    5 => ("np-d".to_string(), "country-y".to_string()),
+                    _ => (format!("np-{}", i), format!("country-{}", i)),
+                };
+                NodeFeatures::from_iter([(NodeFeature::NodeProvider, np), (NodeFeature::Country, country)])
+            })
+            .collect::<Vec<_>>();
+        let score = NakamotoScore::new_from_slice_node_features(&features);
+        assert_eq!(score.score_feature(&NodeFeature::NodeProvider), Some(3.));
+        assert_eq!(score.score_feature(&NodeFeature::Country), Some(2.));
+        assert_eq!(NakamotoScore::joint_nakamoto(&features, &group), (2, 6));
+
+        // Taking the largest actor first is not always optimal: country-x has
+        // 6 of 21 nodes but needs two more actors to get over 7 nodes, while
+        // np-e and np-f together control 8 nodes
+        let features = (0..21)
+            .map(|i| {
+                let (np, country) = match i {
+                    0..=2 => ("np-e".to_string(), "country-x".to_string()),
+                    3 => ("np-e".to_string(), format!("country-{}", i)),
+                    4..=6 => ("np-f".to_string(), "country-x".to_string()),
+                    7 => ("np-f".to_string(), format!("country-{}", i)),
+                    _ => (format!("np-{}", i), format!("country-{}", i)),
+                };
+                NodeFeatures::from_iter([(NodeFeature::NodeProvider, np), (NodeFeature::Country, country)])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(NakamotoScore::joint_nakamoto(&features, &group), (2, 8));
+        assert_eq!(NakamotoScore::joint_nakamoto(&[], &group), (0, 0));
+    }
+
+    #[test]
+    fn memoizes_joint_nakamoto_scores() {
+        let group = FeatureGroup::from_str("node_provider+country").unwrap();
+        let nodes = new_test_nodes_with_overrides("joint", 0, 7, 0, (&NodeFeature::Country, &["CH", "CH", "CH"]));
+        let expected = NakamotoScore::joint_nakamoto(&nodes.iter().map(|n| n.get_features()).collect::<Vec<_>>(), &group);
+        assert_eq!(expected, (1, 3));
+        assert_eq!(NakamotoScore::joint_nakamoto_from_nodes(&nodes, &group), expected);
+        let cached = JOINT_NAKAMOTO_CACHE.with(|cache| cache.borrow().len());
+
+        // The same node set in a different order hits the cache
+        let reversed = nodes.iter().rev().cloned().collect::<Vec<_>>();
+        assert_eq!(NakamotoScore::joint_nakamoto_from_nodes(&reversed, &group), expected);
+        assert_eq!(JOINT_NAKAMOTO_CACHE.with(|cache| cache.borrow().len()), cached);
+
+        let scores = NakamotoScore::joint_scores(&PrincipalId::new_subnet_test_id(1), &nodes);
+        assert_eq!(scores.get(&group), Some(&1.));
+    }
+
     /// Generate a new Vec<Node> of len num_nodes, out of which
     /// num_dfinity_nodes are DFINITY-owned
     fn new_test_nodes(feat_prefix: &str, num_nodes: usize, num_dfinity_nodes: usize) -> Vec<Node> {
@@ -543,6 +795,7 @@ mod tests {
             min_nakamoto_coefficients: None,
             comment: None,
             run_log: Vec::new(),
+            explanations: Vec::new(),
         }
     }
 
@@ -561,6 +814,7 @@ mod tests {
             min_nakamoto_coefficients: None,
             comment: None,
             run_log: Vec::new(),
+            explanations: Vec::new(),
         }
     }
 
@@ -781,6 +1035,7 @@ mod tests {
             min_nakamoto_coefficients: None,
             comment: None,
             run_log: Vec::new(),
+            explanations: Vec::new(),
         };
 
         let available_nodes = serde_json::from_str::<Vec<ic_management_types::Node>>(include_str!("../../test_data/available-nodes.json"))
@@ -859,7 +1114,7 @@ mod tests {
         .with_subnet_id(PrincipalId::from_str("bkfrj-6k62g-dycql-7h53p-atvkj-zg4to-gaogh-netha-ptybj-ntsgw-rqe").unwrap());
         assert_eq!(
             subnet_mix.check_business_rules().unwrap(),
-            (1000, vec!["European subnet has 1 non-European node(s)".to_string()])
+            (1000, vec!["European subnet has 1 node(s) with continent not in [Europe]".to_string()])
         );
     }
     #[test]
@@ -877,7 +1132,7 @@ mod tests {
         .with_subnet_id(PrincipalId::from_str("bkfrj-6k62g-dycql-7h53p-atvkj-zg4to-gaogh-netha-ptybj-ntsgw-rqe").unwrap());
         assert_eq!(
             subnet_mix.check_business_rules().unwrap(),
-            (5000, vec!["European subnet has 5 non-European node(s)".to_string()])
+            (5000, vec!["European subnet has 5 node(s) with continent not in [Europe]".to_string()])
         );
     }
 
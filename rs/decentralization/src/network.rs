@@ -1,11 +1,15 @@
+use crate::explanation::{NodeChange, NodeChangeExplanation, RejectedCandidate};
+use crate::features::CustomNodeFeatures;
 use crate::nakamoto::{self, NakamotoScore};
+use crate::optimizer::{MembershipSearch, OptimizationReport};
+use crate::policy::{BusinessRulesPolicy, RuleViolation, SubnetPolicy};
 use crate::SubnetChangeResponse;
 use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
 use anyhow::anyhow;
 use async_trait::async_trait;
 use ic_base_types::PrincipalId;
-use ic_management_types::{MinNakamotoCoefficients, NetworkError, NodeFeature};
+use ic_management_types::{MinNakamotoCoefficients, NetworkError, NodeFeature, OptimizationStrategy};
 use itertools::Itertools;
 use log::{debug, info, warn};
 use rand::{seq::SliceRandom, SeedableRng};
@@ -83,7 +87,7 @@ impl From<&ic_management_types::Node> for Node {
     fn from(n: &ic_management_types::Node) -> Self {
         Self {
             id: n.principal,
-            features: nakamoto::NodeFeatures::from_iter([
+            features: [
                 (
                     NodeFeature::City,
                     n.operator
@@ -125,7 +129,14 @@ impl From<&ic_management_types::Node> for Node {
                         .unwrap_or_else(|| "unknown".to_string()),
                 ),
                 (NodeFeature::NodeProvider, n.operator.provider.principal.to_string()),
-            ]),
+            ]
+            .into_iter()
+            .chain(CustomNodeFeatures::active().values(
+                &n.principal,
+                &n.operator.provider.principal,
+                n.operator.datacenter.as_ref().map(|d| d.name.as_str()),
+            ))
+            .collect(),
             dfinity_owned: n.dfinity_owned.unwrap_or_default(),
             decentralized: n.decentralized,
         }
@@ -140,14 +151,17 @@ pub struct DecentralizedSubnet {
     pub min_nakamoto_coefficients: Option<MinNakamotoCoefficients>,
     pub comment: Option<String>,
     pub run_log: Vec<String>,
+    /// Why the nodes were added to or removed from the subnet
+    #[serde(default)]
+    pub explanations: Vec<NodeChangeExplanation>,
 }
 
 #[derive(Clone, Debug)]
-struct ReplacementCandidate {
-    node: Node,
-    score: NakamotoScore,
-    penalty: usize,
-    business_rules_log: Vec<String>,
+pub(crate) struct ReplacementCandidate {
+    pub(crate) node: Node,
+    pub(crate) score: NakamotoScore,
+    pub(crate) penalty: usize,
+    pub(crate) violations: Vec<RuleViolation>,
 }
 
 impl DecentralizedSubnet {
@@ -184,6 +198,16 @@ impl DecentralizedSubnet {
                     run_log
                 }
             },
+            explanations: self
+                .explanations
+                .iter()
+                .cloned()
+                .chain(
+                    removed_node_ids
+                        .iter()
+                        .map(|id| NodeChangeExplanation::new(*id, NodeChange::Removed, "requested removal".to_string())),
+                )
+                .collect(),
         })
     }
 
@@ -205,6 +229,15 @@ impl DecentralizedSubnet {
                     run_log
                 }
             },
+            explanations: self
+                .explanations
+                .into_iter()
+                .chain(
+                    nodes
+                        .iter()
+                        .map(|n| NodeChangeExplanation::new(n.id, NodeChange::Added, "requested inclusion".to_string())),
+                )
+                .collect(),
         }
     }
 
@@ -236,7 +269,8 @@ impl DecentralizedSubnet {
     /// Ensure "business rules" or constraints for the subnet nodes are met.
     /// For instance, there needs to be at least one DFINITY-owned node in each
     /// subnet. For the mainnet NNS there needs to be at least 3
-    /// DFINITY-owned nodes.
+    /// DFINITY-owned nodes. The rules come from the active
+    /// [BusinessRulesPolicy].
     pub fn check_business_rules(&self) -> anyhow::Result<(usize, Vec<String>)> {
         Self::_check_business_rules_for_nodes(&self.id, &self.nodes, &self.min_nakamoto_coefficients)
     }
@@ -246,140 +280,9 @@ impl DecentralizedSubnet {
         nodes: &[Node],
         min_nakamoto_coefficients: &Option<MinNakamotoCoefficients>,
     ) -> anyhow::Result<(usize, Vec<String>)> {
-        let mut checks = Vec::new();
-        let mut penalties = 0;
-        if nodes.len() <= 1 {
-            return Ok((1, checks));
-        }
-
-        let nakamoto_scores = Self::_calc_nakamoto_score(nodes);
-        let subnet_id_str = subnet_id.to_string();
-        let is_european_subnet = subnet_id_str == *"bkfrj-6k62g-dycql-7h53p-atvkj-zg4to-gaogh-netha-ptybj-ntsgw-rqe";
-
-        let dfinity_owned_nodes_count: usize = nodes.iter().map(|n| n.dfinity_owned as usize).sum();
-        let target_dfinity_owned_nodes_count = if subnet_id_str == *"tdb26-jop6k-aogll-7ltgs-eruif-6kk7m-qpktf-gdiqx-mxtrf-vb5e6-eqe" {
-            3
-        } else {
-            1
-        };
-
-        if dfinity_owned_nodes_count != target_dfinity_owned_nodes_count {
-            checks.push(format!(
-                "Subnet should have {} DFINITY-owned nodes, got {}",
-                target_dfinity_owned_nodes_count, dfinity_owned_nodes_count
-            ));
-            penalties += target_dfinity_owned_nodes_count.abs_diff(dfinity_owned_nodes_count) * 1000;
-        }
-
-        let count_non_decentralized_nodes = nodes.iter().filter(|n| !n.decentralized).count();
-        if count_non_decentralized_nodes > 0 {
-            checks.push(format!("Subnet has {} non-decentralized node(s)", count_non_decentralized_nodes));
-            penalties += count_non_decentralized_nodes * 100;
-        }
-
-        if subnet_id_str == *"uzr34-akd3s-xrdag-3ql62-ocgoh-ld2ao-tamcv-54e7j-krwgb-2gm4z-oqe"
-            || subnet_id_str == *"tdb26-jop6k-aogll-7ltgs-eruif-6kk7m-qpktf-gdiqx-mxtrf-vb5e6-eqe"
-            || subnet_id_str == *"x33ed-h457x-bsgyx-oqxqf-6pzwv-wkhzr-rm2j3-npodi-purzm-n66cg-gae"
-        {
-            // We keep the backup of the ECDSA key on uzr34, and we don’t want a single
-            // country to be able to extract that key.
-            // The tECDSA key can be extracted with 1/3 of the nodes.
-            // We should use the same NC requirements for uzr34 and the upcoming ECDSA
-            // subnet, since they'll both hold the same valuable key.
-            // Slack discussion: https://dfinity.slack.com/archives/C01DB8MQ5M1/p1668702249558389
-            // For different reasons, there is the same requirement for the NNS and the SNS
-            // subnet.
-            let feature = NodeFeature::Country;
-            match nakamoto_scores.feature_value_counts_max(&feature) {
-                Some((country_dominant, country_nodes_count)) => {
-                    let controlled_nodes_max = nodes.len() / 3;
-                    if country_nodes_count > controlled_nodes_max {
-                        checks.push(format!(
-                            "Country '{}' controls {} of nodes, which is > {} (1/3 - 1) of subnet nodes",
-                            country_dominant, country_nodes_count, controlled_nodes_max
-                        ));
-                        penalties += (country_nodes_count - controlled_nodes_max) * 1000;
-                    }
-                }
-                _ => return Err(anyhow::anyhow!("Incomplete data for {}", feature)),
-            }
-        }
-
-        if is_european_subnet {
-            // European subnet should only take European nodes.
-            let continent_counts = nakamoto_scores.feature_value_counts(&NodeFeature::Continent);
-            let non_european_nodes_count = continent_counts
-                .iter()
-                .filter_map(|(continent, count)| if continent == &"Europe".to_string() { None } else { Some(*count) })
-                .sum::<usize>();
-            if non_european_nodes_count > 0 {
-                checks.push(format!("European subnet has {} non-European node(s)", non_european_nodes_count));
-                penalties += non_european_nodes_count * 1000;
-            }
-        }
-
-        match nakamoto_scores.score_feature(&NodeFeature::NodeProvider) {
-            Some(score) => {
-                if score <= 1.0 && nodes.len() > 3 {
-                    // We restrict to subnets with >3 nodes to be able to build subnet from scratch
-                    checks.push("A single Node Provider can halt the subnet".to_string());
-                    penalties += 10000;
-                }
-            }
-            None => return Err(anyhow::anyhow!("Missing the Nakamoto score for the Node Provider")),
-        }
-
-        if let Some(min_nakamoto_coefficients) = min_nakamoto_coefficients {
-            for (feature, min_coeff) in min_nakamoto_coefficients.coefficients.iter() {
-                match nakamoto_scores.score_feature(feature) {
-                    Some(score) => {
-                        if score < *min_coeff {
-                            checks.push(format!(
-                                "Lower than expected Nakamoto Coefficient {} < {} for feature {}",
-                                score, min_coeff, feature
-                            ));
-                            penalties += ((*min_coeff - score) * 100.) as usize;
-                        }
-                    }
-                    None => return Err(anyhow::anyhow!("NodeFeature '{}' not found", feature.to_string())),
-                }
-            }
-            if nakamoto_scores.score_avg_linear() < min_nakamoto_coefficients.average {
-                checks.push(format!(
-                    "Lower than expected average Nakamoto Coefficient {} < {}",
-                    nakamoto_scores.score_avg_linear(),
-                    min_nakamoto_coefficients.average
-                ));
-                penalties += ((min_nakamoto_coefficients.average - nakamoto_scores.score_avg_linear()) * 100.) as usize;
-            }
-        }
-
-        for feature in &NodeFeature::variants() {
-            match (nakamoto_scores.score_feature(feature), nakamoto_scores.controlled_nodes(feature)) {
-                (Some(score), Some(controlled_nodes)) => {
-                    let european_subnet_continent_penalty = is_european_subnet && feature == &NodeFeature::Continent;
-
-                    if score == 1.0 && controlled_nodes > nodes.len() * 2 / 3 && !european_subnet_continent_penalty {
-                        checks.push(format!(
-                            "NodeFeature '{}' controls {} of nodes, which is > {} (2/3 of all) nodes",
-                            feature,
-                            controlled_nodes,
-                            nodes.len() * 2 / 3
-                        ));
-                        penalties += (controlled_nodes - nodes.len() * 2 / 3) * 1000;
-                    }
-                }
-                (score, controlled_nodes) => {
-                    debug!(
-                        "NodeFeature {} does not have valid score {:?} controlled_nodes {:?}",
-                        feature.to_string(),
-                        &score,
-                        &controlled_nodes
-                    );
-                }
-            }
-        }
-
+        let (penalties, checks) = BusinessRulesPolicy::active()
+            .for_subnet(subnet_id)
+            .check(nodes, min_nakamoto_coefficients)?;
         debug!("Business rules checks succeeded for subnet {}: {:?}", subnet_id.to_string(), checks);
         Ok((penalties, checks))
     }
@@ -425,10 +328,9 @@ impl DecentralizedSubnet {
         }
     }
 
-    /// Pick the best result amongst the list of "suitable" candidates.
-    fn choose_best_candidate(&self, candidates: Vec<ReplacementCandidate>, run_log: &mut Vec<String>) -> Option<ReplacementCandidate> {
-        // First, sort the candidates by their Nakamoto Coefficients
-        let candidates = candidates
+    /// Sort the candidates, with the best candidate at the end.
+    fn rank_candidates(candidates: Vec<ReplacementCandidate>) -> Vec<ReplacementCandidate> {
+        candidates
             .into_iter()
             .sorted_by(|a, b| {
                 // Prefer nodes with lower penalty. This is for example used to prefer
@@ -447,11 +349,15 @@ impl DecentralizedSubnet {
                 }
                 cmp
             })
-            .collect::<Vec<ReplacementCandidate>>();
+            .collect()
+    }
 
+    /// Pick the best result amongst the list of "suitable" candidates, sorted
+    /// with [DecentralizedSubnet::rank_candidates].
+    fn choose_best_candidate(&self, candidates: &[ReplacementCandidate], run_log: &mut Vec<String>) -> Option<ReplacementCandidate> {
         run_log.push("Sorted candidate nodes, with the best candidate at the end:".to_string());
         run_log.push("     <node-id>                                                      <penalty>  <Nakamoto score>".to_string());
-        for s in &candidates {
+        for s in candidates {
             run_log.push(format!(" -=> {} {} {}", s.node.id, s.penalty, s.score));
         }
 
@@ -506,25 +412,35 @@ impl DecentralizedSubnet {
         let mut comment = None;
         let mut total_penalty = 0;
         let mut business_rules_log: Vec<String> = Vec::new();
+        let mut explanations = self.explanations.clone();
 
         run_log.push(format!("Nakamoto score before extension {}", self.nakamoto_score()));
+        let policy = BusinessRulesPolicy::active().for_subnet(&self.id);
 
         for i in 0..how_many_nodes {
             run_log.push("***********************************************************".to_string());
             run_log.push(format!("***  Adding node {}/{}", i + 1, how_many_nodes));
             run_log.push("***********************************************************".to_string());
 
-            let suitable_candidates: Vec<ReplacementCandidate> = available_nodes
+            let (suitable_candidates, ineligible_candidates): (Vec<_>, Vec<_>) = available_nodes
                 .iter()
-                .filter_map(|node| {
+                .map(|node| {
                     let subnet_nodes: Vec<Node> = nodes_initial.iter().chain([node]).cloned().collect();
-                    self._node_to_replacement_candidate(&subnet_nodes, node, &mut run_log)
+                    self._node_to_replacement_candidate(&policy, &subnet_nodes, node, &mut run_log)
                 })
-                .collect();
+                .partition_result();
+            let suitable_candidates = Self::rank_candidates(suitable_candidates);
 
             let mut candidate_run_log = Vec::new();
-            match self.choose_best_candidate(suitable_candidates, &mut candidate_run_log) {
+            match self.choose_best_candidate(&suitable_candidates, &mut candidate_run_log) {
                 Some(best_result) => {
+                    explanations.push(NodeChangeExplanation::picked(
+                        NodeChange::Added,
+                        &Self::_calc_nakamoto_score(&nodes_initial),
+                        &suitable_candidates,
+                        &best_result,
+                        ineligible_candidates,
+                    ));
                     // Append the complete run log
                     run_log.extend(
                         candidate_run_log
@@ -539,8 +455,9 @@ impl DecentralizedSubnet {
                     total_penalty += best_result.penalty;
                     business_rules_log.extend(
                         best_result
-                            .business_rules_log
+                            .violations
                             .iter()
+                            .map(|v| &v.message)
                             .map(|s| format!("node {}/{} ({}): {}", i + 1, how_many_nodes, best_result.node.id, s))
                             .collect::<Vec<String>>(),
                     );
@@ -573,6 +490,7 @@ impl DecentralizedSubnet {
             min_nakamoto_coefficients: self.min_nakamoto_coefficients,
             comment,
             run_log,
+            explanations,
         })
     }
 
@@ -586,24 +504,34 @@ impl DecentralizedSubnet {
         let mut business_rules_log: Vec<String> = Vec::new();
 
         run_log.push(format!("Nakamoto score before removal {}", self.nakamoto_score()));
+        let policy = BusinessRulesPolicy::active().for_subnet(&self.id);
 
         for i in 0..how_many_nodes {
             run_log.push("***********************************************************".to_string());
             run_log.push(format!("***  Removing node {}/{}", i + 1, how_many_nodes));
             run_log.push("***********************************************************".to_string());
 
-            let suitable_candidates: Vec<ReplacementCandidate> = self
+            let (suitable_candidates, ineligible_candidates): (Vec<_>, Vec<_>) = self
                 .nodes
                 .iter()
-                .filter_map(|node| {
+                .map(|node| {
                     let candidate_subnet_nodes: Vec<Node> = self.nodes.iter().filter(|n| n.id != node.id).cloned().collect();
-                    self._node_to_replacement_candidate(&candidate_subnet_nodes, node, &mut run_log)
+                    self._node_to_replacement_candidate(&policy, &candidate_subnet_nodes, node, &mut run_log)
                 })
-                .collect();
+                .partition_result();
+            let suitable_candidates = Self::rank_candidates(suitable_candidates);
 
             let mut candidate_run_log = Vec::new();
-            match self.choose_best_candidate(suitable_candidates, &mut candidate_run_log) {
+            match self.choose_best_candidate(&suitable_candidates, &mut candidate_run_log) {
                 Some(best_result) => {
+                    let explanation = NodeChangeExplanation::picked(
+                        NodeChange::Removed,
+                        &self.nakamoto_score(),
+                        &suitable_candidates,
+                        &best_result,
+                        ineligible_candidates,
+                    );
+                    self.explanations.push(explanation);
                     // Append the complete run log
                     run_log.extend(
                         candidate_run_log
@@ -617,8 +545,9 @@ impl DecentralizedSubnet {
                     total_penalty += best_result.penalty;
                     business_rules_log.extend(
                         best_result
-                            .business_rules_log
+                            .violations
                             .iter()
+                            .map(|v| &v.message)
                             .map(|s| format!("node {}/{} ({}): {}", i + 1, how_many_nodes, best_result.node.id, s))
                             .collect::<Vec<String>>(),
                     );
@@ -652,23 +581,36 @@ impl DecentralizedSubnet {
             min_nakamoto_coefficients: self.min_nakamoto_coefficients,
             comment,
             run_log,
+            explanations: self.explanations,
         })
     }
 
-    fn _node_to_replacement_candidate(&self, subnet_nodes: &[Node], touched_node: &Node, err_log: &mut Vec<String>) -> Option<ReplacementCandidate> {
-        match Self::_check_business_rules_for_nodes(&self.id, subnet_nodes, &self.min_nakamoto_coefficients) {
-            Ok((penalty, business_rules_log)) => {
+    /// Evaluate the subnet nodes with one node added or removed. The policy
+    /// of the subnet is resolved once by the caller, since this runs for every
+    /// candidate node.
+    fn _node_to_replacement_candidate(
+        &self,
+        policy: &SubnetPolicy,
+        subnet_nodes: &[Node],
+        touched_node: &Node,
+        err_log: &mut Vec<String>,
+    ) -> Result<ReplacementCandidate, RejectedCandidate> {
+        match policy.violations(subnet_nodes, &self.min_nakamoto_coefficients) {
+            Ok(violations) => {
                 let new_score = Self::_calc_nakamoto_score(subnet_nodes);
-                Some(ReplacementCandidate {
+                Ok(ReplacementCandidate {
                     node: touched_node.clone(),
                     score: new_score,
-                    penalty,
-                    business_rules_log,
+                    penalty: SubnetPolicy::total_penalty(subnet_nodes, &violations),
+                    violations,
                 })
             }
             Err(err) => {
                 err_log.push(format!("Node {} failed business rule {}", touched_node.id, err));
-                None
+                Err(RejectedCandidate {
+                    node_id: touched_node.id,
+                    reason: format!("business rules cannot be evaluated: {}", err),
+                })
             }
         }
     }
@@ -701,6 +643,7 @@ impl From<&ic_management_types::Subnet> for DecentralizedSubnet {
             min_nakamoto_coefficients: None,
             comment: None,
             run_log: Vec::new(),
+            explanations: Vec::new(),
         }
     }
 }
@@ -815,6 +758,7 @@ pub struct SubnetChangeRequest {
     nodes_to_remove: Vec<Node>,
     nodes_to_keep: Vec<Node>,
     min_nakamoto_coefficients: Option<MinNakamotoCoefficients>,
+    optimizer: OptimizationStrategy,
 }
 
 impl SubnetChangeRequest {
@@ -833,6 +777,7 @@ impl SubnetChangeRequest {
             nodes_to_remove,
             nodes_to_keep,
             min_nakamoto_coefficients,
+            optimizer: OptimizationStrategy::default(),
         }
     }
 
@@ -902,6 +847,13 @@ impl SubnetChangeRequest {
         }
     }
 
+    /// Select the strategy used to pick the nodes. Strategies other than
+    /// [OptimizationStrategy::Greedy] evaluate the changes jointly and report
+    /// how they compare to the greedy result.
+    pub fn with_optimization_strategy(self, optimizer: OptimizationStrategy) -> Self {
+        Self { optimizer, ..self }
+    }
+
     /// Optimize is implemented by removing a certain number of nodes and then
     /// adding the same number back.
     pub fn optimize(mut self, optimize_count: usize, replacements_unhealthy: &Vec<Node>) -> Result<SubnetChange, NetworkError> {
@@ -950,14 +902,31 @@ impl SubnetChangeRequest {
             .with_nodes(self.include_nodes.clone())
             .with_min_nakamoto_coefficients(&self.min_nakamoto_coefficients)
             .subnet_with_more_nodes(how_many_nodes_to_add, &available_nodes)
-            .map_err(|e| NetworkError::ResizeFailed(e.to_string()))?;
+            .and_then(|resized_subnet| {
+                if how_many_nodes_to_remove > 0 {
+                    resized_subnet.subnet_with_fewer_nodes(how_many_nodes_to_remove)
+                } else {
+                    Ok(resized_subnet)
+                }
+            });
 
-        let resized_subnet = if how_many_nodes_to_remove > 0 {
-            resized_subnet
-                .subnet_with_fewer_nodes(how_many_nodes_to_remove)
-                .map_err(|e| NetworkError::ResizeFailed(e.to_string()))?
-        } else {
-            resized_subnet
+        let (resized_subnet, optimization) = match self.optimizer {
+            OptimizationStrategy::Greedy => (resized_subnet.map_err(|e| NetworkError::ResizeFailed(e.to_string()))?, None),
+            strategy => {
+                if let Err(e) = &resized_subnet {
+                    warn!("Greedy resize of subnet {} failed: {}", self.subnet.id, e);
+                }
+                let (resized_subnet, report) = MembershipSearch::new(
+                    &self.subnet.clone().with_min_nakamoto_coefficients(&self.min_nakamoto_coefficients),
+                    self.include_nodes.clone(),
+                    &available_nodes,
+                    how_many_nodes_to_add,
+                    how_many_nodes_to_remove,
+                )
+                .run(strategy, resized_subnet.ok())
+                .map_err(|e| NetworkError::ResizeFailed(e.to_string()))?;
+                (resized_subnet, Some(report))
+            }
         };
 
         let subnet_change = SubnetChange {
@@ -967,6 +936,8 @@ impl SubnetChangeRequest {
             min_nakamoto_coefficients: self.min_nakamoto_coefficients.clone(),
             comment: resized_subnet.comment,
             run_log: resized_subnet.run_log,
+            explanations: resized_subnet.explanations,
+            optimization,
         };
         let node_add_count = subnet_change.added().len();
         let node_remove_count = subnet_change.removed().len();
@@ -993,6 +964,8 @@ pub struct SubnetChange {
     pub min_nakamoto_coefficients: Option<MinNakamotoCoefficients>,
     pub comment: Option<String>,
     pub run_log: Vec<String>,
+    pub explanations: Vec<NodeChangeExplanation>,
+    pub optimization: Option<OptimizationReport>,
 }
 
 impl SubnetChange {
@@ -1023,6 +996,7 @@ impl SubnetChange {
             min_nakamoto_coefficients: self.min_nakamoto_coefficients.clone(),
             comment: self.comment.clone(),
             run_log: Vec::new(),
+            explanations: Vec::new(),
         }
     }
 
@@ -1034,6 +1008,7 @@ impl SubnetChange {
             min_nakamoto_coefficients: self.min_nakamoto_coefficients.clone(),
             comment: self.comment.clone(),
             run_log: self.run_log.clone(),
+            explanations: self.explanations.clone(),
         }
     }
 }
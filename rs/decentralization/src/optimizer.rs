@@ -0,0 +1,676 @@
+use crate::explanation::{NodeChange, NodeChangeExplanation};
+use crate::nakamoto::NakamotoScore;
+use crate::network::{DecentralizedSubnet, Node};
+use crate::policy::{BusinessRulesPolicy, SubnetPolicy};
+use anyhow::anyhow;
+use ic_base_types::PrincipalId;
+use ic_management_types::{MinNakamotoCoefficients, OptimizationStrategy};
+use itertools::Itertools;
+use log::info;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+
+/// Number of partial subnets kept at every step of the beam search
+pub const BEAM_WIDTH: usize = 10;
+/// Number of node swaps attempted by the simulated annealing
+pub const ANNEALING_ITERATIONS: usize = 3000;
+const ANNEALING_TEMPERATURE_START: f64 = 1000.;
+const ANNEALING_TEMPERATURE_END: f64 = 1.;
+/// Maximum number of subnets the exhaustive search is allowed to evaluate
+pub const EXHAUSTIVE_SEARCH_LIMIT: usize = 100_000;
+
+/// Quality of a subnet: the total penalty of the business rules and the
+/// Nakamoto score of its nodes.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SubnetEvaluation {
+    pub penalty: usize,
+    pub score: NakamotoScore,
+}
+
+impl SubnetEvaluation {
+    /// Scalar cost used by the simulated annealing, lower is better. A unit of
+    /// the Nakamoto coefficient weighs as much as the default penalty for
+    /// missing the minimum Nakamoto coefficient by one.
+    fn energy(&self) -> f64 {
+        self.penalty as f64 - 100. * (self.score.score_min() + self.score.score_avg_linear())
+    }
+}
+
+impl Ord for SubnetEvaluation {
+    /// The better evaluation is the greater one: lower penalty first, then the
+    /// higher Nakamoto score
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.penalty.cmp(&self.penalty).then_with(|| self.score.cmp(&other.score))
+    }
+}
+
+impl PartialOrd for SubnetEvaluation {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for SubnetEvaluation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "penalty {}, minimum Nakamoto coefficient {:.2}, average Nakamoto coefficient {:.2}",
+            self.penalty,
+            self.score.score_min(),
+            self.score.score_avg_linear()
+        )
+    }
+}
+
+/// Outcome of a non-greedy optimization, compared to the greedy result.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OptimizationReport {
+    pub strategy: OptimizationStrategy,
+    /// Evaluation of the greedy result, if the greedy algorithm found one
+    /// that the strategy could compare with
+    pub greedy: Option<SubnetEvaluation>,
+    /// Why there is no evaluation of the greedy result
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub greedy_missing: Option<String>,
+    /// Evaluation of the selected result
+    pub best: SubnetEvaluation,
+    /// Number of candidate subnets evaluated by the strategy
+    pub evaluations: usize,
+}
+
+impl OptimizationReport {
+    /// Whether the strategy found a better subnet than the greedy algorithm
+    pub fn improves_on_greedy(&self) -> bool {
+        self.greedy.as_ref().map(|greedy| &self.best > greedy).unwrap_or(true)
+    }
+}
+
+impl Display for OptimizationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.greedy {
+            Some(greedy) if self.improves_on_greedy() => write!(
+                f,
+                "The {} optimizer evaluated {} candidate subnets and improved on the greedy result.\n  greedy: {}\n  {}: {}",
+                self.strategy, self.evaluations, greedy, self.strategy, self.best
+            ),
+            Some(greedy) => write!(
+                f,
+                "The {} optimizer evaluated {} candidate subnets and found no better subnet than the greedy result ({})",
+                self.strategy, self.evaluations, greedy
+            ),
+            None => write!(
+                f,
+                "{}. The {} optimizer evaluated {} candidate subnets and found one with {}",
+                self.greedy_missing.as_deref().unwrap_or("The greedy algorithm found no subnet"),
+                self.strategy,
+                self.evaluations,
+                self.best
+            ),
+        }
+    }
+}
+
+/// Indices of the selected nodes in [MembershipSearch::pool]
+type Selection = BTreeSet<usize>;
+
+/// Joint search over the nodes to remove from a subnet and the nodes to add to
+/// it. Unlike the greedy algorithm, which picks one node at a time, every
+/// candidate is evaluated as a complete subnet.
+pub(crate) struct MembershipSearch {
+    subnet_id: PrincipalId,
+    /// Business rules of the subnet, resolved once for all the evaluations
+    policy: SubnetPolicy,
+    min_nakamoto_coefficients: Option<MinNakamotoCoefficients>,
+    /// Nodes that are part of the subnet in every candidate
+    fixed: Vec<Node>,
+    /// The current subnet nodes, followed by the available nodes
+    pool: Vec<Node>,
+    /// Number of current subnet nodes at the start of the pool
+    current_len: usize,
+    add: usize,
+    remove: usize,
+    evaluations: usize,
+    /// Explanations of the changes made before the search, e.g. removed
+    /// unhealthy nodes
+    explanations: Vec<NodeChangeExplanation>,
+}
+
+impl MembershipSearch {
+    pub(crate) fn new(subnet: &DecentralizedSubnet, fixed: Vec<Node>, available_nodes: &[Node], add: usize, remove: usize) -> Self {
+        let current = subnet.nodes.iter().filter(|n| !fixed.contains(n)).cloned().collect_vec();
+        let available = available_nodes
+            .iter()
+            .filter(|n| !fixed.contains(n) && !current.contains(n))
+            .sorted_by_key(|n| n.id)
+            .cloned()
+            .collect_vec();
+        Self {
+            subnet_id: subnet.id,
+            policy: BusinessRulesPolicy::active().for_subnet(&subnet.id),
+            min_nakamoto_coefficients: subnet.min_nakamoto_coefficients.clone(),
+            fixed,
+            current_len: current.len(),
+            pool: current.into_iter().chain(available).collect(),
+            add,
+            remove,
+            evaluations: 0,
+            explanations: subnet.explanations.clone(),
+        }
+    }
+
+    /// Number of pool nodes in every complete candidate
+    fn target_len(&self) -> usize {
+        (self.current_len + self.add).saturating_sub(self.remove)
+    }
+
+    /// Range for the number of current subnet nodes that can be removed
+    fn removable_range(&self) -> std::ops::RangeInclusive<usize> {
+        self.remove.saturating_sub(self.add)..=self.remove.min(self.current_len)
+    }
+
+    fn removed_current_count(&self, selection: &Selection) -> usize {
+        self.current_len - selection.range(..self.current_len).count()
+    }
+
+    fn is_complete(&self, selection: &Selection) -> bool {
+        selection.len() == self.target_len() && self.removable_range().contains(&self.removed_current_count(selection))
+    }
+
+    fn nodes(&self, selection: &Selection) -> Vec<Node> {
+        let (current, added): (Vec<usize>, Vec<usize>) = selection.iter().partition(|i| **i < self.current_len);
+        current
+            .into_iter()
+            .map(|i| self.pool[i].clone())
+            .chain(self.fixed.iter().cloned())
+            .chain(added.into_iter().map(|i| self.pool[i].clone()))
+            .collect()
+    }
+
+    fn subnet(&self, selection: &Selection) -> DecentralizedSubnet {
+        DecentralizedSubnet {
+            id: self.subnet_id,
+            nodes: self.nodes(selection),
+            removed_nodes: (0..self.current_len)
+                .filter(|i| !selection.contains(i))
+                .map(|i| self.pool[i].clone())
+                .collect(),
+            min_nakamoto_coefficients: self.min_nakamoto_coefficients.clone(),
+            comment: None,
+            run_log: Vec::new(),
+            explanations: Vec::new(),
+        }
+    }
+
+    /// The optimizer evaluates complete subnets, so there is no ranking of
+    /// the individual nodes. The optimization report has the details.
+    fn explanations(&self, selection: &Selection, strategy: OptimizationStrategy) -> Vec<NodeChangeExplanation> {
+        let reason = format!("part of the best subnet found by the {} optimizer", strategy);
+        self.explanations
+            .iter()
+            .cloned()
+            .chain(
+                self.fixed
+                    .iter()
+                    .map(|n| NodeChangeExplanation::new(n.id, NodeChange::Added, "requested inclusion".to_string())),
+            )
+            .chain(
+                (0..self.current_len)
+                    .filter(|i| !selection.contains(i))
+                    .map(|i| NodeChangeExplanation::new(self.pool[i].id, NodeChange::Removed, reason.clone())),
+            )
+            .chain(
+                selection
+                    .range(self.current_len..)
+                    .map(|i| NodeChangeExplanation::new(self.pool[*i].id, NodeChange::Added, reason.clone())),
+            )
+            .collect()
+    }
+
+    /// Evaluate a (partial) candidate. Returns None if the business rules
+    /// cannot be evaluated for the candidate nodes.
+    fn evaluate(&mut self, selection: &Selection) -> Option<SubnetEvaluation> {
+        self.evaluations += 1;
+        let nodes = self.nodes(selection);
+        self.policy
+            .check(&nodes, &self.min_nakamoto_coefficients)
+            .ok()
+            .map(|(penalty, _)| SubnetEvaluation {
+                penalty,
+                score: NakamotoScore::new_from_nodes(&nodes),
+            })
+    }
+
+    /// Map a subnet (e.g. the greedy result) back to a selection, or tell why
+    /// it is not one of the candidates of the search
+    fn selection_of(&self, subnet: &DecentralizedSubnet) -> Result<Selection, String> {
+        let dropped = self.fixed.iter().filter(|n| !subnet.nodes.contains(n)).map(|n| n.id).collect_vec();
+        if !dropped.is_empty() {
+            return Err(format!(
+                "The greedy result does not include the requested node(s) {}",
+                dropped.iter().join(", ")
+            ));
+        }
+        let selection = subnet
+            .nodes
+            .iter()
+            .filter(|n| !self.fixed.contains(n))
+            .map(|n| {
+                self.pool
+                    .iter()
+                    .position(|p| p.id == n.id)
+                    .ok_or_else(|| format!("The greedy result has the node {} that is not available to the optimizer", n.id))
+            })
+            .collect::<Result<Selection, String>>()?;
+        if !self.is_complete(&selection) {
+            return Err("The greedy result does not add and remove the requested number of nodes".to_string());
+        }
+        Ok(selection)
+    }
+
+    /// Any complete selection: keep as many current nodes as possible and
+    /// take the first available nodes
+    fn initial_selection(&self) -> Selection {
+        let keep = self.current_len - *self.removable_range().start();
+        (0..keep).chain(self.current_len..self.current_len + self.target_len() - keep).collect()
+    }
+
+    /// Deterministic seed based on the nodes in the pool, so that the same
+    /// request always gives the same result
+    fn rng(&self) -> rand::rngs::StdRng {
+        let seed = rand_seeder::Seeder::from(self.pool.iter().map(|n| n.id.to_string()).sorted().join("_")).make_seed();
+        rand::rngs::StdRng::from_seed(seed)
+    }
+
+    /// Run the strategy and return the resulting subnet. The result is never
+    /// worse than the provided greedy result.
+    pub(crate) fn run(
+        mut self,
+        strategy: OptimizationStrategy,
+        greedy: Option<DecentralizedSubnet>,
+    ) -> anyhow::Result<(DecentralizedSubnet, OptimizationReport)> {
+        if self.current_len + self.add < self.remove {
+            return Err(anyhow!(
+                "Cannot remove {} nodes from a subnet with {} nodes",
+                self.remove,
+                self.current_len + self.add
+            ));
+        }
+        if self.pool.len() < self.target_len() {
+            return Err(anyhow!(
+                "Not enough available nodes to add {} nodes to subnet {}",
+                self.add,
+                self.subnet_id
+            ));
+        }
+        let greedy_selection = match &greedy {
+            Some(greedy) => self.selection_of(greedy),
+            None => Err("The greedy algorithm found no subnet".to_string()),
+        };
+        let greedy_evaluation = match &greedy_selection {
+            Ok(selection) => self
+                .evaluate(selection)
+                .ok_or_else(|| "The business rules cannot be evaluated for the greedy result".to_string()),
+            Err(reason) => Err(reason.clone()),
+        };
+        let greedy_selection = greedy_selection.ok();
+        self.evaluations = 0;
+
+        let found = match strategy {
+            OptimizationStrategy::Greedy => None,
+            OptimizationStrategy::BeamSearch => self.beam_search(),
+            OptimizationStrategy::SimulatedAnnealing => self.simulated_annealing(greedy_selection.clone()),
+            OptimizationStrategy::Exhaustive => self.exhaustive()?,
+        };
+
+        let (selection, best) = match (found, greedy_selection.clone().zip(greedy_evaluation.clone().ok())) {
+            (Some(found), Some(greedy)) => {
+                if found.1 > greedy.1 {
+                    found
+                } else {
+                    greedy
+                }
+            }
+            (Some(found), None) => found,
+            (None, Some(greedy)) => greedy,
+            (None, None) => {
+                return Err(anyhow!(
+                    "The {} optimizer could not find a subnet for which the business rules can be evaluated",
+                    strategy
+                ))
+            }
+        };
+
+        let report = OptimizationReport {
+            strategy,
+            greedy: greedy_evaluation.clone().ok(),
+            greedy_missing: greedy_evaluation.err(),
+            best,
+            evaluations: self.evaluations,
+        };
+        info!("Subnet {}: {}", self.subnet_id, report);
+
+        let mut result = match greedy {
+            Some(greedy) if Some(&selection) == greedy_selection.as_ref() => greedy,
+            greedy => {
+                let mut result = self.subnet(&selection);
+                let (penalty, business_rules_log) = result.check_business_rules()?;
+                if penalty != 0 {
+                    result.comment = Some(format!(
+                        "Subnet change found by the {} optimizer has the total penalty {}. Penalty causes:\n{}",
+                        strategy,
+                        penalty,
+                        business_rules_log.join("\n")
+                    ));
+                }
+                result.run_log = greedy.map(|g| g.run_log).unwrap_or_default();
+                result.explanations = self.explanations(&selection, strategy);
+                result
+            }
+        };
+        result.run_log.push(report.to_string());
+        Ok((result, report))
+    }
+
+    /// Generalization of the greedy algorithm: first add nodes and then remove
+    /// nodes one at a time, but keep the [BEAM_WIDTH] best partial subnets at
+    /// every step.
+    fn beam_search(&mut self) -> Option<(Selection, SubnetEvaluation)> {
+        let mut beam: Vec<(Selection, Option<SubnetEvaluation>)> = vec![((0..self.current_len).collect(), None)];
+        for _ in 0..self.add {
+            let expanded = beam
+                .iter()
+                .flat_map(|(selection, _)| {
+                    (self.current_len..self.pool.len())
+                        .filter(|i| !selection.contains(i))
+                        .map(|i| selection.iter().copied().chain([i]).collect::<Selection>())
+                })
+                .collect::<BTreeSet<_>>();
+            beam = self.best_of(expanded);
+        }
+        for _ in 0..self.remove {
+            let expanded = beam
+                .iter()
+                .flat_map(|(selection, _)| {
+                    selection
+                        .iter()
+                        .map(|i| selection.iter().copied().filter(|j| j != i).collect::<Selection>())
+                        .collect_vec()
+                })
+                .collect::<BTreeSet<_>>();
+            beam = self.best_of(expanded);
+        }
+        let (selection, evaluation) = beam.into_iter().find(|(selection, _)| self.is_complete(selection))?;
+        match evaluation {
+            Some(evaluation) => Some((selection, evaluation)),
+            None => self.evaluate(&selection).map(|evaluation| (selection, evaluation)),
+        }
+    }
+
+    fn best_of(&mut self, selections: BTreeSet<Selection>) -> Vec<(Selection, Option<SubnetEvaluation>)> {
+        selections
+            .into_iter()
+            .filter_map(|selection| self.evaluate(&selection).map(|evaluation| (selection, evaluation)))
+            .sorted_by(|a, b| b.1.cmp(&a.1))
+            .take(BEAM_WIDTH)
+            .map(|(selection, evaluation)| (selection, Some(evaluation)))
+            .collect()
+    }
+
+    /// Randomized search over node swaps. Worse candidates are accepted with a
+    /// probability that decreases over time, which allows escaping local optima.
+    fn simulated_annealing(&mut self, start: Option<Selection>) -> Option<(Selection, SubnetEvaluation)> {
+        let mut rng = self.rng();
+        let mut current = start.unwrap_or_else(|| self.initial_selection());
+        let mut current_evaluation = self.evaluate(&current);
+        let mut best = current_evaluation.clone().map(|evaluation| (current.clone(), evaluation));
+        if current.is_empty() || current.len() == self.pool.len() {
+            return best;
+        }
+
+        for iteration in 0..ANNEALING_ITERATIONS {
+            let temperature = ANNEALING_TEMPERATURE_START
+                * (ANNEALING_TEMPERATURE_END / ANNEALING_TEMPERATURE_START).powf(iteration as f64 / ANNEALING_ITERATIONS as f64);
+            let swap_out = *current.iter().nth(rng.gen_range(0..current.len())).expect("index is in range");
+            let swap_in = rng.gen_range(0..self.pool.len());
+            if current.contains(&swap_in) {
+                continue;
+            }
+            let candidate: Selection = current.iter().copied().filter(|i| *i != swap_out).chain([swap_in]).collect();
+            if !self.is_complete(&candidate) {
+                continue;
+            }
+            let Some(candidate_evaluation) = self.evaluate(&candidate) else {
+                continue;
+            };
+            let accept = match &current_evaluation {
+                Some(current_evaluation) => {
+                    let delta = candidate_evaluation.energy() - current_evaluation.energy();
+                    delta <= 0. || rng.gen::<f64>() < (-delta / temperature).exp()
+                }
+                None => true,
+            };
+            if accept {
+                if best.as_ref().map(|(_, best)| &candidate_evaluation > best).unwrap_or(true) {
+                    best = Some((candidate.clone(), candidate_evaluation.clone()));
+                }
+                current = candidate;
+                current_evaluation = Some(candidate_evaluation);
+            }
+        }
+        best
+    }
+
+    /// Evaluate every possible change. Available nodes that are identical in all
+    /// features are interchangeable, so only one of them is considered.
+    fn exhaustive(&mut self) -> anyhow::Result<Option<(Selection, SubnetEvaluation)>> {
+        let classes = (self.current_len..self.pool.len())
+            .fold(BTreeMap::<_, Vec<usize>>::new(), |mut acc, i| {
+                let node = &self.pool[i];
+                acc.entry((node.features.feature_map.clone(), node.dfinity_owned, node.decentralized))
+                    .or_default()
+                    .push(i);
+                acc
+            })
+            .into_values()
+            .sorted()
+            .collect_vec();
+        let capacities = classes.iter().map(|c| c.len()).collect_vec();
+
+        let total = self.removable_range().fold(0usize, |acc, removed| {
+            let added = self.add + removed - self.remove;
+            acc.saturating_add(binomial(self.current_len, removed).saturating_mul(multiset_count(&capacities, added)))
+        });
+        if total > EXHAUSTIVE_SEARCH_LIMIT {
+            return Err(anyhow!(
+                "The exhaustive search would need to evaluate {} subnets, which is more than the limit of {}. Please use a different optimizer.",
+                total,
+                EXHAUSTIVE_SEARCH_LIMIT
+            ));
+        }
+
+        let mut best: Option<(Selection, SubnetEvaluation)> = None;
+        for removed in self.removable_range() {
+            let added = self.add + removed - self.remove;
+            let additions = class_choices(&capacities, added);
+            for removed_nodes in (0..self.current_len).combinations(removed) {
+                for addition in &additions {
+                    let selection: Selection = (0..self.current_len)
+                        .filter(|i| !removed_nodes.contains(i))
+                        .chain(addition.iter().flat_map(|(class, count)| classes[*class].iter().take(*count).copied()))
+                        .collect();
+                    if let Some(evaluation) = self.evaluate(&selection) {
+                        if best.as_ref().map(|(_, best)| &evaluation > best).unwrap_or(true) {
+                            best = Some((selection, evaluation));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(best)
+    }
+}
+
+fn binomial(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+    (0..k.min(n - k)).fold(1usize, |acc, i| acc.saturating_mul(n - i) / (i + 1))
+}
+
+/// Number of ways to pick `k` items from classes of interchangeable items
+fn multiset_count(capacities: &[usize], k: usize) -> usize {
+    let mut ways = vec![0usize; k + 1];
+    ways[0] = 1;
+    for capacity in capacities {
+        ways = (0..=k)
+            .map(|j| (0..=j.min(*capacity)).fold(0usize, |acc, t| acc.saturating_add(ways[j - t])))
+            .collect();
+    }
+    ways[k]
+}
+
+/// All ways to pick `k` items from classes of interchangeable items, as a list
+/// of (class index, count) pairs
+fn class_choices(capacities: &[usize], k: usize) -> Vec<Vec<(usize, usize)>> {
+    fn recurse(capacities: &[usize], start: usize, k: usize, prefix: &mut Vec<(usize, usize)>, result: &mut Vec<Vec<(usize, usize)>>) {
+        if k == 0 {
+            result.push(prefix.clone());
+            return;
+        }
+        for class in start..capacities.len() {
+            for count in 1..=capacities[class].min(k) {
+                prefix.push((class, count));
+                recurse(capacities, class + 1, k - count, prefix, result);
+                prefix.pop();
+            }
+        }
+    }
+    let mut result = Vec::new();
+    recurse(capacities, 0, k, &mut Vec::new(), &mut result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::SubnetChangeRequest;
+    use crate::test_utils::new_test_node;
+
+    fn new_test_subnet() -> (DecentralizedSubnet, Vec<Node>) {
+        let nodes = vec![
+            new_test_node(0, "NP0", "CH", true),
+            new_test_node(1, "NP1", "US", false),
+            new_test_node(2, "NP1", "US", false),
+            new_test_node(3, "NP2", "DE", false),
+            new_test_node(4, "NP3", "US", false),
+            new_test_node(5, "NP4", "JP", false),
+            new_test_node(6, "NP5", "US", false),
+        ];
+        let available = (10..30)
+            .map(|i| new_test_node(i, &format!("NP{}", i % 8), ["US", "BE", "IN", "BR", "SG"][i as usize % 5], false))
+            .collect_vec();
+        (
+            DecentralizedSubnet {
+                id: PrincipalId::new_subnet_test_id(1),
+                nodes,
+                ..Default::default()
+            },
+            available,
+        )
+    }
+
+    #[test]
+    fn counting() {
+        assert_eq!(binomial(5, 0), 1);
+        assert_eq!(binomial(5, 2), 10);
+        assert_eq!(binomial(2, 5), 0);
+        assert_eq!(multiset_count(&[2, 1, 3], 2), 5);
+        assert_eq!(class_choices(&[2, 1, 3], 2).len(), 5);
+        assert_eq!(class_choices(&[2, 1, 3], 0), vec![Vec::<(usize, usize)>::new()]);
+    }
+
+    #[test]
+    fn strategies_are_not_worse_than_greedy() {
+        let (subnet, available) = new_test_subnet();
+        for strategy in [
+            OptimizationStrategy::BeamSearch,
+            OptimizationStrategy::SimulatedAnnealing,
+            OptimizationStrategy::Exhaustive,
+        ] {
+            let change = SubnetChangeRequest::new(subnet.clone(), available.clone(), Vec::new(), Vec::new(), Vec::new(), None)
+                .with_optimization_strategy(strategy)
+                .optimize(2, &vec![])
+                .unwrap();
+            let report = change.optimization.clone().expect("the optimizer report is missing");
+            assert_eq!(report.strategy, strategy);
+            assert!(report.best >= report.greedy.clone().unwrap());
+            assert_eq!(change.new_nodes.len(), subnet.nodes.len());
+            assert!(change.removed().len() <= 2);
+            assert_eq!(
+                report.best.score,
+                NakamotoScore::new_from_nodes(&change.new_nodes),
+                "{} reported a different score than the one of the resulting subnet",
+                strategy
+            );
+        }
+    }
+
+    #[test]
+    fn exhaustive_finds_the_best_subnet() {
+        let (subnet, available) = new_test_subnet();
+        let (_, exhaustive) = MembershipSearch::new(&subnet, Vec::new(), &available, 1, 1)
+            .run(OptimizationStrategy::Exhaustive, None)
+            .unwrap();
+        for strategy in [OptimizationStrategy::BeamSearch, OptimizationStrategy::SimulatedAnnealing] {
+            let (_, report) = MembershipSearch::new(&subnet, Vec::new(), &available, 1, 1).run(strategy, None).unwrap();
+            assert!(
+                exhaustive.best >= report.best,
+                "{} found a better subnet than the exhaustive search",
+                strategy
+            );
+        }
+    }
+
+    #[test]
+    fn exhaustive_search_is_limited() {
+        let (subnet, available) = new_test_subnet();
+        let available = (100..400)
+            .map(|i| new_test_node(i, &format!("NP{}", i), &format!("C{}", i), false))
+            .chain(available)
+            .collect_vec();
+        let result = MembershipSearch::new(&subnet, Vec::new(), &available, 3, 3).run(OptimizationStrategy::Exhaustive, None);
+        assert!(result.unwrap_err().to_string().contains("more than the limit"));
+    }
+
+    #[test]
+    fn fixed_nodes_are_kept() {
+        let (subnet, available) = new_test_subnet();
+        let fixed = vec![available[0].clone()];
+        let (result, _) = MembershipSearch::new(&subnet, fixed.clone(), &available, 1, 2)
+            .run(OptimizationStrategy::BeamSearch, None)
+            .unwrap();
+        assert!(result.nodes.contains(&fixed[0]));
+        assert_eq!(result.nodes.len(), subnet.nodes.len());
+    }
+
+    #[test]
+    fn greedy_result_without_fixed_nodes_is_reported() {
+        let (subnet, available) = new_test_subnet();
+        let fixed = vec![available[0].clone()];
+        let (_, report) = MembershipSearch::new(&subnet, fixed.clone(), &available, 1, 1)
+            .run(OptimizationStrategy::BeamSearch, Some(subnet.clone()))
+            .unwrap();
+        assert_eq!(report.greedy, None);
+        let reason = report.greedy_missing.clone().unwrap();
+        assert!(reason.contains(&fixed[0].id.to_string()), "{}", reason);
+        assert!(report.to_string().starts_with(&reason));
+
+        let (_, report) = MembershipSearch::new(&subnet, Vec::new(), &available, 1, 1)
+            .run(OptimizationStrategy::BeamSearch, None)
+            .unwrap();
+        assert_eq!(report.greedy_missing.as_deref(), Some("The greedy algorithm found no subnet"));
+    }
+}
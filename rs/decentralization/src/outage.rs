@@ -0,0 +1,289 @@
+use crate::nakamoto::NakamotoScore;
+use crate::network::DecentralizedSubnet;
+use colored::Colorize;
+use ic_base_types::PrincipalId;
+use ic_management_types::NodeFeature;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Effect on a subnet of losing all nodes that share a feature value.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum OutageImpact {
+    /// The subnet loses nodes but can still tolerate further node failures
+    Degraded,
+    /// The subnet keeps making progress, but a single additional node failure
+    /// would halt it
+    NoFaultTolerance,
+    /// More than 1/3 of the nodes are gone, so the subnet can no longer reach
+    /// consensus and stops making progress
+    ConsensusLost,
+}
+
+impl OutageImpact {
+    /// A subnet of `nodes_total` nodes tolerates `f = (n - 1) / 3` faulty nodes.
+    pub fn new(nodes_total: usize, nodes_lost: usize) -> Option<Self> {
+        let max_faulty = nodes_total.saturating_sub(1) / 3;
+        match nodes_lost {
+            0 => None,
+            lost if lost > max_faulty => Some(OutageImpact::ConsensusLost),
+            lost if lost == max_faulty => Some(OutageImpact::NoFaultTolerance),
+            _ => Some(OutageImpact::Degraded),
+        }
+    }
+}
+
+impl Display for OutageImpact {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutageImpact::Degraded => write!(f, "degraded"),
+            OutageImpact::NoFaultTolerance => write!(f, "no fault tolerance"),
+            OutageImpact::ConsensusLost => write!(f, "consensus lost"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubnetOutage {
+    pub subnet_id: PrincipalId,
+    pub subnet_name: String,
+    pub nodes_total: usize,
+    pub nodes_lost: usize,
+    pub impact: OutageImpact,
+}
+
+/// All nodes with a given value of a [NodeFeature], e.g. all nodes of a node
+/// provider or all nodes in a country.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FailureDomain {
+    pub feature: NodeFeature,
+    pub value: String,
+    /// Subnets that have at least one node in the failure domain, most
+    /// affected first
+    pub subnets: Vec<SubnetOutage>,
+}
+
+impl FailureDomain {
+    pub fn subnets_with_impact(&self, impact: OutageImpact) -> usize {
+        self.subnets.iter().filter(|s| s.impact == impact).count()
+    }
+
+    pub fn nodes_lost(&self) -> usize {
+        self.subnets.iter().map(|s| s.nodes_lost).sum()
+    }
+
+    /// Failure domains are ranked by the number of subnets that lose
+    /// consensus, then by the number of subnets left without fault
+    /// tolerance, then by the number of degraded subnets, and then by the
+    /// number of lost nodes.
+    fn severity(&self) -> (usize, usize, usize, usize) {
+        (
+            self.subnets_with_impact(OutageImpact::ConsensusLost),
+            self.subnets_with_impact(OutageImpact::NoFaultTolerance),
+            self.subnets_with_impact(OutageImpact::Degraded),
+            self.nodes_lost(),
+        )
+    }
+}
+
+/// Single points of failure across the network, most dangerous first.
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct OutageReport {
+    pub failure_domains: Vec<FailureDomain>,
+}
+
+impl OutageReport {
+    pub fn truncated(self, limit: Option<usize>) -> Self {
+        Self {
+            failure_domains: self.failure_domains.into_iter().take(limit.unwrap_or(usize::MAX)).collect(),
+        }
+    }
+}
+
+/// Computes, for every value of every [NodeFeature], what happens to the
+/// subnets if all nodes with that value disappear at once.
+pub struct OutageAnalysis {
+    subnets: Vec<(String, DecentralizedSubnet)>,
+    features: Vec<NodeFeature>,
+}
+
+impl OutageAnalysis {
+    pub fn new(subnets: Vec<(String, DecentralizedSubnet)>) -> Self {
+        Self {
+            subnets,
+            features: NodeFeature::variants(),
+        }
+    }
+
+    /// Only consider failure domains of the given features. All features are
+    /// considered if the list is empty.
+    pub fn with_features(self, features: Vec<NodeFeature>) -> Self {
+        if features.is_empty() {
+            return self;
+        }
+        Self { features, ..self }
+    }
+
+    pub fn analyze(&self) -> OutageReport {
+        let mut domains: BTreeMap<(NodeFeature, String), Vec<SubnetOutage>> = BTreeMap::new();
+        for (name, subnet) in &self.subnets {
+            let score = NakamotoScore::new_from_nodes(&subnet.nodes);
+            for feature in &self.features {
+                for (value, nodes_lost) in score.feature_value_counts(feature) {
+                    if let Some(impact) = OutageImpact::new(subnet.nodes.len(), nodes_lost) {
+                        domains.entry((feature.clone(), value)).or_default().push(SubnetOutage {
+                            subnet_id: subnet.id,
+                            subnet_name: name.clone(),
+                            nodes_total: subnet.nodes.len(),
+                            nodes_lost,
+                            impact,
+                        });
+                    }
+                }
+            }
+        }
+
+        OutageReport {
+            failure_domains: domains
+                .into_iter()
+                .map(|((feature, value), subnets)| FailureDomain {
+                    feature,
+                    value,
+                    subnets: subnets
+                        .into_iter()
+                        .sorted_by(|a, b| b.impact.cmp(&a.impact).then(b.nodes_lost.cmp(&a.nodes_lost)))
+                        .collect(),
+                })
+                // The sort is stable, so equally severe domains stay ordered by feature and value
+                .sorted_by(|a, b| b.severity().cmp(&a.severity()))
+                .collect(),
+        }
+    }
+}
+
+impl Display for OutageReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}\n",
+            format!(
+                "Single points of failure, ranked by impact ({} failure domains)",
+                self.failure_domains.len()
+            )
+            .bold()
+        )?;
+        let mut table = tabular::Table::new("{:>}  {:<}  {:<}  {:>}  {:>}  {:>}  {:>}  {:<}");
+        table.add_row(
+            tabular::Row::new()
+                .with_cell("#")
+                .with_cell("Feature")
+                .with_cell("Value")
+                .with_cell("Consensus lost")
+                .with_cell("No fault tolerance")
+                .with_cell("Degraded")
+                .with_cell("Nodes lost")
+                .with_cell("Subnets losing consensus"),
+        );
+        for (i, domain) in self.failure_domains.iter().enumerate() {
+            let consensus_lost = domain.subnets_with_impact(OutageImpact::ConsensusLost);
+            let row = tabular::Row::new()
+                .with_cell(i + 1)
+                .with_cell(&domain.feature)
+                .with_cell(&domain.value)
+                .with_cell(consensus_lost)
+                .with_cell(domain.subnets_with_impact(OutageImpact::NoFaultTolerance))
+                .with_cell(domain.subnets_with_impact(OutageImpact::Degraded))
+                .with_cell(domain.nodes_lost())
+                .with_cell(
+                    domain
+                        .subnets
+                        .iter()
+                        .filter(|s| s.impact == OutageImpact::ConsensusLost)
+                        .map(|s| format!("{} ({}/{})", s.subnet_name, s.nodes_lost, s.nodes_total))
+                        .join(", "),
+                );
+            table.add_row(row);
+        }
+        for (i, line) in table.to_string().lines().enumerate() {
+            let domain = i.checked_sub(1).and_then(|i| self.failure_domains.get(i));
+            match domain {
+                None => writeln!(f, "{}", line.bold())?,
+                Some(domain) if domain.subnets_with_impact(OutageImpact::ConsensusLost) > 0 => writeln!(f, "{}", line.red())?,
+                Some(domain) if domain.subnets_with_impact(OutageImpact::NoFaultTolerance) > 0 => writeln!(f, "{}", line.yellow())?,
+                Some(_) => writeln!(f, "{}", line)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nakamoto::NodeFeatures;
+    use crate::network::Node;
+
+    fn subnet(id: u64, providers: &[&str]) -> (String, DecentralizedSubnet) {
+        let nodes = providers
+            .iter()
+            .enumerate()
+            .map(|(i, np)| {
+                Node::new_test_node(
+                    id * 100 + i as u64,
+                    NodeFeatures::from_iter([
+                        (NodeFeature::NodeProvider, np.to_string()),
+                        (NodeFeature::Country, format!("country-{}", i)),
+                    ]),
+                    false,
+                    true,
+                )
+            })
+            .collect();
+        (
+            format!("subnet-{}", id),
+            DecentralizedSubnet::default()
+                .with_subnet_id(PrincipalId::new_subnet_test_id(id))
+                .with_nodes(nodes),
+        )
+    }
+
+    #[test]
+    fn impact_thresholds() {
+        assert_eq!(OutageImpact::new(13, 0), None);
+        assert_eq!(OutageImpact::new(13, 3), Some(OutageImpact::Degraded));
+        assert_eq!(OutageImpact::new(13, 4), Some(OutageImpact::NoFaultTolerance));
+        assert_eq!(OutageImpact::new(13, 5), Some(OutageImpact::ConsensusLost));
+        assert_eq!(OutageImpact::new(4, 1), Some(OutageImpact::NoFaultTolerance));
+        assert_eq!(OutageImpact::new(1, 1), Some(OutageImpact::ConsensusLost));
+    }
+
+    #[test]
+    fn ranks_most_dangerous_failure_domains_first() {
+        let report = OutageAnalysis::new(vec![
+            subnet(1, &["np-a", "np-a", "np-b", "np-c"]),
+            subnet(2, &["np-a", "np-b", "np-c", "np-d"]),
+            subnet(3, &["np-b", "np-c", "np-d", "np-e", "np-f", "np-g", "np-h"]),
+        ])
+        .with_features(vec![NodeFeature::NodeProvider])
+        .analyze();
+
+        let top = &report.failure_domains[0];
+        assert_eq!((top.feature.clone(), top.value.as_str()), (NodeFeature::NodeProvider, "np-a"));
+        assert_eq!(top.subnets_with_impact(OutageImpact::ConsensusLost), 1);
+        assert_eq!(top.subnets_with_impact(OutageImpact::NoFaultTolerance), 1);
+        assert_eq!(top.subnets[0].subnet_name, "subnet-1");
+        assert_eq!(top.nodes_lost(), 3);
+
+        // np-b and np-c hit the same subnets in the same way, so they are ranked by name
+        assert_eq!(report.failure_domains[1].value, "np-b");
+        assert_eq!(report.failure_domains[2].value, "np-c");
+        assert!(report
+            .failure_domains
+            .iter()
+            .all(|d| d.feature == NodeFeature::NodeProvider && d.subnets_with_impact(OutageImpact::ConsensusLost) <= 1));
+        assert_eq!(report.clone().truncated(Some(2)).failure_domains.len(), 2);
+        assert_eq!(report.failure_domains.len(), 8);
+    }
+}
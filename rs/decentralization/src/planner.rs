@@ -0,0 +1,402 @@
+use crate::nakamoto::NakamotoScore;
+use crate::network::{DecentralizedSubnet, NetworkHealSubnets, Node, SubnetChange, SubnetChangeRequest};
+use crate::SubnetChangeResponse;
+use colored::Colorize;
+use ic_base_types::PrincipalId;
+use ic_management_types::{NetworkError, NodeFeature};
+use itertools::Itertools;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Plans node swaps across all subnets of the network. Unhealthy nodes are
+/// replaced first, and the remaining swap budget is spent on the subnets with
+/// the lowest Nakamoto score, which maximizes the network-wide minimum Nakamoto
+/// coefficient.
+pub struct NetworkRebalancePlanner {
+    subnets: Vec<NetworkHealSubnets>,
+    max_swaps: Option<usize>,
+    max_swaps_per_subnet: Option<usize>,
+    max_swaps_by_subnet: BTreeMap<PrincipalId, usize>,
+}
+
+struct PlannedSubnet {
+    name: String,
+    initial_nodes: Vec<Node>,
+    subnet: DecentralizedSubnet,
+    unhealthy_nodes: Vec<Node>,
+    unhealthy_replaced: usize,
+    optimized: usize,
+    cannot_improve: bool,
+    /// Position of the first planned change, which determines the proposal order
+    first_change: Option<usize>,
+    comment: Option<String>,
+    run_log: Vec<String>,
+}
+
+impl PlannedSubnet {
+    fn swaps(&self) -> usize {
+        self.unhealthy_replaced + self.optimized
+    }
+
+    fn apply(&mut self, change: SubnetChange, change_index: usize) {
+        self.first_change.get_or_insert(change_index);
+        self.subnet = DecentralizedSubnet {
+            nodes: change.new_nodes,
+            explanations: change.explanations,
+            ..self.subnet.clone()
+        };
+        self.comment = change.comment;
+        self.run_log.extend(change.run_log);
+    }
+
+    fn motivation(&self) -> String {
+        let mut motivations = vec![];
+        if self.unhealthy_replaced > 0 {
+            let replace_target = if self.unhealthy_replaced == 1 { "node" } else { "nodes" };
+            motivations.push(format!("replacing {} unhealthy {}", self.unhealthy_replaced, replace_target));
+        }
+        if self.optimized > 0 {
+            let replace_target = if self.optimized == 1 { "node" } else { "nodes" };
+            motivations.push(format!(
+                "replacing {} {} to improve subnet decentralization",
+                self.optimized, replace_target
+            ));
+        }
+        motivations.join("; ")
+    }
+}
+
+impl NetworkRebalancePlanner {
+    pub fn new(subnets: Vec<NetworkHealSubnets>) -> Self {
+        Self {
+            subnets,
+            max_swaps: None,
+            max_swaps_per_subnet: None,
+            max_swaps_by_subnet: BTreeMap::new(),
+        }
+    }
+
+    /// Limit the total number of node swaps in the plan
+    pub fn with_max_swaps(self, max_swaps: Option<usize>) -> Self {
+        Self { max_swaps, ..self }
+    }
+
+    /// Limit the number of node swaps for every subnet
+    pub fn with_max_swaps_per_subnet(self, max_swaps_per_subnet: Option<usize>) -> Self {
+        Self {
+            max_swaps_per_subnet,
+            ..self
+        }
+    }
+
+    /// Limit the number of node swaps for specific subnets, overriding the
+    /// limit for every subnet
+    pub fn with_max_swaps_by_subnet(self, max_swaps_by_subnet: BTreeMap<PrincipalId, usize>) -> Self {
+        Self { max_swaps_by_subnet, ..self }
+    }
+
+    fn max_swaps_for_subnet(&self, subnet_id: &PrincipalId) -> usize {
+        self.max_swaps_by_subnet
+            .get(subnet_id)
+            .copied()
+            .or(self.max_swaps_per_subnet)
+            .unwrap_or(usize::MAX)
+    }
+
+    pub fn plan(&self, mut available_nodes: Vec<Node>) -> Result<NetworkRebalancePlan, NetworkError> {
+        let mut budget = self.max_swaps.unwrap_or(usize::MAX);
+        let mut change_index = 0;
+        let mut subnets = self
+            .subnets
+            .iter()
+            .sorted_by(|a, b| a.cmp(b).reverse())
+            .map(|s| PlannedSubnet {
+                name: s.name.clone(),
+                initial_nodes: s.decentralized_subnet.nodes.clone(),
+                subnet: s.decentralized_subnet.clone(),
+                unhealthy_nodes: s.unhealthy_nodes.clone(),
+                unhealthy_replaced: 0,
+                optimized: 0,
+                cannot_improve: false,
+                first_change: None,
+                comment: None,
+                run_log: vec![],
+            })
+            .collect_vec();
+        let min_nakamoto_coefficients_before = network_min_nakamoto_coefficients(subnets.iter().map(|s| &s.subnet));
+
+        // Replacing unhealthy nodes takes precedence over improving the decentralization
+        for planned in subnets.iter_mut() {
+            let max_replaceable = self.max_swaps_for_subnet(&planned.subnet.id).min(budget);
+            let unhealthy_nodes = planned.unhealthy_nodes.iter().take(max_replaceable).cloned().collect_vec();
+            if unhealthy_nodes.is_empty() {
+                continue;
+            }
+            if unhealthy_nodes.len() < planned.unhealthy_nodes.len() {
+                warn!(
+                    "Subnet {}: planning the replacement of {} of {} unhealthy nodes",
+                    planned.subnet.id,
+                    unhealthy_nodes.len(),
+                    planned.unhealthy_nodes.len()
+                );
+            }
+            let change = SubnetChangeRequest::new(planned.subnet.clone(), available_nodes.clone(), vec![], vec![], vec![], None)
+                .optimize(0, &unhealthy_nodes)?;
+            available_nodes.retain(|n| !change.added().contains(n));
+            budget -= change.removed().len();
+            planned.unhealthy_replaced += change.removed().len();
+            planned.apply(change, change_index);
+            change_index += 1;
+        }
+
+        // Spend the remaining budget one swap at a time on the currently least
+        // decentralized subnet
+        while budget > 0 {
+            let max_swaps: Vec<usize> = subnets.iter().map(|s| self.max_swaps_for_subnet(&s.subnet.id)).collect();
+            let Some(planned) = subnets
+                .iter_mut()
+                .zip(max_swaps)
+                .filter(|(s, max_swaps)| !s.cannot_improve && s.swaps() < *max_swaps && s.subnet.nodes.len() > 1)
+                .map(|(s, _)| s)
+                .min_by(|a, b| a.subnet.nakamoto_score().cmp(&b.subnet.nakamoto_score()))
+            else {
+                break;
+            };
+
+            let score_before = planned.subnet.nakamoto_score();
+            let penalty_before = planned.subnet.check_business_rules().map(|(penalty, _)| penalty).unwrap_or(usize::MAX);
+            match SubnetChangeRequest::new(planned.subnet.clone(), available_nodes.clone(), vec![], vec![], vec![], None).optimize(1, &vec![]) {
+                Ok(change) => {
+                    let after = change.after();
+                    let penalty_after = after.check_business_rules().map(|(penalty, _)| penalty).unwrap_or(usize::MAX);
+                    if change.added().is_empty() || after.nakamoto_score() <= score_before || penalty_after > penalty_before {
+                        info!("Subnet {}: no node swap improves the decentralization", planned.subnet.id);
+                        planned.cannot_improve = true;
+                        continue;
+                    }
+                    available_nodes.retain(|n| !change.added().contains(n));
+                    budget -= change.removed().len();
+                    planned.optimized += change.removed().len();
+                    planned.apply(change, change_index);
+                    change_index += 1;
+                }
+                Err(e) => {
+                    warn!("Subnet {}: failed to plan a node swap: {}", planned.subnet.id, e);
+                    planned.cannot_improve = true;
+                }
+            }
+        }
+
+        let min_nakamoto_coefficients_after = network_min_nakamoto_coefficients(subnets.iter().map(|s| &s.subnet));
+        let proposals = subnets
+            .iter()
+            .filter(|s| s.first_change.is_some())
+            .sorted_by_key(|s| s.first_change)
+            .map(|s| {
+                let change = SubnetChange {
+                    id: s.subnet.id,
+                    old_nodes: s.initial_nodes.clone(),
+                    new_nodes: s.subnet.nodes.clone(),
+                    min_nakamoto_coefficients: s.subnet.min_nakamoto_coefficients.clone(),
+                    comment: s.comment.clone(),
+                    run_log: s.run_log.clone(),
+                    explanations: s.subnet.explanations.clone(),
+                    optimization: None,
+                };
+                RebalanceProposal {
+                    subnet_name: s.name.clone(),
+                    change: SubnetChangeResponse::from(&change).with_motivation(s.motivation()),
+                }
+            })
+            .collect_vec();
+
+        Ok(NetworkRebalancePlan {
+            swaps: subnets.iter().map(|s| s.swaps()).sum(),
+            proposals,
+            min_nakamoto_coefficients_before,
+            min_nakamoto_coefficients_after,
+        })
+    }
+}
+
+/// The lowest Nakamoto coefficient of any subnet, for every feature
+fn network_min_nakamoto_coefficients<'a>(subnets: impl Iterator<Item = &'a DecentralizedSubnet>) -> BTreeMap<NodeFeature, f64> {
+    subnets
+        .map(|s| NakamotoScore::new_from_nodes(&s.nodes))
+        .fold(BTreeMap::new(), |mut acc, score| {
+            for (feature, coefficient) in score.scores_individual() {
+                let min = acc.entry(feature).or_insert(coefficient);
+                *min = min.min(coefficient);
+            }
+            acc
+        })
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RebalanceProposal {
+    pub subnet_name: String,
+    pub change: SubnetChangeResponse,
+}
+
+/// Ordered list of subnet membership change proposals. Each subnet appears at
+/// most once, since a subnet can only have one open membership change
+/// proposal at a time.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NetworkRebalancePlan {
+    pub proposals: Vec<RebalanceProposal>,
+    /// Total number of node swaps in the plan
+    pub swaps: usize,
+    pub min_nakamoto_coefficients_before: BTreeMap<NodeFeature, f64>,
+    pub min_nakamoto_coefficients_after: BTreeMap<NodeFeature, f64>,
+}
+
+impl NetworkRebalancePlan {
+    pub fn min_nakamoto_coefficient_before(&self) -> f64 {
+        self.min_nakamoto_coefficients_before.values().copied().fold(f64::INFINITY, f64::min)
+    }
+
+    pub fn min_nakamoto_coefficient_after(&self) -> f64 {
+        self.min_nakamoto_coefficients_after.values().copied().fold(f64::INFINITY, f64::min)
+    }
+}
+
+impl Display for NetworkRebalancePlan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}\n",
+            format!(
+                "Network rebalancing plan with {} proposals and {} node swaps",
+                self.proposals.len(),
+                self.swaps
+            )
+            .bold()
+        )?;
+        writeln!(f, "Network-wide minimum Nakamoto coefficients:")?;
+        for (feature, before) in &self.min_nakamoto_coefficients_before {
+            let after = self.min_nakamoto_coefficients_after.get(feature).copied().unwrap_or_default();
+            let output = format!("{}: {:.2} -> {:.2}", feature, before, after);
+            let output = if after > *before {
+                output.bright_green()
+            } else if after < *before {
+                output.bright_red()
+            } else {
+                output.dimmed()
+            };
+            writeln!(f, "{: >40}", output)?;
+        }
+        writeln!(
+            f,
+            "\n{}\n",
+            format!(
+                "\tMinimum: {:.2} -> {:.2}",
+                self.min_nakamoto_coefficient_before(),
+                self.min_nakamoto_coefficient_after()
+            )
+            .bold()
+        )?;
+        for (i, proposal) in self.proposals.iter().enumerate() {
+            writeln!(
+                f,
+                "{}",
+                format!(
+                    "Proposal {}/{}: subnet {} ({}): {}",
+                    i + 1,
+                    self.proposals.len(),
+                    proposal.change.subnet_id.unwrap_or_default(),
+                    proposal.subnet_name,
+                    proposal.change.motivation.clone().unwrap_or_default()
+                )
+                .bold()
+            )?;
+            writeln!(f, "{}", proposal.change)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::new_test_node;
+
+    fn new_test_subnet(subnet_number: u64, providers: &[&str], unhealthy: usize) -> NetworkHealSubnets {
+        let nodes = providers
+            .iter()
+            .enumerate()
+            .map(|(i, provider)| new_test_node(subnet_number * 100 + i as u64, provider, &format!("C{}", i), i == 0))
+            .collect_vec();
+        NetworkHealSubnets {
+            name: format!("subnet {}", subnet_number),
+            unhealthy_nodes: nodes.iter().rev().take(unhealthy).cloned().collect(),
+            decentralized_subnet: DecentralizedSubnet {
+                id: PrincipalId::new_subnet_test_id(subnet_number),
+                nodes,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn new_test_available_nodes() -> Vec<Node> {
+        (1000..1020)
+            .map(|i| new_test_node(i, &format!("spare NP{}", i), &format!("spare C{}", i), false))
+            .collect()
+    }
+
+    #[test]
+    fn plan_heals_and_rebalances() {
+        let subnets = vec![
+            new_test_subnet(1, &["NP0", "NP1", "NP2", "NP3", "NP4", "NP5", "NP6"], 1),
+            new_test_subnet(2, &["NP0", "NP1", "NP1", "NP1", "NP2", "NP2", "NP2"], 0),
+            new_test_subnet(3, &["NP0", "NP1", "NP2", "NP3", "NP4", "NP5", "NP6"], 0),
+        ];
+        let plan = NetworkRebalancePlanner::new(subnets).plan(new_test_available_nodes()).unwrap();
+
+        // The unhealthy node is replaced first
+        let first = &plan.proposals.first().unwrap().change;
+        assert_eq!(first.subnet_id, Some(PrincipalId::new_subnet_test_id(1)));
+        assert_eq!(first.removed, vec![PrincipalId::new_node_test_id(106)]);
+        // Subnet 2 is the least decentralized one and gets rebalanced
+        let second = &plan.proposals[1].change;
+        assert_eq!(second.subnet_id, Some(PrincipalId::new_subnet_test_id(2)));
+        assert!(!second.removed.is_empty());
+        assert!(second.score_after > second.score_before);
+        assert!(plan.min_nakamoto_coefficient_after() > plan.min_nakamoto_coefficient_before());
+        assert_eq!(plan.swaps, plan.proposals.iter().map(|p| p.change.removed.len()).sum::<usize>());
+        // No node is added to more than one subnet
+        let added = plan.proposals.iter().flat_map(|p| p.change.added.clone()).collect_vec();
+        assert_eq!(added.len(), added.iter().unique().count());
+    }
+
+    #[test]
+    fn plan_respects_swap_budget_and_caps() {
+        let subnets = vec![
+            new_test_subnet(1, &["NP0", "NP1", "NP2", "NP3", "NP4", "NP5", "NP6"], 3),
+            new_test_subnet(2, &["NP0", "NP1", "NP1", "NP1", "NP2", "NP2", "NP2"], 0),
+        ];
+
+        let plan = NetworkRebalancePlanner::new(subnets.clone())
+            .with_max_swaps(Some(2))
+            .plan(new_test_available_nodes())
+            .unwrap();
+        assert_eq!(plan.swaps, 2);
+        assert_eq!(plan.proposals.len(), 1);
+        assert_eq!(plan.proposals[0].change.removed.len(), 2);
+
+        let plan = NetworkRebalancePlanner::new(subnets.clone())
+            .with_max_swaps_per_subnet(Some(1))
+            .plan(new_test_available_nodes())
+            .unwrap();
+        assert!(plan.proposals.iter().all(|p| p.change.removed.len() == 1));
+
+        let plan = NetworkRebalancePlanner::new(subnets)
+            .with_max_swaps_per_subnet(Some(1))
+            .with_max_swaps_by_subnet(BTreeMap::from([(PrincipalId::new_subnet_test_id(2), 0)]))
+            .plan(new_test_available_nodes())
+            .unwrap();
+        assert_eq!(plan.proposals.len(), 1);
+        assert_eq!(plan.proposals[0].change.subnet_id, Some(PrincipalId::new_subnet_test_id(1)));
+    }
+}
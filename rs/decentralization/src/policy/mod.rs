@@ -0,0 +1,571 @@
+use crate::nakamoto::NakamotoScore;
+use crate::network::Node;
+use ic_base_types::PrincipalId;
+use ic_management_types::{FeatureGroup, MinNakamotoCoefficients, NodeFeature};
+use itertools::Itertools;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// The policy that is compiled into the binary and used unless a custom one is
+/// activated with [BusinessRulesPolicy::activate].
+const DEFAULT_POLICY: &str = include_str!("default_policy.yaml");
+
+static ACTIVE_POLICY: RwLock<Option<Arc<BusinessRulesPolicy>>> = RwLock::new(None);
+
+/// The business rules that the decentralization engine knows how to evaluate.
+/// Each rule has a penalty weight that can be overridden in the policy file.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, strum_macros::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum BusinessRule {
+    /// The subnet should have exactly the required number of DFINITY-owned nodes
+    DfinityOwnedNodes,
+    /// All nodes in the subnet should be decentralized
+    NonDecentralizedNodes,
+    /// No single feature value should control more than the allowed share of nodes
+    MaxShare,
+    /// Nodes should only have the allowed values for a feature
+    AllowedValues,
+    /// A single node provider should not be able to halt the subnet
+    NodeProviderHalt,
+    /// Nakamoto coefficients should not drop below the required minimum
+    MinNakamotoCoefficient,
+    /// A single feature value should not control more than 2/3 of the nodes
+    DominantFeature,
+}
+
+impl BusinessRule {
+    /// Penalty weight used if the policy does not specify one
+    pub fn default_penalty(&self) -> usize {
+        match self {
+            BusinessRule::DfinityOwnedNodes => 1000,
+            BusinessRule::NonDecentralizedNodes => 100,
+            BusinessRule::MaxShare => 1000,
+            BusinessRule::AllowedValues => 1000,
+            BusinessRule::NodeProviderHalt => 10000,
+            BusinessRule::MinNakamotoCoefficient => 100,
+            BusinessRule::DominantFeature => 1000,
+        }
+    }
+}
+
+/// A business rule that a set of subnet nodes does not satisfy.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RuleViolation {
+    pub rule: BusinessRule,
+    pub penalty: usize,
+    pub message: String,
+}
+
+/// A share of the subnet nodes, written as "numerator/denominator" in the
+/// policy file, e.g. "1/3".
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Fraction {
+    numerator: usize,
+    denominator: usize,
+}
+
+impl Fraction {
+    pub fn new(numerator: usize, denominator: usize) -> anyhow::Result<Self> {
+        if denominator == 0 {
+            return Err(anyhow::anyhow!(
+                "Denominator of the fraction {}/{} must not be zero",
+                numerator,
+                denominator
+            ));
+        }
+        Ok(Self { numerator, denominator })
+    }
+
+    /// The number of nodes this share amounts to, rounded down, for a subnet of
+    /// the given size
+    pub fn of(&self, total: usize) -> usize {
+        total * self.numerator / self.denominator
+    }
+}
+
+impl FromStr for Fraction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (numerator, denominator) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow::anyhow!("Fraction '{}' must be in the form 'numerator/denominator'", s))?;
+        Self::new(numerator.trim().parse()?, denominator.trim().parse()?)
+    }
+}
+
+impl TryFrom<String> for Fraction {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::from_str(&value)
+    }
+}
+
+impl From<Fraction> for String {
+    fn from(value: Fraction) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for Fraction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// Constraints for the nodes of a subnet. Every rule is optional so that
+/// subnet-specific policies can be layered on top of the default one.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SubnetPolicy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dfinity_owned_nodes: Option<usize>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub allowed_values: BTreeMap<NodeFeature, Vec<String>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub max_share: BTreeMap<NodeFeature, Fraction>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub min_nakamoto_coefficients: BTreeMap<NodeFeature, f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_nakamoto_average: Option<f64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub min_joint_nakamoto_coefficients: BTreeMap<FeatureGroup, f64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub penalties: BTreeMap<BusinessRule, usize>,
+}
+
+impl SubnetPolicy {
+    /// Return a new policy with the rules of `other` layered on top of the rules
+    /// of this policy.
+    pub fn overlay(&self, other: &SubnetPolicy) -> SubnetPolicy {
+        SubnetPolicy {
+            name: other.name.clone().or_else(|| self.name.clone()),
+            dfinity_owned_nodes: other.dfinity_owned_nodes.or(self.dfinity_owned_nodes),
+            allowed_values: self.allowed_values.clone().into_iter().chain(other.allowed_values.clone()).collect(),
+            max_share: self.max_share.clone().into_iter().chain(other.max_share.clone()).collect(),
+            min_nakamoto_coefficients: self
+                .min_nakamoto_coefficients
+                .clone()
+                .into_iter()
+                .chain(other.min_nakamoto_coefficients.clone())
+                .collect(),
+            min_nakamoto_average: other.min_nakamoto_average.or(self.min_nakamoto_average),
+            min_joint_nakamoto_coefficients: self
+                .min_joint_nakamoto_coefficients
+                .clone()
+                .into_iter()
+                .chain(other.min_joint_nakamoto_coefficients.clone())
+                .collect(),
+            penalties: self.penalties.clone().into_iter().chain(other.penalties.clone()).collect(),
+        }
+    }
+
+    /// Penalty weight for violating the given rule
+    pub fn penalty(&self, rule: &BusinessRule) -> usize {
+        self.penalties.get(rule).copied().unwrap_or_else(|| rule.default_penalty())
+    }
+
+    /// Combine the minimum Nakamoto coefficients required by the policy with
+    /// the ones from the request, keeping the stricter value for each feature.
+    pub fn effective_min_nakamoto_coefficients(&self, requested: &Option<MinNakamotoCoefficients>) -> Option<MinNakamotoCoefficients> {
+        if self.min_nakamoto_coefficients.is_empty() && self.min_nakamoto_average.is_none() && self.min_joint_nakamoto_coefficients.is_empty() {
+            return requested.clone();
+        }
+        let mut result = requested.clone().unwrap_or_default();
+        for (feature, min_coeff) in &self.min_nakamoto_coefficients {
+            let coeff = result.coefficients.entry(feature.clone()).or_insert(*min_coeff);
+            *coeff = coeff.max(*min_coeff);
+        }
+        for (group, min_coeff) in &self.min_joint_nakamoto_coefficients {
+            let coeff = result.joint_coefficients.entry(group.clone()).or_insert(*min_coeff);
+            *coeff = coeff.max(*min_coeff);
+        }
+        if let Some(average) = self.min_nakamoto_average {
+            result.average = result.average.max(average);
+        }
+        Some(result)
+    }
+
+    /// Evaluate the rules of this policy against the provided subnet nodes.
+    /// Returns the total penalty and a human-readable description of each
+    /// violated rule.
+    pub fn check(&self, nodes: &[Node], min_nakamoto_coefficients: &Option<MinNakamotoCoefficients>) -> anyhow::Result<(usize, Vec<String>)> {
+        let violations = self.violations(nodes, min_nakamoto_coefficients)?;
+        Ok((
+            Self::total_penalty(nodes, &violations),
+            violations.into_iter().map(|v| v.message).collect(),
+        ))
+    }
+
+    /// Total penalty of the violated rules. A subnet with a single node is
+    /// always penalized.
+    pub fn total_penalty(nodes: &[Node], violations: &[RuleViolation]) -> usize {
+        if nodes.len() <= 1 {
+            return 1;
+        }
+        violations.iter().map(|v| v.penalty).sum()
+    }
+
+    /// Evaluate the rules of this policy against the provided subnet nodes and
+    /// return every violated rule together with the penalty it adds.
+    pub fn violations(&self, nodes: &[Node], min_nakamoto_coefficients: &Option<MinNakamotoCoefficients>) -> anyhow::Result<Vec<RuleViolation>> {
+        let mut violations = Vec::new();
+        if nodes.len() <= 1 {
+            return Ok(violations);
+        }
+
+        let nakamoto_scores = NakamotoScore::new_from_nodes(nodes);
+
+        if let Some(target_dfinity_owned_nodes_count) = self.dfinity_owned_nodes {
+            let dfinity_owned_nodes_count: usize = nodes.iter().map(|n| n.dfinity_owned as usize).sum();
+            if dfinity_owned_nodes_count != target_dfinity_owned_nodes_count {
+                violations.push(RuleViolation {
+                    rule: BusinessRule::DfinityOwnedNodes,
+                    penalty: target_dfinity_owned_nodes_count.abs_diff(dfinity_owned_nodes_count) * self.penalty(&BusinessRule::DfinityOwnedNodes),
+                    message: format!(
+                        "Subnet should have {} DFINITY-owned nodes, got {}",
+                        target_dfinity_owned_nodes_count, dfinity_owned_nodes_count
+                    ),
+                });
+            }
+        }
+
+        let count_non_decentralized_nodes = nodes.iter().filter(|n| !n.decentralized).count();
+        if count_non_decentralized_nodes > 0 {
+            violations.push(RuleViolation {
+                rule: BusinessRule::NonDecentralizedNodes,
+                penalty: count_non_decentralized_nodes * self.penalty(&BusinessRule::NonDecentralizedNodes),
+                message: format!("Subnet has {} non-decentralized node(s)", count_non_decentralized_nodes),
+            });
+        }
+
+        for (feature, max_share) in &self.max_share {
+            match nakamoto_scores.feature_value_counts_max(feature) {
+                Some((dominant_value, dominant_nodes_count)) => {
+                    let controlled_nodes_max = max_share.of(nodes.len());
+                    if dominant_nodes_count > controlled_nodes_max {
+                        violations.push(RuleViolation {
+                            rule: BusinessRule::MaxShare,
+                            penalty: (dominant_nodes_count - controlled_nodes_max) * self.penalty(&BusinessRule::MaxShare),
+                            message: format!(
+                                "{} '{}' controls {} of nodes, which is > {} ({} - 1) of subnet nodes",
+                                feature_title(feature),
+                                dominant_value,
+                                dominant_nodes_count,
+                                controlled_nodes_max,
+                                max_share
+                            ),
+                        });
+                    }
+                }
+                None => return Err(anyhow::anyhow!("Incomplete data for {}", feature)),
+            }
+        }
+
+        for (feature, allowed_values) in &self.allowed_values {
+            let disallowed_nodes_count = nodes.iter().filter(|n| !allowed_values.contains(&n.get_feature(feature))).count();
+            if disallowed_nodes_count > 0 {
+                violations.push(RuleViolation {
+                    rule: BusinessRule::AllowedValues,
+                    penalty: disallowed_nodes_count * self.penalty(&BusinessRule::AllowedValues),
+                    // e.g. "European subnet has 1 node(s) with continent not in [Europe]"
+                    message: format!(
+                        "{} has {} node(s) with {} not in [{}]",
+                        self.name.as_ref().map_or_else(|| "Subnet".to_string(), |name| format!("{} subnet", name)),
+                        disallowed_nodes_count,
+                        feature,
+                        allowed_values.join(", ")
+                    ),
+                });
+            }
+        }
+
+        match nakamoto_scores.score_feature(&NodeFeature::NodeProvider) {
+            Some(score) => {
+                if score <= 1.0 && nodes.len() > 3 {
+                    // We restrict to subnets with >3 nodes to be able to build subnet from scratch
+                    violations.push(RuleViolation {
+                        rule: BusinessRule::NodeProviderHalt,
+                        penalty: self.penalty(&BusinessRule::NodeProviderHalt),
+                        message: "A single Node Provider can halt the subnet".to_string(),
+                    });
+                }
+            }
+            None => return Err(anyhow::anyhow!("Missing the Nakamoto score for the Node Provider")),
+        }
+
+        if let Some(min_nakamoto_coefficients) = self.effective_min_nakamoto_coefficients(min_nakamoto_coefficients) {
+            let penalty = self.penalty(&BusinessRule::MinNakamotoCoefficient) as f64;
+            for (feature, min_coeff) in min_nakamoto_coefficients.coefficients.iter() {
+                match nakamoto_scores.score_feature(feature) {
+                    Some(score) => {
+                        if score < *min_coeff {
+                            violations.push(RuleViolation {
+                                rule: BusinessRule::MinNakamotoCoefficient,
+                                penalty: ((*min_coeff - score) * penalty) as usize,
+                                message: format!(
+                                    "Lower than expected Nakamoto Coefficient {} < {} for feature {}",
+                                    score, min_coeff, feature
+                                ),
+                            });
+                        }
+                    }
+                    None => return Err(anyhow::anyhow!("NodeFeature '{}' not found", feature)),
+                }
+            }
+            for (group, min_coeff) in min_nakamoto_coefficients.joint_coefficients.iter() {
+                let score = NakamotoScore::joint_nakamoto_from_nodes(nodes, group).0 as f64;
+                if score < *min_coeff {
+                    violations.push(RuleViolation {
+                        rule: BusinessRule::MinNakamotoCoefficient,
+                        penalty: ((*min_coeff - score) * penalty) as usize,
+                        message: format!(
+                            "Lower than expected joint Nakamoto Coefficient {} < {} for features {}",
+                            score, min_coeff, group
+                        ),
+                    });
+                }
+            }
+            if nakamoto_scores.score_avg_linear() < min_nakamoto_coefficients.average {
+                violations.push(RuleViolation {
+                    rule: BusinessRule::MinNakamotoCoefficient,
+                    penalty: ((min_nakamoto_coefficients.average - nakamoto_scores.score_avg_linear()) * penalty) as usize,
+                    message: format!(
+                        "Lower than expected average Nakamoto Coefficient {} < {}",
+                        nakamoto_scores.score_avg_linear(),
+                        min_nakamoto_coefficients.average
+                    ),
+                });
+            }
+        }
+
+        for feature in &NodeFeature::variants() {
+            // Features restricted to a set of allowed values are expected to be dominated
+            // by those values, e.g. a European subnet has all nodes in Europe
+            if self.allowed_values.contains_key(feature) {
+                continue;
+            }
+            match (nakamoto_scores.score_feature(feature), nakamoto_scores.controlled_nodes(feature)) {
+                (Some(score), Some(controlled_nodes)) => {
+                    if score == 1.0 && controlled_nodes > nodes.len() * 2 / 3 {
+                        violations.push(RuleViolation {
+                            rule: BusinessRule::DominantFeature,
+                            penalty: (controlled_nodes - nodes.len() * 2 / 3) * self.penalty(&BusinessRule::DominantFeature),
+                            message: format!(
+                                "NodeFeature '{}' controls {} of nodes, which is > {} (2/3 of all) nodes",
+                                feature,
+                                controlled_nodes,
+                                nodes.len() * 2 / 3
+                            ),
+                        });
+                    }
+                }
+                (score, controlled_nodes) => {
+                    debug!(
+                        "NodeFeature {} does not have valid score {:?} controlled_nodes {:?}",
+                        feature, &score, &controlled_nodes
+                    );
+                }
+            }
+        }
+
+        Ok(violations)
+    }
+}
+
+/// The feature name as it starts a sentence, e.g. "Country" or "Node provider"
+fn feature_title(feature: &NodeFeature) -> String {
+    let name = feature.to_string().replace('_', " ");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SubnetPolicyEntry {
+    pub subnet: PrincipalId,
+    #[serde(flatten)]
+    pub policy: SubnetPolicy,
+}
+
+/// Declarative set of business rules, evaluated by the decentralization engine
+/// whenever it picks nodes for a subnet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BusinessRulesPolicy {
+    /// Groups of features for which a joint Nakamoto coefficient is reported
+    /// in the subnet change responses of every subnet, see
+    /// [NakamotoScore::joint_scores]
+    pub joint_features: Vec<FeatureGroup>,
+    pub default: SubnetPolicy,
+    pub subnets: Vec<SubnetPolicyEntry>,
+}
+
+impl BusinessRulesPolicy {
+    /// The policy compiled into the binary
+    pub fn builtin() -> Self {
+        serde_yaml::from_str(DEFAULT_POLICY).expect("the built-in business rules policy must be valid")
+    }
+
+    /// Load a policy from a YAML (or JSON) file
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read policy file {}: {}", path.display(), e))?;
+        let policy: Self = serde_yaml::from_str(&contents).map_err(|e| anyhow::anyhow!("Failed to parse policy file {}: {}", path.display(), e))?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(duplicate) = self.subnets.iter().map(|e| e.subnet).duplicates().next() {
+            return Err(anyhow::anyhow!("Subnet {} is listed more than once in the policy", duplicate));
+        }
+        Ok(())
+    }
+
+    /// The effective policy for a subnet: the subnet-specific rules (if any)
+    /// layered on top of the default rules
+    pub fn for_subnet(&self, subnet_id: &PrincipalId) -> SubnetPolicy {
+        match self.subnets.iter().find(|e| &e.subnet == subnet_id) {
+            Some(entry) => self.default.overlay(&entry.policy),
+            None => self.default.clone(),
+        }
+    }
+
+    /// Make this policy the one used by the decentralization engine in this
+    /// process
+    pub fn activate(self) {
+        info!("Activating business rules policy with {} subnet-specific entries", self.subnets.len());
+        *ACTIVE_POLICY.write().expect("business rules policy lock poisoned") = Some(Arc::new(self));
+    }
+
+    /// The policy currently used by the decentralization engine
+    pub fn active() -> Arc<BusinessRulesPolicy> {
+        if let Some(policy) = ACTIVE_POLICY.read().expect("business rules policy lock poisoned").as_ref() {
+            return policy.clone();
+        }
+        ACTIVE_POLICY
+            .write()
+            .expect("business rules policy lock poisoned")
+            .get_or_insert_with(|| Arc::new(Self::builtin()))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nakamoto::NodeFeatures;
+
+    fn new_test_nodes(continents: &[&str]) -> Vec<Node> {
+        continents
+            .iter()
+            .enumerate()
+            .map(|(i, continent)| {
+                let features = NodeFeatures::from_iter(NodeFeature::variants().into_iter().map(|f| {
+                    (
+                        f.clone(),
+                        if f == NodeFeature::Continent {
+                            continent.to_string()
+                        } else {
+                            format!("{} {}", f, i)
+                        },
+                    )
+                }));
+                Node::new_test_node(i as u64, features, i == 0, true)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn builtin_policy_is_valid() {
+        let policy = BusinessRulesPolicy::builtin();
+        policy.validate().unwrap();
+        let nns = PrincipalId::from_str("tdb26-jop6k-aogll-7ltgs-eruif-6kk7m-qpktf-gdiqx-mxtrf-vb5e6-eqe").unwrap();
+        let nns_policy = policy.for_subnet(&nns);
+        assert_eq!(nns_policy.dfinity_owned_nodes, Some(3));
+        assert_eq!(nns_policy.max_share.get(&NodeFeature::Country), Some(&Fraction::new(1, 3).unwrap()));
+        assert_eq!(nns_policy.penalty(&BusinessRule::NodeProviderHalt), 10000);
+        assert_eq!(policy.for_subnet(&PrincipalId::new_subnet_test_id(0)), policy.default);
+    }
+
+    #[test]
+    fn policy_with_unknown_feature_is_invalid() {
+        // Custom features have to be activated before the policy that uses them
+        assert!(serde_yaml::from_str::<BusinessRulesPolicy>("default:\n  max_share:\n    legal_jurisdiction: 1/3").is_err());
+    }
+
+    #[test]
+    fn fraction_parsing() {
+        assert_eq!(Fraction::from_str("1/3").unwrap().of(13), 4);
+        assert_eq!(Fraction::from_str(" 2 / 3 ").unwrap().of(13), 8);
+        assert!(Fraction::from_str("1/0").is_err());
+        assert!(Fraction::from_str("0.33").is_err());
+    }
+
+    #[test]
+    fn subnet_policy_from_yaml() {
+        let policy: BusinessRulesPolicy = serde_yaml::from_str(
+            r#"
+default:
+  dfinity_owned_nodes: 1
+  penalties:
+    allowed_values: 500
+subnets:
+  - subnet: bkfrj-6k62g-dycql-7h53p-atvkj-zg4to-gaogh-netha-ptybj-ntsgw-rqe
+    allowed_values:
+      continent: [Europe, Asia]
+    min_nakamoto_coefficients:
+      node_provider: 5
+"#,
+        )
+        .unwrap();
+        let subnet_policy = policy.for_subnet(&PrincipalId::from_str("bkfrj-6k62g-dycql-7h53p-atvkj-zg4to-gaogh-netha-ptybj-ntsgw-rqe").unwrap());
+        assert_eq!(subnet_policy.dfinity_owned_nodes, Some(1));
+        assert_eq!(subnet_policy.penalty(&BusinessRule::AllowedValues), 500);
+        assert_eq!(subnet_policy.penalty(&BusinessRule::DfinityOwnedNodes), 1000);
+
+        let nodes = new_test_nodes(&["Europe", "Asia", "Europe", "America", "Europe", "Africa", "Europe"]);
+        let (penalty, checks) = subnet_policy.check(&nodes, &None).unwrap();
+        assert_eq!(
+            checks,
+            vec![
+                "Subnet has 2 node(s) with continent not in [Europe, Asia]".to_string(),
+                "Lower than expected Nakamoto Coefficient 3 < 5 for feature node_provider".to_string(),
+            ]
+        );
+        assert_eq!(penalty, 2 * 500 + 2 * 100);
+    }
+
+    #[test]
+    fn effective_min_nakamoto_coefficients_keeps_stricter() {
+        let subnet_policy = SubnetPolicy {
+            min_nakamoto_coefficients: BTreeMap::from([(NodeFeature::NodeProvider, 5.), (NodeFeature::Country, 2.)]),
+            ..Default::default()
+        };
+        let requested = Some(MinNakamotoCoefficients {
+            coefficients: BTreeMap::from([(NodeFeature::NodeProvider, 4.), (NodeFeature::Country, 3.)]),
+            average: 3.,
+            ..Default::default()
+        });
+        assert_eq!(
+            subnet_policy.effective_min_nakamoto_coefficients(&requested),
+            Some(MinNakamotoCoefficients {
+                coefficients: BTreeMap::from([(NodeFeature::NodeProvider, 5.), (NodeFeature::Country, 3.)]),
+                average: 3.,
+                ..Default::default()
+            })
+        );
+    }
+}
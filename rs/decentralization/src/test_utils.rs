@@ -0,0 +1,17 @@
+use crate::nakamoto::NodeFeatures;
+use crate::network::Node;
+use ic_management_types::NodeFeature;
+
+/// A decentralized test node of the given node provider and country, with a
+/// unique value for every other feature
+pub fn new_test_node(node_number: u64, provider: &str, country: &str, dfinity_owned: bool) -> Node {
+    let features = NodeFeatures::from_iter(NodeFeature::variants().into_iter().map(|f| {
+        let value = match f {
+            NodeFeature::NodeProvider => provider.to_string(),
+            NodeFeature::Country => country.to_string(),
+            _ => format!("{} {}", f, node_number),
+        };
+        (f, value)
+    }));
+    Node::new_test_node(node_number, features, dfinity_owned, true)
+}
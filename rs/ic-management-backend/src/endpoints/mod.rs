@@ -3,10 +3,14 @@ pub mod network;
 pub mod nodes_ops;
 pub mod query_decentralization;
 pub mod release;
+pub mod simulation;
 pub mod subnet;
 
 use crate::health::HealthStatusQuerier;
-use crate::{health, prometheus, proposal, registry, registry::RegistryState, release::list_subnets_release_statuses, release::RolloutBuilder};
+use crate::{
+    health, prometheus, proposal, registry, registry::RegistryState, release::list_subnets_release_statuses, release::RolloutBuilder,
+    simulation::ScenarioStore,
+};
 use actix_web::dev::Service;
 use actix_web::{get, post, web, App, Error, HttpResponse, HttpServer, Responder, Result};
 use decentralization::network::AvailableNodesQuerier;
@@ -41,6 +45,7 @@ pub async fn run_backend(
     }
 
     let num_workers = if run_from_cli { 1 } else { 8 };
+    let scenario_store = ScenarioStore::for_network(target_network);
 
     let closure_target_network = target_network.clone();
     let mut srv = HttpServer::new(move || {
@@ -50,6 +55,7 @@ pub async fn run_backend(
         let middleware_registry_state = registry_state.clone();
         App::new()
             .app_data(web::Data::new(registry_state.clone()))
+            .app_data(web::Data::new(scenario_store.clone()))
             .wrap_fn(move |req, srv| {
                 let fut = srv.call(req);
                 let registry_state = middleware_registry_state.clone();
@@ -88,9 +94,17 @@ pub async fn run_backend(
             .service(self::subnet::resize)
             .service(self::subnet::change_preview)
             .service(self::network::heal)
+            .service(self::network::heal_plan)
+            .service(self::network::outage)
             .service(self::nodes_ops::remove)
             .service(self::query_decentralization::decentralization_subnet_query)
             .service(self::query_decentralization::decentralization_whatif_query)
+            .service(self::simulation::run)
+            .service(self::simulation::list_scenarios)
+            .service(self::simulation::get_scenario)
+            .service(self::simulation::save_scenario)
+            .service(self::simulation::delete_scenario)
+            .service(self::simulation::run_scenario)
             .service(self::release::releases_list_all)
             .service(self::release::retireable)
             .service(self::release::blessed)
@@ -1,12 +1,13 @@
 use super::*;
 use crate::subnets;
 use decentralization::network::{DecentralizedSubnet, NetworkHealRequest, NetworkHealSubnets, Node};
-use ic_management_types::{requests::HealRequest, NetworkError};
+use decentralization::outage::OutageAnalysis;
+use decentralization::planner::NetworkRebalancePlanner;
+use ic_management_types::requests::{HealPlanRequest, HealRequest, OutageAnalysisRequest};
 use itertools::Itertools;
 
-#[post("/network/heal")]
-pub(crate) async fn heal(request: web::Json<HealRequest>, registry: web::Data<Arc<RwLock<RegistryState>>>) -> Result<HttpResponse, Error> {
-    let registry = registry.read().await;
+/// Every subnet together with its unhealthy nodes, if any
+async fn subnets_with_unhealthy_nodes(registry: &RegistryState) -> Result<Vec<NetworkHealSubnets>, Error> {
     let health_client = health::HealthClient::new(registry.network());
     let nodes_health = health_client
         .nodes()
@@ -15,18 +16,26 @@ pub(crate) async fn heal(request: web::Json<HealRequest>, registry: web::Data<Ar
     let subnets: BTreeMap<PrincipalId, ic_management_types::Subnet> = registry.subnets();
     let unhealthy_subnets: BTreeMap<PrincipalId, Vec<ic_management_types::Node>> = subnets::unhealthy_with_nodes(&subnets, nodes_health).await;
 
-    let subnets_to_heal = unhealthy_subnets
-        .iter()
-        .flat_map(|(id, unhealthy_nodes)| {
-            let unhealthy_nodes = unhealthy_nodes.iter().map(Node::from).collect::<Vec<_>>();
-            let unhealthy_subnet = subnets.get(id).ok_or(NetworkError::SubnetNotFound(*id))?;
-
-            Ok::<NetworkHealSubnets, NetworkError>(NetworkHealSubnets {
-                name: unhealthy_subnet.metadata.name.clone(),
-                decentralized_subnet: DecentralizedSubnet::from(unhealthy_subnet),
-                unhealthy_nodes,
-            })
+    Ok(subnets
+        .values()
+        .map(|subnet| NetworkHealSubnets {
+            name: subnet.metadata.name.clone(),
+            decentralized_subnet: DecentralizedSubnet::from(subnet),
+            unhealthy_nodes: unhealthy_subnets
+                .get(&subnet.principal)
+                .map(|nodes| nodes.iter().map(Node::from).collect())
+                .unwrap_or_default(),
         })
+        .collect_vec())
+}
+
+#[post("/network/heal")]
+pub(crate) async fn heal(request: web::Json<HealRequest>, registry: web::Data<Arc<RwLock<RegistryState>>>) -> Result<HttpResponse, Error> {
+    let registry = registry.read().await;
+    let subnets_to_heal = subnets_with_unhealthy_nodes(&registry)
+        .await?
+        .into_iter()
+        .filter(|subnet| !subnet.unhealthy_nodes.is_empty())
         .collect_vec();
 
     let subnets_change_response =
@@ -34,3 +43,44 @@ pub(crate) async fn heal(request: web::Json<HealRequest>, registry: web::Data<Ar
 
     Ok(HttpResponse::Ok().json(decentralization::HealResponse { subnets_change_response }))
 }
+
+/// Plans node replacements across all subnets: unhealthy nodes are replaced
+/// first, and the rest of the swap budget is used to improve the least
+/// decentralized subnets.
+#[post("/network/heal/plan")]
+pub(crate) async fn heal_plan(request: web::Json<HealPlanRequest>, registry: web::Data<Arc<RwLock<RegistryState>>>) -> Result<HttpResponse, Error> {
+    let registry = registry.read().await;
+    let all_subnets = subnets_with_unhealthy_nodes(&registry).await?;
+
+    let plan = NetworkRebalancePlanner::new(all_subnets)
+        .with_max_swaps(request.max_swaps)
+        .with_max_swaps_per_subnet(request.max_swaps_per_subnet)
+        .with_max_swaps_by_subnet(request.max_swaps_by_subnet.clone())
+        .plan(registry.available_nodes().await?)?;
+
+    Ok(HttpResponse::Ok().json(plan))
+}
+
+/// Ranks the single points of failure of the network: for every value of every
+/// node feature, which subnets would lose consensus if all nodes with that
+/// value went offline at once.
+#[post("/network/outage")]
+pub(crate) async fn outage(
+    request: web::Json<OutageAnalysisRequest>,
+    registry: web::Data<Arc<RwLock<RegistryState>>>,
+) -> Result<HttpResponse, Error> {
+    let subnets = registry
+        .read()
+        .await
+        .subnets()
+        .values()
+        .map(|subnet| (subnet.metadata.name.clone(), DecentralizedSubnet::from(subnet)))
+        .collect_vec();
+
+    let report = OutageAnalysis::new(subnets)
+        .with_features(request.features.clone())
+        .analyze()
+        .truncated(request.limit);
+
+    Ok(HttpResponse::Ok().json(report))
+}
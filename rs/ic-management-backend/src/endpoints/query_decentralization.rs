@@ -70,6 +70,7 @@ async fn get_decentralization_analysis(
                 min_nakamoto_coefficients: min_nakamoto_coefficients.clone(),
                 comment: None,
                 run_log: Vec::new(),
+                explanations: Vec::new(),
             },
             None => DecentralizedSubnet {
                 id: PrincipalId::new_subnet_test_id(0),
@@ -78,6 +79,7 @@ async fn get_decentralization_analysis(
                 min_nakamoto_coefficients: min_nakamoto_coefficients.clone(),
                 comment: None,
                 run_log: Vec::new(),
+                explanations: Vec::new(),
             },
         })
         .unwrap_or_else(|| DecentralizedSubnet {
@@ -87,6 +89,7 @@ async fn get_decentralization_analysis(
             min_nakamoto_coefficients: min_nakamoto_coefficients.clone(),
             comment: None,
             run_log: Vec::new(),
+            explanations: Vec::new(),
         });
 
     let nodes_to_remove = node_ids_to_remove.map(|node_ids_to_remove| {
@@ -119,6 +122,8 @@ async fn get_decentralization_analysis(
         min_nakamoto_coefficients: updated_subnet.min_nakamoto_coefficients.clone(),
         comment: updated_subnet.comment.clone(),
         run_log: updated_subnet.run_log.clone(),
+        explanations: updated_subnet.explanations.clone(),
+        optimization: None,
     };
 
     let response = DecentralizedSubnetResponse {
@@ -0,0 +1,95 @@
+use super::*;
+use crate::simulation::{Scenario, ScenarioStore, SimulatedTopology, SimulationOperation};
+use actix_web::{delete, put};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct SimulationRequest {
+    operations: Vec<SimulationOperation>,
+}
+
+#[derive(Deserialize)]
+struct ScenarioPath {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ScenarioRequest {
+    description: Option<String>,
+    operations: Vec<SimulationOperation>,
+}
+
+/// Apply a sequence of hypothetical operations to the current topology,
+/// without saving them
+#[post("/simulation/run")]
+pub(crate) async fn run(request: web::Json<SimulationRequest>, registry: web::Data<Arc<RwLock<RegistryState>>>) -> Result<HttpResponse, Error> {
+    let mut topology = SimulatedTopology::from_registry(registry.read().await.deref()).await?;
+    Ok(HttpResponse::Ok().json(topology.run(None, &request.operations)))
+}
+
+#[get("/simulation/scenarios")]
+pub(crate) async fn list_scenarios(store: web::Data<ScenarioStore>) -> Result<HttpResponse, Error> {
+    store
+        .list()
+        .map(|scenarios| HttpResponse::Ok().json(scenarios))
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+#[get("/simulation/scenarios/{name}")]
+pub(crate) async fn get_scenario(request: web::Path<ScenarioPath>, store: web::Data<ScenarioStore>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(find_scenario(&store, &request.name)?))
+}
+
+/// Save the scenario under the given name, replacing any scenario with the
+/// same name
+#[put("/simulation/scenarios/{name}")]
+pub(crate) async fn save_scenario(
+    request: web::Path<ScenarioPath>,
+    scenario: web::Json<ScenarioRequest>,
+    store: web::Data<ScenarioStore>,
+    registry: web::Data<Arc<RwLock<RegistryState>>>,
+) -> Result<HttpResponse, Error> {
+    let scenario = Scenario {
+        name: request.name.clone(),
+        description: scenario.description.clone(),
+        operations: scenario.operations.clone(),
+        registry_version: registry.read().await.version(),
+    };
+    store.save(&scenario).map_err(actix_web::error::ErrorBadRequest)?;
+    Ok(HttpResponse::Ok().json(scenario))
+}
+
+#[delete("/simulation/scenarios/{name}")]
+pub(crate) async fn delete_scenario(request: web::Path<ScenarioPath>, store: web::Data<ScenarioStore>) -> Result<HttpResponse, Error> {
+    match store.delete(&request.name).map_err(actix_web::error::ErrorBadRequest)? {
+        true => Ok(HttpResponse::Ok().finish()),
+        false => Err(actix_web::error::ErrorNotFound(format!("scenario {} not found", request.name))),
+    }
+}
+
+/// Replay a saved scenario against the current registry version
+#[post("/simulation/scenarios/{name}/run")]
+pub(crate) async fn run_scenario(
+    request: web::Path<ScenarioPath>,
+    store: web::Data<ScenarioStore>,
+    registry: web::Data<Arc<RwLock<RegistryState>>>,
+) -> Result<HttpResponse, Error> {
+    let scenario = find_scenario(&store, &request.name)?;
+    let mut topology = SimulatedTopology::from_registry(registry.read().await.deref()).await?;
+    if topology.registry_version() != scenario.registry_version {
+        info!(
+            "Replaying scenario {} saved at registry version {} against version {}",
+            scenario.name,
+            scenario.registry_version,
+            topology.registry_version()
+        );
+    }
+    Ok(HttpResponse::Ok().json(topology.run(Some(scenario.name), &scenario.operations)))
+}
+
+fn find_scenario(store: &ScenarioStore, name: &str) -> Result<Scenario, Error> {
+    store
+        .get(name)
+        .map_err(actix_web::error::ErrorBadRequest)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("scenario {} not found", name)))
+}
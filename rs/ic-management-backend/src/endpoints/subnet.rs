@@ -92,7 +92,8 @@ pub(crate) async fn replace(
     .excluding_from_available(request.exclude.clone().unwrap_or_default())
     .including_from_available(request.only.clone())
     .including_from_available(request.include.clone().unwrap_or_default())
-    .with_min_nakamoto_coefficients(request.min_nakamoto_coefficients.clone());
+    .with_min_nakamoto_coefficients(request.min_nakamoto_coefficients.clone())
+    .with_optimization_strategy(request.optimizer);
 
     let mut replacements_unhealthy: Vec<decentralization::network::Node> = Vec::new();
     if request.heal {
@@ -201,6 +202,7 @@ pub(crate) async fn resize(request: web::Json<SubnetResizeRequest>, registry: we
         .excluding_from_available(request.exclude.clone().unwrap_or_default())
         .including_from_available(request.only.clone().unwrap_or_default())
         .including_from_available(request.include.clone().unwrap_or_default())
+        .with_optimization_strategy(request.optimizer)
         .resize(request.add, request.remove)?;
 
     Ok(HttpResponse::Ok().json(decentralization::SubnetChangeResponse::from(&change)))
@@ -7,4 +7,5 @@ pub mod proposal;
 pub mod public_dashboard;
 pub mod registry;
 pub mod release;
+pub mod simulation;
 pub mod subnets;
@@ -7,10 +7,12 @@ mod proposal;
 mod public_dashboard;
 mod registry;
 mod release;
+mod simulation;
 mod subnets;
 
 use clap::Parser;
 use dotenv::dotenv;
+use std::path::PathBuf;
 use url::Url;
 
 #[actix_web::main]
@@ -19,6 +21,26 @@ async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "info");
     env_logger::init();
     let args = Cli::parse();
+    if let Some(path) = &args.node_features {
+        decentralization::features::CustomNodeFeatures::load(path)
+            .and_then(|features| features.activate())
+            .map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Failed to load the custom node features: {}", e),
+                )
+            })?;
+    }
+    if let Some(path) = &args.business_rules_policy {
+        decentralization::policy::BusinessRulesPolicy::load(path)
+            .map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Failed to load the business rules policy: {}", e),
+                )
+            })?
+            .activate();
+    }
     let target_network = ic_management_types::Network::new(args.network.clone(), &args.nns_urls)
         .await
         .expect("Failed to create network");
@@ -40,4 +62,14 @@ struct Cli {
     // The argument is mandatory for testnets, and is optional for mainnet and staging
     #[clap(long, env = "NNS_URLS", aliases = &["registry-url", "nns-url"], value_delimiter = ',')]
     pub nns_urls: Vec<Url>,
+
+    // Path to a YAML file with the decentralization business rules for subnets.
+    // The built-in policy is used if not provided
+    #[clap(long, env = "BUSINESS_RULES_POLICY")]
+    pub business_rules_policy: Option<PathBuf>,
+
+    // Path to a YAML file with additional node features and their values per
+    // data center, node provider or node
+    #[clap(long, env = "NODE_FEATURES")]
+    pub node_features: Option<PathBuf>,
 }
@@ -25,7 +25,7 @@ use registry_canister::mutations::do_update_elected_hostos_versions::UpdateElect
 use registry_canister::mutations::do_update_nodes_hostos_version::UpdateNodesHostosVersionPayload;
 use registry_canister::mutations::do_update_unassigned_nodes_config::UpdateUnassignedNodesConfigPayload;
 use registry_canister::mutations::node_management::do_remove_nodes::RemoveNodesPayload;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
 #[derive(Clone)]
@@ -34,7 +34,7 @@ pub struct ProposalAgent {
 }
 
 // Copied so it can be serialized
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ProposalInfoInternal {
     pub id: u64,
     pub proposal_timestamp_seconds: u64,
@@ -72,18 +72,24 @@ impl From<ProposalInfo> for ProposalInfoInternal {
     }
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SubnetUpdateProposal {
     pub info: ProposalInfoInternal,
     pub payload: DeployGuestosToAllSubnetNodesPayload,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct UpdateUnassignedNodesProposal {
     pub info: ProposalInfoInternal,
     pub payload: UpdateUnassignedNodesConfigPayload,
 }
 
+#[derive(Clone, Serialize)]
+pub struct UpdateNodesHostosVersionProposal {
+    pub info: ProposalInfoInternal,
+    pub payload: UpdateNodesHostosVersionPayload,
+}
+
 #[allow(dead_code)]
 impl ProposalAgent {
     pub fn new(nns_urls: &[Url]) -> Self {
@@ -203,6 +209,13 @@ impl ProposalAgent {
             .collect::<Vec<_>>())
     }
 
+    pub async fn list_update_nodes_hostos_version_proposals(&self) -> Result<Vec<UpdateNodesHostosVersionProposal>> {
+        Ok(filter_map_nns_function_proposals(&self.list_proposals(vec![]).await?)
+            .into_iter()
+            .map(|(info, payload)| UpdateNodesHostosVersionProposal { info: info.into(), payload })
+            .collect::<Vec<_>>())
+    }
+
     async fn list_proposals(&self, include_status: Vec<ProposalStatus>) -> Result<Vec<ProposalInfo>> {
         let mut proposals = vec![];
         loop {
@@ -1,7 +1,7 @@
 use crate::git_ic_repo::IcRepo;
 use crate::health::HealthStatusQuerier;
 use crate::node_labels;
-use crate::proposal::{self, SubnetUpdateProposal, UpdateUnassignedNodesProposal};
+use crate::proposal::{self, SubnetUpdateProposal, UpdateNodesHostosVersionProposal, UpdateUnassignedNodesProposal};
 use crate::public_dashboard::query_ic_dashboard_list;
 use async_trait::async_trait;
 use decentralization::network::{AvailableNodesQuerier, SubnetQuerier, SubnetQueryBy};
@@ -665,6 +665,12 @@ impl RegistryState {
         proposal_agent.list_update_unassigned_nodes_version_proposals().await
     }
 
+    pub async fn nodes_hostos_upgrade_proposals(&self) -> Result<Vec<UpdateNodesHostosVersionProposal>> {
+        let proposal_agent = proposal::ProposalAgent::new(self.get_nns_urls());
+
+        proposal_agent.list_update_nodes_hostos_version_proposals().await
+    }
+
     async fn retireable_hostos_versions(&self) -> Result<Vec<Release>> {
         let active_releases = self.hostos_releases.get_active_branches();
         let hostos_versions: BTreeSet<String> = self.nodes.values().map(|s| s.hostos_version.clone()).collect();
@@ -805,6 +811,7 @@ impl SubnetQuerier for RegistryState {
                     min_nakamoto_coefficients: None,
                     comment: None,
                     run_log: Vec::new(),
+                    explanations: Vec::new(),
                 })
                 .ok_or(NetworkError::SubnetNotFound(id)),
             SubnetQueryBy::NodeList(nodes) => {
@@ -831,6 +838,7 @@ impl SubnetQuerier for RegistryState {
                         min_nakamoto_coefficients: None,
                         comment: None,
                         run_log: Vec::new(),
+                        explanations: Vec::new(),
                     })
                 } else {
                     Err(NetworkError::IllegalRequest("no subnet found".to_string()))
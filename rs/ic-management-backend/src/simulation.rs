@@ -0,0 +1,649 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use decentralization::nakamoto::{NakamotoScore, NodeFeatures};
+use decentralization::network::{DecentralizedSubnet, Node, SubnetChange, SubnetChangeRequest};
+use ic_base_types::PrincipalId;
+use ic_management_types::{MinNakamotoCoefficients, Network, NetworkError, NodeFeature};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::registry::{self, RegistryState};
+use decentralization::network::AvailableNodesQuerier;
+
+/// Location of a data center that does not exist in the registry yet.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DataCenterLocation {
+    pub owner: String,
+    pub city: String,
+    pub country: String,
+    pub continent: String,
+}
+
+/// A hypothetical change to the network topology.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum SimulationOperation {
+    /// Onboard a node provider with `nodes` unassigned nodes in a data center.
+    /// The location is only needed if the data center is not known yet.
+    AddNodeProvider {
+        provider: String,
+        data_center: String,
+        nodes: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        location: Option<DataCenterLocation>,
+    },
+    /// Take a data center offline. Subnet members in the data center are
+    /// replaced with available nodes if `replace` is set, otherwise the
+    /// subnets simply shrink.
+    RemoveDataCenter {
+        data_center: String,
+        #[serde(default)]
+        replace: bool,
+    },
+    /// Replace the given nodes of a subnet, and optionally `optimize` more
+    /// nodes to improve decentralization.
+    ReplaceNodes {
+        subnet: String,
+        #[serde(default)]
+        nodes: Vec<PrincipalId>,
+        #[serde(default)]
+        optimize: usize,
+    },
+    /// Create a new subnet of the given size from the available nodes.
+    CreateSubnet {
+        name: String,
+        size: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_nakamoto_coefficients: Option<MinNakamotoCoefficients>,
+    },
+}
+
+impl std::fmt::Display for SimulationOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimulationOperation::AddNodeProvider {
+                provider,
+                data_center,
+                nodes,
+                ..
+            } => write!(f, "add node provider {} with {} node(s) in {}", provider, nodes, data_center),
+            SimulationOperation::RemoveDataCenter { data_center, replace } => {
+                write!(
+                    f,
+                    "remove data center {}{}",
+                    data_center,
+                    if *replace { " and replace its nodes" } else { "" }
+                )
+            }
+            SimulationOperation::ReplaceNodes { subnet, nodes, optimize } => {
+                write!(
+                    f,
+                    "replace {} node(s) and optimize {} node(s) in subnet {}",
+                    nodes.len(),
+                    optimize,
+                    subnet
+                )
+            }
+            SimulationOperation::CreateSubnet { name, size, .. } => write!(f, "create subnet {} with {} node(s)", name, size),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SimulatedSubnet {
+    pub id: PrincipalId,
+    pub name: String,
+    pub nodes: Vec<Node>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_nakamoto_coefficients: Option<MinNakamotoCoefficients>,
+}
+
+impl SimulatedSubnet {
+    fn decentralized(&self) -> DecentralizedSubnet {
+        DecentralizedSubnet {
+            id: self.id,
+            nodes: self.nodes.clone(),
+            removed_nodes: Vec::new(),
+            min_nakamoto_coefficients: self.min_nakamoto_coefficients.clone(),
+            comment: None,
+            run_log: Vec::new(),
+            explanations: Vec::new(),
+        }
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        self.name.eq_ignore_ascii_case(query) || self.id.to_string().starts_with(&query.to_lowercase())
+    }
+}
+
+/// Decentralization of a subnet after a simulation step, compared to the
+/// state before the step.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubnetImpact {
+    pub subnet_id: PrincipalId,
+    pub name: String,
+    pub nodes_before: usize,
+    pub nodes_after: usize,
+    pub score_before: NakamotoScore,
+    pub score_after: NakamotoScore,
+    pub penalty: usize,
+    pub violations: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StepReport {
+    pub operation: SimulationOperation,
+    pub subnets: Vec<SubnetImpact>,
+    pub available_nodes_before: usize,
+    pub available_nodes_after: usize,
+    /// Set if the operation could not be applied, in which case the topology
+    /// is left unchanged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SimulationReport {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<String>,
+    pub registry_version: u64,
+    pub steps: Vec<StepReport>,
+}
+
+/// In-memory copy of the network topology that hypothetical operations are
+/// applied to.
+#[derive(Clone, Debug, Default)]
+pub struct SimulatedTopology {
+    registry_version: u64,
+    subnets: BTreeMap<PrincipalId, SimulatedSubnet>,
+    available_nodes: Vec<Node>,
+    next_node_id: u64,
+    next_subnet_id: u64,
+}
+
+impl SimulatedTopology {
+    pub fn new(registry_version: u64, subnets: Vec<SimulatedSubnet>, available_nodes: Vec<Node>) -> Self {
+        Self {
+            registry_version,
+            subnets: subnets.into_iter().map(|s| (s.id, s)).collect(),
+            available_nodes,
+            next_node_id: 0,
+            next_subnet_id: 0,
+        }
+    }
+
+    /// Snapshot of the subnets and the available nodes in the registry.
+    pub async fn from_registry(registry: &RegistryState) -> Result<Self, NetworkError> {
+        let subnets = registry
+            .subnets()
+            .into_values()
+            .map(|s| SimulatedSubnet {
+                id: s.principal,
+                name: s.metadata.name.clone(),
+                nodes: s.nodes.iter().map(Node::from).collect(),
+                min_nakamoto_coefficients: None,
+            })
+            .collect();
+        Ok(Self::new(registry.version(), subnets, registry.available_nodes().await?))
+    }
+
+    pub fn registry_version(&self) -> u64 {
+        self.registry_version
+    }
+
+    pub fn subnets(&self) -> impl Iterator<Item = &SimulatedSubnet> {
+        self.subnets.values()
+    }
+
+    pub fn available_nodes(&self) -> &[Node] {
+        &self.available_nodes
+    }
+
+    /// Apply the operations in order, reporting the effect of each one.
+    pub fn run(&mut self, scenario: Option<String>, operations: &[SimulationOperation]) -> SimulationReport {
+        SimulationReport {
+            scenario,
+            registry_version: self.registry_version,
+            steps: operations.iter().map(|op| self.apply(op)).collect(),
+        }
+    }
+
+    pub fn apply(&mut self, operation: &SimulationOperation) -> StepReport {
+        let before = self.clone();
+        let result = match operation {
+            SimulationOperation::AddNodeProvider {
+                provider,
+                data_center,
+                nodes,
+                location,
+            } => self.add_node_provider(provider, data_center, *nodes, location),
+            SimulationOperation::RemoveDataCenter { data_center, replace } => self.remove_data_center(data_center, *replace),
+            SimulationOperation::ReplaceNodes { subnet, nodes, optimize } => self.replace_nodes(subnet, nodes, *optimize),
+            SimulationOperation::CreateSubnet {
+                name,
+                size,
+                min_nakamoto_coefficients,
+            } => self.create_subnet(name, *size, min_nakamoto_coefficients.clone()),
+        };
+
+        let error = match result {
+            Ok(()) => None,
+            Err(err) => {
+                *self = before.clone();
+                Some(err.to_string())
+            }
+        };
+
+        let subnets = self
+            .subnets
+            .values()
+            .filter_map(|subnet| {
+                let nodes_before = before.subnets.get(&subnet.id).map(|s| s.nodes.clone()).unwrap_or_default();
+                let unchanged = nodes_before.len() == subnet.nodes.len() && subnet.nodes.iter().all(|n| nodes_before.contains(n));
+                if unchanged {
+                    return None;
+                }
+                let (penalty, violations) = match subnet.decentralized().check_business_rules() {
+                    Ok(result) => result,
+                    Err(err) => (0, vec![err.to_string()]),
+                };
+                Some(SubnetImpact {
+                    subnet_id: subnet.id,
+                    name: subnet.name.clone(),
+                    nodes_before: nodes_before.len(),
+                    nodes_after: subnet.nodes.len(),
+                    score_before: NakamotoScore::new_from_nodes(&nodes_before),
+                    score_after: NakamotoScore::new_from_nodes(&subnet.nodes),
+                    penalty,
+                    violations,
+                })
+            })
+            .collect();
+
+        StepReport {
+            operation: operation.clone(),
+            subnets,
+            available_nodes_before: before.available_nodes.len(),
+            available_nodes_after: self.available_nodes.len(),
+            error,
+        }
+    }
+
+    fn all_nodes(&self) -> impl Iterator<Item = &Node> {
+        self.subnets.values().flat_map(|s| s.nodes.iter()).chain(self.available_nodes.iter())
+    }
+
+    fn add_node_provider(
+        &mut self,
+        provider: &str,
+        data_center: &str,
+        nodes: usize,
+        location: &Option<DataCenterLocation>,
+    ) -> Result<(), NetworkError> {
+        let dc_features = match location {
+            Some(location) => NodeFeatures::from_iter([
+                (NodeFeature::DataCenterOwner, location.owner.clone()),
+                (NodeFeature::City, location.city.clone()),
+                (NodeFeature::Country, location.country.clone()),
+                (NodeFeature::Continent, location.continent.clone()),
+            ]),
+            None => self
+                .all_nodes()
+                .find(|n| n.get_feature(&NodeFeature::DataCenter) == data_center)
+                .map(|n| n.get_features())
+                .ok_or_else(|| NetworkError::IllegalRequest(format!("unknown data center {}, a location is required", data_center)))?,
+        };
+
+        let mut features = dc_features;
+        features.feature_map.insert(NodeFeature::DataCenter, data_center.to_string());
+        features.feature_map.insert(NodeFeature::NodeProvider, provider.to_string());
+        for _ in 0..nodes {
+            // NakamotoScores are memoized by node IDs, so the ID of a
+            // synthetic node is derived from its features to never collide
+            // with a node that has different features.
+            let id = PrincipalId::new_self_authenticating(format!("simulated-node/{}/{:?}", self.next_node_id, features.feature_map).as_bytes());
+            self.next_node_id += 1;
+            self.available_nodes.push(Node {
+                id,
+                features: features.clone(),
+                dfinity_owned: false,
+                decentralized: true,
+            });
+        }
+        Ok(())
+    }
+
+    fn remove_data_center(&mut self, data_center: &str, replace: bool) -> Result<(), NetworkError> {
+        let in_dc = |n: &Node| n.get_feature(&NodeFeature::DataCenter) == data_center;
+        if !self.all_nodes().any(in_dc) {
+            return Err(NetworkError::IllegalRequest(format!("no nodes found in data center {}", data_center)));
+        }
+        self.available_nodes.retain(|n| !in_dc(n));
+
+        let affected = self
+            .subnets
+            .values()
+            .filter(|s| s.nodes.iter().any(in_dc))
+            .map(|s| s.id)
+            .collect::<Vec<_>>();
+        for subnet_id in affected {
+            let subnet = self.subnets.get_mut(&subnet_id).expect("subnet exists");
+            let removed = subnet.nodes.iter().filter(|n| in_dc(n)).cloned().collect::<Vec<_>>();
+            if replace {
+                let change = SubnetChangeRequest::new(
+                    subnet.decentralized(),
+                    self.available_nodes.clone(),
+                    vec![],
+                    vec![],
+                    vec![],
+                    subnet.min_nakamoto_coefficients.clone(),
+                )
+                .optimize(0, &removed)?;
+                self.apply_change(&subnet_id, &change, false);
+            } else {
+                subnet.nodes.retain(|n| !in_dc(n));
+            }
+        }
+        Ok(())
+    }
+
+    fn replace_nodes(&mut self, subnet: &str, nodes: &[PrincipalId], optimize: usize) -> Result<(), NetworkError> {
+        let subnet = self.find_subnet(subnet)?.clone();
+        let replaced = nodes
+            .iter()
+            .map(|id| subnet.nodes.iter().find(|n| n.id == *id).cloned().ok_or(NetworkError::NodeNotFound(*id)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let change = SubnetChangeRequest::new(
+            subnet.decentralized(),
+            self.available_nodes.clone(),
+            vec![],
+            vec![],
+            vec![],
+            subnet.min_nakamoto_coefficients.clone(),
+        )
+        .optimize(optimize, &replaced)?;
+        self.apply_change(&subnet.id, &change, true);
+        Ok(())
+    }
+
+    fn create_subnet(&mut self, name: &str, size: usize, min_nakamoto_coefficients: Option<MinNakamotoCoefficients>) -> Result<(), NetworkError> {
+        if self.subnets.values().any(|s| s.name == name) {
+            return Err(NetworkError::IllegalRequest(format!("subnet {} already exists", name)));
+        }
+        let change = SubnetChangeRequest::new(
+            DecentralizedSubnet::default(),
+            self.available_nodes.clone(),
+            vec![],
+            vec![],
+            vec![],
+            min_nakamoto_coefficients.clone(),
+        )
+        .resize(size, 0)?;
+
+        let id = loop {
+            let id = PrincipalId::new_subnet_test_id(self.next_subnet_id);
+            self.next_subnet_id += 1;
+            if !self.subnets.contains_key(&id) {
+                break id;
+            }
+        };
+        self.subnets.insert(
+            id,
+            SimulatedSubnet {
+                id,
+                name: name.to_string(),
+                nodes: vec![],
+                min_nakamoto_coefficients,
+            },
+        );
+        self.apply_change(&id, &change, false);
+        Ok(())
+    }
+
+    fn find_subnet(&self, query: &str) -> Result<&SimulatedSubnet, NetworkError> {
+        let matches = self.subnets.values().filter(|s| s.matches(query)).collect::<Vec<_>>();
+        match matches.as_slice() {
+            [subnet] => Ok(subnet),
+            [] => Err(NetworkError::IllegalRequest(format!("no subnet matches {}", query))),
+            _ => Err(NetworkError::IllegalRequest(format!(
+                "subnet {} is ambiguous, matches: {}",
+                query,
+                matches.iter().map(|s| s.id.to_string()).join(", ")
+            ))),
+        }
+    }
+
+    /// Move the nodes of a change between the available pool and the subnet.
+    /// Removed nodes go back to the pool unless they went offline.
+    fn apply_change(&mut self, subnet_id: &PrincipalId, change: &SubnetChange, return_removed: bool) {
+        let added = change.added();
+        let removed = change.removed();
+        let subnet = self.subnets.get_mut(subnet_id).expect("subnet exists");
+        subnet.nodes.retain(|n| !removed.contains(n));
+        subnet.nodes.extend(added.iter().cloned());
+        self.available_nodes.retain(|n| !added.contains(n));
+        if return_removed {
+            self.available_nodes.extend(removed);
+        }
+    }
+}
+
+/// A named sequence of operations, which can be replayed against later
+/// registry versions.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub operations: Vec<SimulationOperation>,
+    /// Registry version the scenario was saved against.
+    pub registry_version: u64,
+}
+
+/// Scenarios are stored as one JSON file per scenario, next to the local
+/// registry cache of the network.
+#[derive(Clone, Debug)]
+pub struct ScenarioStore {
+    dir: PathBuf,
+}
+
+impl ScenarioStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn for_network(network: &Network) -> Self {
+        Self::new(registry::local_cache_path().join("simulation-scenarios").join(network.name.as_str()))
+    }
+
+    fn path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(anyhow::anyhow!(
+                "invalid scenario name {:?}, only letters, digits, '-' and '_' are allowed",
+                name
+            ));
+        }
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+
+    pub fn list(&self) -> anyhow::Result<Vec<Scenario>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut scenarios = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                scenarios.push(serde_json::from_slice(&std::fs::read(&path)?)?);
+            }
+        }
+        scenarios.sort_by(|a: &Scenario, b: &Scenario| a.name.cmp(&b.name));
+        Ok(scenarios)
+    }
+
+    pub fn get(&self, name: &str) -> anyhow::Result<Option<Scenario>> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    pub fn save(&self, scenario: &Scenario) -> anyhow::Result<()> {
+        let path = self.path(&scenario.name)?;
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(path, serde_json::to_vec_pretty(scenario)?)?;
+        Ok(())
+    }
+
+    /// Returns whether the scenario existed.
+    pub fn delete(&self, name: &str) -> anyhow::Result<bool> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            return Ok(false);
+        }
+        std::fs::remove_file(path)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: u64, provider: &str, dc: &str, country: &str, dfinity_owned: bool) -> Node {
+        Node::new_test_node(
+            id,
+            NodeFeatures::from_iter([
+                (NodeFeature::NodeProvider, provider.to_string()),
+                (NodeFeature::DataCenter, dc.to_string()),
+                (NodeFeature::DataCenterOwner, format!("{}-owner", dc)),
+                (NodeFeature::City, format!("{}-city", dc)),
+                (NodeFeature::Country, country.to_string()),
+                (NodeFeature::Continent, "Europe".to_string()),
+            ]),
+            dfinity_owned,
+            true,
+        )
+    }
+
+    fn topology() -> SimulatedTopology {
+        let subnet_nodes = (0..4)
+            .map(|i| node(1000 + i, &format!("np{}", i), &format!("dc{}", i), &format!("c{}", i), i == 0))
+            .collect();
+        let available_nodes = (4..10)
+            .map(|i| node(1000 + i, &format!("np{}", i), &format!("dc{}", i), &format!("c{}", i), false))
+            .collect();
+        SimulatedTopology::new(
+            42,
+            vec![SimulatedSubnet {
+                id: PrincipalId::new_subnet_test_id(1000),
+                name: "app".to_string(),
+                nodes: subnet_nodes,
+                min_nakamoto_coefficients: None,
+            }],
+            available_nodes,
+        )
+    }
+
+    #[test]
+    fn add_node_provider_in_known_and_new_dc() {
+        let mut topology = topology();
+        let report = topology.run(
+            None,
+            &[
+                SimulationOperation::AddNodeProvider {
+                    provider: "new-np".to_string(),
+                    data_center: "dc1".to_string(),
+                    nodes: 2,
+                    location: None,
+                },
+                SimulationOperation::AddNodeProvider {
+                    provider: "new-np".to_string(),
+                    data_center: "nowhere".to_string(),
+                    nodes: 2,
+                    location: None,
+                },
+            ],
+        );
+        assert_eq!(report.steps[0].error, None);
+        assert_eq!(report.steps[0].available_nodes_after, 8);
+        let added = &topology.available_nodes()[6];
+        assert_eq!(added.get_feature(&NodeFeature::Country), "c1");
+        assert_eq!(added.get_feature(&NodeFeature::NodeProvider), "new-np");
+        assert!(report.steps[1].error.is_some());
+        assert_eq!(topology.available_nodes().len(), 8);
+    }
+
+    #[test]
+    fn remove_data_center_reports_subnet_impact() {
+        let mut topology = topology();
+        let shrink = topology.clone().apply(&SimulationOperation::RemoveDataCenter {
+            data_center: "dc2".to_string(),
+            replace: false,
+        });
+        assert_eq!(shrink.subnets.len(), 1);
+        assert_eq!(shrink.subnets[0].nodes_before, 4);
+        assert_eq!(shrink.subnets[0].nodes_after, 3);
+
+        let replace = topology.apply(&SimulationOperation::RemoveDataCenter {
+            data_center: "dc2".to_string(),
+            replace: true,
+        });
+        assert_eq!(replace.error, None);
+        assert_eq!(replace.subnets[0].nodes_after, 4);
+        assert_eq!(replace.available_nodes_after, 5);
+        assert!(topology.all_nodes().all(|n| n.get_feature(&NodeFeature::DataCenter) != "dc2"));
+    }
+
+    #[test]
+    fn create_subnet_and_replace_nodes() {
+        let mut topology = topology();
+        let report = topology.run(
+            Some("test".to_string()),
+            &[
+                SimulationOperation::CreateSubnet {
+                    name: "new".to_string(),
+                    size: 4,
+                    min_nakamoto_coefficients: None,
+                },
+                SimulationOperation::ReplaceNodes {
+                    subnet: "app".to_string(),
+                    nodes: vec![PrincipalId::new_node_test_id(1001)],
+                    optimize: 0,
+                },
+            ],
+        );
+        assert!(report.steps.iter().all(|s| s.error.is_none()));
+        assert_eq!(report.steps[0].subnets[0].name, "new");
+        assert_eq!(report.steps[0].subnets[0].nodes_after, 4);
+        assert!(report.steps[0].subnets[0].violations.iter().any(|v| v.contains("DFINITY-owned")));
+        assert_eq!(topology.available_nodes().len(), 2);
+        let app = topology.find_subnet("app").unwrap();
+        assert!(app.nodes.iter().all(|n| n.id != PrincipalId::new_node_test_id(1001)));
+        assert!(topology.available_nodes().iter().any(|n| n.id == PrincipalId::new_node_test_id(1001)));
+    }
+
+    #[test]
+    fn scenario_store_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("simulation-scenarios-test-{}", std::process::id()));
+        let store = ScenarioStore::new(dir.clone());
+        let scenario = Scenario {
+            name: "dc-outage".to_string(),
+            description: None,
+            operations: vec![SimulationOperation::RemoveDataCenter {
+                data_center: "dc2".to_string(),
+                replace: true,
+            }],
+            registry_version: 42,
+        };
+        store.save(&scenario).unwrap();
+        assert_eq!(store.list().unwrap().len(), 1);
+        assert_eq!(store.get("dc-outage").unwrap().unwrap().operations, scenario.operations);
+        assert!(store.get("../escape").is_err());
+        assert!(store.delete("dc-outage").unwrap());
+        assert!(store.get("dc-outage").unwrap().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
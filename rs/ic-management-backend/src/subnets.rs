@@ -43,6 +43,8 @@ pub fn get_proposed_subnet_changes(
             min_nakamoto_coefficients: None,
             comment: None,
             run_log: vec![],
+            explanations: vec![],
+            optimization: None,
         }
         .with_nodes(
             proposal
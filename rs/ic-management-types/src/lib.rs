@@ -23,13 +23,13 @@ use registry_canister::mutations::do_update_unassigned_nodes_config::UpdateUnass
 use registry_canister::mutations::node_management::do_remove_nodes::RemoveNodesPayload;
 use serde::{Deserialize, Serialize};
 use std::cmp::{Eq, Ord, PartialEq, PartialOrd};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::net::Ipv6Addr;
 use std::ops::Deref;
 use std::str::FromStr;
-use strum::VariantNames;
+use std::sync::RwLock;
 use strum_macros::EnumString;
 use url::Url;
 
@@ -274,9 +274,15 @@ pub struct Node {
     pub is_api_boundary_node: bool,
 }
 
-#[derive(strum_macros::Display, EnumString, VariantNames, Hash, Eq, PartialEq, Ord, PartialOrd, Clone, Serialize, Deserialize, Debug)]
-#[strum(serialize_all = "snake_case")]
-#[serde(rename_all = "snake_case")]
+/// A dimension along which nodes are grouped for decentralization purposes.
+///
+/// Besides the built-in features that are derived from the registry, nodes can
+/// have custom features (e.g. ASN or legal jurisdiction) whose values come from
+/// an external data file. Custom features need to be registered with
+/// [NodeFeature::register_custom] to be taken into account by
+/// [NodeFeature::variants].
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Clone, Serialize, Deserialize, Debug)]
+#[serde(try_from = "String", into = "String")]
 pub enum NodeFeature {
     NodeProvider,
     DataCenter,
@@ -284,11 +290,174 @@ pub enum NodeFeature {
     City,
     Country,
     Continent,
+    Custom(String),
 }
 
+static CUSTOM_NODE_FEATURES: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
 impl NodeFeature {
+    const BUILTIN: [(&'static str, NodeFeature); 6] = [
+        ("node_provider", NodeFeature::NodeProvider),
+        ("data_center", NodeFeature::DataCenter),
+        ("data_center_owner", NodeFeature::DataCenterOwner),
+        ("city", NodeFeature::City),
+        ("country", NodeFeature::Country),
+        ("continent", NodeFeature::Continent),
+    ];
+
+    /// Built-in features followed by the registered custom features.
     pub fn variants() -> Vec<Self> {
-        NodeFeature::VARIANTS.iter().map(|f| NodeFeature::from_str(f).unwrap()).collect()
+        Self::BUILTIN
+            .into_iter()
+            .map(|(_, feature)| feature)
+            .chain(Self::custom_variants())
+            .collect()
+    }
+
+    pub fn custom_variants() -> Vec<Self> {
+        CUSTOM_NODE_FEATURES
+            .read()
+            .expect("custom node features lock poisoned")
+            .iter()
+            .cloned()
+            .map(NodeFeature::Custom)
+            .collect()
+    }
+
+    /// A custom feature with the given name, which has to be snake_case and
+    /// different from the built-in features. The feature still needs to be
+    /// registered to be known.
+    pub fn new_custom(name: &str) -> Result<Self, String> {
+        if Self::BUILTIN.into_iter().any(|(builtin, _)| builtin == name) {
+            return Err(format!("custom node feature '{}' clashes with a built-in feature", name));
+        }
+        let valid =
+            name.starts_with(|c: char| c.is_ascii_lowercase()) && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid {
+            return Err(format!("invalid node feature '{}', expected a snake_case name", name));
+        }
+        Ok(NodeFeature::Custom(name.to_string()))
+    }
+
+    /// Replaces the set of registered custom features.
+    pub fn register_custom(names: impl IntoIterator<Item = String>) -> Result<(), String> {
+        let mut custom = Vec::new();
+        for name in names {
+            match Self::new_custom(&name)? {
+                NodeFeature::Custom(name) if !custom.contains(&name) => custom.push(name),
+                _ => return Err(format!("custom node feature '{}' is defined more than once", name)),
+            }
+        }
+        *CUSTOM_NODE_FEATURES.write().expect("custom node features lock poisoned") = custom;
+        Ok(())
+    }
+
+    pub fn is_custom(&self) -> bool {
+        matches!(self, NodeFeature::Custom(_))
+    }
+}
+
+impl std::fmt::Display for NodeFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeFeature::Custom(name) => write!(f, "{}", name),
+            builtin => {
+                let (name, _) = Self::BUILTIN
+                    .into_iter()
+                    .find(|(_, feature)| feature == builtin)
+                    .expect("missing built-in feature name");
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+/// Only the built-in features and the registered custom features are
+/// accepted, so that a typo is not taken for a new custom feature. Custom
+/// features therefore have to be registered before parsing anything that
+/// refers to them.
+impl FromStr for NodeFeature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, feature)) = Self::BUILTIN.into_iter().find(|(name, _)| *name == s) {
+            return Ok(feature);
+        }
+        if CUSTOM_NODE_FEATURES
+            .read()
+            .expect("custom node features lock poisoned")
+            .iter()
+            .any(|name| name == s)
+        {
+            return Ok(NodeFeature::Custom(s.to_string()));
+        }
+        Err(format!("unknown node feature '{}'", s))
+    }
+}
+
+impl TryFrom<String> for NodeFeature {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::from_str(&value)
+    }
+}
+
+impl From<NodeFeature> for String {
+    fn from(value: NodeFeature) -> Self {
+        value.to_string()
+    }
+}
+
+/// A set of node features whose actors are assumed to be able to collude,
+/// e.g. node providers together with the countries they operate in. Written as
+/// the feature names joined with `+`, e.g. `node_provider+country`.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FeatureGroup(BTreeSet<NodeFeature>);
+
+impl FeatureGroup {
+    pub fn new(features: impl IntoIterator<Item = NodeFeature>) -> Self {
+        Self(features.into_iter().collect())
+    }
+
+    pub fn features(&self) -> impl Iterator<Item = &NodeFeature> {
+        self.0.iter()
+    }
+}
+
+impl std::fmt::Display for FeatureGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.iter().map(|feature| feature.to_string()).collect::<Vec<_>>().join("+"))
+    }
+}
+
+impl FromStr for FeatureGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let features = s
+            .split('+')
+            .map(|feature| NodeFeature::from_str(feature.trim()).map_err(|_| format!("invalid node feature '{}' in '{}'", feature, s)))
+            .collect::<Result<BTreeSet<_>, _>>()?;
+        if features.len() < 2 {
+            return Err(format!("feature group '{}' must have at least two distinct features", s));
+        }
+        Ok(Self(features))
+    }
+}
+
+impl TryFrom<String> for FeatureGroup {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::from_str(&value)
+    }
+}
+
+impl From<FeatureGroup> for String {
+    fn from(value: FeatureGroup) -> Self {
+        value.to_string()
     }
 }
 
@@ -296,10 +465,30 @@ impl NodeFeature {
 pub struct MinNakamotoCoefficients {
     pub coefficients: BTreeMap<NodeFeature, f64>,
     pub average: f64,
+    /// Minimum number of actors across a group of features, e.g. node providers
+    /// or countries, that together control more than 1/3 of the nodes
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub joint_coefficients: BTreeMap<FeatureGroup, f64>,
 }
 
 impl Eq for MinNakamotoCoefficients {}
 
+/// Search strategy used to pick the nodes to add to or remove from a subnet.
+#[derive(strum_macros::Display, EnumString, Default, Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum OptimizationStrategy {
+    /// Pick the best node one at a time
+    #[default]
+    Greedy,
+    /// Keep the best few partial subnets at every step instead of only the best one
+    BeamSearch,
+    /// Randomized search over node swaps, starting from the greedy result
+    SimulatedAnnealing,
+    /// Evaluate every possible change, only feasible for small search spaces
+    Exhaustive,
+}
+
 #[derive(Clone, Serialize, Debug, Deserialize)]
 pub struct TopologyProposal {
     pub id: u64,
@@ -878,4 +1067,25 @@ mod tests {
 
         assert_eq!(network.legacy_name(), "mercury");
     }
+
+    #[test]
+    fn test_node_feature_names() {
+        for feature in NodeFeature::variants() {
+            assert_eq!(NodeFeature::from_str(&feature.to_string()), Ok(feature));
+        }
+        assert_eq!(NodeFeature::from_str("data_center_owner"), Ok(NodeFeature::DataCenterOwner));
+        assert!(NodeFeature::from_str("Legal Jurisdiction").is_err());
+        assert!(NodeFeature::new_custom("Legal Jurisdiction").is_err());
+        assert!(NodeFeature::new_custom("country").is_err());
+
+        // Custom features are only known once registered
+        assert!(NodeFeature::from_str("asn").is_err());
+        NodeFeature::register_custom(["asn".to_string()]).unwrap();
+        assert_eq!(NodeFeature::from_str("asn"), Ok(NodeFeature::Custom("asn".to_string())));
+        assert!(NodeFeature::from_str("asm").is_err());
+        assert_eq!(
+            serde_json::to_string(&[NodeFeature::Country, NodeFeature::Custom("asn".to_string())]).unwrap(),
+            r#"["country","asn"]"#
+        );
+    }
 }
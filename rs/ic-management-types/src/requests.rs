@@ -1,6 +1,7 @@
-use crate::{MinNakamotoCoefficients, Node, Status};
+use crate::{MinNakamotoCoefficients, Node, NodeFeature, OptimizationStrategy, Status};
 use ic_base_types::PrincipalId;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Serialize, Deserialize)]
 pub struct MembershipReplaceRequest {
@@ -11,6 +12,8 @@ pub struct MembershipReplaceRequest {
     pub only: Vec<String>,
     pub include: Option<Vec<PrincipalId>>,
     pub min_nakamoto_coefficients: Option<MinNakamotoCoefficients>,
+    #[serde(default)]
+    pub optimizer: OptimizationStrategy,
 }
 
 // impl Display for MembershipReplaceRequest
@@ -45,6 +48,9 @@ impl std::fmt::Display for MembershipReplaceRequest {
         if let Some(min_nakamoto_coefficients) = &self.min_nakamoto_coefficients {
             write!(f, " min_nakamoto_coefficients: {:?}", min_nakamoto_coefficients)?;
         }
+        if self.optimizer != OptimizationStrategy::Greedy {
+            write!(f, " optimizer: {}", self.optimizer)?;
+        }
         Ok(())
     }
 }
@@ -75,6 +81,8 @@ pub struct SubnetResizeRequest {
     pub exclude: Option<Vec<String>>,
     pub only: Option<Vec<String>>,
     pub include: Option<Vec<PrincipalId>>,
+    #[serde(default)]
+    pub optimizer: OptimizationStrategy,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -119,3 +127,24 @@ impl NodeRemovalReason {
 pub struct HealRequest {
     pub max_replaceable_nodes_per_sub: Option<usize>,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct HealPlanRequest {
+    /// Max number of node swaps in the whole plan
+    pub max_swaps: Option<usize>,
+    /// Max number of node swaps per subnet
+    pub max_swaps_per_subnet: Option<usize>,
+    /// Max number of node swaps for specific subnets, overriding
+    /// `max_swaps_per_subnet`
+    #[serde(default)]
+    pub max_swaps_by_subnet: BTreeMap<PrincipalId, usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OutageAnalysisRequest {
+    /// Only analyze failure domains of these features, all features if empty
+    #[serde(default)]
+    pub features: Vec<NodeFeature>,
+    /// Max number of failure domains to return
+    pub limit: Option<usize>,
+}
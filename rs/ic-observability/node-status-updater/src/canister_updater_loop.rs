@@ -1,36 +1,54 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use crossbeam::select;
 use crossbeam_channel::Receiver;
 use ic_agent::export::Principal;
-use obs_canister_clients::node_status_canister_client::{NodeStatus, NodeStatusCanister};
-use prometheus_http_query::Client;
-use service_discovery::IcServiceDiscovery;
+use obs_canister_clients::node_status_canister_client::{HealthLevel, NodeStatus, NodeStatusCanister, NodeStatusCanisterError};
+use service_discovery::{job_types::JobType, IcServiceDiscovery};
 use slog::{info, warn};
 
+use crate::health::{node_health, HealthChecker, HealthSamples};
+
+/// Statuses of the nodes in the registry, by node and its subnet
+fn node_statuses(registry_nodes: &BTreeMap<Principal, Option<Principal>>, samples: &HealthSamples) -> Vec<NodeStatus> {
+    registry_nodes
+        .iter()
+        .map(|(node_id, subnet_id)| {
+            let health = node_health(node_id, samples);
+            NodeStatus {
+                node_id: *node_id,
+                subnet_id: *subnet_id,
+                status: health.level != HealthLevel::Down,
+                health: Some(health),
+            }
+        })
+        .collect()
+}
+
 pub fn canister_updater_loop(
     log: slog::Logger,
-    _discovery: Arc<dyn IcServiceDiscovery>,
+    discovery: Arc<dyn IcServiceDiscovery>,
     shutdown_signal: Receiver<()>,
     update_signal_recv: Receiver<()>,
     canister: NodeStatusCanister,
     rt: tokio::runtime::Handle,
-    prom_client: Client,
+    health_checker: HealthChecker,
 ) -> impl FnMut() {
     move || loop {
-        let prom_response: Vec<NodeStatus> = match rt.block_on(prom_client.query("up{ ic_node=~\".+\", job=\"node_exporter\" }").get()) {
-            Ok(response) => response
-                .data()
-                .clone()
-                .into_vector()
-                .unwrap_or_default()
-                .iter()
-                .map(|entry| NodeStatus {
-                    node_id: Principal::from_text(entry.metric().get("ic_node").unwrap()).unwrap(),
-                    status: entry.sample().value() > 0.0,
-                    subnet_id: entry.metric().get("ic_subnet").map(|subnet_id| Principal::from_text(subnet_id).unwrap()),
-                })
+        let registry_nodes: BTreeMap<Principal, Option<Principal>> = match discovery.get_target_groups(JobType::Replica, log.clone()) {
+            Ok(target_groups) => target_groups
+                .into_iter()
+                .map(|group| (group.node_id.get().0, group.subnet_id.map(|subnet_id| subnet_id.get().0)))
                 .collect(),
+            Err(err) => {
+                warn!(log, "Failed to get nodes from the registry: {:?}", err);
+                BTreeMap::new()
+            }
+        };
+
+        let statuses = match rt.block_on(health_checker.query()) {
+            Ok(samples) => node_statuses(&registry_nodes, &samples),
             Err(err) => {
                 warn!(log, "Failed to query Prometheus: {:?}", err);
                 Vec::new()
@@ -47,13 +65,13 @@ pub fn canister_updater_loop(
 
         let mut diff = vec![];
 
-        for node in prom_response.iter() {
+        for node in statuses.iter() {
             if !present_nodes.contains(node) {
                 diff.push(node.clone());
             }
         }
 
-        info!(log, "Updating node status"; "prom_response_nodes" => prom_response.len(), "diff" => diff.len());
+        info!(log, "Updating node status"; "registry_nodes" => registry_nodes.len(), "diff" => diff.len());
 
         if diff.is_empty() {
             info!(log, "No node status updates");
@@ -62,12 +80,37 @@ pub fn canister_updater_loop(
                 Ok(_) => {
                     info!(log, "Successfully updated node status");
                 }
+                Err(NodeStatusCanisterError::Unauthorized(principal)) => {
+                    warn!(log, "Canister refused to update node status, {} is not one of its writers", principal);
+                }
                 Err(err) => {
                     warn!(log, "Failed to update node status: {:?}", err);
                 }
             };
         }
 
+        // Without the nodes of the registry every node would look removed
+        let removed: Vec<Principal> = if registry_nodes.is_empty() {
+            vec![]
+        } else {
+            present_nodes
+                .iter()
+                .map(|node| node.node_id)
+                .filter(|node| !registry_nodes.contains_key(node))
+                .collect()
+        };
+        if !removed.is_empty() {
+            info!(log, "Removing nodes that are no longer in the registry"; "removed" => removed.len());
+            match rt.block_on(canister.remove_nodes(removed)) {
+                Ok(_) => {
+                    info!(log, "Successfully removed nodes");
+                }
+                Err(err) => {
+                    warn!(log, "Failed to remove nodes: {:?}", err);
+                }
+            };
+        }
+
         select! {
             recv(shutdown_signal) -> _ => {
                     info!(log, "Received shutdown signal in canister_updater_loop");
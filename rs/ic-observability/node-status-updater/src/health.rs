@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use ic_agent::export::Principal;
+use obs_canister_clients::node_status_canister_client::{HealthLevel, HealthReason, NodeHealth};
+use prometheus_http_query::Client;
+
+/// A signal from Prometheus that the health of a node is derived from. Every
+/// check returns a sample per node, and fails for the nodes whose sample is
+/// not positive.
+#[derive(ValueEnum, Copy, Clone, Debug, Ord, Eq, PartialEq, PartialOrd)]
+pub enum HealthCheck {
+    /// The node exporter of the GuestOS can be scraped
+    NodeExporter,
+    /// The replica can be scraped
+    Replica,
+    /// The replica's finalized height increased in the last 5 minutes
+    Finalization,
+    /// The orchestrator can be scraped
+    Orchestrator,
+    /// The metrics proxy of the GuestOS can be scraped
+    MetricsProxy,
+}
+
+impl HealthCheck {
+    fn query(&self) -> &'static str {
+        match self {
+            HealthCheck::NodeExporter => r#"up{ ic_node=~".+", job="node_exporter" }"#,
+            HealthCheck::Replica => r#"up{ ic_node=~".+", job="replica" }"#,
+            HealthCheck::Finalization => {
+                r#"increase(artifact_pool_consensus_height_stat{ ic_node=~".+", job="replica", type="finalization", pool_type="validated", stat="max" }[5m])"#
+            }
+            HealthCheck::Orchestrator => r#"up{ ic_node=~".+", job="orchestrator" }"#,
+            HealthCheck::MetricsProxy => r#"up{ ic_node=~".+", job="guest_metrics_proxy" }"#,
+        }
+    }
+
+    /// The health of a node for which the check fails
+    fn failure(&self) -> (HealthLevel, HealthReason) {
+        match self {
+            HealthCheck::NodeExporter => (HealthLevel::Down, HealthReason::NodeExporterDown),
+            HealthCheck::Replica => (HealthLevel::Down, HealthReason::ReplicaDown),
+            HealthCheck::Finalization => (HealthLevel::Degraded, HealthReason::FinalizationStalled),
+            HealthCheck::Orchestrator => (HealthLevel::Degraded, HealthReason::OrchestratorDown),
+            HealthCheck::MetricsProxy => (HealthLevel::Degraded, HealthReason::MetricsProxyUnreachable),
+        }
+    }
+}
+
+/// Whether each check passed, by check and by node
+pub type HealthSamples = BTreeMap<HealthCheck, BTreeMap<Principal, bool>>;
+
+/// Queries the selected health checks from Prometheus
+pub struct HealthChecker {
+    client: Client,
+    checks: Vec<HealthCheck>,
+}
+
+impl HealthChecker {
+    pub fn new(client: Client, checks: Vec<HealthCheck>) -> Self {
+        Self { client, checks }
+    }
+
+    pub async fn query(&self) -> anyhow::Result<HealthSamples> {
+        let mut samples = BTreeMap::new();
+        for check in self.checks.iter() {
+            let response = self
+                .client
+                .query(check.query())
+                .get()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to query {:?} check: {:?}", check, e))?;
+            let passed = response
+                .data()
+                .clone()
+                .into_vector()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|entry| {
+                    let node = Principal::from_text(entry.metric().get("ic_node")?).ok()?;
+                    Some((node, entry.sample().value() > 0.0))
+                })
+                .collect();
+            samples.insert(*check, passed);
+        }
+        Ok(samples)
+    }
+}
+
+/// Combines the checks of a node into its health. The node is as unhealthy as
+/// the worst of its failed checks, and down if no check returned anything for
+/// it.
+pub fn node_health(node: &Principal, samples: &HealthSamples) -> NodeHealth {
+    let mut seen = false;
+    let mut level = HealthLevel::Healthy;
+    let mut reasons = vec![];
+    for (check, passed) in samples {
+        match passed.get(node) {
+            Some(true) => seen = true,
+            Some(false) => {
+                seen = true;
+                let (failed_level, reason) = check.failure();
+                level = level.max(failed_level);
+                reasons.push(reason);
+            }
+            None => {}
+        }
+    }
+    if !seen {
+        return NodeHealth {
+            level: HealthLevel::Down,
+            reasons: vec![HealthReason::NoMetrics],
+        };
+    }
+    NodeHealth { level, reasons }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combines_checks() {
+        let node = |id: u64| Principal::from_slice(&id.to_be_bytes());
+        let samples = HealthSamples::from([
+            (
+                HealthCheck::NodeExporter,
+                BTreeMap::from([(node(1), true), (node(2), true), (node(3), false)]),
+            ),
+            (HealthCheck::Finalization, BTreeMap::from([(node(1), true), (node(2), false)])),
+            (
+                HealthCheck::Orchestrator,
+                BTreeMap::from([(node(1), true), (node(2), false), (node(3), false)]),
+            ),
+        ]);
+
+        assert_eq!(
+            node_health(&node(1), &samples),
+            NodeHealth {
+                level: HealthLevel::Healthy,
+                reasons: vec![]
+            }
+        );
+        assert_eq!(
+            node_health(&node(2), &samples),
+            NodeHealth {
+                level: HealthLevel::Degraded,
+                reasons: vec![HealthReason::FinalizationStalled, HealthReason::OrchestratorDown]
+            }
+        );
+        assert_eq!(
+            node_health(&node(3), &samples),
+            NodeHealth {
+                level: HealthLevel::Down,
+                reasons: vec![HealthReason::NodeExporterDown, HealthReason::OrchestratorDown]
+            }
+        );
+        assert_eq!(
+            node_health(&node(4), &samples),
+            NodeHealth {
+                level: HealthLevel::Down,
+                reasons: vec![HealthReason::NoMetrics]
+            }
+        );
+    }
+}
@@ -1,8 +1,11 @@
 use anyhow::{bail, Result};
 use clap::Parser;
 use futures_util::FutureExt;
+use health::{HealthCheck, HealthChecker};
 use humantime::parse_duration;
 use ic_agent::export::Principal;
+use ic_agent::identity::{AnonymousIdentity, BasicIdentity, Secp256k1Identity};
+use ic_agent::Identity;
 use ic_async_utils::shutdown_signal;
 use ic_metrics::MetricsRegistry;
 use obs_canister_clients::node_status_canister_client::NodeStatusCanister;
@@ -13,6 +16,7 @@ use std::{path::PathBuf, sync::Arc, time::Duration};
 use url::Url;
 
 mod canister_updater_loop;
+mod health;
 
 fn main() -> Result<()> {
     let decorator = slog_term::TermDecorator::new().build();
@@ -78,9 +82,13 @@ fn main() -> Result<()> {
         ic_discovery.clone(),
         stop_signal_rcv.clone(),
         update_signal_rcv.clone(),
-        NodeStatusCanister::new(nns_url, cli_args.canister_id.parse().unwrap()),
+        NodeStatusCanister::new_with_identity(
+            nns_url,
+            cli_args.canister_id.parse().unwrap(),
+            load_identity(&log, cli_args.identity_pem)?,
+        ),
         rt.handle().clone(),
-        Client::try_from(cli_args.prometheus_url.as_str())?,
+        HealthChecker::new(Client::try_from(cli_args.prometheus_url.as_str())?, cli_args.health_checks),
     );
     info!(log, "Spawning canister updater thread");
     let canister_join_handle = std::thread::spawn(canister_updater_loop);
@@ -180,6 +188,46 @@ NNS-url to use for syncing the registry version.
 "#
     )]
     prometheus_url: Url,
+
+    #[clap(
+        long = "identity-pem",
+        help = r#"
+Path to the PEM file of the identity used to update the canister. The
+principal of the identity has to be added to the writers of the canister
+by one of its controllers.
+"#
+    )]
+    identity_pem: Option<PathBuf>,
+
+    #[clap(
+        long = "health-checks",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "node-exporter,replica,finalization,orchestrator,metrics-proxy",
+        help = r#"
+The checks that the health of the nodes is derived from. A node is down if
+its node exporter or replica can't be scraped, and degraded if its
+finalization stalled, or its orchestrator or metrics proxy can't be scraped.
+"#
+    )]
+    health_checks: Vec<HealthCheck>,
+}
+
+fn load_identity(log: &slog::Logger, path: Option<PathBuf>) -> Result<Arc<dyn Identity>> {
+    let identity: Arc<dyn Identity> = match path {
+        Some(path) => match BasicIdentity::from_pem_file(&path) {
+            Ok(identity) => Arc::new(identity),
+            Err(_) => Arc::new(Secp256k1Identity::from_pem_file(&path).map_err(|e| anyhow::anyhow!("Couldn't load identity: {:?}", e))?),
+        },
+        None => {
+            warn!(log, "No identity given, the canister will refuse the updates of the anonymous principal");
+            Arc::new(AnonymousIdentity)
+        }
+    };
+    if let Ok(principal) = identity.sender() {
+        info!(log, "Updating the canister as {}", principal);
+    }
+    Ok(identity)
 }
 
 impl CliArgs {
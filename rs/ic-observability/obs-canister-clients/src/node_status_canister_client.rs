@@ -1,8 +1,9 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use candid::{CandidType, Decode, Encode};
 use ic_agent::agent::http_transport::ReqwestTransport;
-use ic_agent::{export::Principal, identity::AnonymousIdentity, Agent};
+use ic_agent::{export::Principal, identity::AnonymousIdentity, Agent, Identity};
 use rand::seq::SliceRandom;
 use serde::Deserialize;
 use url::Url;
@@ -17,6 +18,28 @@ pub enum NodeStatusCanisterError {
     Encoding(String),
     Decoding(String),
     Unknown(String),
+    /// The canister refused the update because the caller is not on its list
+    /// of writers
+    Unauthorized(Principal),
+    /// The canister refused to change its list of writers because the caller
+    /// is not one of its controllers
+    NotController(Principal),
+}
+
+/// Errors returned by the canister itself
+#[derive(CandidType, Deserialize, Debug, PartialEq, Clone)]
+enum CanisterError {
+    Unauthorized(Principal),
+    NotController(Principal),
+}
+
+impl From<CanisterError> for NodeStatusCanisterError {
+    fn from(err: CanisterError) -> Self {
+        match err {
+            CanisterError::Unauthorized(principal) => NodeStatusCanisterError::Unauthorized(principal),
+            CanisterError::NotController(principal) => NodeStatusCanisterError::NotController(principal),
+        }
+    }
 }
 
 #[derive(CandidType, Default)]
@@ -26,7 +49,9 @@ struct Argument {}
 pub struct NodeStatus {
     pub node_id: Principal,
     pub subnet_id: Option<Principal>,
+    /// Whether the node is up, i.e. its health is not `Down`
     pub status: bool,
+    pub health: Option<NodeHealth>,
 }
 
 impl Default for NodeStatus {
@@ -35,12 +60,44 @@ impl Default for NodeStatus {
             node_id: Principal::anonymous(),
             subnet_id: None,
             status: false,
+            health: None,
         }
     }
 }
 
+#[derive(CandidType, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum HealthLevel {
+    Healthy,
+    Degraded,
+    Down,
+}
+
+#[derive(CandidType, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum HealthReason {
+    NodeExporterDown,
+    ReplicaDown,
+    FinalizationStalled,
+    OrchestratorDown,
+    MetricsProxyUnreachable,
+    /// None of the health checks returned anything for the node
+    NoMetrics,
+}
+
+#[derive(CandidType, Deserialize, Debug, PartialEq, Clone)]
+pub struct NodeHealth {
+    pub level: HealthLevel,
+    /// Why the node is not healthy
+    pub reasons: Vec<HealthReason>,
+}
+
 impl NodeStatusCanister {
     pub fn new(url: Vec<Url>, canister_id: String) -> Self {
+        Self::new_with_identity(url, canister_id, Arc::new(AnonymousIdentity))
+    }
+
+    /// Updates are only accepted from the writers of the canister, so writing
+    /// clients have to be created with the identity of one of them.
+    pub fn new_with_identity(url: Vec<Url>, canister_id: String, identity: Arc<dyn Identity>) -> Self {
         assert!(!url.is_empty(), "empty list of URLs passed to NodeStatusCanister::new()");
 
         NodeStatusCanister {
@@ -55,7 +112,7 @@ impl NodeStatusCanister {
                         .expect("Could not create HTTP client.");
                     Agent::builder()
                         .with_transport(ReqwestTransport::create_with_client(url.as_str(), client).expect("Failed to create transport"))
-                        .with_identity(AnonymousIdentity)
+                        .with_arc_identity(identity.clone())
                         .with_verify_query_signatures(false)
                         .build()
                         .expect("Failed to build agent")
@@ -102,33 +159,43 @@ impl NodeStatusCanister {
     }
 
     pub async fn update_node_statuses(&self, statuses: Vec<NodeStatus>) -> Result<bool, NodeStatusCanisterError> {
+        let arg = Encode! { &statuses }
+            .map_err(|err| NodeStatusCanisterError::Encoding(format!("Error encoding argument for update_node_status: {}", err)))?;
+        self.update("update_node_status", arg).await
+    }
+
+    /// Removes the statuses of nodes that are no longer in the registry
+    pub async fn remove_nodes(&self, node_ids: Vec<Principal>) -> Result<bool, NodeStatusCanisterError> {
+        let arg =
+            Encode! { &node_ids }.map_err(|err| NodeStatusCanisterError::Encoding(format!("Error encoding argument for remove_nodes: {}", err)))?;
+        self.update("remove_nodes", arg).await
+    }
+
+    async fn update(&self, method: &str, arg: Vec<u8>) -> Result<bool, NodeStatusCanisterError> {
         let request_id = match self
             .choose_random_agent()
             .await
-            .update(&self.canister_id, "update_node_status")
+            .update(&self.canister_id, method)
             .with_effective_canister_id(self.canister_id)
-            .with_arg(
-                Encode! { &statuses }
-                    .map_err(|err| NodeStatusCanisterError::Encoding(format!("Error encoding argument for update_node_status: {}", err)))?,
-            )
+            .with_arg(arg)
             .call()
             .await
         {
             Ok(result) => result,
-            Err(err) => return Err(NodeStatusCanisterError::Unknown(format!("Error on update_node_status request: {}", err))),
+            Err(err) => return Err(NodeStatusCanisterError::Unknown(format!("Error on {} request: {}", method, err))),
         };
 
         match self.choose_random_agent().await.wait(request_id, self.canister_id).await {
-            Ok(response) => match Decode!(response.as_slice(), bool) {
-                Ok(response) => Ok(response),
+            Ok(response) => match Decode!(response.as_slice(), Result<bool, CanisterError>) {
+                Ok(response) => response.map_err(NodeStatusCanisterError::from),
                 Err(e) => Err(NodeStatusCanisterError::Decoding(format!(
-                    "Error decoding response for update_node_status: {}",
-                    e
+                    "Error decoding response for {}: {}",
+                    method, e
                 ))),
             },
             Err(err) => Err(NodeStatusCanisterError::Unknown(format!(
-                "Error on getting response for update_node_status: {}",
-                err
+                "Error on getting response for {}: {}",
+                method, err
             ))),
         }
     }
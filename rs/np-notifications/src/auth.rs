@@ -0,0 +1,260 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use ic_types::PrincipalId;
+use rand::{thread_rng, RngCore};
+
+const CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+const SESSION_TTL: Duration = Duration::from_secs(60 * 60);
+/// Challenges can be asked for without being authenticated, so there is a
+/// limit to how many can be pending at once
+const MAX_CHALLENGES: usize = 10_000;
+/// A node provider can log in as many times as they want, so there is also a
+/// limit to how many sessions can be open at once
+const MAX_SESSIONS: usize = 10_000;
+
+/// Authentication of the node providers using the subscription API.
+///
+/// A node provider asks for a challenge for their principal, signs it with
+/// the key of that principal, and gets a session token in exchange for the
+/// signature and the DER encoded public key. The principal has to be the self
+/// authenticating principal of the key, which is the case for the principals
+/// of Ed25519 and secp256k1 identities, as created by `dfx identity`.
+#[derive(Debug, Default)]
+pub struct Auth {
+    challenges: Mutex<BTreeMap<String, Pending>>,
+    sessions: Mutex<BTreeMap<String, Pending>>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    principal: PrincipalId,
+    expires: Instant,
+}
+
+impl Auth {
+    pub fn challenge(&self, principal: PrincipalId) -> Result<String> {
+        let challenge = random_hex();
+        let mut challenges = self.challenges.lock().unwrap();
+        let now = Instant::now();
+        challenges.retain(|_, pending| pending.expires > now);
+        if challenges.len() >= MAX_CHALLENGES {
+            return Err(anyhow!("Too many pending challenges, try again later"));
+        }
+        challenges.insert(
+            challenge.clone(),
+            Pending {
+                principal,
+                expires: now + CHALLENGE_TTL,
+            },
+        );
+        Ok(challenge)
+    }
+
+    /// Exchanges a signed challenge for a session token. A challenge can only
+    /// be used once, and only the given node providers can log in.
+    pub fn login(
+        &self,
+        principal: PrincipalId,
+        challenge: &str,
+        public_key: &[u8],
+        signature: &[u8],
+        node_providers: &BTreeSet<PrincipalId>,
+    ) -> Result<String> {
+        if !node_providers.contains(&principal) {
+            return Err(anyhow!("{} is not a registered node provider", principal));
+        }
+        let pending = self
+            .challenges
+            .lock()
+            .unwrap()
+            .remove(challenge)
+            .filter(|pending| pending.expires > Instant::now() && pending.principal == principal)
+            .ok_or_else(|| anyhow!("Unknown or expired challenge"))?;
+        if PrincipalId::new_self_authenticating(public_key) != pending.principal {
+            return Err(anyhow!("The public key is not the one of principal {}", principal));
+        }
+        verify(public_key, &challenge_message(challenge), signature)?;
+
+        let token = random_hex();
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        sessions.retain(|_, session| session.expires > now);
+        if sessions.len() >= MAX_SESSIONS {
+            return Err(anyhow!("Too many open sessions, try again later"));
+        }
+        sessions.insert(
+            token.clone(),
+            Pending {
+                principal,
+                expires: now + SESSION_TTL,
+            },
+        );
+        Ok(token)
+    }
+
+    /// The principal the session token was given to, if it is still valid
+    pub fn principal(&self, token: &str) -> Option<PrincipalId> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(token)
+            .filter(|session| session.expires > Instant::now())
+            .map(|session| session.principal)
+    }
+}
+
+/// What is signed to answer a challenge. The prefix prevents the signature
+/// from being used for anything else.
+pub fn challenge_message(challenge: &str) -> Vec<u8> {
+    format!("np-notifications login: {}", challenge).into_bytes()
+}
+
+fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<()> {
+    use ed25519_dalek::pkcs8::DecodePublicKey as _;
+    use k256::ecdsa::signature::Verifier as _;
+    use k256::pkcs8::DecodePublicKey as _;
+
+    if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_der(public_key) {
+        let signature = ed25519_dalek::Signature::from_slice(signature)?;
+        return key.verify_strict(message, &signature).map_err(|_| anyhow!("Invalid signature"));
+    }
+    if let Ok(key) = k256::ecdsa::VerifyingKey::from_public_key_der(public_key) {
+        let signature = k256::ecdsa::Signature::from_slice(signature)?;
+        return key.verify(message, &signature).map_err(|_| anyhow!("Invalid signature"));
+    }
+    Err(anyhow!("Unsupported public key, expected a DER encoded Ed25519 or secp256k1 key"))
+}
+
+fn random_hex() -> String {
+    let mut bytes = [0u8; 32];
+    thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::pkcs8::EncodePublicKey as _;
+    use ed25519_dalek::Signer as _;
+    use std::{
+        collections::BTreeSet,
+        time::{Duration, Instant},
+    };
+
+    use ic_types::PrincipalId;
+    use k256::ecdsa::signature::Signer as _;
+
+    use super::{challenge_message, Auth, Pending, MAX_CHALLENGES, MAX_SESSIONS};
+
+    #[test]
+    fn ed25519_login() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let public_key = key.verifying_key().to_public_key_der().unwrap().into_vec();
+        let principal = PrincipalId::new_self_authenticating(&public_key);
+        let node_providers = BTreeSet::from([principal]);
+        let auth = Auth::default();
+
+        let challenge = auth.challenge(principal).unwrap();
+        let signature = key.sign(&challenge_message(&challenge)).to_bytes();
+        let token = auth.login(principal, &challenge, &public_key, &signature, &node_providers).unwrap();
+        assert_eq!(auth.principal(&token), Some(principal));
+
+        // Challenges can only be used once
+        assert!(auth.login(principal, &challenge, &public_key, &signature, &node_providers).is_err());
+        assert_eq!(auth.principal("unknown"), None);
+    }
+
+    #[test]
+    fn secp256k1_login() {
+        let key = k256::ecdsa::SigningKey::from_slice(&[1; 32]).unwrap();
+        let public_key = k256::pkcs8::EncodePublicKey::to_public_key_der(key.verifying_key()).unwrap().into_vec();
+        let principal = PrincipalId::new_self_authenticating(&public_key);
+        let node_providers = BTreeSet::from([principal]);
+        let auth = Auth::default();
+
+        let challenge = auth.challenge(principal).unwrap();
+        let signature: k256::ecdsa::Signature = key.sign(&challenge_message(&challenge));
+        assert!(auth
+            .login(principal, &challenge, &public_key, &signature.to_bytes(), &node_providers)
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_other_principal_or_signature() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let public_key = key.verifying_key().to_public_key_der().unwrap().into_vec();
+        let principal = PrincipalId::new_self_authenticating(&public_key);
+        let other = PrincipalId::new_user_test_id(1);
+        let node_providers = BTreeSet::from([principal, other]);
+        let auth = Auth::default();
+
+        // The key is not the one of the principal
+        let challenge = auth.challenge(other).unwrap();
+        let signature = key.sign(&challenge_message(&challenge)).to_bytes();
+        assert!(auth.login(other, &challenge, &public_key, &signature, &node_providers).is_err());
+
+        // The challenge was given to another principal
+        let challenge = auth.challenge(other).unwrap();
+        let signature = key.sign(&challenge_message(&challenge)).to_bytes();
+        assert!(auth.login(principal, &challenge, &public_key, &signature, &node_providers).is_err());
+
+        // The signature is not the one of the challenge
+        let challenge = auth.challenge(principal).unwrap();
+        let signature = key.sign(b"something else").to_bytes();
+        assert!(auth.login(principal, &challenge, &public_key, &signature, &node_providers).is_err());
+    }
+
+    #[test]
+    fn pending_challenges_are_capped() {
+        let auth = Auth::default();
+        for _ in 0..MAX_CHALLENGES {
+            auth.challenge(PrincipalId::new_user_test_id(1)).unwrap();
+        }
+        assert!(auth.challenge(PrincipalId::new_user_test_id(1)).is_err());
+    }
+
+    #[test]
+    fn rejects_unregistered_node_provider() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let public_key = key.verifying_key().to_public_key_der().unwrap().into_vec();
+        let principal = PrincipalId::new_self_authenticating(&public_key);
+        let auth = Auth::default();
+
+        let challenge = auth.challenge(principal).unwrap();
+        let signature = key.sign(&challenge_message(&challenge)).to_bytes();
+        let node_providers = BTreeSet::from([PrincipalId::new_user_test_id(1)]);
+        assert!(auth.login(principal, &challenge, &public_key, &signature, &node_providers).is_err());
+    }
+
+    #[test]
+    fn sessions_are_capped() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let public_key = key.verifying_key().to_public_key_der().unwrap().into_vec();
+        let principal = PrincipalId::new_self_authenticating(&public_key);
+        let node_providers = BTreeSet::from([principal]);
+        let auth = Auth::default();
+        let expires = Instant::now() + Duration::from_secs(60);
+        auth.sessions
+            .lock()
+            .unwrap()
+            .extend((0..MAX_SESSIONS).map(|i| (i.to_string(), Pending { principal, expires })));
+
+        let challenge = auth.challenge(principal).unwrap();
+        let signature = key.sign(&challenge_message(&challenge)).to_bytes();
+        assert!(auth.login(principal, &challenge, &public_key, &signature, &node_providers).is_err());
+
+        // Expired sessions do not count
+        auth.sessions
+            .lock()
+            .unwrap()
+            .values_mut()
+            .for_each(|session| session.expires = Instant::now());
+        let challenge = auth.challenge(principal).unwrap();
+        let signature = key.sign(&challenge_message(&challenge)).to_bytes();
+        assert!(auth.login(principal, &challenge, &public_key, &signature, &node_providers).is_ok());
+    }
+}
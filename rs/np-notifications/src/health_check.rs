@@ -2,6 +2,7 @@ use actix_web::{rt::time::sleep, web};
 use core::time;
 use ic_management_types::NodeProvidersResponse;
 use std::sync::mpsc::Sender;
+use std::time::SystemTime;
 
 use ic_management_backend::{
     health::{HealthClient, HealthStatusQuerier},
@@ -11,13 +12,17 @@ use ic_management_backend::{
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
 
-use crate::{nodes_status::NodesStatus, notification::Notification, ServiceHealth};
+use crate::{nodes_status::NodesStatus, notification::Notification, state::StateDir, subscriptions::Subscriptions, ServiceHealth};
+
+const NODES_STATUS_FILE: &str = "nodes_status";
 
 pub struct HealthCheckLoopConfig {
     pub notification_sender: Sender<Notification>,
     pub cancellation_token: CancellationToken,
     pub registry_state: RegistryState,
     pub service_health: web::Data<ServiceHealth>,
+    pub state_dir: StateDir,
+    pub subscriptions: web::Data<Subscriptions>,
 }
 
 // There is no real information in the Config, so just print its name as debug
@@ -35,7 +40,18 @@ pub async fn start_health_check_loop(config: HealthCheckLoopConfig) {
         .await
         .expect("failed to create mainnet network");
     let hc = HealthClient::new(network.clone());
-    let mut nodes_status = NodesStatus::from(hc.nodes().await.unwrap());
+    // Starting from the statuses seen before a restart notifies the changes
+    // that happened while the service was down. Nodes seen for the first time
+    // have had their status since now, as far as the service knows.
+    let mut nodes_status = match config.state_dir.load::<NodesStatus>(NODES_STATUS_FILE) {
+        Ok(Some(nodes_status)) => nodes_status,
+        Ok(None) => NodesStatus::from(hc.nodes().await.unwrap()),
+        Err(e) => {
+            error!(message = "Failed to load the last nodes statuses", error = e.to_string());
+            NodesStatus::from(hc.nodes().await.unwrap())
+        }
+    }
+    .seeded(SystemTime::now());
 
     let mut rs = config.registry_state;
     // NOTE: The way this is now, we would only update the list of node
@@ -60,10 +76,13 @@ pub async fn start_health_check_loop(config: HealthCheckLoopConfig) {
                 config.service_health.set_health_check_loop_readiness(true);
                 // Probably need to change the way we create the notifications there to
                 // include the fetching from the registry
-                let (new_nodes_status, notifications) = nodes_status.updated_from_map(new_statuses);
+                let (new_nodes_status, notifications) = nodes_status.updated_from_map(new_statuses, SystemTime::now());
                 // We probably want to have the registry updates separate, so
                 // that we don't update every 5 seconds
                 let _ = rs.update_node_details(&node_providers).await;
+                config
+                    .subscriptions
+                    .set_node_providers(rs.operators().values().map(|operator| operator.provider.principal).collect());
                 for notification in notifications {
                     let node = rs.node(notification.node_id).await;
 
@@ -74,11 +93,17 @@ pub async fn start_health_check_loop(config: HealthCheckLoopConfig) {
                         .notification_sender
                         .send(Notification {
                             node_provider: Some(node.operator.provider),
+                            node_operator_id: Some(node.operator.principal),
+                            dc_id: node.operator.datacenter.map(|dc| dc.name),
+                            subnet_id: node.subnet_id,
                             ..notification.clone()
                         })
                         .expect("Could not send notification. The notification sender is probably dead, exitting...");
                 }
                 nodes_status = new_nodes_status;
+                if let Err(e) = config.state_dir.save(NODES_STATUS_FILE, &nodes_status) {
+                    error!(message = "Failed to save the nodes statuses", error = e.to_string());
+                }
             }
             Err(e) => {
                 // TODO if we cannot get the nodes, this will kill the loop.
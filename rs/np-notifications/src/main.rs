@@ -25,7 +25,9 @@
 // What happens on first start ?
 // How reliable does the service need to be ?
 
+use std::path::PathBuf;
 use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
 
 use actix_web::rt::signal;
 use actix_web::{web, App, HttpServer};
@@ -42,18 +44,26 @@ use url::Url;
 
 use crate::health_check::start_health_check_loop;
 use crate::notification::start_notification_sender_loop;
+use crate::pipeline::{DigestSchedule, Pipeline};
 use crate::registry::{start_registry_updater_loop, RegistryLoopConfig};
 use crate::router::Router;
 use crate::service_health::ServiceHealth;
 use crate::sink::{LogSink, Sink};
+use crate::state::StateDir;
+use crate::subscriptions::Subscriptions;
 
+mod auth;
 mod health_check;
 mod nodes_status;
 mod notification;
+mod outbox;
+mod pipeline;
 mod registry;
 mod router;
 mod service_health;
 mod sink;
+mod state;
+mod subscriptions;
 
 #[actix_web::main]
 async fn main() {
@@ -68,11 +78,15 @@ async fn main() {
 
     // TODO Centralize sending all notifications using the router
     let router = Router::new_from_config_file().expect("should create a new router");
+    let state_dir = StateDir::new(cli_opts.state_dir.clone()).expect("should create the state directory");
 
     let (notif_sender, notif_receiver) = mpsc::channel();
     let cancellation_token = CancellationToken::new();
 
     let service_health = web::Data::new(ServiceHealth::new());
+    let subscriptions =
+        web::Data::new(Subscriptions::load(state_dir.clone(), cli_opts.allow_private_webhooks).expect("should load the subscriptions"));
+    let auth = web::Data::new(auth::Auth::default());
 
     actix_web::rt::spawn(start_registry_updater_loop(RegistryLoopConfig {
         cancellation_token: cancellation_token.clone(),
@@ -85,6 +99,8 @@ async fn main() {
         cancellation_token: cancellation_token.clone(),
         registry_state: registry::create_registry_state(target_network).await,
         service_health: service_health.clone(),
+        state_dir: state_dir.clone(),
+        subscriptions: subscriptions.clone(),
     }));
 
     actix_web::rt::spawn(start_notification_sender_loop(
@@ -93,6 +109,11 @@ async fn main() {
             cancellation_token: cancellation_token.clone(),
             router,
             service_health: service_health.clone(),
+            pipeline: Pipeline::new(cli_opts.dwell_time),
+            digest_schedule: DigestSchedule::new(cli_opts.digest_hour, SystemTime::now()),
+            state_dir,
+            max_attempts: cli_opts.max_attempts,
+            subscriptions: subscriptions.clone(),
         },
         vec![Sink::Log(LogSink {})],
     ));
@@ -101,8 +122,16 @@ async fn main() {
     let srv = HttpServer::new(move || {
         App::new()
             .app_data(service_health.clone())
+            .app_data(auth.clone())
+            .app_data(subscriptions.clone())
             .service(service_health::alive)
             .service(service_health::ready)
+            .service(subscriptions::challenge)
+            .service(subscriptions::login)
+            .service(subscriptions::list)
+            .service(subscriptions::create)
+            .service(subscriptions::remove)
+            .service(subscriptions::send_test)
     })
     .shutdown_timeout(5)
     .disable_signals()
@@ -138,4 +167,31 @@ struct Cli {
     // The argument is mandatory for testnets, and is optional for mainnet and staging
     #[clap(long, env = "NNS_URLS", aliases = &["registry-url", "nns-url"], value_delimiter = ',')]
     pub nns_urls: Vec<Url>,
+
+    // How long a node has to stay in its new status before the change is
+    // notified. Changes that are reverted in the meantime are not notified.
+    #[clap(long, env = "DWELL_TIME", default_value = "5m", value_parser = humantime::parse_duration)]
+    dwell_time: Duration,
+
+    // Hour of the day, in UTC, at which the digests of the routes that deliver
+    // them are sent
+    #[clap(long, env = "DIGEST_HOUR", default_value = "8", value_parser = clap::value_parser!(u64).range(0..24))]
+    digest_hour: u64,
+
+    // Directory where the state is kept across restarts: the last seen
+    // statuses, the notifications that remain to be sent, and the ones that
+    // could not be sent. Nothing is kept if not set.
+    #[clap(long, env = "STATE_DIR")]
+    state_dir: Option<PathBuf>,
+
+    // How many times sending a notification to a sink is attempted before
+    // giving up on it
+    #[clap(long, env = "MAX_ATTEMPTS", default_value = "10")]
+    max_attempts: u32,
+
+    // Lets the webhooks registered through the subscription API target
+    // loopback, private and link-local addresses. Only meant for local
+    // testing.
+    #[clap(long, env = "ALLOW_PRIVATE_WEBHOOKS")]
+    allow_private_webhooks: bool,
 }
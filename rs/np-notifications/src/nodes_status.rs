@@ -1,24 +1,46 @@
 use std::collections::{BTreeMap, BTreeSet};
+use std::time::SystemTime;
 
 use ic_management_types::Status;
 use ic_types::PrincipalId;
+use serde::{Deserialize, Serialize};
 
 use crate::notification::Notification;
 
-#[derive(Debug, PartialOrd, Ord, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialOrd, Ord, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct NodesStatus {
     nodes: BTreeMap<PrincipalId, Status>,
+    /// When each node changed to its current status, or when it was first
+    /// seen if that is not known. It is persisted with the statuses, so that
+    /// the duration of the previous status is known after a restart.
+    #[serde(default)]
+    since: BTreeMap<PrincipalId, SystemTime>,
 }
 
 impl From<BTreeMap<PrincipalId, Status>> for NodesStatus {
     fn from(tree: BTreeMap<PrincipalId, Status>) -> Self {
-        Self { nodes: tree }
+        Self {
+            nodes: tree,
+            since: BTreeMap::new(),
+        }
     }
 }
 
 impl NodesStatus {
     pub fn _new() -> Self {
-        Self { nodes: BTreeMap::new() }
+        Self {
+            nodes: BTreeMap::new(),
+            since: BTreeMap::new(),
+        }
+    }
+
+    /// Takes `now` as the time since which the nodes without a known time,
+    /// e.g. on the first start of the service, have their current status
+    pub fn seeded(mut self, now: SystemTime) -> Self {
+        for node_id in self.nodes.keys() {
+            self.since.entry(*node_id).or_insert(now);
+        }
+        self
     }
 
     pub fn get_set_of_node_ids(&self) -> BTreeSet<PrincipalId> {
@@ -29,11 +51,14 @@ impl NodesStatus {
         self.nodes.get(&id)
     }
 
-    pub fn updated_from_map(&self, map: BTreeMap<PrincipalId, Status>) -> (NodesStatus, Vec<Notification>) {
-        self.updated(Self::from(map))
+    pub fn updated_from_map(&self, map: BTreeMap<PrincipalId, Status>, now: SystemTime) -> (NodesStatus, Vec<Notification>) {
+        self.updated(Self::from(map), now)
     }
 
-    pub fn updated(&self, new_statuses: NodesStatus) -> (NodesStatus, Vec<Notification>) {
+    /// The new statuses, along with the notifications of the nodes whose
+    /// status changed at the time `now`, with the duration of their previous
+    /// status when it is known
+    pub fn updated(&self, new_statuses: NodesStatus, now: SystemTime) -> (NodesStatus, Vec<Notification>) {
         let mut notifications = vec![];
 
         // If node in new_statuses and in current, test status change
@@ -56,6 +81,7 @@ impl NodesStatus {
                         .clone(),
                 ),
                 node_provider: None,
+                ..Default::default()
             })
         }
 
@@ -71,6 +97,7 @@ impl NodesStatus {
                     Status::Unknown,
                 ),
                 node_provider: None,
+                ..Default::default()
             })
         }
 
@@ -90,20 +117,39 @@ impl NodesStatus {
                             .clone(),
                     ),
                     node_provider: None,
+                    ..Default::default()
                 })
             }
         }
 
-        (new_statuses, notifications)
+        let since = self
+            .since
+            .iter()
+            .filter(|(node_id, _)| new_statuses.nodes.contains_key(node_id))
+            .map(|(node_id, since)| (*node_id, *since))
+            .collect();
+        let mut new_statuses = NodesStatus { since, ..new_statuses };
+        for notification in notifications.iter_mut() {
+            notification.previous_status_duration = self.since.get(&notification.node_id).and_then(|since| now.duration_since(*since).ok());
+            if new_statuses.nodes.contains_key(&notification.node_id) {
+                new_statuses.since.insert(notification.node_id, now);
+            }
+        }
+
+        (new_statuses.seeded(now), notifications)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use ic_management_types::Status;
     use ic_types::PrincipalId;
     use pretty_assertions::assert_eq;
 
+    use crate::state::StateDir;
+
     use super::*;
 
     #[test]
@@ -119,30 +165,76 @@ mod tests {
             PrincipalId::new_node_test_id(3),
         ];
 
-        let statuses = NodesStatus {
-            nodes: BTreeMap::from([(ids[0], Status::Healthy), (ids[1], Status::Healthy), (ids[2], Status::Healthy)]),
-        };
-        let new_statuses = NodesStatus {
-            nodes: BTreeMap::from([(ids[0], Status::Healthy), (ids[1], Status::Degraded), (ids[3], Status::Healthy)]),
-        };
-        let (statuses, notifications) = statuses.updated(new_statuses.clone());
-
-        assert_eq!(statuses, new_statuses);
+        let statuses = NodesStatus::from(BTreeMap::from([
+            (ids[0], Status::Healthy),
+            (ids[1], Status::Healthy),
+            (ids[2], Status::Healthy),
+        ]));
+        let new_statuses = NodesStatus::from(BTreeMap::from([
+            (ids[0], Status::Healthy),
+            (ids[1], Status::Degraded),
+            (ids[3], Status::Healthy),
+        ]));
+        let now = SystemTime::now();
+        let (statuses, notifications) = statuses.updated(new_statuses.clone(), now);
+
+        assert_eq!(statuses, new_statuses.seeded(now));
         assert_eq!(notifications.len(), 3);
         assert!(notifications.contains(&Notification {
             node_id: ids[1],
             node_provider: None,
             status_change: (Status::Healthy, Status::Degraded),
+            ..Default::default()
         }));
         assert!(notifications.contains(&Notification {
             node_id: ids[2],
             node_provider: None,
             status_change: (Status::Healthy, Status::Unknown),
+            ..Default::default()
         }));
         assert!(notifications.contains(&Notification {
             node_id: ids[3],
             node_provider: None,
             status_change: (Status::Unknown, Status::Healthy),
+            ..Default::default()
         }));
     }
+
+    #[test]
+    fn previous_status_duration_survives_a_restart() {
+        let node_id = PrincipalId::new_node_test_id(0);
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let statuses = NodesStatus::from(BTreeMap::from([(node_id, Status::Healthy)])).seeded(start);
+
+        // The service restarts with the statuses it saved
+        let dir = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(Some(dir.path().to_path_buf())).unwrap();
+        state_dir.save("nodes_status", &statuses).unwrap();
+        let statuses = state_dir
+            .load::<NodesStatus>("nodes_status")
+            .unwrap()
+            .unwrap()
+            .seeded(start + Duration::from_secs(3600));
+
+        let (statuses, notifications) = statuses.updated_from_map(BTreeMap::from([(node_id, Status::Dead)]), start + Duration::from_secs(7200));
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].previous_status_duration, Some(Duration::from_secs(7200)));
+
+        let (_, notifications) = statuses.updated_from_map(BTreeMap::from([(node_id, Status::Healthy)]), start + Duration::from_secs(7260));
+        assert_eq!(notifications[0].previous_status_duration, Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn statuses_saved_without_times_are_seeded() {
+        let node_id = PrincipalId::new_node_test_id(0);
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut saved = serde_json::to_value(NodesStatus::from(BTreeMap::from([(node_id, Status::Healthy)]))).unwrap();
+        saved.as_object_mut().unwrap().remove("since");
+        let statuses: NodesStatus = serde_json::from_value(saved).unwrap();
+
+        let (_, notifications) = statuses
+            .seeded(start)
+            .updated_from_map(BTreeMap::from([(node_id, Status::Dead)]), start + Duration::from_secs(600));
+        assert_eq!(notifications[0].previous_status_duration, Some(Duration::from_secs(600)));
+    }
 }
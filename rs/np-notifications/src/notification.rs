@@ -2,17 +2,27 @@ use core::time;
 use std::{
     fmt::{self, Display},
     sync::mpsc::Receiver,
+    time::{Duration, Instant, SystemTime},
 };
 
 use actix_web::{rt::time::sleep, web};
 use ic_management_types::{Provider, Status};
 use ic_types::PrincipalId;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::ser::{SerializeStruct, Serializer};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tokio_util::sync::CancellationToken;
-use tracing::debug;
+use tracing::{debug, error};
 
-use crate::{router::Router, sink::Sink, ServiceHealth};
+use crate::{
+    outbox::{self, Outbox},
+    pipeline::{DigestSchedule, Pipeline, StoredPending},
+    router::Router,
+    sink::Sink,
+    state::StateDir,
+    subscriptions::Subscriptions,
+    ServiceHealth,
+};
 
 #[derive(Debug)]
 pub struct NotificationSenderLoopConfig {
@@ -20,21 +30,128 @@ pub struct NotificationSenderLoopConfig {
     pub cancellation_token: CancellationToken,
     pub router: Router,
     pub service_health: web::Data<ServiceHealth>,
+    pub pipeline: Pipeline,
+    pub digest_schedule: DigestSchedule,
+    pub state_dir: StateDir,
+    /// How many times sending a message is attempted before it is moved to
+    /// the dead letters
+    pub max_attempts: u32,
+    pub subscriptions: web::Data<Subscriptions>,
+}
+
+const SENDER_STATE_FILE: &str = "notifications";
+
+/// What the notification sender loop needs to resume after a restart. It is
+/// saved at once, so that a notification is always either held, waiting for
+/// a digest, or in the outbox.
+#[derive(Debug, Serialize, Deserialize)]
+struct SenderState<O> {
+    pending: Vec<StoredPending>,
+    digests: Vec<PendingDigest>,
+    /// Day of the last digest that was sent, absent from the states saved
+    /// before it was
+    #[serde(default)]
+    digest_last_sent_day: Option<u64>,
+    outbox: O,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingDigest {
+    /// Id of the route the notifications are held for
+    route: String,
+    #[serde(with = "crate::state::notifications")]
+    notifications: Vec<Notification>,
+}
+
+fn save_sender_state(config: &NotificationSenderLoopConfig, outbox: &Outbox, now: Instant) {
+    let state = SenderState {
+        pending: config.pipeline.stored(now),
+        digests: config
+            .router
+            .pending_digests()
+            .into_iter()
+            .map(|(route, notifications)| PendingDigest { route, notifications })
+            .collect(),
+        digest_last_sent_day: Some(config.digest_schedule.last_sent_day()),
+        outbox,
+    };
+    if let Err(e) = config.state_dir.save(SENDER_STATE_FILE, &state) {
+        error!(message = "Failed to save the notifications state", error = e.to_string());
+    }
 }
 
 #[tracing::instrument]
-pub async fn start_notification_sender_loop(config: NotificationSenderLoopConfig, sinks: Vec<Sink>) {
+pub async fn start_notification_sender_loop(mut config: NotificationSenderLoopConfig, sinks: Vec<Sink>) {
     debug!("Starting notification sender loop");
+    let state = config.state_dir.load::<SenderState<Outbox>>(SENDER_STATE_FILE).unwrap_or_else(|e| {
+        error!(
+            message = "Failed to load the notifications state, starting from scratch",
+            error = e.to_string()
+        );
+        None
+    });
+    let mut outbox = Outbox::default();
+    let mut subscriptions_version = 0;
+    if let Some(state) = state {
+        config.pipeline.restore(state.pending, Instant::now());
+        let removed = config
+            .router
+            .restore_pending_digests(state.digests.into_iter().map(|d| (d.route, d.notifications)).collect());
+        let dead_lettered = !removed.is_empty();
+        for (route, notifications) in removed {
+            outbox::dead_letter_digest(&config.state_dir, route, notifications);
+        }
+        if let Some(day) = state.digest_last_sent_day {
+            config.digest_schedule.restore(day);
+        }
+        outbox = state.outbox;
+        // Saved right away, for the dead letters not to be stored again
+        if dead_lettered {
+            save_sender_state(&config, &outbox, Instant::now());
+        }
+    }
     loop {
         if config.cancellation_token.is_cancelled() {
             break;
         }
         config.service_health.set_notification_loop_readiness(true);
+        if config.subscriptions.version() != subscriptions_version {
+            subscriptions_version = config.subscriptions.version();
+            config
+                .router
+                .set_subscriptions(&config.subscriptions.all(), config.subscriptions.allows_private_targets());
+        }
+        let now = Instant::now();
+        let mut changed = false;
         while let Ok(notification) = config.notification_receiver.try_recv() {
+            config.pipeline.push(notification, now);
+            changed = true;
+        }
+        for group in config.pipeline.ready(now) {
             for sink in sinks.iter() {
-                let _ = sink.send(notification.clone()).await;
+                let _ = sink.send_group(&group).await;
             }
-            let _ = config.router.route(notification).await;
+            for (sink_id, message) in config.router.route_group(&group) {
+                outbox.push(sink_id, message, SystemTime::now());
+            }
+            changed = true;
+        }
+        if config.digest_schedule.due(SystemTime::now()) {
+            for (sink_id, digest) in config.router.digests() {
+                outbox.push(sink_id, digest, SystemTime::now());
+            }
+            changed = true;
+        }
+        // The messages are saved before being sent, so that they are sent
+        // again if the service stops while sending them
+        if changed {
+            save_sender_state(&config, &outbox, now);
+        }
+        if outbox
+            .deliver(&config.router, &config.state_dir, config.max_attempts, SystemTime::now())
+            .await
+        {
+            save_sender_state(&config, &outbox, now);
         }
         sleep(time::Duration::from_secs(1)).await;
     }
@@ -46,6 +163,61 @@ pub struct Notification {
     pub node_id: PrincipalId,
     pub node_provider: Option<Provider>,
     pub status_change: (Status, Status),
+    pub node_operator_id: Option<PrincipalId>,
+    pub dc_id: Option<String>,
+    pub subnet_id: Option<PrincipalId>,
+    /// How long the node had been in the status it changed from
+    pub previous_status_duration: Option<Duration>,
+}
+
+impl Default for Notification {
+    fn default() -> Self {
+        Self {
+            node_id: PrincipalId::new_anonymous(),
+            node_provider: None,
+            status_change: (Status::Unknown, Status::Unknown),
+            node_operator_id: None,
+            dc_id: None,
+            subnet_id: None,
+            previous_status_duration: None,
+        }
+    }
+}
+
+impl Notification {
+    /// One line description of the change, for the sinks that show text
+    pub fn summary(&self) -> String {
+        format!(
+            "Node {} changed status: {} -> {}",
+            self.node_id, self.status_change.0, self.status_change.1
+        )
+    }
+
+    /// What is known about the node, as label and value, for the sinks that
+    /// show text
+    pub fn details(&self) -> Vec<(&'static str, String)> {
+        let mut details = vec![("Node", self.node_id.to_string())];
+        if let Some(provider) = &self.node_provider {
+            details.push(("Node provider", provider.name.clone().unwrap_or_else(|| provider.principal.to_string())));
+        }
+        if let Some(operator) = &self.node_operator_id {
+            details.push(("Node operator", operator.to_string()));
+        }
+        if let Some(dc_id) = &self.dc_id {
+            details.push(("Data center", dc_id.clone()));
+        }
+        if let Some(subnet_id) = &self.subnet_id {
+            details.push(("Subnet", subnet_id.to_string()));
+        }
+        details.push(("Status", format!("{} -> {}", self.status_change.0, self.status_change.1)));
+        if let Some(duration) = self.previous_status_duration {
+            details.push((
+                "Previous status for",
+                humantime::format_duration(Duration::from_secs(duration.as_secs())).to_string(),
+            ));
+        }
+        details
+    }
 }
 
 impl Display for Notification {
@@ -67,20 +239,120 @@ impl Serialize for Notification {
     where
         S: Serializer,
     {
-        let n_fields = match &self.node_provider {
-            Some(_) => 3,
-            None => 2,
-        };
+        let n_fields = 2 + [
+            self.node_provider.is_some(),
+            self.node_operator_id.is_some(),
+            self.dc_id.is_some(),
+            self.subnet_id.is_some(),
+            self.previous_status_duration.is_some(),
+        ]
+        .iter()
+        .filter(|present| **present)
+        .count();
         let mut state = serializer.serialize_struct("Notification", n_fields)?;
         state.serialize_field("node_id", &self.node_id.to_string())?;
         if let Some(provider) = &self.node_provider {
             state.serialize_field("node_provider_id", &provider.principal.to_string())?;
         }
         state.serialize_field("status_change", &self.status_change)?;
+        if let Some(operator) = &self.node_operator_id {
+            state.serialize_field("node_operator_id", &operator.to_string())?;
+        }
+        if let Some(dc_id) = &self.dc_id {
+            state.serialize_field("dc_id", dc_id)?;
+        }
+        if let Some(subnet_id) = &self.subnet_id {
+            state.serialize_field("subnet_id", &subnet_id.to_string())?;
+        }
+        if let Some(duration) = &self.previous_status_duration {
+            state.serialize_field("previous_status_duration_seconds", &duration.as_secs())?;
+        }
         state.end()
     }
 }
 
+/// Notifications sent to the sinks as a single message. Either the changes of
+/// a node provider's nodes that happened together, or a digest of the changes
+/// over a day.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct NotificationGroup {
+    pub notifications: Vec<Notification>,
+    pub digest: bool,
+    /// Identifies the message across the attempts to send it, so that the
+    /// receiver can ignore the ones it already got
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+}
+
+impl From<Notification> for NotificationGroup {
+    fn from(notification: Notification) -> Self {
+        Self {
+            notifications: vec![notification],
+            digest: false,
+            idempotency_key: None,
+        }
+    }
+}
+
+impl NotificationGroup {
+    pub fn new(notifications: Vec<Notification>, digest: bool) -> Self {
+        Self {
+            notifications,
+            digest,
+            idempotency_key: Some(thread_rng().sample_iter(&Alphanumeric).take(32).map(char::from).collect()),
+        }
+    }
+
+    /// The notification, if the group only is a single real time change
+    pub fn single(&self) -> Option<&Notification> {
+        match self.notifications.as_slice() {
+            [notification] if !self.digest => Some(notification),
+            _ => None,
+        }
+    }
+
+    /// The node provider the notifications are about, if they are all about
+    /// the same one
+    pub fn node_provider(&self) -> Option<&Provider> {
+        let provider = self.notifications.first()?.node_provider.as_ref()?;
+        self.notifications
+            .iter()
+            .all(|n| n.node_provider.as_ref().map(|p| p.principal) == Some(provider.principal))
+            .then_some(provider)
+    }
+
+    pub fn summary(&self) -> String {
+        if let Some(notification) = self.single() {
+            return notification.summary();
+        }
+        let provider = self
+            .node_provider()
+            .map(|p| format!(" for {}", p.name.clone().unwrap_or_else(|| p.principal.to_string())))
+            .unwrap_or_default();
+        if self.digest {
+            format!("Daily digest{}: {} node status changes", provider, self.notifications.len())
+        } else {
+            format!("{} nodes changed status{}", self.notifications.len(), provider)
+        }
+    }
+
+    /// Label and value lines, for the sinks that show text. A single change
+    /// shows all its details, a group one line per change.
+    pub fn details(&self) -> Vec<(String, String)> {
+        if let Some(notification) = self.single() {
+            return notification
+                .details()
+                .into_iter()
+                .map(|(label, value)| (label.to_string(), value))
+                .collect();
+        }
+        self.notifications
+            .iter()
+            .map(|n| (format!("Node {}", n.node_id), format!("{} -> {}", n.status_change.0, n.status_change.1)))
+            .collect()
+    }
+}
+
 #[cfg(test)]
 impl Notification {
     pub fn new_test(id: u64) -> Self {
@@ -92,12 +364,15 @@ impl Notification {
                 website: None,
             }),
             status_change: (Status::Healthy, Status::Degraded),
+            ..Default::default()
         }
     }
 }
 #[cfg(test)]
 mod tests {
-    use crate::notification::Notification;
+    use ic_management_types::Status;
+
+    use crate::notification::{Notification, NotificationGroup};
 
     #[test]
     fn notification_serialization() {
@@ -110,4 +385,32 @@ mod tests {
         let serialized_notification = serde_json::to_string(&n).unwrap();
         assert_eq!(expected_serialized_notification, serialized_notification);
     }
+
+    #[test]
+    fn group_text() {
+        let single = NotificationGroup::from(Notification::new_test(0));
+        assert_eq!(single.summary(), Notification::new_test(0).summary());
+
+        let group = NotificationGroup {
+            notifications: vec![
+                Notification::new_test(0),
+                Notification {
+                    status_change: (Status::Degraded, Status::Dead),
+                    ..Notification::new_test(0)
+                },
+            ],
+            digest: false,
+            idempotency_key: None,
+        };
+        assert_eq!(group.summary(), "2 nodes changed status for test");
+        assert_eq!(
+            group.details(),
+            vec![
+                ("Node gwp4o-eaaaa-aaaaa-aaaap-2ai".to_string(), "Healthy -> Degraded".to_string()),
+                ("Node gwp4o-eaaaa-aaaaa-aaaap-2ai".to_string(), "Degraded -> Dead".to_string()),
+            ]
+        );
+        let digest = NotificationGroup { digest: true, ..group };
+        assert_eq!(digest.summary(), "Daily digest for test: 2 node status changes");
+    }
 }
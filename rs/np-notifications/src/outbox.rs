@@ -0,0 +1,188 @@
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::{
+    notification::{Notification, NotificationGroup},
+    router::Router,
+    state::StateDir,
+};
+
+const DEAD_LETTERS_FILE: &str = "dead_letters";
+/// Delay before the first retry, doubled at every failed attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// The messages that remain to be sent, by sink. A message that fails to be
+/// sent is retried with an exponential backoff, and moved to the dead letters
+/// once it failed `max_attempts` times.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Outbox {
+    messages: Vec<OutboxMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OutboxMessage {
+    sink_id: String,
+    #[serde(with = "crate::state::notifications")]
+    notifications: Vec<Notification>,
+    digest: bool,
+    idempotency_key: Option<String>,
+    attempts: u32,
+    next_attempt: SystemTime,
+    last_error: Option<String>,
+}
+
+impl OutboxMessage {
+    fn group(&self) -> NotificationGroup {
+        NotificationGroup {
+            notifications: self.notifications.clone(),
+            digest: self.digest,
+            idempotency_key: self.idempotency_key.clone(),
+        }
+    }
+}
+
+impl Outbox {
+    pub fn push(&mut self, sink_id: String, group: NotificationGroup, now: SystemTime) {
+        self.messages.push(OutboxMessage {
+            sink_id,
+            notifications: group.notifications,
+            digest: group.digest,
+            idempotency_key: group.idempotency_key,
+            attempts: 0,
+            next_attempt: now,
+            last_error: None,
+        });
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Sends the messages that are due, and returns whether the outbox
+    /// changed
+    pub async fn deliver(&mut self, router: &Router, dead_letters: &StateDir, max_attempts: u32, now: SystemTime) -> bool {
+        let mut changed = false;
+        let mut remaining = vec![];
+        for mut message in std::mem::take(&mut self.messages) {
+            if message.next_attempt > now {
+                remaining.push(message);
+                continue;
+            }
+            changed = true;
+            if !router.has_sink(&message.sink_id) {
+                message.last_error = Some("The sink is not in the configuration anymore".to_string());
+                dead_letter(dead_letters, &message);
+                continue;
+            }
+            match router.send(&message.sink_id, &message.group()).await {
+                Ok(()) => {}
+                Err(e) => {
+                    message.attempts += 1;
+                    message.last_error = Some(e.to_string());
+                    if message.attempts >= max_attempts {
+                        dead_letter(dead_letters, &message);
+                    } else {
+                        message.next_attempt = now + backoff(message.attempts);
+                        remaining.push(message);
+                    }
+                }
+            }
+        }
+        self.messages = remaining;
+        changed
+    }
+}
+
+fn backoff(attempts: u32) -> Duration {
+    INITIAL_BACKOFF
+        .checked_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .unwrap_or(MAX_BACKOFF)
+        .min(MAX_BACKOFF)
+}
+
+/// Stores the notifications held for the digest of a route that is not in the
+/// configuration anymore
+pub fn dead_letter_digest(dead_letters: &StateDir, route_id: String, notifications: Vec<Notification>) {
+    let message = OutboxMessage {
+        sink_id: route_id,
+        notifications,
+        digest: true,
+        idempotency_key: None,
+        attempts: 0,
+        next_attempt: SystemTime::now(),
+        last_error: Some("The route is not in the configuration anymore".to_string()),
+    };
+    dead_letter(dead_letters, &message);
+}
+
+fn dead_letter(dead_letters: &StateDir, message: &OutboxMessage) {
+    warn!(
+        message = "Giving up on sending notification",
+        sink = &message.sink_id,
+        attempts = message.attempts,
+        error = message.last_error.clone().unwrap_or_default(),
+    );
+    if let Err(e) = dead_letters.append(DEAD_LETTERS_FILE, message) {
+        error!(message = "Failed to store dead letter", error = e.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{backoff, Outbox};
+    use crate::{
+        notification::{Notification, NotificationGroup},
+        router::Router,
+        state::StateDir,
+    };
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert_eq!(backoff(1), Duration::from_secs(10));
+        assert_eq!(backoff(2), Duration::from_secs(20));
+        assert_eq!(backoff(4), Duration::from_secs(80));
+        assert_eq!(backoff(20), Duration::from_secs(3600));
+        assert_eq!(backoff(100), Duration::from_secs(3600));
+    }
+
+    #[actix_web::test]
+    async fn retries_then_dead_letters() {
+        let server = httptest::Server::run();
+        server.expect(
+            httptest::Expectation::matching(httptest::matchers::request::method_path("POST", "/hook"))
+                .times(3)
+                .respond_with(httptest::responders::status_code(500)),
+        );
+        let router = Router::new_from_config(&format!("routes:\n  - sinks:\n      - webhook:\n          url: {}", server.url("/hook"))).unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state = StateDir::new(Some(temp_dir.path().to_path_buf())).unwrap();
+
+        let start = SystemTime::now();
+        let mut outbox = Outbox::default();
+        let group = NotificationGroup::new(vec![Notification::new_test(0)], false);
+        for (sink_id, group) in router.route_group(&group) {
+            outbox.push(sink_id, group, start);
+        }
+
+        assert!(outbox.deliver(&router, &state, 3, start).await);
+        // Not due yet
+        assert!(!outbox.deliver(&router, &state, 3, start + Duration::from_secs(5)).await);
+        assert!(outbox.deliver(&router, &state, 3, start + Duration::from_secs(10)).await);
+        assert!(!outbox.is_empty());
+
+        // The outbox survives a restart
+        let mut outbox: Outbox = serde_json::from_str(&serde_json::to_string(&outbox).unwrap()).unwrap();
+        assert!(outbox.deliver(&router, &state, 3, start + Duration::from_secs(30)).await);
+        assert!(outbox.is_empty());
+
+        let dead_letters = std::fs::read_to_string(temp_dir.path().join("dead_letters.jsonl")).unwrap();
+        assert_eq!(dead_letters.lines().count(), 1);
+        assert!(dead_letters.contains(&group.idempotency_key.unwrap()));
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use ic_types::PrincipalId;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::notification::{Notification, NotificationGroup};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Stage between the health checks and the router, that holds the status
+/// changes of nodes until they settle.
+///
+/// A change is only released once the node stayed in its new status for the
+/// dwell time. If the node changes again in the meantime, both changes are
+/// merged, and dropped if the node is back to the status it started from, so
+/// that flapping nodes do not notify. The changes released at the same time
+/// are grouped by node provider, so that an outage affecting many nodes of a
+/// provider is a single message.
+#[derive(Debug)]
+pub struct Pipeline {
+    dwell_time: Duration,
+    pending: BTreeMap<PrincipalId, Pending>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    notification: Notification,
+    since: Instant,
+}
+
+/// A change held by the pipeline, as persisted
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredPending {
+    #[serde(with = "crate::state::notification")]
+    notification: Notification,
+    /// How long the change had been held
+    held_for: Duration,
+}
+
+impl Pipeline {
+    pub fn new(dwell_time: Duration) -> Self {
+        Self {
+            dwell_time,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, notification: Notification, now: Instant) {
+        let notification = match self.pending.remove(&notification.node_id) {
+            None => notification,
+            Some(Pending { notification: previous, .. }) => {
+                if previous.status_change.0 == notification.status_change.1 {
+                    debug!(
+                        message = "Suppressing flapping node",
+                        node_id = notification.node_id.to_string(),
+                        status = notification.status_change.1.to_string(),
+                    );
+                    return;
+                }
+                Notification {
+                    status_change: (previous.status_change.0, notification.status_change.1),
+                    previous_status_duration: previous.previous_status_duration,
+                    ..notification
+                }
+            }
+        };
+        self.pending.insert(notification.node_id, Pending { notification, since: now });
+    }
+
+    pub fn stored(&self, now: Instant) -> Vec<StoredPending> {
+        self.pending
+            .values()
+            .map(|pending| StoredPending {
+                notification: pending.notification.clone(),
+                held_for: now.duration_since(pending.since),
+            })
+            .collect()
+    }
+
+    /// Holds the changes again after a restart, for the time they were not
+    /// held yet
+    pub fn restore(&mut self, stored: Vec<StoredPending>, now: Instant) {
+        for StoredPending { notification, held_for } in stored {
+            let since = now.checked_sub(held_for).unwrap_or(now);
+            self.pending.insert(notification.node_id, Pending { notification, since });
+        }
+    }
+
+    /// Takes the changes that have settled, grouped by node provider
+    pub fn ready(&mut self, now: Instant) -> Vec<NotificationGroup> {
+        let settled: Vec<PrincipalId> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| now.duration_since(pending.since) >= self.dwell_time)
+            .map(|(node_id, _)| *node_id)
+            .collect();
+        let mut by_provider: BTreeMap<Option<PrincipalId>, Vec<Notification>> = BTreeMap::new();
+        for node_id in settled {
+            let notification = self.pending.remove(&node_id).expect("settled node is pending").notification;
+            by_provider
+                .entry(notification.node_provider.as_ref().map(|p| p.principal))
+                .or_default()
+                .push(notification);
+        }
+        by_provider
+            .into_values()
+            .map(|notifications| NotificationGroup::new(notifications, false))
+            .collect()
+    }
+}
+
+/// When the daily digests are sent
+#[derive(Debug)]
+pub struct DigestSchedule {
+    /// Hour of the day, in UTC
+    hour: u64,
+    last_sent_day: u64,
+}
+
+impl DigestSchedule {
+    /// The first digest is sent on the next occurrence of the hour
+    pub fn new(hour: u64, now: SystemTime) -> Self {
+        let (day, current_hour) = day_and_hour(now);
+        Self {
+            hour,
+            last_sent_day: if current_hour >= hour { day } else { day.saturating_sub(1) },
+        }
+    }
+
+    /// Whether the digests are due, in which case they are considered sent
+    pub fn due(&mut self, now: SystemTime) -> bool {
+        let (day, hour) = day_and_hour(now);
+        if day > self.last_sent_day && hour >= self.hour {
+            self.last_sent_day = day;
+            return true;
+        }
+        false
+    }
+
+    /// The day of the last digest, for it to be saved
+    pub fn last_sent_day(&self) -> u64 {
+        self.last_sent_day
+    }
+
+    /// Resumes from the day of the last digest that was saved, so that a
+    /// digest that was not sent before a restart still is sent that day
+    pub fn restore(&mut self, last_sent_day: u64) {
+        self.last_sent_day = last_sent_day;
+    }
+}
+
+fn day_and_hour(time: SystemTime) -> (u64, u64) {
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    (seconds / SECONDS_PER_DAY, seconds % SECONDS_PER_DAY / 3600)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    use ic_management_types::Status;
+
+    use super::{DigestSchedule, Pipeline};
+    use crate::notification::Notification;
+
+    fn change(id: u64, from: Status, to: Status) -> Notification {
+        Notification {
+            status_change: (from, to),
+            ..Notification::new_test(id)
+        }
+    }
+
+    #[test]
+    fn debounces_and_groups() {
+        let start = Instant::now();
+        let minute = Duration::from_secs(60);
+        let mut pipeline = Pipeline::new(5 * minute);
+
+        // Flap, back to its initial status before settling
+        pipeline.push(change(0, Status::Healthy, Status::Dead), start);
+        pipeline.push(change(0, Status::Dead, Status::Healthy), start + minute);
+        // Two changes before settling
+        pipeline.push(change(1, Status::Healthy, Status::Degraded), start);
+        pipeline.push(change(1, Status::Degraded, Status::Dead), start + minute);
+        // Two nodes of the same provider
+        let other_node = Notification {
+            node_id: ic_types::PrincipalId::new_node_test_id(3),
+            ..change(2, Status::Healthy, Status::Dead)
+        };
+        pipeline.push(change(2, Status::Healthy, Status::Dead), start);
+        pipeline.push(other_node.clone(), start);
+
+        assert!(pipeline.ready(start + 4 * minute).is_empty());
+
+        let groups = pipeline.ready(start + 5 * minute);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].notifications.len(), 2);
+        assert!(groups[0].notifications.contains(&change(2, Status::Healthy, Status::Dead)));
+        assert!(groups[0].notifications.contains(&other_node));
+
+        let groups = pipeline.ready(start + 6 * minute);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].notifications, vec![change(1, Status::Healthy, Status::Dead)]);
+
+        assert!(pipeline.ready(start + 60 * minute).is_empty());
+    }
+
+    #[test]
+    fn restores_held_changes() {
+        let start = Instant::now();
+        let minute = Duration::from_secs(60);
+        let mut pipeline = Pipeline::new(5 * minute);
+        pipeline.push(change(0, Status::Healthy, Status::Dead), start);
+
+        let stored = serde_json::to_string(&pipeline.stored(start + 2 * minute)).unwrap();
+        let restart = start + 10 * minute;
+        let mut restarted = Pipeline::new(5 * minute);
+        restarted.restore(serde_json::from_str(&stored).unwrap(), restart);
+
+        assert!(restarted.ready(restart + 2 * minute).is_empty());
+        let groups = restarted.ready(restart + 3 * minute);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].notifications, vec![change(0, Status::Healthy, Status::Dead)]);
+    }
+
+    #[test]
+    fn digest_schedule() {
+        let hour = Duration::from_secs(3600);
+        let day_start = UNIX_EPOCH + 100 * 24 * hour;
+
+        let mut schedule = DigestSchedule::new(8, day_start + 9 * hour);
+        assert!(!schedule.due(day_start + 10 * hour));
+        assert!(!schedule.due(day_start + 31 * hour));
+        assert!(schedule.due(day_start + 32 * hour));
+        assert!(!schedule.due(day_start + 33 * hour));
+
+        let mut schedule = DigestSchedule::new(8, day_start + 7 * hour);
+        assert!(schedule.due(day_start + 8 * hour));
+    }
+
+    #[test]
+    fn restored_digest_schedule_sends_the_missed_digest() {
+        let hour = Duration::from_secs(3600);
+        let day_start = UNIX_EPOCH + 100 * 24 * hour;
+
+        // Stopped before the digest hour, restarted after it
+        let saved = DigestSchedule::new(8, day_start + 7 * hour);
+        let mut schedule = DigestSchedule::new(8, day_start + 9 * hour);
+        schedule.restore(saved.last_sent_day());
+        assert!(schedule.due(day_start + 9 * hour));
+        assert!(!schedule.due(day_start + 10 * hour));
+
+        // Restarted after the digest of the day was sent
+        let mut schedule = DigestSchedule::new(8, day_start + 7 * hour);
+        schedule.restore(100);
+        assert!(!schedule.due(day_start + 9 * hour));
+    }
+}
@@ -1,20 +1,37 @@
-use std::path::Path;
+use std::{cell::RefCell, collections::BTreeMap, path::Path, time::Duration};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use ic_management_types::Status;
 use ic_types::PrincipalId;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::error;
 
 use crate::{
-    notification::Notification,
-    sink::{Sink, WebhookSink},
+    notification::{Notification, NotificationGroup},
+    sink::{EmailSink, LogSink, MatrixSink, PagerDutySink, Sink, SlackSink, WebhookFormat, WebhookSink},
+    subscriptions::Subscription,
 };
 
 const CONFIG_FILE_PATH_VAR_NAME: &str = "ROUTER_CONFIG_PATH";
 
 #[derive(Debug)]
 struct Route {
+    /// Hash of the configuration of the route, see [`RouteConfig::id`]
+    id: String,
     matcher: Matcher,
     sinks: Vec<Sink>,
+    delivery: Delivery,
+}
+
+/// When the notifications of a route are sent
+#[derive(Debug, Default)]
+enum Delivery {
+    /// As soon as they are routed
+    #[default]
+    Realtime,
+    /// Once a day, holding the notifications routed since the last digest
+    Digest(RefCell<Vec<Notification>>),
 }
 
 impl Route {
@@ -23,14 +40,55 @@ impl Route {
     }
 }
 
-#[derive(Deserialize, Debug)]
+/// Conditions a notification has to fulfill to be routed. Conditions that are
+/// not set match any notification.
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
 struct Matcher {
     pub node_provider_id: Option<PrincipalId>,
+    pub node_operator_id: Option<PrincipalId>,
+    pub dc_id: Option<String>,
+    pub subnet_id: Option<PrincipalId>,
+    pub status_change: Option<StatusTransition>,
+    /// Only match nodes that had been in their previous status for at least
+    /// this long
+    #[serde(default, with = "humantime_serde")]
+    pub min_previous_status_duration: Option<Duration>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+struct StatusTransition {
+    pub from: Option<Status>,
+    pub to: Option<Status>,
 }
 
 impl Matcher {
     fn matches(&self, notification: &Notification) -> bool {
         self.matches_node_provider_id(notification)
+            && matches_if_set(&self.node_operator_id, &notification.node_operator_id)
+            && matches_if_set(&self.dc_id, &notification.dc_id)
+            && matches_if_set(&self.subnet_id, &notification.subnet_id)
+            && self.matches_status_change(notification)
+            && self.matches_previous_status_duration(notification)
+    }
+
+    fn matches_status_change(&self, notification: &Notification) -> bool {
+        match &self.status_change {
+            None => true,
+            Some(transition) => {
+                transition.from.as_ref().map_or(true, |from| *from == notification.status_change.0)
+                    && transition.to.as_ref().map_or(true, |to| *to == notification.status_change.1)
+            }
+        }
+    }
+
+    fn matches_previous_status_duration(&self, notification: &Notification) -> bool {
+        match (self.min_previous_status_duration, notification.previous_status_duration) {
+            (None, _) => true,
+            (Some(min), Some(duration)) => duration >= min,
+            (Some(_), None) => false,
+        }
     }
 
     fn matches_node_provider_id(&self, notification: &Notification) -> bool {
@@ -44,10 +102,23 @@ impl Matcher {
     }
 }
 
+/// A condition that is only checked when the matcher sets it
+fn matches_if_set<T: PartialEq>(expected: &Option<T>, actual: &Option<T>) -> bool {
+    match expected {
+        None => true,
+        Some(expected) => actual.as_ref() == Some(expected),
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct RouterConfig {
+    /// Webhooks of node providers, kept for existing configurations. Each entry
+    /// is the same as a route matching on the node provider with a webhook sink
+    /// in the legacy format
     #[serde(default)]
     node_providers: Vec<NPMatch>,
+    #[serde(default)]
+    routes: Vec<RouteConfig>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -56,6 +127,101 @@ struct NPMatch {
     url: url::Url,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct RouteConfig {
+    #[serde(default, rename = "match")]
+    matcher: Matcher,
+    sinks: Vec<SinkConfig>,
+    #[serde(default)]
+    delivery: DeliveryConfig,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum DeliveryConfig {
+    #[default]
+    Realtime,
+    Digest,
+}
+
+impl From<DeliveryConfig> for Delivery {
+    fn from(config: DeliveryConfig) -> Self {
+        match config {
+            DeliveryConfig::Realtime => Delivery::Realtime,
+            DeliveryConfig::Digest => Delivery::Digest(RefCell::new(vec![])),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+enum SinkConfig {
+    Log,
+    Webhook {
+        url: url::Url,
+        #[serde(default)]
+        auth: Option<BasicAuth>,
+        /// Left out of the route id when it is the default, so that the ids
+        /// of the routes configured before the formats existed don't change
+        #[serde(default, skip_serializing_if = "WebhookFormat::is_default")]
+        format: WebhookFormat,
+    },
+    Email(EmailSink),
+    Slack(SlackSink),
+    Matrix(MatrixSink),
+    #[serde(rename = "pagerduty")]
+    PagerDuty(PagerDutySink),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct BasicAuth {
+    username: String,
+    password: String,
+}
+
+impl RouteConfig {
+    /// Identifies the route by its matcher and sinks, so that the messages
+    /// waiting to be sent, and the notifications held for its digests, stay
+    /// with the route when other routes are added or removed, and are not
+    /// sent to other sinks when the route itself changed. Identical routes
+    /// share their id, which is fine as they send the same messages to the
+    /// same sinks.
+    fn id(&self) -> String {
+        let config = serde_json::to_vec(&(&self.matcher, &self.sinks)).expect("route configurations can be serialized");
+        hex::encode(&Sha256::digest(config)[..8])
+    }
+}
+
+impl From<RouteConfig> for Route {
+    fn from(config: RouteConfig) -> Self {
+        Self {
+            id: config.id(),
+            matcher: config.matcher,
+            sinks: config.sinks.into_iter().map(Sink::from).collect(),
+            delivery: config.delivery.into(),
+        }
+    }
+}
+
+impl From<SinkConfig> for Sink {
+    fn from(config: SinkConfig) -> Self {
+        match config {
+            SinkConfig::Log => Sink::Log(LogSink {}),
+            SinkConfig::Webhook { url, auth, format } => Sink::Webhook(WebhookSink {
+                url,
+                auth: auth.map(|auth| (auth.username, auth.password)),
+                public_only: false,
+                format,
+            }),
+            SinkConfig::Email(sink) => Sink::Email(sink),
+            SinkConfig::Slack(sink) => Sink::Slack(sink),
+            SinkConfig::Matrix(sink) => Sink::Matrix(sink),
+            SinkConfig::PagerDuty(sink) => Sink::PagerDuty(sink),
+        }
+    }
+}
+
 impl RouterConfig {
     fn load_from_file(file_path: String) -> Result<RouterConfig> {
         let path = Path::new(&file_path);
@@ -69,21 +235,31 @@ impl RouterConfig {
 
     fn get_routes(&self) -> Vec<Route> {
         self.node_providers
-            .clone()
-            .into_iter()
-            .map(|np| Route {
+            .iter()
+            .map(|np| RouteConfig {
                 matcher: Matcher {
                     node_provider_id: Some(np.principal_id),
+                    ..Default::default()
                 },
-                sinks: vec![Sink::Webhook(WebhookSink { url: np.url, auth: None })],
+                sinks: vec![SinkConfig::Webhook {
+                    url: np.url.clone(),
+                    auth: None,
+                    format: WebhookFormat::Legacy,
+                }],
+                delivery: DeliveryConfig::Realtime,
             })
+            .chain(self.routes.iter().cloned())
+            .map(Route::from)
             .collect()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Router {
     routes: Vec<Route>,
+    /// The webhooks node providers registered through the API, by
+    /// subscription id
+    subscriptions: Vec<(String, Route)>,
 }
 
 impl Router {
@@ -98,7 +274,7 @@ impl Router {
     }
 
     pub fn new() -> Self {
-        Self { routes: vec![] }
+        Self::default()
     }
 
     #[cfg(test)]
@@ -107,30 +283,196 @@ impl Router {
         Ok(Self::from(config))
     }
 
+    /// Sends the notification to the sinks of every matching route, without
+    /// retrying
+    #[cfg(test)]
     pub async fn route(&self, notification: Notification) -> Result<()> {
+        let mut failed = vec![];
+        for (sink_id, group) in self.route_group(&NotificationGroup::from(notification)) {
+            if self.send(&sink_id, &group).await.is_err() {
+                failed.push(sink_id);
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Failed to send notification to sinks: {}", failed.join(", ")))
+        }
+    }
+
+    /// The messages to send for the group, by sink: the notifications of the
+    /// group that match a route, for each sink of the route. The
+    /// notifications of the routes that deliver digests are held for the next
+    /// digest instead.
+    pub fn route_group(&self, group: &NotificationGroup) -> Vec<(String, NotificationGroup)> {
+        let mut messages = vec![];
+        for route in self.routes.iter() {
+            let notifications: Vec<Notification> = group.notifications.iter().filter(|n| route.matches(n)).cloned().collect();
+            if notifications.is_empty() {
+                continue;
+            }
+            match &route.delivery {
+                Delivery::Realtime => {
+                    let matching = NotificationGroup {
+                        notifications,
+                        ..group.clone()
+                    };
+                    messages.extend(
+                        route
+                            .sinks
+                            .iter()
+                            .enumerate()
+                            .map(|(i, sink)| (sink_id(route, i, sink), matching.clone())),
+                    );
+                }
+                Delivery::Digest(pending) => pending.borrow_mut().extend(notifications),
+            }
+        }
+        for (id, route) in self.subscriptions.iter() {
+            let notifications: Vec<Notification> = group.notifications.iter().filter(|n| route.matches(n)).cloned().collect();
+            if !notifications.is_empty() {
+                messages.push((
+                    subscription_sink_id(id),
+                    NotificationGroup {
+                        notifications,
+                        ..group.clone()
+                    },
+                ));
+            }
+        }
+        messages
+    }
+
+    /// Replaces the routes of the subscriptions. The sinks of the
+    /// subscriptions keep their ids, so that the messages waiting to be sent
+    /// to the ones that are kept are still sent. Unless private targets are
+    /// allowed, their webhooks are only sent to public addresses.
+    pub fn set_subscriptions(&mut self, subscriptions: &[Subscription], allow_private_targets: bool) {
+        self.subscriptions = subscriptions
+            .iter()
+            .map(|subscription| {
+                let route = Route {
+                    id: subscription_sink_id(&subscription.id),
+                    matcher: Matcher {
+                        node_provider_id: Some(subscription.node_provider_id),
+                        ..Default::default()
+                    },
+                    sinks: vec![Sink::Webhook(WebhookSink {
+                        url: subscription.url.clone(),
+                        auth: None,
+                        public_only: !allow_private_targets,
+                        format: WebhookFormat::V2,
+                    })],
+                    delivery: Delivery::Realtime,
+                };
+                (subscription.id.clone(), route)
+            })
+            .collect();
+    }
+
+    /// The digests of the routes that deliver them, by sink, one per node
+    /// provider, with the notifications held since the last digests
+    pub fn digests(&self) -> Vec<(String, NotificationGroup)> {
+        let mut messages = vec![];
         for route in self.routes.iter() {
-            if route.matches(&notification) {
-                for sink in &route.sinks {
-                    // TODO Ensure we try sending to all sinks even if one fail
-                    // As it is, if one sink was to fail, all of the sinks after
-                    // it would fail as well
-                    sink.send(notification.clone()).await?
+            let Delivery::Digest(pending) = &route.delivery else {
+                continue;
+            };
+            let mut by_provider: BTreeMap<Option<PrincipalId>, Vec<Notification>> = BTreeMap::new();
+            for notification in pending.take() {
+                by_provider
+                    .entry(notification.node_provider.as_ref().map(|p| p.principal))
+                    .or_default()
+                    .push(notification);
+            }
+            for notifications in by_provider.into_values() {
+                let digest = NotificationGroup::new(notifications, true);
+                messages.extend(route.sinks.iter().enumerate().map(|(i, sink)| (sink_id(route, i, sink), digest.clone())));
+            }
+        }
+        messages
+    }
+
+    /// The notifications held for the next digests, by route id
+    pub fn pending_digests(&self) -> BTreeMap<String, Vec<Notification>> {
+        self.routes
+            .iter()
+            .filter_map(|route| match &route.delivery {
+                Delivery::Digest(pending) if !pending.borrow().is_empty() => Some((route.id.clone(), pending.borrow().clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Holds the notifications again for the next digests, after a restart.
+    /// Returns the ones of the routes that are not in the configuration
+    /// anymore, or do not deliver digests anymore.
+    pub fn restore_pending_digests(&self, digests: BTreeMap<String, Vec<Notification>>) -> BTreeMap<String, Vec<Notification>> {
+        let mut removed = BTreeMap::new();
+        for (id, notifications) in digests {
+            match self.routes.iter().find(|route| route.id == id).map(|route| &route.delivery) {
+                Some(Delivery::Digest(pending)) => pending.borrow_mut().extend(notifications),
+                _ => {
+                    removed.insert(id, notifications);
                 }
             }
         }
-        Ok(())
+        removed
+    }
+
+    pub fn has_sink(&self, sink_id: &str) -> bool {
+        self.sink(sink_id).is_some()
+    }
+
+    pub async fn send(&self, sink_id: &str, group: &NotificationGroup) -> Result<()> {
+        let sink = self.sink(sink_id).ok_or_else(|| anyhow!("Unknown sink {}", sink_id))?;
+        sink.send_group(group).await.map_err(|e| {
+            error!(message = "Failed to send notification", sink = sink_id, error = e.to_string());
+            e
+        })
+    }
+
+    fn sink(&self, id: &str) -> Option<&Sink> {
+        if let Some((_, route)) = self
+            .subscriptions
+            .iter()
+            .find(|(subscription, _)| subscription_sink_id(subscription) == id)
+        {
+            return route.sinks.first();
+        }
+        self.routes.iter().find_map(|route| {
+            route
+                .sinks
+                .iter()
+                .enumerate()
+                .find(|(i, sink)| sink_id(route, *i, sink) == id)
+                .map(|(_, sink)| sink)
+        })
     }
 }
 
+/// Identifies a sink of the configuration by the id of its route, which
+/// changes along with any of its sinks, and its position in the route
+fn sink_id(route: &Route, sink_index: usize, sink: &Sink) -> String {
+    format!("{}/{}/{}", route.id, sink_index, sink.name())
+}
+
+fn subscription_sink_id(subscription_id: &str) -> String {
+    format!("subscription/{}", subscription_id)
+}
+
 impl From<RouterConfig> for Router {
     fn from(config: RouterConfig) -> Self {
-        Self { routes: config.get_routes() }
+        Self {
+            routes: config.get_routes(),
+            ..Default::default()
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Route, Router, RouterConfig};
+    use super::{Delivery, Route, Router, RouterConfig, StatusTransition};
 
     use ic_management_types::{Provider, Status};
     use ic_types::PrincipalId;
@@ -139,12 +481,14 @@ mod tests {
     use rand::{thread_rng, Rng};
     use std::path::Path;
     use std::rc::Rc;
+    use std::time::Duration;
     use std::{fs::File, io::Write, str::FromStr};
 
     use crate::router::CONFIG_FILE_PATH_VAR_NAME;
     use crate::{
-        notification::Notification,
-        sink::{Sink, TestSink},
+        notification::{Notification, NotificationGroup},
+        sink::{Sink, TestSink, WebhookFormat, WebhookSink},
+        subscriptions::Subscription,
     };
 
     use super::Matcher;
@@ -174,7 +518,10 @@ node_providers:
         let sink = &route.sinks[0];
 
         match sink {
-            Sink::Webhook(s) => assert_eq!(s.url, url::Url::parse("https://localhost:8080").unwrap()),
+            Sink::Webhook(s) => {
+                assert_eq!(s.url, url::Url::parse("https://localhost:8080").unwrap());
+                assert_eq!(s.format, WebhookFormat::Legacy);
+            }
             _ => unreachable!(),
         }
     }
@@ -215,11 +562,16 @@ node_providers:
 
         let m_some_1 = Matcher {
             node_provider_id: Some(principal_id_1),
+            ..Default::default()
         };
         let m_some_2 = Matcher {
             node_provider_id: Some(principal_id_2),
+            ..Default::default()
+        };
+        let m_none = Matcher {
+            node_provider_id: None,
+            ..Default::default()
         };
-        let m_none = Matcher { node_provider_id: None };
 
         let notification_some_1 = Notification {
             node_id: PrincipalId::new_node_test_id(0),
@@ -229,6 +581,7 @@ node_providers:
                 website: None,
             }),
             status_change: (Status::Healthy, Status::Degraded),
+            ..Default::default()
         };
 
         let notification_some_2 = Notification {
@@ -239,12 +592,14 @@ node_providers:
                 website: None,
             }),
             status_change: (Status::Healthy, Status::Degraded),
+            ..Default::default()
         };
 
         let notification_none = Notification {
             node_id: PrincipalId::new_node_test_id(1),
             node_provider: None,
             status_change: (Status::Healthy, Status::Degraded),
+            ..Default::default()
         };
 
         assert!(m_some_1.matches(&notification_some_1));
@@ -271,6 +626,7 @@ node_providers:
                 website: None,
             }),
             status_change: (Status::Healthy, Status::Degraded),
+            ..Default::default()
         };
 
         let principal_id_2 = PrincipalId::new_user_test_id(2);
@@ -282,16 +638,21 @@ node_providers:
                 website: None,
             }),
             status_change: (Status::Healthy, Status::Degraded),
+            ..Default::default()
         };
 
         let test_sink = Rc::new(TestSink::new());
         let router = Router {
             routes: vec![Route {
+                id: "route".to_string(),
                 matcher: Matcher {
                     node_provider_id: Some(principal_id_1),
+                    ..Default::default()
                 },
                 sinks: vec![Sink::Test(test_sink.clone())],
+                delivery: Delivery::Realtime,
             }],
+            ..Default::default()
         };
 
         let _ = router.route(notification_some_1.clone()).await;
@@ -301,4 +662,246 @@ node_providers:
         assert_eq!(received_notifications.len(), 1);
         assert_eq!(received_notifications[0], notification_some_1);
     }
+
+    #[test]
+    fn loading_routes_config() {
+        let config = r#"
+node_providers:
+  - principal_id: eipr5-izbom-neyqh-s3ec2-52eww-cyfpg-qfomg-3dpwj-4pffh-34xcu-7qe
+    url: https://localhost:8080
+routes:
+  - match:
+      dc_id: zh1
+      status_change:
+        from: Healthy
+        to: Dead
+      min_previous_status_duration: 1h
+    sinks:
+      - webhook:
+          url: https://localhost:8081
+          auth:
+            username: user
+            password: password
+      - slack:
+          url: https://hooks.slack.com/services/T/B/X
+      - pagerduty:
+          routing_key: key
+  - sinks:
+      - log"#;
+        let router = Router::new_from_config(config).unwrap();
+        assert_eq!(router.routes.len(), 3);
+
+        let route = &router.routes[1];
+        assert_eq!(route.matcher.dc_id, Some("zh1".to_string()));
+        assert_eq!(route.matcher.min_previous_status_duration, Some(std::time::Duration::from_secs(3600)));
+        assert_eq!(route.sinks.len(), 3);
+        match &route.sinks[0] {
+            Sink::Webhook(s) => {
+                assert_eq!(s.auth, Some(("user".to_string(), "password".to_string())));
+                assert_eq!(s.format, WebhookFormat::V2);
+            }
+            _ => unreachable!(),
+        }
+        match &route.sinks[2] {
+            Sink::PagerDuty(s) => assert_eq!(s.url.as_str(), "https://events.pagerduty.com/v2/enqueue"),
+            _ => unreachable!(),
+        }
+        assert!(matches!(router.routes[2].sinks[0], Sink::Log(_)));
+
+        assert!(Router::new_from_config("routes:\n  - match:\n      provider: abc\n    sinks: []").is_err());
+    }
+
+    #[test]
+    fn route_ids_follow_the_config() {
+        let dead =
+            "  - match:\n      status_change:\n        to: Dead\n    sinks:\n      - slack:\n          url: https://hooks.slack.com/services/T/B/X\n";
+        let all = "  - sinks:\n      - log\n";
+        let both = Router::new_from_config(&format!("routes:\n{}{}", dead, all)).unwrap();
+        let only_all = Router::new_from_config(&format!("routes:\n{}", all)).unwrap();
+        assert_eq!(both.routes[1].id, only_all.routes[0].id);
+        assert_ne!(both.routes[0].id, both.routes[1].id);
+
+        let sink_id = format!("{}/0/log", only_all.routes[0].id);
+        assert!(both.has_sink(&sink_id));
+        assert!(only_all.has_sink(&sink_id));
+
+        let other_url = Router::new_from_config(&format!("routes:\n{}", dead.replace("/X", "/Y"))).unwrap();
+        assert_ne!(other_url.routes[0].id, both.routes[0].id);
+        let to_degraded = Router::new_from_config(&format!("routes:\n{}", dead.replace("Dead", "Degraded"))).unwrap();
+        assert_ne!(to_degraded.routes[0].id, both.routes[0].id);
+    }
+
+    #[test]
+    fn rich_matching() {
+        let operator = PrincipalId::new_user_test_id(10);
+        let subnet = PrincipalId::new_subnet_test_id(1);
+        let notification = Notification {
+            node_id: PrincipalId::new_node_test_id(0),
+            status_change: (Status::Healthy, Status::Dead),
+            node_operator_id: Some(operator),
+            dc_id: Some("zh1".to_string()),
+            subnet_id: Some(subnet),
+            previous_status_duration: Some(Duration::from_secs(7200)),
+            ..Default::default()
+        };
+
+        let all = Matcher {
+            node_operator_id: Some(operator),
+            dc_id: Some("zh1".to_string()),
+            subnet_id: Some(subnet),
+            status_change: Some(StatusTransition {
+                from: Some(Status::Healthy),
+                to: Some(Status::Dead),
+            }),
+            min_previous_status_duration: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        assert!(all.matches(&notification));
+        assert!(Matcher::default().matches(&notification));
+
+        let to_dead = Matcher {
+            status_change: Some(StatusTransition {
+                from: None,
+                to: Some(Status::Dead),
+            }),
+            ..Default::default()
+        };
+        assert!(to_dead.matches(&notification));
+        let from_degraded = Matcher {
+            status_change: Some(StatusTransition {
+                from: Some(Status::Degraded),
+                to: None,
+            }),
+            ..Default::default()
+        };
+        assert!(!from_degraded.matches(&notification));
+
+        let other_dc = Matcher {
+            dc_id: Some("fr1".to_string()),
+            ..Default::default()
+        };
+        assert!(!other_dc.matches(&notification));
+        let other_subnet = Matcher {
+            subnet_id: Some(PrincipalId::new_subnet_test_id(2)),
+            ..Default::default()
+        };
+        assert!(!other_subnet.matches(&notification));
+
+        let long_lived = Matcher {
+            min_previous_status_duration: Some(Duration::from_secs(86400)),
+            ..Default::default()
+        };
+        assert!(!long_lived.matches(&notification));
+        assert!(!all.matches(&Notification {
+            previous_status_duration: None,
+            ..notification
+        }));
+    }
+
+    #[actix_web::test]
+    async fn routing_continues_after_failing_sink() {
+        let notification = Notification::new_test(0);
+        let test_sink = Rc::new(TestSink::new());
+        let router = Router {
+            routes: vec![Route {
+                id: "route".to_string(),
+                matcher: Matcher::default(),
+                sinks: vec![
+                    Sink::Webhook(WebhookSink {
+                        url: url::Url::parse("http://127.0.0.1:1/unreachable").unwrap(),
+                        auth: None,
+                        public_only: false,
+                        format: WebhookFormat::V2,
+                    }),
+                    Sink::Test(test_sink.clone()),
+                ],
+                delivery: Delivery::Realtime,
+            }],
+            ..Default::default()
+        };
+
+        assert!(router.route(notification.clone()).await.is_err());
+        assert_eq!(test_sink.notifications(), vec![notification]);
+    }
+
+    #[actix_web::test]
+    async fn digests_per_node_provider() {
+        let test_sink = Rc::new(TestSink::new());
+        let router = Router::from(RouterConfig::load("routes:\n  - sinks: [log]\n    delivery: digest").unwrap());
+        assert!(matches!(router.routes[0].delivery, Delivery::Digest(_)));
+        let router = Router {
+            routes: vec![Route {
+                id: "route".to_string(),
+                matcher: Matcher::default(),
+                sinks: vec![Sink::Test(test_sink.clone())],
+                delivery: Delivery::Digest(Default::default()),
+            }],
+            ..Default::default()
+        };
+
+        let group = NotificationGroup {
+            notifications: vec![Notification::new_test(0), Notification::new_test(1)],
+            digest: false,
+            idempotency_key: None,
+        };
+        assert!(router.route_group(&group).is_empty());
+        router.route(Notification::new_test(2)).await.unwrap();
+        assert!(test_sink.notifications().is_empty());
+
+        // Survives a restart
+        let mut pending = router.pending_digests();
+        assert_eq!(pending["route"].len(), 3);
+        router.digests();
+        pending.insert("removed".to_string(), vec![Notification::new_test(3)]);
+        let removed = router.restore_pending_digests(pending);
+        assert_eq!(removed.into_keys().collect::<Vec<_>>(), vec!["removed".to_string()]);
+
+        let digests = router.digests();
+        assert_eq!(digests.len(), 3);
+        for (sink_id, digest) in digests {
+            assert_eq!(sink_id, "route/0/test");
+            assert!(digest.digest);
+            router.send(&sink_id, &digest).await.unwrap();
+        }
+        let mut received = test_sink.notifications();
+        received.sort();
+        assert_eq!(
+            received,
+            vec![Notification::new_test(0), Notification::new_test(1), Notification::new_test(2)]
+        );
+
+        // Nothing is held anymore
+        assert!(router.digests().is_empty());
+        assert!(router.pending_digests().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn subscriptions_reload() {
+        let test_sink = Rc::new(TestSink::new());
+        let mut router = Router {
+            routes: vec![Route {
+                id: "route".to_string(),
+                matcher: Matcher::default(),
+                sinks: vec![Sink::Test(test_sink.clone())],
+                delivery: Delivery::Realtime,
+            }],
+            ..Default::default()
+        };
+        let subscription = Subscription {
+            id: "abc".to_string(),
+            node_provider_id: PrincipalId::new_user_test_id(0),
+            url: url::Url::parse("https://localhost:8080").unwrap(),
+        };
+        router.set_subscriptions(&[subscription.clone()], false);
+
+        let messages = router.route_group(&NotificationGroup::from(Notification::new_test(0)));
+        let sink_ids: Vec<&str> = messages.iter().map(|(sink_id, _)| sink_id.as_str()).collect();
+        assert_eq!(sink_ids, vec!["route/0/test", "subscription/abc"]);
+        assert!(router.has_sink("subscription/abc"));
+        assert_eq!(router.route_group(&NotificationGroup::from(Notification::new_test(1))).len(), 1);
+
+        router.set_subscriptions(&[], false);
+        assert!(!router.has_sink("subscription/abc"));
+        assert_eq!(router.route_group(&NotificationGroup::from(Notification::new_test(0))).len(), 1);
+    }
 }
@@ -1,33 +1,93 @@
 use std::cell::RefCell;
+use std::net::IpAddr;
 use std::rc::Rc;
 
 use anyhow::anyhow;
 use anyhow::Result;
-use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info};
+use url::Host;
 
-use crate::notification::Notification;
+use crate::notification::{Notification, NotificationGroup};
+
+pub use email::EmailSink;
+pub use matrix::MatrixSink;
+pub use pagerduty::PagerDutySink;
+pub use slack::SlackSink;
+
+mod email;
+mod matrix;
+mod pagerduty;
+mod slack;
 
 #[derive(Debug)]
 pub enum Sink {
     Log(LogSink),
     #[allow(unused)]
     Webhook(WebhookSink),
+    Email(EmailSink),
+    Slack(SlackSink),
+    Matrix(MatrixSink),
+    PagerDuty(PagerDutySink),
     #[allow(unused)]
     Test(Rc<TestSink>),
 }
 
 impl Sink {
+    #[cfg(test)]
     pub async fn send(&self, notification: Notification) -> Result<()> {
+        self.send_group(&NotificationGroup::from(notification)).await
+    }
+
+    pub async fn send_group(&self, group: &NotificationGroup) -> Result<()> {
         match self {
-            Sink::Log(sink) => sink.send(notification),
-            Sink::Webhook(sink) => sink.send(notification).await,
+            Sink::Log(sink) => group.notifications.iter().try_for_each(|n| sink.send(n.clone())),
+            Sink::Webhook(sink) => sink.send(group).await,
+            Sink::Email(sink) => sink.send(group).await,
+            Sink::Slack(sink) => sink.send(group).await,
+            Sink::Matrix(sink) => sink.send(group).await,
+            // Incidents are tracked per node, so a group still is an event per node
+            Sink::PagerDuty(sink) => {
+                let mut result = Ok(());
+                for notification in group.notifications.iter() {
+                    result = result.and(sink.send(notification).await);
+                }
+                result
+            }
             Sink::Test(sink) => {
-                sink.send(notification);
+                group.notifications.iter().for_each(|n| sink.send(n.clone()));
                 Ok(())
             }
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sink::Log(_) => "log",
+            Sink::Webhook(_) => "webhook",
+            Sink::Email(_) => "email",
+            Sink::Slack(_) => "slack",
+            Sink::Matrix(_) => "matrix",
+            Sink::PagerDuty(_) => "pagerduty",
+            Sink::Test(_) => "test",
+        }
+    }
+}
+
+/// Fails if the service a sink sent a notification to did not accept it
+async fn check_response(sink: &str, summary: &str, response: reqwest::Response) -> Result<()> {
+    if response.status().is_success() {
+        return Ok(());
+    }
+    let status = response.status();
+    error!(
+        message = "Error while sending the notification",
+        sink = sink,
+        notification = summary,
+        status = status.to_string(),
+        response = response.text().await?,
+    );
+    Err(anyhow!("Failed to send notification to {}: {}", sink, status))
 }
 
 #[derive(Clone, Debug)]
@@ -40,40 +100,128 @@ impl LogSink {
     }
 }
 
+/// Version of the payload posted by the webhooks in the [WebhookFormat::V2]
+/// format
+pub const WEBHOOK_PAYLOAD_VERSION: u32 = 2;
+
+/// Payload posted by a webhook
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// Every notification is posted on its own, as the bare notification, for
+    /// the receivers built before notifications were grouped
+    Legacy,
+    /// Every group, single notification or digest, is posted at once, along
+    /// with the version of the payload
+    #[default]
+    V2,
+}
+
+impl WebhookFormat {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    version: u32,
+    #[serde(flatten)]
+    group: &'a NotificationGroup,
+}
+
 #[derive(Debug)]
 pub struct WebhookSink {
     pub url: url::Url,
     pub auth: Option<(String, String)>,
+    /// Only send to public addresses, without following redirects, as for
+    /// the webhooks node providers registered themselves
+    pub public_only: bool,
+    pub format: WebhookFormat,
 }
 
 impl WebhookSink {
-    async fn send(&self, notification: Notification) -> Result<()> {
-        debug!(
-            message = "Sending notification",
-            url = &self.url.to_string(),
-            notification = notification.to_string(),
-        );
-        let client = reqwest::Client::new();
-        let response = client.post(&self.url.to_string()).json(&notification).send().await.map_err(|e| {
+    /// In the [WebhookFormat::V2] format, every group is posted as
+    /// `{"version": 2, "notifications": [...], "digest": bool, "idempotency_key": "..."}`.
+    /// In the [WebhookFormat::Legacy] format, every notification of the group
+    /// is posted on its own, without the idempotency key.
+    async fn send(&self, group: &NotificationGroup) -> Result<()> {
+        match self.format {
+            WebhookFormat::V2 => {
+                let payload = WebhookPayload {
+                    version: WEBHOOK_PAYLOAD_VERSION,
+                    group,
+                };
+                self.post(&payload, &group.summary()).await
+            }
+            WebhookFormat::Legacy => {
+                for notification in group.notifications.iter() {
+                    self.post(notification, &notification.summary()).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn post<T: Serialize>(&self, payload: &T, summary: &str) -> Result<()> {
+        debug!(message = "Sending notifications", url = &self.url.to_string(), notification = summary);
+        let mut client = reqwest::Client::builder();
+        if self.public_only {
+            check_public_url(&self.url).await?;
+            client = client.redirect(reqwest::redirect::Policy::none());
+        }
+        let mut request = client.build()?.post(self.url.clone()).json(payload);
+        if let Some((username, password)) = &self.auth {
+            request = request.basic_auth(username, Some(password));
+        }
+        let response = request.send().await.map_err(|e| {
             error!(
                 message = "Error while sending the notification",
-                notification = notification.to_string(),
+                notification = summary,
                 error = e.to_string(),
             );
             e
         })?;
-        match response.status() {
-            StatusCode::OK => Ok(()),
-            _ => {
-                error!(
-                    message = "Error while sending the notification",
-                    notification = notification.to_string(),
-                    status = response.status().to_string(),
-                    response = response.text().await?,
-                );
-                Err(anyhow!("Failed to send notification"))
-            }
+        check_response("webhook", summary, response).await
+    }
+}
+
+/// Fails if the host of the url is, or resolves to, an address that is not
+/// public, e.g. loopback, private or link-local, so that the service cannot
+/// be used to reach its own network
+pub async fn check_public_url(url: &url::Url) -> Result<()> {
+    let addresses: Vec<IpAddr> = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![ip.into()],
+        Some(Host::Ipv6(ip)) => vec![ip.into()],
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, url.port_or_known_default().unwrap_or(443)))
+            .await?
+            .map(|address| address.ip())
+            .collect(),
+        None => return Err(anyhow!("The url has no host")),
+    };
+    if addresses.is_empty() {
+        return Err(anyhow!("{} does not resolve to any address", url));
+    }
+    match addresses.into_iter().find(|ip| !is_public(*ip)) {
+        Some(ip) => Err(anyhow!("{} resolves to {}, which is not a public address", url, ip)),
+        None => Ok(()),
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64;
+            !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast() || shared)
         }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                let unique_local = ip.segments()[0] & 0xfe00 == 0xfc00;
+                let link_local = ip.segments()[0] & 0xffc0 == 0xfe80;
+                !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+            }
+        },
     }
 }
 
@@ -103,48 +251,112 @@ impl TestSink {
 #[cfg(test)]
 mod test {
     use crate::{
-        notification::Notification,
+        notification::{Notification, NotificationGroup},
         sink::{LogSink, Sink},
     };
 
-    use super::WebhookSink;
-    use httptest::{all_of, matchers::request, responders::status_code, Expectation};
+    use super::{check_public_url, WebhookFormat, WebhookSink};
+    use httptest::{
+        all_of,
+        matchers::{eq, json_decoded, request},
+        responders::status_code,
+        Expectation,
+    };
     use test_log::test;
 
     #[actix_web::test]
     async fn webhook_sends_requests() {
-        let notification = Notification::new_test(0);
+        let group = NotificationGroup::from(Notification::new_test(0));
+        let expected = serde_json::json!({
+            "version": 2,
+            "notifications": [Notification::new_test(0)],
+            "digest": false,
+        });
         let server = httptest::Server::run();
 
         server.expect(
             Expectation::matching(all_of![
                 request::method_path("POST", "/success"),
-                request::body(serde_json::to_string(&notification).unwrap())
+                request::body(json_decoded(eq(expected.clone())))
             ])
             .respond_with(status_code(200)),
         );
         let wh = WebhookSink {
             url: url::Url::parse(&server.url("/success").to_string()).unwrap(),
             auth: None,
+            public_only: false,
+            format: WebhookFormat::V2,
         };
-        let result = wh.send(notification.clone()).await;
+        let result = wh.send(&group).await;
         assert!(result.is_ok());
 
         server.expect(
             Expectation::matching(all_of![
                 request::method_path("POST", "/failure"),
-                request::body(serde_json::to_string(&notification).unwrap())
+                request::body(json_decoded(eq(expected)))
             ])
             .respond_with(status_code(500)),
         );
         let wh = WebhookSink {
             url: url::Url::parse(&server.url("/failure").to_string()).unwrap(),
             auth: None,
+            public_only: false,
+            format: WebhookFormat::V2,
         };
-        let result = wh.send(notification).await;
+        let result = wh.send(&group).await;
         assert!(result.is_err());
     }
 
+    #[actix_web::test]
+    async fn webhook_sends_idempotency_key() {
+        let notification = Notification::new_test(0);
+        let group = NotificationGroup {
+            idempotency_key: Some("key".to_string()),
+            ..NotificationGroup::from(notification.clone())
+        };
+        let expected = serde_json::json!({
+            "version": 2,
+            "notifications": [notification],
+            "digest": false,
+            "idempotency_key": "key",
+        });
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(all_of![request::method_path("POST", "/hook"), request::body(json_decoded(eq(expected)))])
+                .respond_with(status_code(200)),
+        );
+        let sink = Sink::Webhook(WebhookSink {
+            url: url::Url::parse(&server.url("/hook").to_string()).unwrap(),
+            auth: None,
+            public_only: false,
+            format: WebhookFormat::V2,
+        });
+        assert!(sink.send_group(&group).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn legacy_webhook_sends_every_notification() {
+        let notifications = vec![Notification::new_test(0), Notification::new_test(1)];
+        let group = NotificationGroup::new(notifications.clone(), true);
+        let server = httptest::Server::run();
+        for notification in notifications {
+            server.expect(
+                Expectation::matching(all_of![
+                    request::method_path("POST", "/hook"),
+                    request::body(json_decoded(eq(serde_json::to_value(&notification).unwrap())))
+                ])
+                .respond_with(status_code(200)),
+            );
+        }
+        let sink = Sink::Webhook(WebhookSink {
+            url: url::Url::parse(&server.url("/hook").to_string()).unwrap(),
+            auth: None,
+            public_only: false,
+            format: WebhookFormat::Legacy,
+        });
+        assert!(sink.send_group(&group).await.is_ok());
+    }
+
     #[test(actix_web::test)]
     async fn log_sink_display() {
         // test mostly used to see our log lines.
@@ -153,4 +365,34 @@ mod test {
         let log_sink = Sink::Log(LogSink {});
         let _ = log_sink.send(n.clone()).await;
     }
+
+    #[actix_web::test]
+    async fn public_urls() {
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://localhost/hook",
+            "https://10.1.2.3",
+            "https://192.168.0.1",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/hook",
+            "http://[fe80::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+        ] {
+            assert!(check_public_url(&url.parse().unwrap()).await.is_err(), "{}", url);
+        }
+        assert!(check_public_url(&"https://8.8.8.8/hook".parse().unwrap()).await.is_ok());
+        assert!(check_public_url(&"https://[2001:4860:4860::8888]/hook".parse().unwrap()).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn public_only_webhook_rejects_local_addresses() {
+        let server = httptest::Server::run();
+        let sink = Sink::Webhook(WebhookSink {
+            url: url::Url::parse(&server.url("/hook").to_string()).unwrap(),
+            auth: None,
+            public_only: true,
+        });
+        assert!(sink.send(Notification::new_test(0)).await.is_err());
+    }
 }
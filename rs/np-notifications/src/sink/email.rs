@@ -0,0 +1,159 @@
+use anyhow::{anyhow, Result};
+use lettre::{
+    message::header::ContentType, transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use crate::notification::NotificationGroup;
+
+/// Sends notifications as plain text emails through an SMTP server
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EmailSink {
+    pub smtp_server: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Whether to use STARTTLS. Only meant to be disabled for local servers
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+    #[serde(default)]
+    pub credentials: Option<SmtpCredentials>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SmtpCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+fn default_port() -> u16 {
+    587
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+impl EmailSink {
+    pub async fn send(&self, group: &NotificationGroup) -> Result<()> {
+        debug!(
+            message = "Sending notification",
+            sink = "email",
+            smtp_server = &self.smtp_server,
+            notification = group.summary(),
+        );
+        let email = self.message(group)?;
+        let mut transport = if self.tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.smtp_server)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.smtp_server)
+        }
+        .port(self.port);
+        if let Some(credentials) = &self.credentials {
+            transport = transport.credentials(Credentials::new(credentials.username.clone(), credentials.password.clone()));
+        }
+        transport.build().send(email).await.map_err(|e| {
+            error!(
+                message = "Error while sending the notification",
+                sink = "email",
+                notification = group.summary(),
+                error = e.to_string(),
+            );
+            anyhow!("Failed to send notification to email: {}", e)
+        })?;
+        Ok(())
+    }
+
+    fn message(&self, group: &NotificationGroup) -> Result<Message> {
+        let mut builder = Message::builder()
+            .from(self.from.parse()?)
+            .subject(group.summary())
+            .message_id(group.idempotency_key.as_ref().map(|key| format!("<{}@np-notifications>", key)));
+        for to in self.to.iter() {
+            builder = builder.to(to.parse()?);
+        }
+        let body = group
+            .details()
+            .into_iter()
+            .map(|(label, value)| format!("{}: {}", label, value))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok(builder.header(ContentType::TEXT_PLAIN).body(body)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::mpsc,
+        time::Duration,
+    };
+
+    use super::EmailSink;
+    use crate::notification::Notification;
+
+    /// Accepts a single SMTP session and hands over the data of the received
+    /// message
+    fn mock_smtp_server() -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            let mut data = None;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                match data.as_mut() {
+                    Some(data) if line != ".\r\n" => data.push_str(&line),
+                    Some(_) => {
+                        sender.send(data.take().unwrap()).unwrap();
+                        stream.write_all(b"250 OK\r\n").unwrap();
+                    }
+                    None => {
+                        let command = line.to_uppercase();
+                        if command.starts_with("EHLO") {
+                            stream.write_all(b"250-localhost\r\n250 OK\r\n").unwrap();
+                        } else if command.starts_with("DATA") {
+                            data = Some(String::new());
+                            stream.write_all(b"354 Go ahead\r\n").unwrap();
+                        } else if command.starts_with("QUIT") {
+                            stream.write_all(b"221 Bye\r\n").unwrap();
+                            break;
+                        } else {
+                            stream.write_all(b"250 OK\r\n").unwrap();
+                        }
+                    }
+                }
+                line.clear();
+            }
+        });
+        (port, receiver)
+    }
+
+    #[actix_web::test]
+    async fn email_sends_message() {
+        let (port, received) = mock_smtp_server();
+        let sink = EmailSink {
+            smtp_server: "127.0.0.1".to_string(),
+            port,
+            tls: false,
+            credentials: None,
+            from: "alerts@example.com".to_string(),
+            to: vec!["np@example.com".to_string()],
+        };
+        let notification = Notification::new_test(0);
+
+        sink.send(&notification.clone().into()).await.unwrap();
+
+        let message = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(message.contains("To: np@example.com"));
+        assert!(message.contains(&format!("Subject: {}", notification.summary())));
+        assert!(message.contains(&format!("Node: {}", notification.node_id)));
+    }
+}
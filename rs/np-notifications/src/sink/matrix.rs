@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Result};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+
+use crate::notification::NotificationGroup;
+
+use super::check_response;
+
+/// Sends notifications as messages to a Matrix room, through the client-server
+/// API of its homeserver
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MatrixSink {
+    pub homeserver: url::Url,
+    pub room_id: String,
+    pub access_token: String,
+}
+
+impl MatrixSink {
+    pub async fn send(&self, group: &NotificationGroup) -> Result<()> {
+        debug!(
+            message = "Sending notification",
+            sink = "matrix",
+            room_id = &self.room_id,
+            notification = group.summary(),
+        );
+        let body = std::iter::once(group.summary())
+            .chain(group.details().into_iter().map(|(label, value)| format!("{}: {}", label, value)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let response = reqwest::Client::new()
+            .put(self.message_url(group)?)
+            .bearer_auth(&self.access_token)
+            .json(&json!({"msgtype": "m.text", "body": body}))
+            .send()
+            .await?;
+        check_response("matrix", &group.summary(), response).await
+    }
+
+    /// The transaction id only needs to be unique for the access token, it
+    /// lets the homeserver drop retries of the same message
+    fn message_url(&self, group: &NotificationGroup) -> Result<url::Url> {
+        let txn_id = group
+            .idempotency_key
+            .clone()
+            .unwrap_or_else(|| thread_rng().sample_iter(&Alphanumeric).take(16).map(char::from).collect());
+        let mut url = self.homeserver.clone();
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid homeserver url {}", self.homeserver))?
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3", "rooms", &self.room_id, "send", "m.room.message", &txn_id]);
+        Ok(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use httptest::{
+        all_of,
+        matchers::{contains, eq, json_decoded, matches, request},
+        responders::status_code,
+        Expectation,
+    };
+    use serde_json::json;
+
+    use super::MatrixSink;
+    use crate::notification::Notification;
+
+    #[actix_web::test]
+    async fn matrix_sends_message() {
+        let notification = Notification::new_test(0);
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("PUT"),
+                request::path(matches(
+                    "^/_matrix/client/v3/rooms/!room:example.org/send/m.room.message/[A-Za-z0-9]{16}$"
+                )),
+                request::headers(contains(("authorization", "Bearer token"))),
+                request::body(json_decoded(eq(json!({
+                    "msgtype": "m.text",
+                    "body": format!(
+                        "{}\nNode: {}\nNode provider: test\nStatus: Healthy -> Degraded",
+                        notification.summary(),
+                        notification.node_id
+                    ),
+                })))),
+            ])
+            .respond_with(status_code(200)),
+        );
+        let sink = MatrixSink {
+            homeserver: url::Url::parse(&server.url("/").to_string()).unwrap(),
+            room_id: "!room:example.org".to_string(),
+            access_token: "token".to_string(),
+        };
+
+        assert!(sink.send(&notification.clone().into()).await.is_ok());
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use ic_management_types::Status;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::notification::Notification;
+
+use super::check_response;
+
+/// Triggers and resolves incidents through a PagerDuty compatible Events API
+/// (v2). There is one incident per node, resolved once the node is healthy
+/// again.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PagerDutySink {
+    #[serde(default = "default_url")]
+    pub url: url::Url,
+    pub routing_key: String,
+}
+
+fn default_url() -> url::Url {
+    url::Url::parse("https://events.pagerduty.com/v2/enqueue").expect("valid url")
+}
+
+impl PagerDutySink {
+    pub async fn send(&self, notification: &Notification) -> Result<()> {
+        debug!(
+            message = "Sending notification",
+            sink = "pagerduty",
+            notification = notification.summary()
+        );
+        let response = reqwest::Client::new()
+            .post(self.url.clone())
+            .json(&self.event(notification))
+            .send()
+            .await?;
+        check_response("pagerduty", &notification.summary(), response).await
+    }
+
+    fn event(&self, notification: &Notification) -> Value {
+        let dedup_key = format!("np-notifications-{}", notification.node_id);
+        let severity = match notification.status_change.1 {
+            Status::Healthy => {
+                return json!({
+                    "routing_key": self.routing_key,
+                    "event_action": "resolve",
+                    "dedup_key": dedup_key,
+                })
+            }
+            Status::Degraded => "warning",
+            Status::Dead => "critical",
+            Status::Unknown => "error",
+        };
+        let custom_details = notification.details().into_iter().collect::<BTreeMap<_, _>>();
+        json!({
+            "routing_key": self.routing_key,
+            "event_action": "trigger",
+            "dedup_key": dedup_key,
+            "payload": {
+                "summary": notification.summary(),
+                "source": notification.node_id.to_string(),
+                "severity": severity,
+                "custom_details": custom_details,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use httptest::{
+        all_of,
+        matchers::{eq, json_decoded, request},
+        responders::status_code,
+        Expectation,
+    };
+    use ic_management_types::Status;
+    use serde_json::json;
+
+    use super::PagerDutySink;
+    use crate::notification::Notification;
+
+    #[actix_web::test]
+    async fn pagerduty_triggers_and_resolves() {
+        let notification = Notification::new_test(0);
+        let dedup_key = format!("np-notifications-{}", notification.node_id);
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/v2/enqueue"),
+                request::body(json_decoded(eq(json!({
+                    "routing_key": "key",
+                    "event_action": "trigger",
+                    "dedup_key": dedup_key,
+                    "payload": {
+                        "summary": notification.summary(),
+                        "source": notification.node_id.to_string(),
+                        "severity": "warning",
+                        "custom_details": {
+                            "Node": notification.node_id.to_string(),
+                            "Node provider": "test",
+                            "Status": "Healthy -> Degraded",
+                        },
+                    },
+                }))))
+            ])
+            .respond_with(status_code(202)),
+        );
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/v2/enqueue"),
+                request::body(json_decoded(eq(json!({
+                    "routing_key": "key",
+                    "event_action": "resolve",
+                    "dedup_key": dedup_key,
+                }))))
+            ])
+            .respond_with(status_code(202)),
+        );
+        let sink = PagerDutySink {
+            url: url::Url::parse(&server.url("/v2/enqueue").to_string()).unwrap(),
+            routing_key: "key".to_string(),
+        };
+
+        assert!(sink.send(&notification).await.is_ok());
+        let recovered = Notification {
+            status_change: (Status::Degraded, Status::Healthy),
+            ..notification
+        };
+        assert!(sink.send(&recovered).await.is_ok());
+    }
+}
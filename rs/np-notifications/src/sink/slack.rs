@@ -0,0 +1,93 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::notification::NotificationGroup;
+
+use super::check_response;
+
+/// Posts notifications to a Slack incoming webhook, formatted with blocks
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SlackSink {
+    pub url: url::Url,
+}
+
+impl SlackSink {
+    pub async fn send(&self, group: &NotificationGroup) -> Result<()> {
+        debug!(message = "Sending notification", sink = "slack", notification = group.summary());
+        let response = reqwest::Client::new().post(self.url.clone()).json(&message(group)).send().await?;
+        check_response("slack", &group.summary(), response).await
+    }
+}
+
+/// Slack accepts at most 10 fields per section, so the details of large groups
+/// are split over several sections
+const MAX_SECTION_FIELDS: usize = 10;
+
+fn message(group: &NotificationGroup) -> Value {
+    let fields = group
+        .details()
+        .into_iter()
+        .map(|(label, value)| json!({"type": "mrkdwn", "text": format!("*{}*\n{}", label, value)}))
+        .collect::<Vec<_>>();
+    let blocks = std::iter::once(json!({"type": "section", "text": {"type": "mrkdwn", "text": format!("*{}*", group.summary())}}))
+        .chain(
+            fields
+                .chunks(MAX_SECTION_FIELDS)
+                .map(|fields| json!({"type": "section", "fields": fields})),
+        )
+        .collect::<Vec<_>>();
+    json!({
+        "text": group.summary(),
+        "blocks": blocks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use httptest::{
+        all_of,
+        matchers::{eq, json_decoded, request},
+        responders::status_code,
+        Expectation,
+    };
+    use serde_json::json;
+
+    use super::SlackSink;
+    use crate::notification::Notification;
+
+    #[actix_web::test]
+    async fn slack_sends_blocks() {
+        let notification = Notification::new_test(0);
+        let server = httptest::Server::run();
+        let summary = notification.summary();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/hook"),
+                request::body(json_decoded(eq(json!({
+                    "text": summary,
+                    "blocks": [
+                        {"type": "section", "text": {"type": "mrkdwn", "text": format!("*{}*", summary)}},
+                        {"type": "section", "fields": [
+                            {"type": "mrkdwn", "text": format!("*Node*\n{}", notification.node_id)},
+                            {"type": "mrkdwn", "text": "*Node provider*\ntest"},
+                            {"type": "mrkdwn", "text": "*Status*\nHealthy -> Degraded"},
+                        ]},
+                    ],
+                }))))
+            ])
+            .respond_with(status_code(200)),
+        );
+        let sink = SlackSink {
+            url: url::Url::parse(&server.url("/hook").to_string()).unwrap(),
+        };
+        assert!(sink.send(&notification.clone().into()).await.is_ok());
+
+        server.expect(Expectation::matching(request::method_path("POST", "/broken")).respond_with(status_code(404)));
+        let sink = SlackSink {
+            url: url::Url::parse(&server.url("/broken").to_string()).unwrap(),
+        };
+        assert!(sink.send(&notification.clone().into()).await.is_err());
+    }
+}
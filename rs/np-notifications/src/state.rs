@@ -0,0 +1,195 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use ic_management_types::{Provider, Status};
+use ic_types::PrincipalId;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::notification::Notification;
+
+/// Directory where the service keeps what it needs to resume after a restart.
+/// Without a directory nothing is persisted.
+#[derive(Debug, Clone, Default)]
+pub struct StateDir {
+    path: Option<PathBuf>,
+}
+
+impl StateDir {
+    pub fn new(path: Option<PathBuf>) -> Result<Self> {
+        if let Some(path) = &path {
+            fs::create_dir_all(path).with_context(|| format!("Failed to create state directory {}", path.display()))?;
+        }
+        Ok(Self { path })
+    }
+
+    pub fn load<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>> {
+        let Some(path) = self.file(name) else {
+            return Ok(None);
+        };
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Some(
+            serde_json::from_slice(&contents).with_context(|| format!("Failed to parse {}", path.display()))?,
+        ))
+    }
+
+    /// Replaces the file atomically, so that a crash leaves either the
+    /// previous or the new contents
+    pub fn save<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        let Some(path) = self.file(name) else {
+            return Ok(());
+        };
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(value)?).with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path).with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Appends the value as a JSON line to the file
+    pub fn append<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        let Some(path) = self.path.as_ref().map(|dir| dir.join(format!("{}.jsonl", name))) else {
+            return Ok(());
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(value)?)?;
+        Ok(())
+    }
+
+    fn file(&self, name: &str) -> Option<PathBuf> {
+        self.path.as_ref().map(|dir| dir.join(format!("{}.json", name)))
+    }
+}
+
+/// Complete representation of a notification, for persisting it. The
+/// `Serialize` implementation of `Notification` is the format sent to the
+/// webhooks, which leaves out details.
+#[derive(Serialize, Deserialize)]
+struct StoredNotification {
+    node_id: PrincipalId,
+    node_provider: Option<Provider>,
+    status_change: (Status, Status),
+    node_operator_id: Option<PrincipalId>,
+    dc_id: Option<String>,
+    subnet_id: Option<PrincipalId>,
+    previous_status_duration: Option<Duration>,
+}
+
+impl From<&Notification> for StoredNotification {
+    fn from(n: &Notification) -> Self {
+        Self {
+            node_id: n.node_id,
+            node_provider: n.node_provider.clone(),
+            status_change: n.status_change.clone(),
+            node_operator_id: n.node_operator_id,
+            dc_id: n.dc_id.clone(),
+            subnet_id: n.subnet_id,
+            previous_status_duration: n.previous_status_duration,
+        }
+    }
+}
+
+impl From<StoredNotification> for Notification {
+    fn from(n: StoredNotification) -> Self {
+        Self {
+            node_id: n.node_id,
+            node_provider: n.node_provider,
+            status_change: n.status_change,
+            node_operator_id: n.node_operator_id,
+            dc_id: n.dc_id,
+            subnet_id: n.subnet_id,
+            previous_status_duration: n.previous_status_duration,
+        }
+    }
+}
+
+/// For `#[serde(with = "...")]` on a persisted `Notification`
+pub mod notification {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::StoredNotification;
+    use crate::notification::Notification;
+
+    pub fn serialize<S: Serializer>(notification: &Notification, serializer: S) -> Result<S::Ok, S::Error> {
+        StoredNotification::from(notification).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Notification, D::Error> {
+        Ok(StoredNotification::deserialize(deserializer)?.into())
+    }
+}
+
+/// For `#[serde(with = "...")]` on persisted `Vec<Notification>`
+pub mod notifications {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::StoredNotification;
+    use crate::notification::Notification;
+
+    pub fn serialize<S: Serializer>(notifications: &[Notification], serializer: S) -> Result<S::Ok, S::Error> {
+        notifications
+            .iter()
+            .map(StoredNotification::from)
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Notification>, D::Error> {
+        Ok(Vec::<StoredNotification>::deserialize(deserializer)?
+            .into_iter()
+            .map(Notification::from)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::StateDir;
+    use crate::notification::Notification;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Stored {
+        #[serde(with = "super::notifications")]
+        notifications: Vec<Notification>,
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state = StateDir::new(Some(temp_dir.path().join("state"))).unwrap();
+        assert_eq!(state.load::<Stored>("stored").unwrap(), None);
+
+        let stored = Stored {
+            notifications: vec![Notification {
+                dc_id: Some("zh1".to_string()),
+                previous_status_duration: Some(std::time::Duration::from_secs(60)),
+                ..Notification::new_test(0)
+            }],
+        };
+        state.save("stored", &stored).unwrap();
+        assert_eq!(state.load::<Stored>("stored").unwrap(), Some(stored));
+
+        state.append("lines", &BTreeMap::from([("a", 1)])).unwrap();
+        state.append("lines", &BTreeMap::from([("a", 2)])).unwrap();
+        let lines = std::fs::read_to_string(temp_dir.path().join("state/lines.jsonl")).unwrap();
+        assert_eq!(lines, "{\"a\":1}\n{\"a\":2}\n");
+
+        let in_memory = StateDir::default();
+        in_memory.save("stored", &1).unwrap();
+        assert_eq!(in_memory.load::<u32>("stored").unwrap(), None);
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use anyhow::{anyhow, Result};
+use ic_management_types::{Provider, Status};
+use ic_types::PrincipalId;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    auth::Auth,
+    notification::{Notification, NotificationGroup},
+    sink::{check_public_url, Sink, WebhookFormat, WebhookSink},
+    state::StateDir,
+};
+
+const SUBSCRIPTIONS_FILE: &str = "subscriptions";
+const MAX_SUBSCRIPTIONS_PER_NODE_PROVIDER: usize = 10;
+
+/// A webhook a node provider registered through the API, which receives the
+/// notifications about the nodes of the node provider
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: String,
+    pub node_provider_id: PrincipalId,
+    pub url: url::Url,
+}
+
+/// The subscriptions of all the node providers, persisted in the state
+/// directory. The version changes with every change of the subscriptions, so
+/// that the notification sender loop knows when to reload them.
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    state_dir: StateDir,
+    subscriptions: Mutex<BTreeMap<String, Subscription>>,
+    version: AtomicU64,
+    /// The node providers of the registry, the only principals that can
+    /// subscribe
+    node_providers: Mutex<BTreeSet<PrincipalId>>,
+    /// Whether webhooks can be sent to loopback, private or link-local
+    /// addresses, which is only meant for local testing
+    allow_private_targets: bool,
+}
+
+impl Subscriptions {
+    pub fn load(state_dir: StateDir, allow_private_targets: bool) -> Result<Self> {
+        let subscriptions = state_dir.load(SUBSCRIPTIONS_FILE)?.unwrap_or_default();
+        Ok(Self {
+            state_dir,
+            subscriptions: Mutex::new(subscriptions),
+            version: AtomicU64::new(1),
+            node_providers: Mutex::new(BTreeSet::new()),
+            allow_private_targets,
+        })
+    }
+
+    pub fn set_node_providers(&self, node_providers: BTreeSet<PrincipalId>) {
+        *self.node_providers.lock().unwrap() = node_providers;
+    }
+
+    pub fn node_providers(&self) -> BTreeSet<PrincipalId> {
+        self.node_providers.lock().unwrap().clone()
+    }
+
+    pub fn allows_private_targets(&self) -> bool {
+        self.allow_private_targets
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    pub fn all(&self) -> Vec<Subscription> {
+        self.subscriptions.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn of(&self, node_provider_id: PrincipalId) -> Vec<Subscription> {
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|s| s.node_provider_id == node_provider_id)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn add(&self, node_provider_id: PrincipalId, url: url::Url) -> Result<Subscription> {
+        if !["http", "https"].contains(&url.scheme()) {
+            return Err(anyhow!("Only http and https webhooks are supported"));
+        }
+        if !self.node_providers.lock().unwrap().contains(&node_provider_id) {
+            return Err(anyhow!("{} is not a registered node provider", node_provider_id));
+        }
+        if !self.allow_private_targets {
+            check_public_url(&url).await?;
+        }
+        let subscription = Subscription {
+            id: thread_rng().sample_iter(&Alphanumeric).take(16).map(char::from).collect(),
+            node_provider_id,
+            url,
+        };
+        self.update(|subscriptions| {
+            if subscriptions.values().filter(|s| s.node_provider_id == node_provider_id).count() >= MAX_SUBSCRIPTIONS_PER_NODE_PROVIDER {
+                return Err(anyhow!(
+                    "A node provider can have at most {} subscriptions",
+                    MAX_SUBSCRIPTIONS_PER_NODE_PROVIDER
+                ));
+            }
+            subscriptions.insert(subscription.id.clone(), subscription.clone());
+            Ok(())
+        })?;
+        Ok(subscription)
+    }
+
+    /// Removes the subscription if it belongs to the node provider, and
+    /// returns whether it did
+    pub fn remove(&self, node_provider_id: PrincipalId, id: &str) -> Result<bool> {
+        let mut removed = false;
+        self.update(|subscriptions| {
+            if subscriptions.get(id).is_some_and(|s| s.node_provider_id == node_provider_id) {
+                removed = subscriptions.remove(id).is_some();
+            }
+            Ok(())
+        })?;
+        Ok(removed)
+    }
+
+    fn update(&self, change: impl FnOnce(&mut BTreeMap<String, Subscription>) -> Result<()>) -> Result<()> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let mut updated = subscriptions.clone();
+        change(&mut updated)?;
+        self.state_dir.save(SUBSCRIPTIONS_FILE, &updated)?;
+        *subscriptions = updated;
+        self.version.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct ChallengeRequest {
+    principal: PrincipalId,
+}
+
+#[post("/api/v1/auth/challenge")]
+pub async fn challenge(auth: web::Data<Auth>, request: web::Json<ChallengeRequest>) -> impl Responder {
+    match auth.challenge(request.principal) {
+        Ok(challenge) => HttpResponse::Ok().json(json!({ "challenge": challenge })),
+        Err(e) => HttpResponse::TooManyRequests().body(e.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginRequest {
+    principal: PrincipalId,
+    challenge: String,
+    /// DER encoded public key, as hex
+    public_key: String,
+    /// Signature of the challenge message, as hex
+    signature: String,
+}
+
+#[post("/api/v1/auth/login")]
+pub async fn login(auth: web::Data<Auth>, subscriptions: web::Data<Subscriptions>, request: web::Json<LoginRequest>) -> impl Responder {
+    let token = hex::decode(&request.public_key)
+        .map_err(anyhow::Error::new)
+        .and_then(|public_key| Ok((public_key, hex::decode(&request.signature)?)))
+        .and_then(|(public_key, signature)| {
+            auth.login(
+                request.principal,
+                &request.challenge,
+                &public_key,
+                &signature,
+                &subscriptions.node_providers(),
+            )
+        });
+    match token {
+        Ok(token) => HttpResponse::Ok().json(json!({ "token": token })),
+        Err(e) => HttpResponse::Unauthorized().body(e.to_string()),
+    }
+}
+
+/// The node provider the bearer token of the request was given to
+fn authenticated(auth: &Auth, request: &HttpRequest) -> Result<PrincipalId, HttpResponse> {
+    request
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| auth.principal(token))
+        .ok_or_else(|| HttpResponse::Unauthorized().body("Missing or invalid session token"))
+}
+
+#[get("/api/v1/subscriptions")]
+pub async fn list(auth: web::Data<Auth>, subscriptions: web::Data<Subscriptions>, request: HttpRequest) -> impl Responder {
+    match authenticated(&auth, &request) {
+        Ok(principal) => HttpResponse::Ok().json(subscriptions.of(principal)),
+        Err(response) => response,
+    }
+}
+
+#[derive(Deserialize)]
+struct SubscriptionRequest {
+    url: url::Url,
+}
+
+#[post("/api/v1/subscriptions")]
+pub async fn create(
+    auth: web::Data<Auth>,
+    subscriptions: web::Data<Subscriptions>,
+    request: HttpRequest,
+    body: web::Json<SubscriptionRequest>,
+) -> impl Responder {
+    let principal = match authenticated(&auth, &request) {
+        Ok(principal) => principal,
+        Err(response) => return response,
+    };
+    match subscriptions.add(principal, body.into_inner().url).await {
+        Ok(subscription) => HttpResponse::Created().json(subscription),
+        Err(e) => {
+            error!(message = "Failed to add subscription", error = e.to_string());
+            HttpResponse::BadRequest().body(e.to_string())
+        }
+    }
+}
+
+#[delete("/api/v1/subscriptions/{id}")]
+pub async fn remove(auth: web::Data<Auth>, subscriptions: web::Data<Subscriptions>, request: HttpRequest, id: web::Path<String>) -> impl Responder {
+    let principal = match authenticated(&auth, &request) {
+        Ok(principal) => principal,
+        Err(response) => return response,
+    };
+    match subscriptions.remove(principal, &id) {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            error!(message = "Failed to remove subscription", error = e.to_string());
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Sends a test notification to the webhook. It is about the anonymous
+/// principal, with an unknown status before and after.
+#[post("/api/v1/subscriptions/{id}/test")]
+pub async fn send_test(
+    auth: web::Data<Auth>,
+    subscriptions: web::Data<Subscriptions>,
+    request: HttpRequest,
+    id: web::Path<String>,
+) -> impl Responder {
+    let principal = match authenticated(&auth, &request) {
+        Ok(principal) => principal,
+        Err(response) => return response,
+    };
+    let Some(subscription) = subscriptions.of(principal).into_iter().find(|s| s.id == *id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let notification = Notification {
+        node_provider: Some(Provider {
+            principal,
+            name: None,
+            website: None,
+        }),
+        status_change: (Status::Unknown, Status::Unknown),
+        ..Default::default()
+    };
+    let sink = Sink::Webhook(WebhookSink {
+        url: subscription.url,
+        auth: None,
+        public_only: !subscriptions.allows_private_targets(),
+        format: WebhookFormat::V2,
+    });
+    match sink.send_group(&NotificationGroup::new(vec![notification], false)).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => HttpResponse::BadGateway().body(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use actix_web::{http::StatusCode, test, web, App};
+    use ed25519_dalek::{pkcs8::EncodePublicKey, Signer};
+    use httptest::{matchers::request, responders::status_code, Expectation};
+    use ic_types::PrincipalId;
+    use serde_json::{json, Value};
+
+    use super::{Subscription, Subscriptions, MAX_SUBSCRIPTIONS_PER_NODE_PROVIDER};
+    use crate::{
+        auth::{challenge_message, Auth},
+        state::StateDir,
+    };
+
+    #[actix_web::test]
+    async fn self_service() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state_dir = StateDir::new(Some(temp_dir.path().to_path_buf())).unwrap();
+        // The webhook of the test is served locally
+        let subscriptions = web::Data::new(Subscriptions::load(state_dir.clone(), true).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Auth::default()))
+                .app_data(subscriptions.clone())
+                .service(super::challenge)
+                .service(super::login)
+                .service(super::list)
+                .service(super::create)
+                .service(super::remove)
+                .service(super::send_test),
+        )
+        .await;
+
+        let key = ed25519_dalek::SigningKey::from_bytes(&[3; 32]);
+        let public_key = key.verifying_key().to_public_key_der().unwrap().into_vec();
+        let principal = PrincipalId::new_self_authenticating(&public_key);
+        subscriptions.set_node_providers(BTreeSet::from([principal, PrincipalId::new_user_test_id(1)]));
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/subscriptions")
+            .set_json(json!({"url": "https://example.com"}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/auth/challenge")
+            .set_json(json!({ "principal": principal.to_string() }))
+            .to_request();
+        let response: Value = test::call_and_read_body_json(&app, req).await;
+        let challenge = response["challenge"].as_str().unwrap();
+        let req = test::TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .set_json(json!({
+                "principal": principal.to_string(),
+                "challenge": challenge,
+                "public_key": hex::encode(&public_key),
+                "signature": hex::encode(key.sign(&challenge_message(challenge)).to_bytes()),
+            }))
+            .to_request();
+        let response: Value = test::call_and_read_body_json(&app, req).await;
+        let authorization = ("Authorization", format!("Bearer {}", response["token"].as_str().unwrap()));
+
+        let server = httptest::Server::run();
+        server.expect(Expectation::matching(request::method_path("POST", "/hook")).respond_with(status_code(200)));
+        let req = test::TestRequest::post()
+            .uri("/api/v1/subscriptions")
+            .insert_header(authorization.clone())
+            .set_json(json!({ "url": server.url("/hook").to_string() }))
+            .to_request();
+        let subscription: Subscription = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(subscription.node_provider_id, principal);
+        let version = subscriptions.version();
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/subscriptions")
+            .insert_header(authorization.clone())
+            .to_request();
+        let listed: Vec<Subscription> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(listed, vec![subscription.clone()]);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/v1/subscriptions/{}/test", subscription.id))
+            .insert_header(authorization.clone())
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        // Persisted
+        assert_eq!(Subscriptions::load(state_dir.clone(), true).unwrap().all(), vec![subscription.clone()]);
+
+        // Other node providers cannot remove it
+        subscriptions
+            .add(PrincipalId::new_user_test_id(1), "https://example.com".parse().unwrap())
+            .await
+            .unwrap();
+        let other = subscriptions.of(PrincipalId::new_user_test_id(1)).remove(0);
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/v1/subscriptions/{}", other.id))
+            .insert_header(authorization.clone())
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/v1/subscriptions/{}", subscription.id))
+            .insert_header(authorization.clone())
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+        assert_eq!(subscriptions.all(), vec![other]);
+        assert!(subscriptions.version() > version);
+    }
+
+    #[actix_web::test]
+    async fn only_registered_node_providers_subscribe_to_public_webhooks() {
+        let node_provider = PrincipalId::new_user_test_id(0);
+        let url: url::Url = "https://example.com/hook".parse().unwrap();
+        let subscriptions = Subscriptions::default();
+        assert!(subscriptions.add(node_provider, url.clone()).await.is_err());
+
+        subscriptions.set_node_providers(BTreeSet::from([node_provider]));
+        for private in ["http://127.0.0.1:8080/hook", "http://169.254.169.254/", "https://10.0.0.1/hook"] {
+            assert!(subscriptions.add(node_provider, private.parse().unwrap()).await.is_err());
+        }
+        assert!(subscriptions.all().is_empty());
+
+        let subscriptions = Subscriptions {
+            allow_private_targets: true,
+            ..Default::default()
+        };
+        subscriptions.set_node_providers(BTreeSet::from([node_provider]));
+        for _ in 0..MAX_SUBSCRIPTIONS_PER_NODE_PROVIDER {
+            subscriptions.add(node_provider, url.clone()).await.unwrap();
+        }
+        assert!(subscriptions.add(node_provider, url).await.is_err());
+        assert_eq!(subscriptions.of(node_provider).len(), MAX_SUBSCRIPTIONS_PER_NODE_PROVIDER);
+    }
+}
@@ -1,20 +1,29 @@
 use std::time::Duration;
 
+use chrono::Utc;
 use dre::{
     detect_neuron::Neuron,
     ic_admin::{IcAdminWrapper, ProposeCommand, ProposeOptions},
 };
 use ic_base_types::PrincipalId;
 use ic_management_types::Network;
-use slog::{info, Logger};
+use serde::{Deserialize, Serialize};
+use slog::{info, warn, Logger};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+use crate::{
+    history::{ActionOutcome, Iteration},
+    rollbacks::{RollbackLog, RollbackRecord},
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum SubnetAction {
     Noop {
         subnet_short: String,
     },
     Baking {
         subnet_short: String,
+        #[serde(with = "humantime_serde")]
         remaining: Duration,
     },
     PendingProposal {
@@ -29,10 +38,68 @@ pub enum SubnetAction {
     WaitForNextWeek {
         subnet_short: String,
     },
+    /// The subnet became unhealthy while baking and is put back on the
+    /// version of the previous release
+    Rollback {
+        subnet_principal: PrincipalId,
+        release: String,
+        from_version: String,
+        to_version: String,
+        reason: String,
+    },
+    /// A subnet was rolled back, so no more subnets are upgraded to the
+    /// release
+    RolloutPaused {
+        release: String,
+        reason: String,
+    },
+    /// Upgrade the HostOS of some nodes of a group in the HostOS rollout
+    PlaceHostosProposal {
+        group: String,
+        nodes: Vec<PrincipalId>,
+        version: String,
+    },
+    HostosBaking {
+        group: String,
+        #[serde(with = "humantime_serde")]
+        remaining: Duration,
+    },
+    HostosPendingProposal {
+        group: String,
+        proposal_id: u64,
+    },
+    /// The proposal of the action is not placed because of a freeze, a
+    /// blackout window, a holiday or the allowed hours of the stage
+    Blocked {
+        action: Box<SubnetAction>,
+        reason: String,
+    },
 }
 
 impl SubnetAction {
-    fn print(&self) -> String {
+    /// Principal, or principal prefix, of the subnet the action is about
+    pub fn subnet(&self) -> Option<String> {
+        match self {
+            SubnetAction::Noop { subnet_short }
+            | SubnetAction::Baking { subnet_short, .. }
+            | SubnetAction::PendingProposal { subnet_short, .. }
+            | SubnetAction::WaitForNextWeek { subnet_short } => Some(subnet_short.clone()),
+            SubnetAction::PlaceProposal {
+                is_unassigned: false,
+                subnet_principal,
+                ..
+            }
+            | SubnetAction::Rollback { subnet_principal, .. } => Some(subnet_principal.to_string()),
+            SubnetAction::PlaceProposal { is_unassigned: true, .. }
+            | SubnetAction::RolloutPaused { .. }
+            | SubnetAction::PlaceHostosProposal { .. }
+            | SubnetAction::HostosBaking { .. }
+            | SubnetAction::HostosPendingProposal { .. } => None,
+            SubnetAction::Blocked { action, .. } => action.subnet(),
+        }
+    }
+
+    pub fn print(&self) -> String {
         match self {
             SubnetAction::Noop { subnet_short } => format!("Noop for subnet '{}'", subnet_short),
             SubnetAction::Baking { subnet_short, remaining } => {
@@ -58,6 +125,32 @@ impl SubnetAction {
             SubnetAction::WaitForNextWeek { subnet_short } => {
                 format!("Waiting for next week to place proposal for '{}'", subnet_short)
             }
+            SubnetAction::Rollback {
+                subnet_principal,
+                from_version,
+                to_version,
+                reason,
+                ..
+            } => format!(
+                "Rolling back subnet '{}' from version '{}' to version '{}' because {}",
+                subnet_principal, from_version, to_version, reason
+            ),
+            SubnetAction::RolloutPaused { release, reason } => format!("Rollout of release '{}' is paused: {}", release, reason),
+            SubnetAction::PlaceHostosProposal { group, nodes, version } => format!(
+                "Placing proposal for {} nodes of {} to upgrade to HostOS version '{}'",
+                nodes.len(),
+                group,
+                version
+            ),
+            SubnetAction::HostosBaking { group, remaining } => {
+                let humantime = humantime::format_duration(*remaining);
+                format!("HostOS of {} is pending to bake for {}", group, humantime)
+            }
+            SubnetAction::HostosPendingProposal { group, proposal_id } => format!(
+                "HostOS of {} has a pending proposal with id '{}' that has to be voted on",
+                group, proposal_id
+            ),
+            SubnetAction::Blocked { action, reason } => format!("{} is blocked, {}", action.print(), reason),
         }
     }
 }
@@ -101,6 +194,73 @@ impl<'a> SubnetAction {
             executor.ic_admin_wrapper.propose_run(proposal, opts, executor.simulate).await?;
         }
 
+        if let SubnetAction::Rollback {
+            subnet_principal,
+            release,
+            from_version,
+            to_version,
+            reason,
+        } = self
+        {
+            if !blessed_replica_versions.contains(to_version) {
+                return Err(anyhow::anyhow!("GuestOS version '{}' is not elected.", to_version));
+            }
+            let principal_string = subnet_principal.to_string();
+
+            let proposal = ProposeCommand::DeployGuestosToAllSubnetNodes {
+                subnet: *subnet_principal,
+                version: to_version.to_string(),
+            };
+
+            let opts = ProposeOptions {
+                title: Some(format!(
+                    "Roll back subnet {} to GuestOS version {}",
+                    principal_string.split_once('-').expect("Should contain '-'").0,
+                    to_version.split_at(8).0
+                )),
+                summary: Some(format!(
+                    "Roll back subnet {} from GuestOS version {} to GuestOS version {}.\n\nThe subnet became unhealthy while baking the new version: {}",
+                    principal_string, from_version, to_version, reason
+                )),
+                ..Default::default()
+            };
+
+            executor.ic_admin_wrapper.propose_run(proposal, opts, executor.simulate).await?;
+
+            // A simulated rollback must not pause the rollout for real
+            if !executor.simulate {
+                executor.rollback_log.record(RollbackRecord {
+                    release: release.clone(),
+                    subnet: principal_string,
+                    from_version: from_version.clone(),
+                    to_version: to_version.clone(),
+                    reason: reason.clone(),
+                    timestamp: Utc::now(),
+                })?;
+            }
+            if let Some(logger) = executor.logger {
+                warn!(
+                    logger,
+                    "Rollout of release '{}' is paused after rolling back subnet '{}'", release, subnet_principal
+                )
+            }
+        }
+
+        if let SubnetAction::PlaceHostosProposal { nodes, version, .. } = self {
+            let title = format!("Set HostOS version: {} on {} nodes", version, nodes.len());
+            let proposal = ProposeCommand::DeployHostosToSomeNodes {
+                nodes: nodes.clone(),
+                version: version.to_string(),
+            };
+            let opts = ProposeOptions {
+                title: Some(title.clone()),
+                summary: Some(title),
+                ..Default::default()
+            };
+
+            executor.ic_admin_wrapper.propose_run(proposal, opts, executor.simulate).await?;
+        }
+
         Ok(())
     }
 }
@@ -109,15 +269,24 @@ pub struct ActionExecutor<'a> {
     ic_admin_wrapper: IcAdminWrapper,
     simulate: bool,
     logger: Option<&'a Logger>,
+    rollback_log: RollbackLog,
 }
 
 impl<'a> ActionExecutor<'a> {
-    pub async fn new(neuron_id: u64, private_key_pem: String, network: Network, simulate: bool, logger: Option<&'a Logger>) -> anyhow::Result<Self> {
+    pub async fn new(
+        neuron_id: u64,
+        private_key_pem: String,
+        network: Network,
+        simulate: bool,
+        logger: Option<&'a Logger>,
+        rollback_log: RollbackLog,
+    ) -> anyhow::Result<Self> {
         let neuron = Neuron::new(&network, Some(neuron_id), Some(private_key_pem), None, None, None).await;
         Ok(Self {
             ic_admin_wrapper: IcAdminWrapper::new(network, None, true, neuron),
             simulate,
             logger,
+            rollback_log,
         })
     }
 
@@ -127,10 +296,13 @@ impl<'a> ActionExecutor<'a> {
             ic_admin_wrapper: IcAdminWrapper::new(network, None, true, neuron),
             simulate: true,
             logger,
+            rollback_log: RollbackLog::disabled(),
         })
     }
 
-    pub async fn execute(&self, actions: &[SubnetAction], blessed_replica_versions: &[String]) -> anyhow::Result<()> {
+    /// Executes the actions in order, stopping at the first one that fails.
+    /// The outcome of every executed action is recorded in the iteration.
+    pub async fn execute(&self, actions: &[SubnetAction], blessed_replica_versions: &[String], iteration: &mut Iteration) -> anyhow::Result<()> {
         if let Some(logger) = self.logger {
             info!(logger, "Executing following actions: {:?}", actions)
         }
@@ -139,7 +311,12 @@ impl<'a> ActionExecutor<'a> {
             if let Some(logger) = self.logger {
                 info!(logger, "Executing action {}: {:?}", i, action)
             }
-            action.execute(self, blessed_replica_versions).await?;
+            let result = action.execute(self, blessed_replica_versions).await;
+            iteration.outcomes.push(ActionOutcome {
+                action: i,
+                error: result.as_ref().err().map(|e| e.to_string()),
+            });
+            result?;
         }
 
         Ok(())
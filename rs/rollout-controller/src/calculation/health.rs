@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+
+use ic_management_types::Subnet;
+use itertools::Itertools;
+use prometheus_http_query::Client;
+use serde::{Deserialize, Serialize};
+use slog::{info, warn, Logger};
+
+use crate::actions::SubnetAction;
+
+use super::{stage_checks::DesiredReleaseVersion, Release};
+
+/// A Prometheus query that tells if a subnet stays healthy while it bakes a
+/// new GuestOS version, e.g. its finalization rate or its error rate.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct HealthCheck {
+    pub name: String,
+    /// Instant query that returns one sample per subnet, labelled with
+    /// `ic_subnet`, e.g. `sum by (ic_subnet) (rate(artifact_pool_consensus_height_stat{type="finalization"}[10m]))`
+    pub query: String,
+    /// The subnet is unhealthy if the sample is below this value
+    #[serde(default)]
+    pub min: Option<f64>,
+    /// The subnet is unhealthy if the sample is above this value
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+impl HealthCheck {
+    fn breach(&self, value: f64) -> Option<String> {
+        match (self.min, self.max) {
+            (Some(min), _) if value < min => Some(format!("{} is {} which is below {}", self.name, value, min)),
+            (_, Some(max)) if value > max => Some(format!("{} is {} which is above {}", self.name, value, max)),
+            _ => None,
+        }
+    }
+}
+
+/// Samples of the health checks, by check name and subnet principal
+pub type HealthSamples = BTreeMap<String, BTreeMap<String, f64>>;
+
+pub async fn query_health(prometheus_client: &Client, health_checks: &[HealthCheck]) -> anyhow::Result<HealthSamples> {
+    let mut samples = HealthSamples::new();
+    for check in health_checks {
+        let result = prometheus_client.query(check.query.as_str()).get().await?;
+        let vector = match result.data().clone().into_vector().into_iter().last() {
+            Some(data) => data,
+            None => return Err(anyhow::anyhow!("Health check '{}' should return an instant vector", check.name)),
+        };
+        let check_samples = samples.entry(check.name.clone()).or_default();
+        for sample in vector.iter() {
+            if let Some(subnet) = sample.metric().get("ic_subnet") {
+                check_samples.insert(subnet.to_string(), sample.sample().value());
+            }
+        }
+    }
+    Ok(samples)
+}
+
+/// If a subnet that is baking the new version breaches any of the health
+/// checks, the actions are replaced by rollbacks of the unhealthy subnets to
+/// the version of the previous release. No other actions are taken, which
+/// pauses the rest of the rollout.
+pub fn check_health(
+    actions: Vec<SubnetAction>,
+    health_checks: &[HealthCheck],
+    samples: &HealthSamples,
+    subnets: &[Subnet],
+    releases: &[Release],
+    desired_versions: &DesiredReleaseVersion,
+    logger: Option<&Logger>,
+) -> anyhow::Result<Vec<SubnetAction>> {
+    let mut rollbacks = vec![];
+    for action in &actions {
+        let subnet_short = match action {
+            SubnetAction::Baking { subnet_short, .. } => subnet_short,
+            _ => continue,
+        };
+        let subnet = subnets
+            .iter()
+            .find(|s| s.principal.to_string().starts_with(subnet_short))
+            .ok_or_else(|| anyhow::anyhow!("Subnet '{}' not found", subnet_short))?;
+
+        let principal = subnet.principal.to_string();
+        let breaches = health_checks
+            .iter()
+            .filter_map(|check| match samples.get(&check.name).and_then(|s| s.get(&principal)) {
+                Some(value) => check.breach(*value),
+                None => {
+                    if let Some(logger) = logger {
+                        warn!(logger, "No sample of health check '{}' for subnet '{}'", check.name, subnet_short)
+                    }
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if breaches.is_empty() {
+            continue;
+        }
+
+        let to_version = previous_version(releases, &desired_versions.release, subnet).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Subnet '{}' is unhealthy ({}), but there is no previous release to roll back to",
+                subnet_short,
+                breaches.join(", ")
+            )
+        })?;
+        if let Some(logger) = logger {
+            info!(logger, "Subnet '{}' is unhealthy while baking: {}", subnet_short, breaches.join(", "))
+        }
+        rollbacks.push(SubnetAction::Rollback {
+            subnet_principal: subnet.principal,
+            release: desired_versions.release.rc_name.clone(),
+            from_version: subnet.replica_version.clone(),
+            to_version,
+            reason: breaches.join(", "),
+        });
+    }
+
+    if rollbacks.is_empty() {
+        return Ok(actions);
+    }
+    Ok(rollbacks)
+}
+
+/// The version the subnet ran in the release before `release`
+fn previous_version(releases: &[Release], release: &Release, subnet: &Subnet) -> Option<String> {
+    let position = releases.iter().position(|r| r == release)?;
+    let previous = releases.get(position + 1)?;
+    previous
+        .versions
+        .iter()
+        .find_or_first(|v| v.subnets.iter().any(|vs| subnet.principal.to_string().starts_with(vs)))
+        .map(|v| v.version.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ic_base_types::PrincipalId;
+    use pretty_assertions::assert_eq;
+
+    use crate::calculation::stage_checks::desired_rollout_release_version;
+    use crate::calculation::Version;
+
+    use super::*;
+
+    fn subnet(id: u64, version: &str) -> Subnet {
+        Subnet {
+            principal: PrincipalId::new_subnet_test_id(id),
+            replica_version: version.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn release(name: &str, versions: Vec<(&str, Vec<u64>)>) -> Release {
+        Release {
+            rc_name: name.to_string(),
+            versions: versions
+                .into_iter()
+                .map(|(v, subnets)| Version {
+                    version: v.to_string(),
+                    subnets: subnets.into_iter().map(|id| PrincipalId::new_subnet_test_id(id).to_string()).collect(),
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    fn finalization_rate() -> HealthCheck {
+        HealthCheck {
+            name: "finalization rate".to_string(),
+            min: Some(0.5),
+            ..Default::default()
+        }
+    }
+
+    fn samples(values: &[(u64, f64)]) -> HealthSamples {
+        BTreeMap::from([(
+            "finalization rate".to_string(),
+            values
+                .iter()
+                .map(|(id, value)| (PrincipalId::new_subnet_test_id(*id).to_string(), *value))
+                .collect(),
+        )])
+    }
+
+    fn baking(id: u64) -> SubnetAction {
+        SubnetAction::Baking {
+            subnet_short: PrincipalId::new_subnet_test_id(id).to_string(),
+            remaining: Duration::from_secs(3600),
+        }
+    }
+
+    #[test]
+    fn healthy_subnets_keep_baking() {
+        let subnets = vec![subnet(1, "b"), subnet(2, "a")];
+        let releases = vec![release("rc--2", vec![("b", vec![])]), release("rc--1", vec![("a", vec![])])];
+        let desired_versions = desired_rollout_release_version(&subnets, &releases);
+        let actions = vec![baking(1)];
+
+        let checked = check_health(
+            actions.clone(),
+            &[finalization_rate()],
+            &samples(&[(1, 0.9), (2, 0.1)]),
+            &subnets,
+            &releases,
+            &desired_versions,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(checked, actions);
+    }
+
+    #[test]
+    fn unhealthy_subnet_is_rolled_back_to_previous_release() {
+        let subnets = vec![subnet(1, "b.feat"), subnet(2, "b"), subnet(3, "a")];
+        let releases = vec![
+            release("rc--2", vec![("b", vec![]), ("b.feat", vec![1])]),
+            release("rc--1", vec![("a", vec![]), ("a.feat", vec![1])]),
+        ];
+        let desired_versions = desired_rollout_release_version(&subnets, &releases);
+
+        let checked = check_health(
+            vec![baking(1), baking(2)],
+            &[finalization_rate()],
+            &samples(&[(1, 0.2), (2, 0.9)]),
+            &subnets,
+            &releases,
+            &desired_versions,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            checked,
+            vec![SubnetAction::Rollback {
+                subnet_principal: PrincipalId::new_subnet_test_id(1),
+                release: "rc--2".to_string(),
+                from_version: "b.feat".to_string(),
+                to_version: "a.feat".to_string(),
+                reason: "finalization rate is 0.2 which is below 0.5".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn cannot_roll_back_without_previous_release() {
+        let subnets = vec![subnet(1, "b"), subnet(2, "a")];
+        let releases = vec![release("rc--2", vec![("b", vec![])]), release("rc--1", vec![("a", vec![])])];
+        let desired_versions = desired_rollout_release_version(&subnets, &releases);
+
+        let checked = check_health(
+            vec![baking(1)],
+            &[finalization_rate()],
+            &samples(&[(1, 0.2)]),
+            &subnets,
+            &releases[..1],
+            &desired_versions,
+            None,
+        );
+
+        assert!(checked.is_err());
+    }
+}
@@ -0,0 +1,340 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use chrono::{DateTime, Utc};
+use dre::{
+    cli::hostos::{NodeAssignment, NodeOwner},
+    operations::hostos_rollout::{HostosRollout, HostosRolloutResponse, NodeGroup, NodeGroupUpdate, NumberOfNodes},
+};
+use ic_base_types::{NodeId, PrincipalId};
+use ic_management_backend::{proposal::UpdateNodesHostosVersionProposal, registry::RegistryState};
+use ic_management_types::{Artifact, Node};
+use serde::{Deserialize, Serialize};
+use slog::{info, warn, Logger};
+
+use crate::actions::SubnetAction;
+
+/// HostOS rollout of a single version, in stages over groups of nodes.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct HostosRolloutIndex {
+    pub version: String,
+    pub stages: Vec<HostosStage>,
+}
+
+/// A stage is done once the given percentage of the nodes of its group runs
+/// the version and the last proposal that updated them has baked.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HostosStage {
+    #[serde(with = "value_enum", default)]
+    pub assignment: NodeAssignment,
+    #[serde(with = "value_enum", default)]
+    pub owner: NodeOwner,
+    /// Percentage of the nodes of the group that run the version once the
+    /// stage is done, from 0 to 100. The nodes to update are taken from every
+    /// subnet in proportion to its size, so assigned nodes get the version
+    /// spread over the subnets.
+    #[serde(deserialize_with = "percentage")]
+    pub percentage: u8,
+    #[serde(with = "humantime_serde")]
+    pub bake_time: Duration,
+}
+
+impl HostosStage {
+    fn node_group(&self) -> NodeGroup {
+        NodeGroup::new(self.assignment, self.owner)
+    }
+
+    fn contains(&self, group: &NodeGroup) -> bool {
+        (self.assignment == NodeAssignment::All || self.assignment == group.assignment) && (self.owner == NodeOwner::All || self.owner == group.owner)
+    }
+}
+
+/// What the controller should do about the first HostOS stage that is not
+/// done yet.
+#[derive(Debug, PartialEq)]
+enum HostosStageStatus {
+    /// More nodes of the group have to run the version
+    UpdateNodes {
+        stage: usize,
+        percentage: u8,
+    },
+    Pending {
+        stage: usize,
+        proposal_id: u64,
+    },
+    Baking {
+        stage: usize,
+        remaining: Duration,
+    },
+}
+
+/// (De)serializes the clap value enums of the dre CLI by their CLI names
+mod value_enum {
+    use clap::ValueEnum;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: ValueEnum, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(value.to_possible_value().expect("Variants should not be skipped").get_name())
+    }
+
+    pub fn deserialize<'de, T: ValueEnum, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        T::from_str(&name, true).map_err(serde::de::Error::custom)
+    }
+}
+
+fn percentage<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    match u8::deserialize(deserializer)? {
+        percentage @ 0..=100 => Ok(percentage),
+        percentage => Err(serde::de::Error::custom(format!("percentage {} is not between 0 and 100", percentage))),
+    }
+}
+
+/// Finds the first stage that is not done, given the nodes grouped the same
+/// way as `dre hostos rollout-from-node-group` groups them.
+fn check_hostos_stages(
+    index: &HostosRolloutIndex,
+    grouped_nodes: &BTreeMap<NodeGroup, Vec<Node>>,
+    proposals: &[UpdateNodesHostosVersionProposal],
+    now_seconds: u64,
+) -> Option<HostosStageStatus> {
+    for (i, stage) in index.stages.iter().enumerate() {
+        let nodes = grouped_nodes
+            .iter()
+            .filter(|(group, _)| stage.contains(group))
+            .flat_map(|(_, nodes)| nodes)
+            .collect::<Vec<_>>();
+        if nodes.is_empty() {
+            continue;
+        }
+        let node_ids = nodes.iter().map(|n| NodeId::from(n.principal)).collect::<Vec<_>>();
+        let proposals_for_group = proposals
+            .iter()
+            .filter(|p| {
+                p.payload.hostos_version_id.as_deref() == Some(index.version.as_str()) && p.payload.node_ids.iter().any(|n| node_ids.contains(n))
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(proposal) = proposals_for_group.iter().find(|p| !p.info.executed) {
+            return Some(HostosStageStatus::Pending {
+                stage: i,
+                proposal_id: proposal.info.id,
+            });
+        }
+
+        let updated = nodes.iter().filter(|n| n.hostos_version == index.version).count();
+        let updated_percentage = (updated * 100 / nodes.len()) as u8;
+        if updated_percentage < stage.percentage {
+            return Some(HostosStageStatus::UpdateNodes {
+                stage: i,
+                percentage: stage.percentage - updated_percentage,
+            });
+        }
+
+        let last_executed = proposals_for_group
+            .iter()
+            .map(|p| p.info.executed_timestamp_seconds)
+            .max()
+            .unwrap_or_default();
+        let baked = Duration::from_secs(now_seconds.saturating_sub(last_executed));
+        if baked < stage.bake_time {
+            return Some(HostosStageStatus::Baking {
+                stage: i,
+                remaining: stage.bake_time - baked,
+            });
+        }
+    }
+    None
+}
+
+/// Actions for the HostOS rollout in the index at the time `now`, if there is
+/// one. Nodes are picked with the same logic as `dre hostos
+/// rollout-from-node-group`.
+pub async fn hostos_actions(
+    index: &HostosRolloutIndex,
+    registry_state: &RegistryState,
+    now: DateTime<Utc>,
+    logger: &Logger,
+) -> anyhow::Result<Vec<SubnetAction>> {
+    if !registry_state.blessed_versions(&Artifact::HostOs).await?.contains(&index.version) {
+        warn!(logger, "HostOS version '{}' is not elected, skipping the HostOS rollout", index.version);
+        return Ok(vec![]);
+    }
+
+    let network = registry_state.network();
+    let rollout = HostosRollout::new(
+        registry_state.nodes(),
+        registry_state.subnets(),
+        &network,
+        ic_management_backend::proposal::ProposalAgent::new(registry_state.get_nns_urls()),
+        &index.version,
+        &None,
+    );
+    let proposals = registry_state.nodes_hostos_upgrade_proposals().await?;
+    let now_seconds = now.timestamp() as u64;
+
+    let status = match check_hostos_stages(index, &rollout.grouped_nodes, &proposals, now_seconds) {
+        Some(status) => status,
+        None => {
+            info!(logger, "HostOS rollout of version '{}' is completed", index.version);
+            return Ok(vec![]);
+        }
+    };
+
+    let action = match status {
+        HostosStageStatus::Pending { stage, proposal_id } => SubnetAction::HostosPendingProposal {
+            group: index.stages[stage].node_group().to_string(),
+            proposal_id,
+        },
+        HostosStageStatus::Baking { stage, remaining } => SubnetAction::HostosBaking {
+            group: index.stages[stage].node_group().to_string(),
+            remaining,
+        },
+        HostosStageStatus::UpdateNodes { stage, percentage } => {
+            let stage = &index.stages[stage];
+            let update = NodeGroupUpdate::new(Some(stage.assignment), Some(stage.owner), NumberOfNodes::Percentage(percentage.into()));
+            match rollout.execute(update).await? {
+                HostosRolloutResponse::Ok(nodes, _) if !nodes.is_empty() => SubnetAction::PlaceHostosProposal {
+                    group: stage.node_group().to_string(),
+                    nodes: nodes.into_iter().map(|n| n.principal).collect::<Vec<PrincipalId>>(),
+                    version: index.version.clone(),
+                },
+                HostosRolloutResponse::Ok(..) => {
+                    info!(logger, "No nodes to update in {}", stage.node_group());
+                    return Ok(vec![]);
+                }
+                HostosRolloutResponse::None(reasons) => {
+                    for (group, reason) in reasons {
+                        info!(logger, "No nodes to update in {}: {}", group, reason);
+                    }
+                    return Ok(vec![]);
+                }
+            }
+        }
+    };
+    Ok(vec![action])
+}
+
+#[cfg(test)]
+mod tests {
+    use ic_management_backend::proposal::ProposalInfoInternal;
+    use ic_management_types::Operator;
+    use pretty_assertions::assert_eq;
+    use registry_canister::mutations::do_update_nodes_hostos_version::UpdateNodesHostosVersionPayload;
+
+    use super::*;
+
+    fn node(id: u64, assigned: bool, dfinity_owned: bool, version: &str) -> Node {
+        Node {
+            principal: PrincipalId::new_node_test_id(id),
+            ip_addr: std::net::Ipv6Addr::LOCALHOST,
+            operator: Operator::default(),
+            subnet_id: assigned.then(|| PrincipalId::new_subnet_test_id(1)),
+            hostos_release: None,
+            hostos_version: version.to_string(),
+            dfinity_owned: Some(dfinity_owned),
+            hostname: None,
+            proposal: None,
+            label: None,
+            decentralized: false,
+            duplicates: None,
+            is_api_boundary_node: false,
+        }
+    }
+
+    fn grouped(nodes: Vec<Node>) -> BTreeMap<NodeGroup, Vec<Node>> {
+        nodes.into_iter().fold(BTreeMap::new(), |mut acc, node| {
+            let assignment = if node.subnet_id.is_some() {
+                NodeAssignment::Assigned
+            } else {
+                NodeAssignment::Unassigned
+            };
+            let owner = if node.dfinity_owned == Some(true) {
+                NodeOwner::Dfinity
+            } else {
+                NodeOwner::Others
+            };
+            acc.entry(NodeGroup::new(assignment, owner)).or_default().push(node);
+            acc
+        })
+    }
+
+    fn proposal(id: u64, executed: bool, executed_at: u64, nodes: &[u64]) -> UpdateNodesHostosVersionProposal {
+        UpdateNodesHostosVersionProposal {
+            info: ProposalInfoInternal {
+                id,
+                proposal_timestamp_seconds: 0,
+                executed_timestamp_seconds: executed_at,
+                executed,
+            },
+            payload: UpdateNodesHostosVersionPayload {
+                node_ids: nodes.iter().map(|id| NodeId::from(PrincipalId::new_node_test_id(*id))).collect(),
+                hostos_version_id: Some("new".to_string()),
+            },
+        }
+    }
+
+    fn index() -> HostosRolloutIndex {
+        serde_yaml::from_str(
+            r#"
+version: new
+stages:
+  - assignment: unassigned
+    owner: dfinity
+    percentage: 50
+    bake_time: 1h
+  - assignment: assigned
+    percentage: 100
+    bake_time: 1d
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn percentage_above_100_is_invalid() {
+        let stage = "assignment: assigned\npercentage: 101\nbake_time: 1d";
+        assert!(serde_yaml::from_str::<HostosStage>(stage).is_err());
+        let stage = "assignment: assigned\npercentage: -1\nbake_time: 1d";
+        assert!(serde_yaml::from_str::<HostosStage>(stage).is_err());
+    }
+
+    #[test]
+    fn stages_progress_with_versions_and_proposals() {
+        let index = index();
+        let old_nodes = || vec![node(1, false, true, "old"), node(2, false, true, "old"), node(3, true, false, "old")];
+
+        assert_eq!(
+            check_hostos_stages(&index, &grouped(old_nodes()), &[], 10_000),
+            Some(HostosStageStatus::UpdateNodes { stage: 0, percentage: 50 })
+        );
+        assert_eq!(
+            check_hostos_stages(&index, &grouped(old_nodes()), &[proposal(7, false, 0, &[1])], 10_000),
+            Some(HostosStageStatus::Pending { stage: 0, proposal_id: 7 })
+        );
+
+        let mut nodes = old_nodes();
+        nodes[0].hostos_version = "new".to_string();
+        assert_eq!(
+            check_hostos_stages(&index, &grouped(nodes.clone()), &[proposal(7, true, 9_000, &[1])], 10_000),
+            Some(HostosStageStatus::Baking {
+                stage: 0,
+                remaining: Duration::from_secs(2_600)
+            })
+        );
+        assert_eq!(
+            check_hostos_stages(&index, &grouped(nodes.clone()), &[proposal(7, true, 9_000, &[1])], 20_000),
+            Some(HostosStageStatus::UpdateNodes { stage: 1, percentage: 100 })
+        );
+
+        nodes[2].hostos_version = "new".to_string();
+        assert_eq!(
+            check_hostos_stages(
+                &index,
+                &grouped(nodes),
+                &[proposal(7, true, 9_000, &[1]), proposal(8, true, 10_000, &[3])],
+                200_000
+            ),
+            None
+        );
+    }
+}
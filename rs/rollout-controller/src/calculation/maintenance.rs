@@ -0,0 +1,516 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Timelike, Utc, Weekday};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use slog::{warn, Logger};
+
+use crate::actions::SubnetAction;
+
+use super::Rollout;
+
+/// A time of the week in UTC, written as e.g. `Fri 12:00`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WeekTime {
+    pub weekday: Weekday,
+    pub time: NaiveTime,
+}
+
+impl WeekTime {
+    fn minutes_since_monday(&self) -> u32 {
+        self.weekday.num_days_from_monday() * 24 * 60 + self.time.hour() * 60 + self.time.minute()
+    }
+
+    fn of(datetime: DateTime<Utc>) -> Self {
+        Self {
+            weekday: datetime.weekday(),
+            time: datetime.time(),
+        }
+    }
+}
+
+impl FromStr for WeekTime {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (weekday, time) = s
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| anyhow::anyhow!("Time of the week '{}' should look like 'Fri 12:00'", s))?;
+        Ok(Self {
+            weekday: Weekday::from_str(weekday).map_err(|_| anyhow::anyhow!("Invalid weekday '{}'", weekday))?,
+            time: NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|e| anyhow::anyhow!("Invalid time '{}': {}", time, e))?,
+        })
+    }
+}
+
+impl Display for WeekTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.weekday, self.time.format("%H:%M"))
+    }
+}
+
+impl Serialize for WeekTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for WeekTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        WeekTime::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A window that recurs every week during which no proposals are placed. The
+/// window may span the end of the week, e.g. from `Fri 12:00` to `Mon 08:00`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlackoutWindow {
+    pub from: WeekTime,
+    pub to: WeekTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl BlackoutWindow {
+    fn contains(&self, now: DateTime<Utc>) -> bool {
+        let (from, to, now) = (
+            self.from.minutes_since_monday(),
+            self.to.minutes_since_monday(),
+            WeekTime::of(now).minutes_since_monday(),
+        );
+        match from <= to {
+            true => from <= now && now < to,
+            false => now >= from || now < to,
+        }
+    }
+}
+
+/// Hours of the day, in UTC, during which proposals of a stage may be placed.
+/// The hours may span midnight, e.g. from `22:00` to `02:00`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AllowedHours {
+    #[serde(with = "hour_minute")]
+    pub from: NaiveTime,
+    #[serde(with = "hour_minute")]
+    pub to: NaiveTime,
+}
+
+impl AllowedHours {
+    fn contains(&self, now: DateTime<Utc>) -> bool {
+        let now = now.time();
+        match self.from <= self.to {
+            true => self.from <= now && now < self.to,
+            false => now >= self.from || now < self.to,
+        }
+    }
+}
+
+/// No proposals are placed for the subnet until the freeze expires
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SubnetFreeze {
+    /// Principal, or principal prefix, of the subnet
+    pub subnet: String,
+    pub until: DateTime<Utc>,
+    pub reason: String,
+}
+
+/// An event of the holidays calendar
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Holiday {
+    pub summary: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+mod hour_minute {
+    use chrono::NaiveTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&time.format("%H:%M").to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&s, "%H:%M").map_err(serde::de::Error::custom)
+    }
+}
+
+/// Loads the holidays calendar from an `http(s)` url or from a local path
+pub async fn load_holidays(location: &str) -> anyhow::Result<Vec<Holiday>> {
+    let contents = match location.starts_with("http://") || location.starts_with("https://") {
+        true => reqwest::get(location)
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| anyhow::anyhow!("Error fetching holidays calendar: {:?}", e))?
+            .text()
+            .await
+            .map_err(|e| anyhow::anyhow!("Error reading holidays calendar: {:?}", e))?,
+        false => std::fs::read_to_string(location).map_err(|e| anyhow::anyhow!("Failed to read holidays calendar {}: {}", location, e))?,
+    };
+    parse_ical(&contents)
+}
+
+/// How long a fetched holidays calendar is used before fetching it again
+const HOLIDAYS_REFRESH_INTERVAL: Duration = Duration::from_secs(6 * 3600);
+
+/// The holidays calendar of the rollout, fetched at most once per
+/// [HOLIDAYS_REFRESH_INTERVAL]. A calendar that can't be fetched doesn't stop
+/// the controller: the last calendar fetched from the same location is used
+/// instead, or no holidays at all.
+#[derive(Default)]
+pub struct HolidaysCalendar {
+    cached: Option<CachedHolidays>,
+}
+
+struct CachedHolidays {
+    location: String,
+    fetched: DateTime<Utc>,
+    holidays: Vec<Holiday>,
+}
+
+impl HolidaysCalendar {
+    pub async fn holidays(&mut self, location: Option<&str>, now: DateTime<Utc>, logger: &Logger) -> Vec<Holiday> {
+        let location = match location {
+            Some(location) => location,
+            None => return vec![],
+        };
+        let cached = self.cached.as_ref().filter(|c| c.location == location);
+        if let Some(cached) = cached.filter(|c| (now - c.fetched).to_std().unwrap_or_default() < HOLIDAYS_REFRESH_INTERVAL) {
+            return cached.holidays.clone();
+        }
+        match load_holidays(location).await {
+            Ok(holidays) => {
+                self.cached = Some(CachedHolidays {
+                    location: location.to_string(),
+                    fetched: now,
+                    holidays: holidays.clone(),
+                });
+                holidays
+            }
+            Err(e) => {
+                warn!(logger, "Continuing with the last holidays calendar that was fetched, if any: {:?}", e);
+                cached.map(|c| c.holidays.clone()).unwrap_or_default()
+            }
+        }
+    }
+}
+
+/// Parses the events of an iCalendar file. Only `DTSTART`, `DTEND` and
+/// `SUMMARY` are taken into account, recurrence rules are not expanded, and
+/// times that are not in UTC are taken as UTC.
+fn parse_ical(contents: &str) -> anyhow::Result<Vec<Holiday>> {
+    // Long lines are folded by starting the continuation with a whitespace
+    let mut lines: Vec<String> = vec![];
+    for line in contents.lines().map(|l| l.trim_end_matches('\r')) {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut holidays = vec![];
+    let mut event: Option<Event> = None;
+    for line in lines {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.split(';').next().unwrap_or_default().to_uppercase(), value),
+            None => continue,
+        };
+        match (name.as_str(), value) {
+            ("BEGIN", "VEVENT") => event = Some(Event::default()),
+            ("END", "VEVENT") => {
+                if let Some(event) = event.take() {
+                    holidays.push(event.into_holiday()?);
+                }
+            }
+            ("DTSTART", value) => {
+                if let Some(event) = event.as_mut() {
+                    let (start, all_day) = parse_ical_time(value)?;
+                    event.start = Some(start);
+                    event.all_day = all_day;
+                }
+            }
+            ("DTEND", value) => {
+                if let Some(event) = event.as_mut() {
+                    event.end = Some(parse_ical_time(value)?.0);
+                }
+            }
+            ("SUMMARY", value) => {
+                if let Some(event) = event.as_mut() {
+                    event.summary = value.to_string();
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(holidays)
+}
+
+#[derive(Default)]
+struct Event {
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    summary: String,
+    all_day: bool,
+}
+
+impl Event {
+    fn into_holiday(self) -> anyhow::Result<Holiday> {
+        let start = self
+            .start
+            .ok_or_else(|| anyhow::anyhow!("Event '{}' of the holidays calendar has no start", self.summary))?;
+        // Without an end, an all-day event lasts for the day
+        let end = self.end.unwrap_or(match self.all_day {
+            true => start + TimeDelta::try_days(1).expect("Should be a valid duration"),
+            false => start,
+        });
+        Ok(Holiday {
+            summary: self.summary,
+            start,
+            end,
+        })
+    }
+}
+
+/// Parses a `DATE` or `DATE-TIME` value, telling whether it was a date
+fn parse_ical_time(value: &str) -> anyhow::Result<(DateTime<Utc>, bool)> {
+    let value = value.trim().trim_end_matches('Z');
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return Ok((date.and_time(NaiveTime::MIN).and_utc(), true));
+    }
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .map(|datetime| (datetime.and_utc(), false))
+        .map_err(|e| anyhow::anyhow!("Invalid time '{}' in the holidays calendar: {}", value, e))
+}
+
+/// Replaces the actions that would place proposals by [SubnetAction::Blocked]
+/// when a freeze, a blackout window, a holiday or the allowed hours of the
+/// stage forbid placing them right now. Rollbacks are never blocked.
+pub fn apply_maintenance(actions: Vec<SubnetAction>, rollout: &Rollout, holidays: &[Holiday], now: DateTime<Utc>) -> Vec<SubnetAction> {
+    actions
+        .into_iter()
+        .map(|action| match blocked_reason(&action, rollout, holidays, now) {
+            Some(reason) => SubnetAction::Blocked {
+                action: Box::new(action),
+                reason,
+            },
+            None => action,
+        })
+        .collect()
+}
+
+fn blocked_reason(action: &SubnetAction, rollout: &Rollout, holidays: &[Holiday], now: DateTime<Utc>) -> Option<String> {
+    let subnet = match action {
+        SubnetAction::PlaceProposal {
+            is_unassigned,
+            subnet_principal,
+            ..
+        } => (!is_unassigned).then(|| subnet_principal.to_string()),
+        SubnetAction::PlaceHostosProposal { .. } => None,
+        _ => return None,
+    };
+
+    if let Some(freeze) = subnet
+        .as_ref()
+        .and_then(|subnet| rollout.freezes.iter().find(|f| now < f.until && subnet.starts_with(&f.subnet)))
+    {
+        return Some(format!(
+            "subnet is frozen until {}: {}",
+            freeze.until.format("%Y-%m-%d %H:%M UTC"),
+            freeze.reason
+        ));
+    }
+
+    if let Some(window) = rollout.blackout_windows.iter().find(|w| w.contains(now)) {
+        return Some(match &window.reason {
+            Some(reason) => format!("blackout window {} - {}: {}", window.from, window.to, reason),
+            None => format!("blackout window {} - {}", window.from, window.to),
+        });
+    }
+
+    if let Some(holiday) = holidays.iter().find(|h| h.start <= now && now < h.end) {
+        return Some(format!("holiday: {}", holiday.summary));
+    }
+
+    let stage = match action {
+        SubnetAction::PlaceProposal { is_unassigned: true, .. } => rollout.stages.iter().find(|s| s.update_unassigned_nodes),
+        _ => subnet
+            .as_ref()
+            .and_then(|subnet| rollout.stages.iter().find(|s| s.subnets.iter().any(|short| subnet.starts_with(short)))),
+    };
+    match stage.and_then(|s| s.allowed_hours.as_ref()) {
+        Some(hours) if !hours.contains(now) => Some(format!(
+            "outside of the allowed hours of the stage, {} - {} UTC",
+            hours.from.format("%H:%M"),
+            hours.to.format("%H:%M")
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ic_base_types::PrincipalId;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn at(datetime: &str) -> DateTime<Utc> {
+        DateTime::from_str(datetime).unwrap()
+    }
+
+    fn place(id: u64) -> SubnetAction {
+        SubnetAction::PlaceProposal {
+            is_unassigned: false,
+            subnet_principal: PrincipalId::new_subnet_test_id(id),
+            version: "b".to_string(),
+        }
+    }
+
+    fn rollout() -> Rollout {
+        serde_yaml::from_str(&format!(
+            r#"
+skip_days: []
+stages:
+  - subnets: [{}]
+    allowed_hours:
+      from: "08:00"
+      to: "16:00"
+  - subnets: [{}]
+blackout_windows:
+  - from: Fri 12:00
+    to: Mon 08:00
+    reason: weekend
+freezes:
+  - subnet: {}
+    until: 2024-03-20T00:00:00Z
+    reason: incident
+"#,
+            PrincipalId::new_subnet_test_id(1),
+            PrincipalId::new_subnet_test_id(2),
+            PrincipalId::new_subnet_test_id(2).to_string().split_once('-').unwrap().0,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn blackout_window_spans_the_weekend() {
+        let window = &rollout().blackout_windows[0];
+        // 2024-03-15 is a Friday
+        assert!(!window.contains(at("2024-03-15T11:59:00Z")));
+        assert!(window.contains(at("2024-03-15T12:00:00Z")));
+        assert!(window.contains(at("2024-03-17T23:00:00Z")));
+        assert!(!window.contains(at("2024-03-18T08:00:00Z")));
+        assert!(!window.contains(at("2024-03-13T12:00:00Z")));
+    }
+
+    #[test]
+    fn proposals_are_blocked() {
+        let rollout = rollout();
+        let baking = SubnetAction::Baking {
+            subnet_short: "other".to_string(),
+            remaining: std::time::Duration::from_secs(60),
+        };
+        let blocked = |action: SubnetAction, reason: &str| SubnetAction::Blocked {
+            action: Box::new(action),
+            reason: reason.to_string(),
+        };
+
+        // Wednesday, within the allowed hours of the first stage
+        let actions = apply_maintenance(vec![place(1), place(2), baking.clone()], &rollout, &[], at("2024-03-13T10:00:00Z"));
+        assert_eq!(
+            actions,
+            vec![
+                place(1),
+                blocked(place(2), "subnet is frozen until 2024-03-20 00:00 UTC: incident"),
+                baking.clone()
+            ]
+        );
+
+        // Wednesday, outside of the allowed hours, after the freeze expired
+        let actions = apply_maintenance(vec![place(1), place(2)], &rollout, &[], at("2024-03-20T18:00:00Z"));
+        assert_eq!(
+            actions,
+            vec![
+                blocked(place(1), "outside of the allowed hours of the stage, 08:00 - 16:00 UTC"),
+                place(2)
+            ]
+        );
+
+        // Saturday
+        let actions = apply_maintenance(vec![place(1)], &rollout, &[], at("2024-03-23T10:00:00Z"));
+        assert_eq!(actions, vec![blocked(place(1), "blackout window Fri 12:00 - Mon 08:00: weekend")]);
+
+        let holidays = parse_ical(
+            "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART;VALUE=DATE:20240321\r\nSUMMARY:Spring\r\n  holiday\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n",
+        )
+        .unwrap();
+        let actions = apply_maintenance(vec![place(1)], &rollout, &holidays, at("2024-03-21T10:00:00Z"));
+        assert_eq!(actions, vec![blocked(place(1), "holiday: Spring holiday")]);
+        let actions = apply_maintenance(vec![place(1)], &rollout, &holidays, at("2024-03-22T10:00:00Z"));
+        assert_eq!(actions, vec![place(1)]);
+    }
+
+    #[test]
+    fn parse_holidays() {
+        let holidays = parse_ical(
+            r#"BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VEVENT
+UID:1
+DTSTART;VALUE=DATE:20241225
+DTEND;VALUE=DATE:20241227
+SUMMARY:Christmas
+END:VEVENT
+BEGIN:VEVENT
+DTSTART:20241231T120000Z
+DTEND:20250101T120000Z
+SUMMARY:New year
+END:VEVENT
+END:VCALENDAR
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            holidays,
+            vec![
+                Holiday {
+                    summary: "Christmas".to_string(),
+                    start: at("2024-12-25T00:00:00Z"),
+                    end: at("2024-12-27T00:00:00Z"),
+                },
+                Holiday {
+                    summary: "New year".to_string(),
+                    start: at("2024-12-31T12:00:00Z"),
+                    end: at("2025-01-01T12:00:00Z"),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn holidays_calendar_is_cached() {
+        let logger = Logger::root(slog::Discard, slog::o!());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("holidays.ics");
+        std::fs::write(&path, "BEGIN:VEVENT\nDTSTART;VALUE=DATE:20240321\nSUMMARY:Spring\nEND:VEVENT\n").unwrap();
+        let location = path.to_str();
+
+        let mut calendar = HolidaysCalendar::default();
+        assert_eq!(calendar.holidays(location, at("2024-03-20T10:00:00Z"), &logger).await.len(), 1);
+
+        // The calendar is cached until it is refreshed, and kept when refreshing fails
+        std::fs::write(&path, "not a calendar:").unwrap();
+        assert_eq!(calendar.holidays(location, at("2024-03-20T11:00:00Z"), &logger).await.len(), 1);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(calendar.holidays(location, at("2024-03-21T11:00:00Z"), &logger).await.len(), 1);
+
+        assert!(HolidaysCalendar::default()
+            .holidays(location, at("2024-03-21T11:00:00Z"), &logger)
+            .await
+            .is_empty());
+        assert!(calendar.holidays(None, at("2024-03-21T11:00:00Z"), &logger).await.is_empty());
+    }
+}
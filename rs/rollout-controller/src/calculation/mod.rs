@@ -1,35 +1,79 @@
 use std::{collections::BTreeMap, time::Duration};
 
 use crate::calculation::should_proceed::should_proceed;
-use chrono::{Local, NaiveDate, TimeDelta};
-use ic_management_backend::registry::RegistryState;
-use ic_management_types::Subnet;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, Utc};
 use itertools::Itertools;
 use prometheus_http_query::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use slog::{info, Logger};
 
+use self::health::{check_health, HealthCheck};
+use self::hostos::HostosRolloutIndex;
+use self::maintenance::{apply_maintenance, AllowedHours, BlackoutWindow, Holiday, SubnetFreeze};
+use self::sources::{RegistryView, RolloutMetrics};
 use self::stage_checks::{check_stages, desired_rollout_release_version};
 use crate::actions::SubnetAction;
+use crate::history::{Iteration, OpenProposal};
+use crate::rollbacks::RollbackLog;
 
+pub mod health;
+pub mod hostos;
+pub mod maintenance;
+pub mod plan;
 mod should_proceed;
+pub mod sources;
 mod stage_checks;
 
-#[derive(Deserialize, Clone, Default)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct Index {
     pub rollout: Rollout,
     pub releases: Vec<Release>,
+    /// HostOS version to roll out to the nodes, alongside the GuestOS
+    /// releases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostos_rollout: Option<HostosRolloutIndex>,
 }
 
-#[derive(Deserialize, Clone, Default)]
+impl Index {
+    /// SHA-256 of the index, to tell which version of it was used
+    pub fn hash(&self) -> String {
+        let serialized = serde_json::to_vec(self).expect("Index should be serializable");
+        format!("{:x}", Sha256::digest(serialized))
+    }
+
+    /// Stage that upgrades the unassigned nodes, or the subnet with the given
+    /// principal or principal prefix
+    pub fn stage_of(&self, is_unassigned: bool, subnet: &str) -> Option<usize> {
+        self.rollout.stages.iter().position(|stage| match is_unassigned {
+            true => stage.update_unassigned_nodes,
+            false => stage.subnets.iter().any(|s| subnet.starts_with(s.as_str()) || s.starts_with(subnet)),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct Rollout {
     #[serde(default)]
     pub pause: bool,
     pub skip_days: Vec<NaiveDate>,
     pub stages: Vec<Stage>,
+    /// Checks of the health of subnets that are baking a new version. A
+    /// subnet that fails any of them is rolled back to the previous version.
+    #[serde(default)]
+    pub health_checks: Vec<HealthCheck>,
+    /// Weekly windows during which no proposals are placed
+    #[serde(default)]
+    pub blackout_windows: Vec<BlackoutWindow>,
+    /// Url or path of an iCalendar file with days on which no proposals are
+    /// placed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub holidays_calendar: Option<String>,
+    #[serde(default)]
+    pub freezes: Vec<SubnetFreeze>,
 }
 
-#[derive(Deserialize, Default, Clone)]
+#[derive(Serialize, Deserialize, Default, Clone)]
 #[serde(default)]
 
 pub struct Stage {
@@ -38,15 +82,18 @@ pub struct Stage {
     pub bake_time: Duration,
     pub wait_for_next_week: bool,
     update_unassigned_nodes: bool,
+    /// Hours of the day during which proposals of the stage may be placed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_hours: Option<AllowedHours>,
 }
 
-#[derive(Deserialize, Clone, Default, Eq, PartialEq, Hash, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, Eq, PartialEq, Hash, Debug)]
 pub struct Release {
     pub rc_name: String,
     pub versions: Vec<Version>,
 }
 
-#[derive(Deserialize, Clone, Default, Eq, PartialEq, Hash, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, Eq, PartialEq, Hash, Debug)]
 pub struct Version {
     pub version: String,
     pub name: String,
@@ -56,17 +103,122 @@ pub struct Version {
     pub subnets: Vec<String>,
 }
 
-pub async fn calculate_progress<'a>(
+/// Calculates the actions that bring the rollout forward at the time `now`,
+/// based on the metrics, the registry view and the holidays of the calendar
+/// of the rollout. While the rollout is paused or the day is skipped no
+/// proposals are placed, but the health of the baking subnets is still
+/// checked and unhealthy subnets are still rolled back.
+#[allow(clippy::too_many_arguments)]
+pub async fn calculate_progress<'a, M: RolloutMetrics, R: RegistryView>(
+    logger: &'a Logger,
+    index: Index,
+    metrics: &'a M,
+    registry: &'a R,
+    rollback_log: &'a RollbackLog,
+    holidays: &'a [Holiday],
+    iteration: &'a mut Iteration,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Vec<SubnetAction>> {
+    let proceed = should_proceed(&index, now.date_naive());
+    let hostos_rollout = index.hostos_rollout.clone();
+    let rollout = index.rollout.clone();
+
+    let mut actions = calculate_guestos_progress(logger, index, metrics, registry, rollback_log, iteration, now).await?;
+    if let Some(hostos_rollout) = hostos_rollout {
+        actions.extend(registry.hostos_actions(&hostos_rollout, now, logger).await?);
+    }
+    if !proceed {
+        info!(logger, "Rollout controller paused or should skip this day, no proposals are placed.");
+        actions.retain(|action| !matches!(action, SubnetAction::PlaceProposal { .. } | SubnetAction::PlaceHostosProposal { .. }));
+    }
+    Ok(apply_maintenance(actions, &rollout, holidays, now))
+}
+
+async fn calculate_guestos_progress<'a, M: RolloutMetrics, R: RegistryView>(
     logger: &'a Logger,
     index: Index,
-    prometheus_client: &'a Client,
-    registry_state: RegistryState,
+    metrics: &'a M,
+    registry: &'a R,
+    rollback_log: &'a RollbackLog,
+    iteration: &'a mut Iteration,
+    now: DateTime<Utc>,
 ) -> anyhow::Result<Vec<SubnetAction>> {
-    if !should_proceed(&index, Local::now().to_utc().date_naive()) {
-        info!(logger, "Rollout controller paused or should skip this day.");
-        return Ok(vec![]);
+    let last_bake_status = metrics.last_bake_status().await?;
+    iteration.last_bake_status = last_bake_status.clone();
+
+    let subnets = registry.subnets();
+    let desired_versions = desired_rollout_release_version(&subnets, &index.releases);
+    iteration.release = Some(desired_versions.release.rc_name.clone());
+    iteration.desired_versions = desired_versions
+        .subnets
+        .iter()
+        .map(|(subnet, version)| (subnet.to_string(), version.version.clone()))
+        .chain(std::iter::once((
+            "unassigned-nodes".to_string(),
+            desired_versions.unassigned_nodes.version.clone(),
+        )))
+        .collect();
+
+    let rollbacks = rollback_log.for_release(&desired_versions.release.rc_name)?;
+    if !rollbacks.is_empty() {
+        info!(
+            logger,
+            "Rollout of release '{}' is paused after rollbacks", desired_versions.release.rc_name
+        );
+        return Ok(vec![SubnetAction::RolloutPaused {
+            release: desired_versions.release.rc_name.clone(),
+            reason: rollbacks
+                .iter()
+                .map(|r| format!("subnet '{}' rolled back: {}", r.subnet, r.reason))
+                .join("; "),
+        }]);
+    }
+
+    let release_start = metrics.release_start(&desired_versions.release).await?;
+
+    let subnet_update_proposals = registry.subnet_update_proposals().await?;
+    let unassigned_nodes_version = registry.unassigned_nodes_version().await?;
+    let unassigned_nodes_proposals = registry.unassigned_nodes_proposals().await?;
+    iteration.open_proposals = subnet_update_proposals
+        .iter()
+        .filter(|p| !p.info.executed)
+        .map(|p| OpenProposal {
+            id: p.info.id,
+            target: p.payload.subnet_id.to_string(),
+            version: p.payload.replica_version_id.clone(),
+        })
+        .chain(unassigned_nodes_proposals.iter().filter(|p| !p.info.executed).map(|p| OpenProposal {
+            id: p.info.id,
+            target: "unassigned-nodes".to_string(),
+            version: p.payload.replica_version.clone().unwrap_or_default(),
+        }))
+        .collect();
+
+    let health_checks = index.rollout.health_checks.clone();
+    let releases = index.releases.clone();
+    let actions = check_stages(
+        &last_bake_status,
+        &subnet_update_proposals,
+        &unassigned_nodes_proposals,
+        index,
+        Some(logger),
+        &unassigned_nodes_version,
+        &subnets,
+        now.with_timezone(&Local).date_naive(),
+        release_start,
+        desired_versions.clone(),
+    )?;
+
+    if health_checks.is_empty() || !actions.iter().any(|a| matches!(a, SubnetAction::Baking { .. })) {
+        return Ok(actions);
     }
+    let samples = metrics.health(&health_checks).await?;
+    iteration.health_samples = samples.clone();
+    check_health(actions, &health_checks, &samples, &subnets, &releases, &desired_versions, Some(logger))
+}
 
+/// Seconds since the last version change of every subnet, by subnet principal
+pub async fn query_last_bake_status(prometheus_client: &Client) -> anyhow::Result<BTreeMap<String, f64>> {
     let mut last_bake_status: BTreeMap<String, f64> = BTreeMap::new();
     let result = prometheus_client
         .query(
@@ -92,9 +244,12 @@ pub async fn calculate_progress<'a>(
         last_bake_status.insert(subnet.to_string(), last_update);
     }
 
-    let subnets = registry_state.subnets().into_values().collect::<Vec<Subnet>>();
-    let desired_versions = desired_rollout_release_version(&subnets, &index.releases);
-    let concatenated_versions = desired_versions.release.versions.iter().map(|v| v.version.clone()).join("|");
+    Ok(last_bake_status)
+}
+
+/// The day on which the first subnet got a version of the release
+pub async fn query_release_start(prometheus_client: &Client, release: &Release) -> anyhow::Result<NaiveDate> {
+    let concatenated_versions = release.versions.iter().map(|v| v.version.clone()).join("|");
 
     let result = prometheus_client
         .query(format!(
@@ -113,25 +268,8 @@ pub async fn calculate_progress<'a>(
         None => return Err(anyhow::anyhow!("There should be data regarding start of releases")),
     };
 
-    let subnet_update_proposals = registry_state.open_subnet_upgrade_proposals().await?;
-    let unassigned_nodes_version = registry_state.get_unassigned_nodes_replica_version().await?;
-    let unassigned_nodes_proposals = registry_state.open_upgrade_unassigned_nodes_proposals().await?;
-
-    let actions = check_stages(
-        &last_bake_status,
-        &subnet_update_proposals,
-        &unassigned_nodes_proposals,
-        index,
-        Some(logger),
-        &unassigned_nodes_version,
-        &subnets,
-        Local::now().date_naive(),
-        Local::now()
-            .checked_sub_signed(TimeDelta::try_seconds(since_start as i64).expect("Should be able to convert to seconds"))
-            .expect("Should be able to sub from now")
-            .date_naive(),
-        desired_versions,
-    )?;
-
-    Ok(actions)
+    Ok(Local::now()
+        .checked_sub_signed(TimeDelta::try_seconds(since_start as i64).expect("Should be able to convert to seconds"))
+        .expect("Should be able to sub from now")
+        .date_naive())
 }
@@ -0,0 +1,533 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Datelike, DurationRound, NaiveDate, TimeDelta, Utc, Weekday};
+use clap::Parser;
+use ic_base_types::PrincipalId;
+use ic_management_types::Subnet;
+use serde::{Deserialize, Serialize};
+
+use crate::actions::SubnetAction;
+
+use super::{
+    maintenance::{apply_maintenance, Holiday},
+    sources::{RegistryView, RolloutMetrics},
+    stage_checks::{check_stages, desired_rollout_release_version},
+    Index,
+};
+
+/// Target of the upgrades of the unassigned nodes
+const UNASSIGNED_NODES: &str = "unassigned nodes";
+
+/// The state of the network that a plan starts from. It is either taken from
+/// the registry and Prometheus, or loaded from a snapshot that was saved
+/// earlier.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RolloutState {
+    pub taken: DateTime<Utc>,
+    /// Day on which the first subnet got a version of the release
+    pub release_start: NaiveDate,
+    /// GuestOS version by subnet principal
+    pub subnets: BTreeMap<String, String>,
+    /// Seconds since the last version change, by subnet principal
+    pub last_bake_status: BTreeMap<String, f64>,
+    pub unassigned_nodes_version: String,
+}
+
+impl RolloutState {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read snapshot {}: {}", path.display(), e))?;
+        serde_yaml::from_str(&contents).map_err(|e| anyhow::anyhow!("Failed to parse snapshot {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_yaml::to_string(self)?).map_err(|e| anyhow::anyhow!("Failed to write snapshot {}: {}", path.display(), e))
+    }
+
+    fn subnets(&self) -> anyhow::Result<Vec<Subnet>> {
+        self.subnets
+            .iter()
+            .map(|(principal, version)| {
+                Ok(Subnet {
+                    principal: principal
+                        .parse::<PrincipalId>()
+                        .map_err(|e| anyhow::anyhow!("Invalid subnet principal '{}': {}", principal, e))?,
+                    replica_version: version.clone(),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct PlanArgs {
+    #[clap(
+        long = "index-file",
+        help = "Path to a local release index to plan with, instead of the index fetched with '--url'"
+    )]
+    pub index_file: Option<PathBuf>,
+
+    #[clap(
+        long = "url",
+        default_value = "https://raw.githubusercontent.com/dfinity/dre/main/release-index.yaml",
+        help = "The url of the raw release index file in github"
+    )]
+    pub url: String,
+
+    #[clap(
+        long,
+        help = "Plan from a snapshot saved with '--save-snapshot' instead of the current state of the network"
+    )]
+    pub snapshot: Option<PathBuf>,
+
+    #[clap(long = "save-snapshot", help = "Save the state the plan starts from, to plan from it again later")]
+    pub save_snapshot: Option<PathBuf>,
+
+    #[clap(long, default_value = "30", help = "Number of days to plan ahead")]
+    pub days: u64,
+
+    #[clap(long, help = "Print the plan as JSON")]
+    pub json: bool,
+}
+
+impl RolloutState {
+    /// The state of the network right now, from the registry and Prometheus
+    pub async fn current<R: RegistryView, M: RolloutMetrics>(registry: &R, metrics: &M, index: &Index) -> anyhow::Result<Self> {
+        let subnets = registry.subnets();
+        let desired_versions = desired_rollout_release_version(&subnets, &index.releases);
+        Ok(Self {
+            taken: Utc::now(),
+            release_start: metrics.release_start(&desired_versions.release).await?,
+            subnets: subnets.iter().map(|s| (s.principal.to_string(), s.replica_version.clone())).collect(),
+            last_bake_status: metrics.last_bake_status().await?,
+            unassigned_nodes_version: registry.unassigned_nodes_version().await?,
+        })
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ProjectedUpgrade {
+    pub stage: usize,
+    /// Subnet principal, or `unassigned nodes`
+    pub target: String,
+    pub version: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockReason {
+    Paused,
+    SkipDay,
+    /// The stage waits for the week after the start of the release
+    WaitForNextWeek,
+    /// The stage waits for the next week, and the day is on a weekend
+    Weekend,
+    /// A freeze, a blackout window, a holiday or the allowed hours of the
+    /// stage, see [apply_maintenance]
+    Maintenance(String),
+}
+
+impl Display for BlockReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockReason::Paused => write!(f, "rollout is paused"),
+            BlockReason::SkipDay => write!(f, "skip day"),
+            BlockReason::WaitForNextWeek => write!(f, "waiting for the next week"),
+            BlockReason::Weekend => write!(f, "weekend, waiting for the next week"),
+            BlockReason::Maintenance(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BlockedStage {
+    pub day: NaiveDate,
+    pub stage: Option<usize>,
+    pub reason: BlockReason,
+}
+
+/// When each subnet and the unassigned nodes are projected to get the
+/// versions of the release being rolled out.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RolloutPlan {
+    pub release: String,
+    pub start: DateTime<Utc>,
+    pub days: u64,
+    /// Subnets that already run the version they should get
+    pub already_upgraded: Vec<(String, String)>,
+    pub upgrades: Vec<ProjectedUpgrade>,
+    pub blocked: Vec<BlockedStage>,
+    /// When the last stage is baked, if that happens within the planned days
+    pub completed: Option<DateTime<Utc>>,
+}
+
+/// Simulates the controller day by day, starting from `state`, by feeding
+/// the simulated state to the same stage checks that the controller uses.
+///
+/// Proposals are assumed to be adopted as soon as they are placed, so a
+/// proposal that is open in `state` is treated like one that still has to be
+/// placed. Bake time passes on every day, but no proposals are placed on skip
+/// days, and none while the maintenance settings of the rollout or the
+/// holidays block them.
+pub fn plan(index: &Index, state: &RolloutState, holidays: &[Holiday], days: u64) -> anyhow::Result<RolloutPlan> {
+    let mut subnets = state.subnets()?;
+    let desired_versions = desired_rollout_release_version(&subnets, &index.releases);
+    let mut unassigned_nodes_version = state.unassigned_nodes_version.clone();
+
+    // Subnets without bake status are assumed to have been upgraded long ago
+    let mut upgraded_at: BTreeMap<PrincipalId, DateTime<Utc>> = subnets
+        .iter()
+        .map(|s| {
+            let since = state
+                .last_bake_status
+                .get(&s.principal.to_string())
+                .map(|secs| TimeDelta::seconds(*secs as i64))
+                .unwrap_or(TimeDelta::days(365));
+            (s.principal, state.taken - since)
+        })
+        .collect();
+
+    let mut plan = RolloutPlan {
+        release: desired_versions.release.rc_name.clone(),
+        start: state.taken,
+        days,
+        already_upgraded: subnets
+            .iter()
+            .filter(|s| desired_versions.subnets.get(&s.principal).is_some_and(|v| v.version == s.replica_version))
+            .map(|s| (s.principal.to_string(), s.replica_version.clone()))
+            .collect(),
+        upgrades: vec![],
+        blocked: vec![],
+        completed: None,
+    };
+
+    if index.rollout.pause {
+        plan.blocked.push(BlockedStage {
+            day: state.taken.date_naive(),
+            stage: None,
+            reason: BlockReason::Paused,
+        });
+        return Ok(plan);
+    }
+
+    let mut now = state.taken;
+    for day in state.taken.date_naive().iter_days().take(days as usize) {
+        let day_start = day.and_hms_opt(0, 0, 0).expect("Midnight should be valid").and_utc();
+        let day_end = day_start + TimeDelta::days(1);
+        now = now.max(day_start);
+
+        while now < day_end {
+            let last_bake_status = upgraded_at
+                .iter()
+                .map(|(principal, at)| (principal.to_string(), (now - *at).num_seconds() as f64))
+                .collect::<BTreeMap<_, _>>();
+            let actions = check_stages(
+                &last_bake_status,
+                &[],
+                &[],
+                index.clone(),
+                None,
+                &unassigned_nodes_version,
+                &subnets,
+                day,
+                state.release_start,
+                desired_versions.clone(),
+            )?;
+            if actions.is_empty() {
+                plan.completed = Some(now);
+                return Ok(plan);
+            }
+
+            let placements = proposals_to_place(&actions);
+            if let Some((is_unassigned, subnet_principal, _)) = placements.first() {
+                let stage = index.stage_of(*is_unassigned, &subnet_principal.to_string());
+                if index.rollout.skip_days.contains(&day) {
+                    plan.blocked.push(BlockedStage {
+                        day,
+                        stage,
+                        reason: BlockReason::SkipDay,
+                    });
+                    break;
+                }
+
+                let maintained = apply_maintenance(actions.clone(), &index.rollout, holidays, now);
+                let placements = proposals_to_place(&maintained);
+                if placements.is_empty() {
+                    let reason = maintained.into_iter().find_map(|a| match a {
+                        SubnetAction::Blocked { reason, .. } => Some(reason),
+                        _ => None,
+                    });
+                    let blocked = BlockedStage {
+                        day,
+                        stage,
+                        reason: BlockReason::Maintenance(reason.unwrap_or_default()),
+                    };
+                    if plan.blocked.last() != Some(&blocked) {
+                        plan.blocked.push(blocked);
+                    }
+                    // Wait for the first minute in which a proposal may be placed
+                    while now < day_end && proposals_to_place(&apply_maintenance(actions.clone(), &index.rollout, holidays, now)).is_empty() {
+                        now = now.duration_trunc(TimeDelta::minutes(1)).expect("Should truncate to a minute") + TimeDelta::minutes(1);
+                    }
+                    continue;
+                }
+
+                for (is_unassigned, subnet_principal, version) in placements {
+                    let target = match is_unassigned {
+                        true => {
+                            unassigned_nodes_version = version.clone();
+                            UNASSIGNED_NODES.to_string()
+                        }
+                        false => {
+                            if let Some(subnet) = subnets.iter_mut().find(|s| s.principal == subnet_principal) {
+                                subnet.replica_version = version.clone();
+                            }
+                            upgraded_at.insert(subnet_principal, now);
+                            subnet_principal.to_string()
+                        }
+                    };
+                    plan.upgrades.push(ProjectedUpgrade {
+                        stage: index.stage_of(is_unassigned, &target).unwrap_or_default(),
+                        target,
+                        version,
+                        at: now,
+                    });
+                }
+                continue;
+            }
+
+            if let Some(SubnetAction::WaitForNextWeek { subnet_short }) = actions.iter().find(|a| matches!(a, SubnetAction::WaitForNextWeek { .. })) {
+                plan.blocked.push(BlockedStage {
+                    day,
+                    stage: index.stage_of(false, subnet_short),
+                    reason: match day.weekday() {
+                        Weekday::Sat | Weekday::Sun => BlockReason::Weekend,
+                        _ => BlockReason::WaitForNextWeek,
+                    },
+                });
+                break;
+            }
+
+            let remaining = actions
+                .iter()
+                .filter_map(|a| match a {
+                    SubnetAction::Baking { remaining, .. } => Some(remaining.as_secs_f64()),
+                    _ => None,
+                })
+                .min_by(|a, b| a.total_cmp(b));
+            match remaining {
+                Some(remaining) => now += TimeDelta::seconds((remaining.ceil() as i64).max(1)),
+                // Nothing the controller could do on its own, e.g. pending proposals
+                None => break,
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+/// The proposals to place among the actions, as `(is_unassigned, subnet, version)`
+fn proposals_to_place(actions: &[SubnetAction]) -> Vec<(bool, PrincipalId, String)> {
+    actions
+        .iter()
+        .filter_map(|a| match a {
+            SubnetAction::PlaceProposal {
+                is_unassigned,
+                subnet_principal,
+                version,
+            } => Some((*is_unassigned, *subnet_principal, version.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn short(principal: &str) -> &str {
+    principal.split_once('-').map(|(short, _)| short).unwrap_or(principal)
+}
+
+impl Display for RolloutPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Projected rollout of release '{}' from {}",
+            self.release,
+            self.start.format("%Y-%m-%d %H:%M UTC")
+        )?;
+        writeln!(f)?;
+        writeln!(f, "{:<6} {:<18} {:<42} {}", "Stage", "Target", "Version", "Projected")?;
+        for (subnet, version) in &self.already_upgraded {
+            writeln!(f, "{:<6} {:<18} {:<42} already on the version", "-", short(subnet), version)?;
+        }
+        for upgrade in &self.upgrades {
+            writeln!(
+                f,
+                "{:<6} {:<18} {:<42} {}",
+                upgrade.stage,
+                short(&upgrade.target),
+                upgrade.version,
+                upgrade.at.format("%Y-%m-%d %H:%M UTC (%A)")
+            )?;
+        }
+
+        if !self.blocked.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Blocked:")?;
+            for blocked in &self.blocked {
+                let stage = blocked.stage.map(|s| format!("stage {}", s)).unwrap_or("all stages".to_string());
+                writeln!(f, "  {}  {}: {}", blocked.day.format("%Y-%m-%d (%A)"), stage, blocked.reason)?;
+            }
+        }
+
+        writeln!(f)?;
+        match self.completed {
+            Some(completed) => writeln!(f, "Rollout completes at {}", completed.format("%Y-%m-%d %H:%M UTC (%A)")),
+            None => writeln!(
+                f,
+                "Rollout does not complete within {} days{}",
+                self.days,
+                match self.blocked.last() {
+                    Some(blocked) => format!(", last blocked on {} by: {}", blocked.day, blocked.reason),
+                    None => String::new(),
+                }
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::calculation::{Release, Rollout, Stage, Version};
+
+    use super::*;
+
+    fn principal(id: u64) -> String {
+        PrincipalId::new_subnet_test_id(id).to_string()
+    }
+
+    fn stage(subnet_ids: &[u64], bake_time: &str, wait_for_next_week: bool) -> Stage {
+        Stage {
+            subnets: subnet_ids.iter().map(|id| principal(*id)).collect(),
+            bake_time: humantime::parse_duration(bake_time).unwrap(),
+            wait_for_next_week,
+            ..Default::default()
+        }
+    }
+
+    fn index(skip_days: Vec<NaiveDate>) -> Index {
+        let release = |name: &str, version: &str| Release {
+            rc_name: name.to_string(),
+            versions: vec![Version {
+                version: version.to_string(),
+                ..Default::default()
+            }],
+        };
+        Index {
+            rollout: Rollout {
+                skip_days,
+                stages: vec![
+                    stage(&[1], "8h", false),
+                    stage(&[2, 3], "4h", false),
+                    Stage {
+                        update_unassigned_nodes: true,
+                        ..Default::default()
+                    },
+                    stage(&[4], "4h", true),
+                ],
+                ..Default::default()
+            },
+            releases: vec![release("rc--2024-02-21_23-01", "b"), release("rc--2024-02-14_23-01", "a")],
+            hostos_rollout: None,
+        }
+    }
+
+    fn state(taken: &str) -> RolloutState {
+        RolloutState {
+            taken: DateTime::from_str(taken).unwrap(),
+            release_start: NaiveDate::from_str("2024-02-28").unwrap(),
+            subnets: (1..=4).map(|id| (principal(id), "a".to_string())).collect(),
+            last_bake_status: (1..=4).map(|id| (principal(id), 7. * 24. * 3600.)).collect(),
+            unassigned_nodes_version: "a".to_string(),
+        }
+    }
+
+    fn at(datetime: &str) -> DateTime<Utc> {
+        DateTime::from_str(datetime).unwrap()
+    }
+
+    #[test]
+    fn projects_stages_until_the_next_week() {
+        let plan = plan(&index(vec![]), &state("2024-02-28T10:00:00Z"), &[], 14).unwrap();
+
+        assert_eq!(
+            plan.upgrades.iter().map(|u| (u.stage, u.target.clone(), u.at)).collect::<Vec<_>>(),
+            vec![
+                (0, principal(1), at("2024-02-28T10:00:00Z")),
+                (1, principal(2), at("2024-02-28T18:00:00Z")),
+                (1, principal(3), at("2024-02-28T18:00:00Z")),
+                (2, UNASSIGNED_NODES.to_string(), at("2024-02-28T22:00:00Z")),
+                (3, principal(4), at("2024-03-04T00:00:00Z")),
+            ]
+        );
+        assert_eq!(
+            plan.blocked
+                .iter()
+                .map(|b| (b.day.to_string(), b.stage, b.reason.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("2024-02-28".to_string(), Some(3), BlockReason::WaitForNextWeek),
+                ("2024-02-29".to_string(), Some(3), BlockReason::WaitForNextWeek),
+                ("2024-03-01".to_string(), Some(3), BlockReason::WaitForNextWeek),
+                ("2024-03-02".to_string(), Some(3), BlockReason::Weekend),
+                ("2024-03-03".to_string(), Some(3), BlockReason::Weekend),
+            ]
+        );
+        assert_eq!(plan.completed, Some(at("2024-03-04T04:00:00Z")));
+    }
+
+    #[test]
+    fn skip_days_block_placing_proposals() {
+        let skip_day = NaiveDate::from_str("2024-02-28").unwrap();
+        let index = index(vec![skip_day]);
+        let plan = plan(&index, &state("2024-02-28T10:00:00Z"), &[], 2).unwrap();
+
+        assert_eq!(plan.upgrades[0].at, at("2024-02-29T00:00:00Z"));
+        assert_eq!(
+            plan.blocked[0],
+            BlockedStage {
+                day: skip_day,
+                stage: Some(0),
+                reason: BlockReason::SkipDay
+            }
+        );
+        assert_eq!(plan.completed, None);
+    }
+
+    #[test]
+    fn holidays_block_placing_proposals() {
+        let holidays = [Holiday {
+            summary: "Offsite".to_string(),
+            start: at("2024-02-28T09:00:00Z"),
+            end: at("2024-02-28T14:30:00Z"),
+        }];
+        let plan = plan(&index(vec![]), &state("2024-02-28T10:00:00Z"), &holidays, 2).unwrap();
+
+        assert_eq!(plan.upgrades[0].at, at("2024-02-28T14:30:00Z"));
+        assert_eq!(
+            plan.blocked[0],
+            BlockedStage {
+                day: NaiveDate::from_str("2024-02-28").unwrap(),
+                stage: Some(0),
+                reason: BlockReason::Maintenance("holiday: Offsite".to_string())
+            }
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use ic_management_backend::{
+    proposal::{SubnetUpdateProposal, UpdateUnassignedNodesProposal},
+    registry::RegistryState,
+};
+use ic_management_types::Subnet;
+use prometheus_http_query::Client;
+use slog::Logger;
+
+use crate::actions::SubnetAction;
+
+use super::{
+    health::{query_health, HealthCheck, HealthSamples},
+    hostos::{hostos_actions, HostosRolloutIndex},
+    query_last_bake_status, query_release_start, Release,
+};
+
+/// Metrics the controller bases its decisions on
+pub trait RolloutMetrics {
+    /// Seconds since the last version change of every subnet, by subnet principal
+    async fn last_bake_status(&self) -> anyhow::Result<BTreeMap<String, f64>>;
+
+    /// The day on which the first subnet got a version of the release
+    async fn release_start(&self, release: &Release) -> anyhow::Result<NaiveDate>;
+
+    async fn health(&self, health_checks: &[HealthCheck]) -> anyhow::Result<HealthSamples>;
+}
+
+/// The part of the registry, and of the proposals to it, that the controller
+/// looks at
+pub trait RegistryView {
+    fn subnets(&self) -> Vec<Subnet>;
+
+    async fn subnet_update_proposals(&self) -> anyhow::Result<Vec<SubnetUpdateProposal>>;
+
+    async fn unassigned_nodes_version(&self) -> anyhow::Result<String>;
+
+    async fn unassigned_nodes_proposals(&self) -> anyhow::Result<Vec<UpdateUnassignedNodesProposal>>;
+
+    /// Actions of the HostOS rollout of the index at the time `now`
+    async fn hostos_actions(&self, index: &HostosRolloutIndex, now: DateTime<Utc>, logger: &Logger) -> anyhow::Result<Vec<SubnetAction>>;
+}
+
+impl RolloutMetrics for Client {
+    async fn last_bake_status(&self) -> anyhow::Result<BTreeMap<String, f64>> {
+        query_last_bake_status(self).await
+    }
+
+    async fn release_start(&self, release: &Release) -> anyhow::Result<NaiveDate> {
+        query_release_start(self, release).await
+    }
+
+    async fn health(&self, health_checks: &[HealthCheck]) -> anyhow::Result<HealthSamples> {
+        query_health(self, health_checks).await
+    }
+}
+
+impl RegistryView for RegistryState {
+    fn subnets(&self) -> Vec<Subnet> {
+        RegistryState::subnets(self).into_values().collect()
+    }
+
+    async fn subnet_update_proposals(&self) -> anyhow::Result<Vec<SubnetUpdateProposal>> {
+        self.open_subnet_upgrade_proposals().await
+    }
+
+    async fn unassigned_nodes_version(&self) -> anyhow::Result<String> {
+        self.get_unassigned_nodes_replica_version().await
+    }
+
+    async fn unassigned_nodes_proposals(&self) -> anyhow::Result<Vec<UpdateUnassignedNodesProposal>> {
+        self.open_upgrade_unassigned_nodes_proposals().await
+    }
+
+    async fn hostos_actions(&self, index: &HostosRolloutIndex, now: DateTime<Utc>, logger: &Logger) -> anyhow::Result<Vec<SubnetAction>> {
+        hostos_actions(index, self, now, logger).await
+    }
+}
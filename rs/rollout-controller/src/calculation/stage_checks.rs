@@ -609,11 +609,13 @@ mod check_stages_tests {
                 pause: false,
                 skip_days: vec![],
                 stages: vec![stage(&[1], "8h"), stage(&[2, 3], "4h"), stage_unassigned(), stage_next_week(&[4], "4h")],
+                ..Default::default()
             },
             releases: vec![
                 release("rc--2024-02-21_23-01", vec![("b", vec![])]),
                 release("rc--2024-02-14_23-01", vec![("a", vec![])]),
             ],
+            hostos_rollout: None,
         }
     }
 
@@ -912,11 +914,13 @@ mod check_stages_tests {
                 pause: false,
                 skip_days: vec![],
                 stages: vec![stage(&[1], "8h"), stage(&[2, 3], "4h"), stage_unassigned(), stage_next_week(&[4], "4h")],
+                ..Default::default()
             },
             releases: vec![
                 release("rc--2024-02-21_23-01", vec![("b", vec![]), ("b.feat", vec![1, 2])]),
                 release("rc--2024-02-14_23-01", vec![("a", vec![])]),
             ],
+            hostos_rollout: None,
         };
         let tests = vec![
             TestCase::new("Beginning of a new rollout")
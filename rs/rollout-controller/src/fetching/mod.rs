@@ -29,6 +29,7 @@ pub async fn resolve(subcmd: Commands, logger: Logger) -> anyhow::Result<Rollout
             .await
             .map(RolloutScheduleFetcherImplementation::Git),
         Commands::Curl(CurlFetcherConfig { url }) => CurlFetcher::new(logger, url).map(RolloutScheduleFetcherImplementation::Curl),
+        Commands::History(_) | Commands::Plan(_) | Commands::Replay(_) => Err(anyhow::anyhow!("The subcommand doesn't run the rollout controller")),
     }
 }
 
@@ -0,0 +1,239 @@
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::actions::SubnetAction;
+
+/// A proposal that was open when the iteration ran
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OpenProposal {
+    pub id: u64,
+    /// Subnet principal, or `unassigned-nodes`
+    pub target: String,
+    pub version: String,
+}
+
+/// Outcome of executing one of the actions of an iteration
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ActionOutcome {
+    /// Position of the action in the actions of the iteration
+    pub action: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Everything that one iteration of the controller loop saw and did.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Iteration {
+    pub timestamp: DateTime<Utc>,
+    /// SHA-256 of the rollout index that was used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_hash: Option<String>,
+    /// Release that was being rolled out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    /// Seconds since the last version change, by subnet principal
+    #[serde(default)]
+    pub last_bake_status: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub open_proposals: Vec<OpenProposal>,
+    /// Version of the release that every subnet, and `unassigned-nodes`,
+    /// should run
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub desired_versions: BTreeMap<String, String>,
+    /// Samples of the health checks, by check name and subnet principal
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub health_samples: BTreeMap<String, BTreeMap<String, f64>>,
+    #[serde(default)]
+    pub actions: Vec<SubnetAction>,
+    #[serde(default)]
+    pub outcomes: Vec<ActionOutcome>,
+    /// Why the iteration stopped early, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Iteration {
+    pub fn start() -> Self {
+        Self {
+            timestamp: Utc::now(),
+            index_hash: None,
+            release: None,
+            last_bake_status: BTreeMap::new(),
+            open_proposals: vec![],
+            desired_versions: BTreeMap::new(),
+            health_samples: BTreeMap::new(),
+            actions: vec![],
+            outcomes: vec![],
+            error: None,
+        }
+    }
+
+    fn concerns(&self, subnet: &str) -> bool {
+        self.actions.iter().any(|a| a.subnet().is_some_and(|s| s.starts_with(subnet)))
+            || self.open_proposals.iter().any(|p| p.target.starts_with(subnet))
+    }
+}
+
+/// Append-only log of the iterations of the controller, one JSON object per
+/// line, so that its decisions can be reconstructed after the fact.
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn append(&self, iteration: &Iteration) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| anyhow::anyhow!("Failed to open history {}: {}", self.path.display(), e))?;
+        let line = serde_json::to_string(iteration)?;
+        writeln!(file, "{}", line).map_err(|e| anyhow::anyhow!("Failed to write history {}: {}", self.path.display(), e))
+    }
+
+    pub fn load(&self) -> anyhow::Result<Vec<Iteration>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let file = std::fs::File::open(&self.path).map_err(|e| anyhow::anyhow!("Failed to open history {}: {}", self.path.display(), e))?;
+        BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.as_ref().is_ok_and(|l| l.trim().is_empty()))
+            .map(|(i, line)| {
+                let line = line?;
+                serde_json::from_str(&line).map_err(|e| anyhow::anyhow!("Failed to parse line {} of history {}: {}", i + 1, self.path.display(), e))
+            })
+            .collect()
+    }
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct HistoryArgs {
+    #[clap(long, default_value = "20", help = "Number of most recent iterations to show")]
+    pub last: usize,
+
+    #[clap(
+        long,
+        help = "Only show iterations with actions or open proposals for the subnet with this principal prefix"
+    )]
+    pub subnet: Option<String>,
+
+    #[clap(long, help = "Only show iterations at or after this time, e.g. 2024-03-12T10:00:00Z")]
+    pub since: Option<DateTime<Utc>>,
+
+    #[clap(long, help = "Print the iterations as JSON lines, with all recorded inputs")]
+    pub json: bool,
+}
+
+pub fn print(history: &History, args: &HistoryArgs) -> anyhow::Result<()> {
+    let iterations = history
+        .load()?
+        .into_iter()
+        .filter(|i| args.since.map_or(true, |since| i.timestamp >= since))
+        .filter(|i| args.subnet.as_ref().map_or(true, |subnet| i.concerns(subnet)))
+        .collect::<Vec<_>>();
+    let iterations = &iterations[iterations.len().saturating_sub(args.last)..];
+
+    if args.json {
+        for iteration in iterations {
+            println!("{}", serde_json::to_string(iteration)?);
+        }
+        return Ok(());
+    }
+
+    for iteration in iterations {
+        println!(
+            "{}  index {}  release {}  {} open proposals",
+            iteration.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            iteration.index_hash.as_deref().map(|h| &h[..h.len().min(12)]).unwrap_or("-"),
+            iteration.release.as_deref().unwrap_or("-"),
+            iteration.open_proposals.len()
+        );
+        if !iteration.open_proposals.is_empty() {
+            println!(
+                "    open proposals: {}",
+                iteration
+                    .open_proposals
+                    .iter()
+                    .map(|p| format!("{} ({} -> {})", p.id, p.target, p.version))
+                    .join(", ")
+            );
+        }
+        for (i, action) in iteration.actions.iter().enumerate() {
+            let outcome = match iteration.outcomes.iter().find(|o| o.action == i) {
+                Some(ActionOutcome { error: None, .. }) => "ok".to_string(),
+                Some(ActionOutcome { error: Some(e), .. }) => format!("failed: {}", e),
+                None => "not executed".to_string(),
+            };
+            println!("    {} [{}]", action.print(), outcome);
+        }
+        if let Some(error) = &iteration.error {
+            println!("    error: {}", error);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ic_base_types::PrincipalId;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn iterations_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::new(dir.path().join("history.jsonl"));
+        assert_eq!(history.load().unwrap(), vec![]);
+
+        let subnet = PrincipalId::new_subnet_test_id(1);
+        let mut placed = Iteration::start();
+        placed.index_hash = Some("abc".to_string());
+        placed.last_bake_status = BTreeMap::from([(subnet.to_string(), 3600.)]);
+        placed.actions = vec![
+            SubnetAction::Baking {
+                subnet_short: "other".to_string(),
+                remaining: Duration::from_secs(60),
+            },
+            SubnetAction::PlaceProposal {
+                is_unassigned: false,
+                subnet_principal: subnet,
+                version: "b".to_string(),
+            },
+        ];
+        placed.outcomes = vec![
+            ActionOutcome { action: 0, error: None },
+            ActionOutcome {
+                action: 1,
+                error: Some("not elected".to_string()),
+            },
+        ];
+        let mut failed = Iteration::start();
+        failed.error = Some("registry sync failed".to_string());
+
+        history.append(&placed).unwrap();
+        history.append(&failed).unwrap();
+
+        let loaded = history.load().unwrap();
+        assert_eq!(loaded, vec![placed, failed]);
+        assert!(loaded[0].concerns(&subnet.to_string()[..5]));
+        assert!(!loaded[1].concerns(&subnet.to_string()[..5]));
+    }
+}
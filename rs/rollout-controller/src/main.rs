@@ -1,5 +1,11 @@
-use std::{path::PathBuf, time::Duration};
-
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use axum_otel_metrics::HttpMetricsLayerBuilder;
 use clap::{Parser, Subcommand};
 use fetching::{curl_fetcher::CurlFetcherConfig, sparse_checkout_fetcher::SparseCheckoutFetcherConfig};
 use humantime::parse_duration;
@@ -9,16 +15,45 @@ use tokio::select;
 use tokio_util::sync::CancellationToken;
 use url::Url;
 
-use crate::{actions::ActionExecutor, calculation::calculate_progress, registry_wrappers::sync_wrap};
+use crate::{
+    actions::ActionExecutor,
+    calculation::{
+        calculate_progress,
+        maintenance::{load_holidays, HolidaysCalendar},
+        plan::{plan, PlanArgs, RolloutState},
+        Index,
+    },
+    fetching::{curl_fetcher::CurlFetcher, RolloutScheduleFetcher},
+    history::{History, HistoryArgs, Iteration},
+    metrics::ControllerMetrics,
+    registry_wrappers::sync_wrap,
+    replay::{Recorder, Recording, ReplayArgs, Snapshot},
+    rollbacks::RollbackLog,
+    status::{RolloutStatus, SharedStatus},
+};
 
 mod actions;
 mod calculation;
 mod fetching;
+mod history;
+mod metrics;
 mod registry_wrappers;
+mod replay;
+mod rollbacks;
+mod status;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
+    let history = History::new(args.history_file.clone().unwrap_or_else(|| args.targets_dir.join("history.jsonl")));
+    let rollbacks_file = args.rollbacks_file.clone().unwrap_or_else(|| args.targets_dir.join("rollbacks.yaml"));
+    if let Commands::History(history_args) = &args.subcommand {
+        return history::print(&history, history_args);
+    }
+    if let Commands::Replay(replay_args) = &args.subcommand {
+        return replay::run(replay_args, &rollbacks_file).await;
+    }
+
     let target_network = ic_management_types::Network::new(args.network.clone(), &args.nns_urls)
         .await
         .expect("Failed to create network");
@@ -27,6 +62,10 @@ async fn main() -> anyhow::Result<()> {
 
     let client = Client::try_from(prometheus_endpoint.to_string()).map_err(|e| anyhow::anyhow!("Couldn't create prometheus client: {:?}", e))?;
 
+    if let Commands::Plan(plan_args) = &args.subcommand {
+        return run_plan(plan_args, &logger, &args, target_network, &client).await;
+    }
+
     let shutdown = tokio::signal::ctrl_c();
     let token = CancellationToken::new();
     info!(logger, "Running release controller with arguments: {:#?}", args);
@@ -41,12 +80,44 @@ async fn main() -> anyhow::Result<()> {
         info!(shutdown_logger, "Received shutdown");
     });
 
+    // Initialize the metrics layer because in the build method the `global::provider`
+    // is set. We can use global::meter only after that call.
+    let metrics_layer = HttpMetricsLayerBuilder::new().build();
+    let metrics = ControllerMetrics::new();
+    let status: SharedStatus = Arc::new(RwLock::new(RolloutStatus::default()));
+    let server_handle = tokio::spawn(status::serve(
+        logger.clone(),
+        args.listen_address,
+        status.clone(),
+        metrics_layer,
+        token.clone(),
+    ));
+    let reporter = Reporter {
+        logger: logger.clone(),
+        history,
+        status,
+        metrics,
+    };
+
     let fetcher = fetching::resolve(args.subcommand, logger.clone()).await?;
 
     let executor = match args.private_key_pem {
-        Some(path) => ActionExecutor::new(args.neuron_id, path, target_network.clone(), false, Some(&logger)).await?,
+        Some(path) => {
+            ActionExecutor::new(
+                args.neuron_id,
+                path,
+                target_network.clone(),
+                false,
+                Some(&logger),
+                RollbackLog::new(rollbacks_file.clone()),
+            )
+            .await?
+        }
         None => ActionExecutor::test(target_network.clone(), Some(&logger)).await?,
     };
+    let rollback_log = RollbackLog::new(rollbacks_file);
+    let recorder = args.record_file.clone().map(|path| Recorder::new(path, args.record_interval));
+    let mut holidays_calendar = HolidaysCalendar::default();
 
     let mut interval = tokio::time::interval(args.poll_interval);
     let mut should_sleep = false;
@@ -60,6 +131,7 @@ async fn main() -> anyhow::Result<()> {
             break;
         }
         should_sleep = true;
+        let mut iteration = Iteration::start();
 
         info!(logger, "Syncing registry for network '{}'", target_network);
         let maybe_registry_state = select! {
@@ -73,6 +145,7 @@ async fn main() -> anyhow::Result<()> {
             }
             Err(e) => {
                 warn!(logger, "{:?}", e);
+                reporter.record(iteration, None, Some(("sync_registry", e)));
                 should_sleep = false;
                 continue;
             }
@@ -86,16 +159,19 @@ async fn main() -> anyhow::Result<()> {
             }
             Err(e) => {
                 warn!(logger, "{:?}", e);
+                reporter.record(iteration, None, Some(("fetch_index", e)));
                 should_sleep = false;
                 continue;
             }
         };
+        iteration.index_hash = Some(index.hash());
 
         // Get elected GuestOS versions for later
         let elected_guestos_versions = match registry_state.get_elected_guestos_versions().await {
             Ok(versions) => versions,
             Err(e) => {
                 warn!(logger, "{:?}", e);
+                reporter.record(iteration, Some(&index), Some(("elected_versions", e)));
                 should_sleep = false;
                 continue;
             }
@@ -103,32 +179,153 @@ async fn main() -> anyhow::Result<()> {
 
         // Calculate what should be done
         info!(logger, "Calculating the progress of the current release");
-        let actions = match calculate_progress(&logger, index, &client, registry_state).await {
+        let now = iteration.timestamp;
+        let holidays = holidays_calendar.holidays(index.rollout.holidays_calendar.as_deref(), now, &logger).await;
+        let snapshot = Mutex::new(Snapshot {
+            holidays: holidays.clone(),
+            ..Snapshot::new(now, index.clone())
+        });
+        let result = match &recorder {
+            Some(_) => {
+                let (recorded_metrics, recorded_registry) = (Recording::new(&client, &snapshot), Recording::new(&registry_state, &snapshot));
+                calculate_progress(
+                    &logger,
+                    index.clone(),
+                    &recorded_metrics,
+                    &recorded_registry,
+                    &rollback_log,
+                    &holidays,
+                    &mut iteration,
+                    now,
+                )
+                .await
+            }
+            None => {
+                calculate_progress(
+                    &logger,
+                    index.clone(),
+                    &client,
+                    &registry_state,
+                    &rollback_log,
+                    &holidays,
+                    &mut iteration,
+                    now,
+                )
+                .await
+            }
+        };
+        let actions = match result {
             Ok(actions) => actions,
             Err(e) => {
                 warn!(logger, "{:?}", e);
+                reporter.record(iteration, Some(&index), Some(("calculate", e)));
                 continue;
             }
         };
+        if let Some(recorder) = &recorder {
+            let mut snapshot = snapshot.into_inner().unwrap();
+            snapshot.actions = actions.clone();
+            if let Err(e) = recorder.record(&snapshot) {
+                warn!(logger, "Failed to record the inputs of the iteration: {:?}", e)
+            }
+        }
         info!(logger, "Calculating completed");
+        iteration.actions = actions.clone();
 
         if actions.is_empty() {
             info!(logger, "Rollout completed");
+            reporter.record(iteration, Some(&index), None);
             token.cancel();
             break;
         }
         info!(logger, "Calculated actions: {:#?}", actions);
-        match executor.execute(&actions, &elected_guestos_versions).await {
-            Ok(()) => info!(logger, "Actions taken successfully"),
-            Err(e) => warn!(logger, "{:?}", e),
+        let result = executor.execute(&actions, &elected_guestos_versions, &mut iteration).await;
+        match result {
+            Ok(()) => {
+                info!(logger, "Actions taken successfully");
+                reporter.record(iteration, Some(&index), None);
+            }
+            Err(e) => {
+                warn!(logger, "{:?}", e);
+                reporter.record(iteration, Some(&index), Some(("execute", e)));
+            }
         };
     }
+    token.cancel();
+    if let Err(e) = server_handle.await? {
+        warn!(logger, "Status server failed: {:?}", e)
+    }
     info!(logger, "Shutdown complete");
     shutdown_handle.await.unwrap();
 
     Ok(())
 }
 
+async fn run_plan(
+    plan_args: &PlanArgs,
+    logger: &Logger,
+    args: &Cli,
+    target_network: ic_management_types::Network,
+    client: &Client,
+) -> anyhow::Result<()> {
+    let index = match &plan_args.index_file {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read release index {}: {}", path.display(), e))?;
+            serde_yaml::from_str::<Index>(&contents).map_err(|e| anyhow::anyhow!("Couldn't parse release index: {:?}", e))?
+        }
+        None => CurlFetcher::new(logger.clone(), plan_args.url.clone())?.fetch().await?,
+    };
+
+    let state = match &plan_args.snapshot {
+        Some(path) => RolloutState::load(path)?,
+        None => {
+            info!(logger, "Syncing registry for network '{}'", target_network);
+            let registry_state = sync_wrap(logger.clone(), args.targets_dir.clone(), target_network).await?;
+            RolloutState::current(&registry_state, client, &index).await?
+        }
+    };
+    if let Some(path) = &plan_args.save_snapshot {
+        state.save(path)?;
+    }
+
+    let holidays = match &index.rollout.holidays_calendar {
+        Some(location) => load_holidays(location).await.unwrap_or_else(|e| {
+            warn!(logger, "Planning without holidays: {:?}", e);
+            vec![]
+        }),
+        None => vec![],
+    };
+    let plan = plan(&index, &state, &holidays, plan_args.days)?;
+    match plan_args.json {
+        true => println!("{}", serde_json::to_string_pretty(&plan)?),
+        false => println!("{}", plan),
+    }
+    Ok(())
+}
+
+/// Makes the outcome of every iteration visible in the history, the status
+/// server and the metrics
+struct Reporter {
+    logger: Logger,
+    history: History,
+    status: SharedStatus,
+    metrics: ControllerMetrics,
+}
+
+impl Reporter {
+    /// Records the iteration, along with the phase of the loop in which it
+    /// failed and the error, if it did.
+    fn record(&self, mut iteration: Iteration, index: Option<&Index>, error: Option<(&'static str, anyhow::Error)>) {
+        let failed_phase = error.as_ref().map(|(phase, _)| *phase);
+        iteration.error = error.map(|(_, e)| format!("{:?}", e));
+        self.metrics.observe_iteration(&iteration, index, failed_phase);
+        self.status.write().unwrap().update(&iteration);
+        if let Err(e) = self.history.append(&iteration) {
+            warn!(self.logger, "Failed to record the iteration in the history: {:?}", e)
+        }
+    }
+}
+
 fn make_logger(level: Level) -> Logger {
     let decorator = slog_term::TermDecorator::new().build();
     let full_format = slog_term::FullFormat::new(decorator).build().fuse();
@@ -212,6 +409,54 @@ By default is 0.
     )]
     neuron_id: u64,
 
+    #[clap(
+        long = "rollbacks-file",
+        help = r#"
+Path to the file where rollbacks of unhealthy subnets are recorded. The rollout
+of a release stays paused while the file contains a rollback for it.
+By default it is 'rollbacks.yaml' in the targets dir.
+    "#
+    )]
+    rollbacks_file: Option<PathBuf>,
+
+    #[clap(
+        long = "history-file",
+        help = r#"
+Path to the file where the inputs, actions and outcomes of every iteration
+are appended. By default it is 'history.jsonl' in the targets dir.
+    "#
+    )]
+    history_file: Option<PathBuf>,
+
+    #[clap(
+        long = "listen-address",
+        default_value = "127.0.0.1:8080",
+        help = r#"
+Address on which the status of the rollout is served as JSON on '/status',
+along with the metrics of the controller on '/metrics'.
+    "#
+    )]
+    listen_address: SocketAddr,
+
+    #[clap(
+        long = "record-file",
+        help = r#"
+Path to the file where the inputs of the iterations are recorded, to replay
+them later with the 'replay' subcommand.
+    "#
+    )]
+    record_file: Option<PathBuf>,
+
+    #[clap(
+        long = "record-interval",
+        default_value = "10m",
+        value_parser = parse_duration,
+        help = r#"
+Minimum time between two recorded iterations.
+    "#
+    )]
+    record_interval: Duration,
+
     #[clap(subcommand)]
     pub(crate) subcommand: Commands,
 }
@@ -220,6 +465,12 @@ By default is 0.
 enum Commands {
     Git(SparseCheckoutFetcherConfig),
     Curl(CurlFetcherConfig),
+    /// Show what the controller saw and did in past iterations
+    History(HistoryArgs),
+    /// Project when each subnet will get the versions of the release
+    Plan(PlanArgs),
+    /// Re-run the controller over recorded inputs, e.g. with a changed index
+    Replay(ReplayArgs),
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,165 @@
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use opentelemetry::{
+    global,
+    metrics::{Counter, Histogram, Observer, Unit},
+    KeyValue,
+};
+
+use crate::{actions::SubnetAction, calculation::Index, history::Iteration};
+
+const METER: &str = "rollout-controller";
+const ACTION: &str = "action";
+const STAGE: &str = "stage";
+const PHASE: &str = "phase";
+/// Stage label of the errors that are not about an action of a rollout stage
+const NO_STAGE: &str = "none";
+
+/// Metrics of the controller loop. They are exported on the `/metrics`
+/// endpoint of the status server.
+#[derive(Clone)]
+pub struct ControllerMetrics {
+    loop_duration: Histogram<f64>,
+    actions_placed: Counter<u64>,
+    errors: Counter<u64>,
+    last_success: Arc<RwLock<Option<DateTime<Utc>>>>,
+}
+
+impl Default for ControllerMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ControllerMetrics {
+    /// Has to be called after the metrics layer of the status server is
+    /// built, because building it sets the global meter provider.
+    pub fn new() -> Self {
+        let meter = global::meter(METER);
+        let loop_duration = meter
+            .f64_histogram("rollout_controller.loop.duration")
+            .with_description("Duration of the iterations of the controller loop")
+            .with_unit(Unit::new("s"))
+            .init();
+        let actions_placed = meter
+            .u64_counter("rollout_controller.actions.placed")
+            .with_description("Number of proposals placed by the controller, by kind of action")
+            .init();
+        let errors = meter
+            .u64_counter("rollout_controller.errors")
+            .with_description("Number of iterations that failed, by the rollout stage of the actions that failed and by the phase of the loop")
+            .init();
+        let since_last_success = meter
+            .f64_observable_gauge("rollout_controller.last_success.age")
+            .with_description("Time since the last iteration that completed without errors")
+            .with_unit(Unit::new("s"))
+            .init();
+
+        let last_success: Arc<RwLock<Option<DateTime<Utc>>>> = Arc::new(RwLock::new(None));
+        let s = last_success.clone();
+        meter
+            .register_callback(&[since_last_success.as_any()], move |observer: &dyn Observer| {
+                if let Some(last_success) = *s.read().unwrap() {
+                    let age = (Utc::now() - last_success).num_milliseconds() as f64 / 1000.;
+                    observer.observe_f64(&since_last_success, age, &[])
+                }
+            })
+            .unwrap();
+
+        Self {
+            loop_duration,
+            actions_placed,
+            errors,
+            last_success,
+        }
+    }
+
+    /// Records a finished iteration. `failed_phase` is the phase of the loop
+    /// in which the iteration failed, if it did, and `index` the index that
+    /// the actions of the iteration come from, if it was fetched.
+    pub fn observe_iteration(&self, iteration: &Iteration, index: Option<&Index>, failed_phase: Option<&'static str>) {
+        let duration = (Utc::now() - iteration.timestamp).num_milliseconds() as f64 / 1000.;
+        self.loop_duration.record(duration, &[]);
+
+        for outcome in iteration.outcomes.iter().filter(|o| o.error.is_none()) {
+            let kind = match iteration.actions.get(outcome.action) {
+                Some(SubnetAction::PlaceProposal { .. }) => "place_proposal",
+                Some(SubnetAction::Rollback { .. }) => "rollback",
+                Some(SubnetAction::PlaceHostosProposal { .. }) => "place_hostos_proposal",
+                _ => continue,
+            };
+            self.actions_placed.add(1, &[KeyValue::new(ACTION, kind)]);
+        }
+
+        match failed_phase {
+            Some(phase) => {
+                for stage in failed_stages(iteration, index) {
+                    self.errors.add(1, &[KeyValue::new(STAGE, stage), KeyValue::new(PHASE, phase)])
+                }
+            }
+            None => *self.last_success.write().unwrap() = Some(Utc::now()),
+        }
+    }
+}
+
+/// Rollout stages of the actions that failed, or [NO_STAGE] if none of them
+/// belongs to a stage, e.g. when the iteration failed before executing any
+/// action
+fn failed_stages(iteration: &Iteration, index: Option<&Index>) -> Vec<String> {
+    let mut stages = iteration
+        .outcomes
+        .iter()
+        .filter(|o| o.error.is_some())
+        .filter_map(|o| {
+            let index = index?;
+            match iteration.actions.get(o.action)? {
+                SubnetAction::PlaceProposal { is_unassigned: true, .. } => index.stage_of(true, ""),
+                action => index.stage_of(false, &action.subnet()?),
+            }
+        })
+        .map(|stage| stage.to_string())
+        .collect::<Vec<_>>();
+    stages.sort();
+    stages.dedup();
+    if stages.is_empty() {
+        stages.push(NO_STAGE.to_string());
+    }
+    stages
+}
+
+#[cfg(test)]
+mod tests {
+    use ic_base_types::PrincipalId;
+    use pretty_assertions::assert_eq;
+
+    use crate::history::ActionOutcome;
+
+    use super::*;
+
+    #[test]
+    fn errors_are_labelled_by_rollout_stage() {
+        let index: Index = serde_yaml::from_str(&format!(
+            "rollout:\n  skip_days: []\n  stages:\n    - subnets: [{}]\n    - update_unassigned_nodes: true\nreleases: []\n",
+            PrincipalId::new_subnet_test_id(1)
+        ))
+        .unwrap();
+        let place = |is_unassigned: bool| SubnetAction::PlaceProposal {
+            is_unassigned,
+            subnet_principal: PrincipalId::new_subnet_test_id(1),
+            version: "b".to_string(),
+        };
+        let mut iteration = Iteration::start();
+        assert_eq!(failed_stages(&iteration, Some(&index)), vec![NO_STAGE]);
+
+        iteration.actions = vec![place(false), place(true), place(false)];
+        iteration.outcomes = (0..3)
+            .map(|action| ActionOutcome {
+                action,
+                error: Some("failed".to_string()),
+            })
+            .collect();
+        assert_eq!(failed_stages(&iteration, Some(&index)), vec!["0", "1"]);
+        assert_eq!(failed_stages(&iteration, None), vec![NO_STAGE]);
+    }
+}
@@ -0,0 +1,638 @@
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::Parser;
+use ic_management_backend::proposal::{SubnetUpdateProposal, UpdateUnassignedNodesProposal};
+use ic_management_types::Subnet;
+use serde::{Deserialize, Serialize};
+use slog::{o, Logger};
+
+use crate::{
+    actions::SubnetAction,
+    calculation::{
+        calculate_progress,
+        health::{HealthCheck, HealthSamples},
+        hostos::HostosRolloutIndex,
+        maintenance::Holiday,
+        sources::{RegistryView, RolloutMetrics},
+        Index, Release,
+    },
+    history::Iteration,
+    rollbacks::{RollbackLog, RollbackRecord},
+};
+
+/// The inputs of one iteration of the controller, and the actions it
+/// calculated from them.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub timestamp: DateTime<Utc>,
+    pub index: Index,
+    #[serde(default)]
+    pub last_bake_status: BTreeMap<String, f64>,
+    /// Start of the releases, by release name
+    #[serde(default)]
+    pub release_starts: BTreeMap<String, NaiveDate>,
+    #[serde(default)]
+    pub health_samples: HealthSamples,
+    /// Subnets without their nodes, which the controller doesn't look at
+    #[serde(default)]
+    pub subnets: Vec<Subnet>,
+    #[serde(default)]
+    pub subnet_update_proposals: Vec<SubnetUpdateProposal>,
+    #[serde(default)]
+    pub unassigned_nodes_version: Option<String>,
+    #[serde(default)]
+    pub unassigned_nodes_proposals: Vec<UpdateUnassignedNodesProposal>,
+    /// Holidays of the calendar of the rollout, as they were known then
+    #[serde(default)]
+    pub holidays: Vec<Holiday>,
+    /// Actions of the HostOS rollout, if the index had one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostos_actions: Option<Vec<SubnetAction>>,
+    #[serde(default)]
+    pub actions: Vec<SubnetAction>,
+}
+
+impl Snapshot {
+    pub fn new(timestamp: DateTime<Utc>, index: Index) -> Self {
+        Self {
+            timestamp,
+            index,
+            last_bake_status: BTreeMap::new(),
+            release_starts: BTreeMap::new(),
+            health_samples: HealthSamples::new(),
+            subnets: vec![],
+            subnet_update_proposals: vec![],
+            unassigned_nodes_version: None,
+            unassigned_nodes_proposals: vec![],
+            holidays: vec![],
+            hostos_actions: None,
+            actions: vec![],
+        }
+    }
+}
+
+/// Replays the recorded inputs. Inputs that were not recorded, e.g. the
+/// samples of a health check that was added to the index since, are errors.
+impl RolloutMetrics for Snapshot {
+    async fn last_bake_status(&self) -> anyhow::Result<BTreeMap<String, f64>> {
+        Ok(self.last_bake_status.clone())
+    }
+
+    async fn release_start(&self, release: &Release) -> anyhow::Result<NaiveDate> {
+        self.release_starts
+            .get(&release.rc_name)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Start of release '{}' was not recorded", release.rc_name))
+    }
+
+    async fn health(&self, health_checks: &[HealthCheck]) -> anyhow::Result<HealthSamples> {
+        health_checks
+            .iter()
+            .map(|check| match self.health_samples.get(&check.name) {
+                Some(samples) => Ok((check.name.clone(), samples.clone())),
+                None => Err(anyhow::anyhow!("Samples of health check '{}' were not recorded", check.name)),
+            })
+            .collect()
+    }
+}
+
+/// The HostOS actions depend on all the nodes of the network, which are not
+/// recorded, so the recorded actions are replayed as they are.
+impl RegistryView for Snapshot {
+    fn subnets(&self) -> Vec<Subnet> {
+        self.subnets.clone()
+    }
+
+    async fn subnet_update_proposals(&self) -> anyhow::Result<Vec<SubnetUpdateProposal>> {
+        Ok(self.subnet_update_proposals.clone())
+    }
+
+    async fn unassigned_nodes_version(&self) -> anyhow::Result<String> {
+        self.unassigned_nodes_version
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Version of the unassigned nodes was not recorded"))
+    }
+
+    async fn unassigned_nodes_proposals(&self) -> anyhow::Result<Vec<UpdateUnassignedNodesProposal>> {
+        Ok(self.unassigned_nodes_proposals.clone())
+    }
+
+    async fn hostos_actions(&self, _index: &HostosRolloutIndex, _now: DateTime<Utc>, _logger: &Logger) -> anyhow::Result<Vec<SubnetAction>> {
+        self.hostos_actions
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Actions of the HostOS rollout were not recorded"))
+    }
+}
+
+/// Captures the inputs that the controller gets from a live source into a
+/// snapshot.
+pub struct Recording<'a, S> {
+    inner: &'a S,
+    snapshot: &'a Mutex<Snapshot>,
+}
+
+impl<'a, S> Recording<'a, S> {
+    pub fn new(inner: &'a S, snapshot: &'a Mutex<Snapshot>) -> Self {
+        Self { inner, snapshot }
+    }
+}
+
+impl<M: RolloutMetrics> RolloutMetrics for Recording<'_, M> {
+    async fn last_bake_status(&self) -> anyhow::Result<BTreeMap<String, f64>> {
+        let last_bake_status = self.inner.last_bake_status().await?;
+        self.snapshot.lock().unwrap().last_bake_status.clone_from(&last_bake_status);
+        Ok(last_bake_status)
+    }
+
+    async fn release_start(&self, release: &Release) -> anyhow::Result<NaiveDate> {
+        let release_start = self.inner.release_start(release).await?;
+        self.snapshot
+            .lock()
+            .unwrap()
+            .release_starts
+            .insert(release.rc_name.clone(), release_start);
+        Ok(release_start)
+    }
+
+    async fn health(&self, health_checks: &[HealthCheck]) -> anyhow::Result<HealthSamples> {
+        let samples = self.inner.health(health_checks).await?;
+        self.snapshot.lock().unwrap().health_samples.extend(samples.clone());
+        Ok(samples)
+    }
+}
+
+impl<R: RegistryView> RegistryView for Recording<'_, R> {
+    fn subnets(&self) -> Vec<Subnet> {
+        let subnets = self.inner.subnets();
+        self.snapshot.lock().unwrap().subnets = subnets.iter().map(|s| Subnet { nodes: vec![], ..s.clone() }).collect();
+        subnets
+    }
+
+    async fn subnet_update_proposals(&self) -> anyhow::Result<Vec<SubnetUpdateProposal>> {
+        let proposals = self.inner.subnet_update_proposals().await?;
+        self.snapshot.lock().unwrap().subnet_update_proposals.clone_from(&proposals);
+        Ok(proposals)
+    }
+
+    async fn unassigned_nodes_version(&self) -> anyhow::Result<String> {
+        let version = self.inner.unassigned_nodes_version().await?;
+        self.snapshot.lock().unwrap().unassigned_nodes_version = Some(version.clone());
+        Ok(version)
+    }
+
+    async fn unassigned_nodes_proposals(&self) -> anyhow::Result<Vec<UpdateUnassignedNodesProposal>> {
+        let proposals = self.inner.unassigned_nodes_proposals().await?;
+        self.snapshot.lock().unwrap().unassigned_nodes_proposals.clone_from(&proposals);
+        Ok(proposals)
+    }
+
+    async fn hostos_actions(&self, index: &HostosRolloutIndex, now: DateTime<Utc>, logger: &Logger) -> anyhow::Result<Vec<SubnetAction>> {
+        let actions = self.inner.hostos_actions(index, now, logger).await?;
+        self.snapshot.lock().unwrap().hostos_actions = Some(actions.clone());
+        Ok(actions)
+    }
+}
+
+/// Appends snapshots to a file, one JSON object per line, at most once per
+/// interval.
+pub struct Recorder {
+    path: PathBuf,
+    interval: Duration,
+    last: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        Self {
+            path,
+            interval,
+            last: Mutex::new(None),
+        }
+    }
+
+    pub fn record(&self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        let mut last = self.last.lock().unwrap();
+        if last.is_some_and(|last| (snapshot.timestamp - last).to_std().unwrap_or_default() < self.interval) {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| anyhow::anyhow!("Failed to open recording {}: {}", self.path.display(), e))?;
+        writeln!(file, "{}", serde_json::to_string(snapshot)?)
+            .map_err(|e| anyhow::anyhow!("Failed to write recording {}: {}", self.path.display(), e))?;
+        *last = Some(snapshot.timestamp);
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Vec<Snapshot>> {
+        let file = std::fs::File::open(path).map_err(|e| anyhow::anyhow!("Failed to open recording {}: {}", path.display(), e))?;
+        BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.as_ref().is_ok_and(|l| l.trim().is_empty()))
+            .map(|(i, line)| {
+                let line = line?;
+                serde_json::from_str(&line).map_err(|e| anyhow::anyhow!("Failed to parse line {} of recording {}: {}", i + 1, path.display(), e))
+            })
+            .collect()
+    }
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct ReplayArgs {
+    #[clap(long, help = "Recording made by running the controller with '--record-file'")]
+    pub recording: PathBuf,
+
+    #[clap(
+        long = "index-file",
+        help = "Release index to replay with, instead of the index that was recorded with every iteration"
+    )]
+    pub index_file: Option<PathBuf>,
+
+    #[clap(
+        long = "rollbacks-file",
+        help = "Rollback log to replay with. Rollbacks of the replay are recorded in it. By default a temporary file is used. The rollback log of the controller is refused"
+    )]
+    pub rollbacks_file: Option<PathBuf>,
+
+    #[clap(long, help = "Fail if the actions of any iteration differ from the recorded ones")]
+    pub check: bool,
+
+    #[clap(long, help = "Print the replayed iterations as JSON lines")]
+    pub json: bool,
+}
+
+/// An iteration of the replay, along with the actions that were recorded for
+/// it
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ReplayedIteration {
+    pub timestamp: DateTime<Utc>,
+    pub recorded: Vec<SubnetAction>,
+    pub replayed: Vec<SubnetAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ReplayedIteration {
+    fn changed(&self) -> bool {
+        self.error.is_some() || self.recorded != self.replayed
+    }
+}
+
+/// Re-runs the calculation of the controller over the snapshots, in order,
+/// with the given index instead of the recorded one if there is one.
+pub async fn replay(snapshots: Vec<Snapshot>, index: Option<&Index>, rollback_log: &RollbackLog) -> Vec<ReplayedIteration> {
+    let logger = Logger::root(slog::Discard, o!());
+    let mut replayed = vec![];
+    for snapshot in snapshots {
+        let mut iteration = Iteration::start();
+        iteration.timestamp = snapshot.timestamp;
+        let index = index.cloned().unwrap_or_else(|| snapshot.index.clone());
+        let result = calculate_progress(
+            &logger,
+            index,
+            &snapshot,
+            &snapshot,
+            rollback_log,
+            &snapshot.holidays,
+            &mut iteration,
+            snapshot.timestamp,
+        )
+        .await;
+
+        // Rollbacks are recorded as if they were executed, so that they pause
+        // the rest of the replay
+        let mut error = None;
+        for action in result.as_deref().unwrap_or_default() {
+            if let SubnetAction::Rollback {
+                subnet_principal,
+                release,
+                from_version,
+                to_version,
+                reason,
+            } = action
+            {
+                let record = RollbackRecord {
+                    release: release.clone(),
+                    subnet: subnet_principal.to_string(),
+                    from_version: from_version.clone(),
+                    to_version: to_version.clone(),
+                    reason: reason.clone(),
+                    timestamp: snapshot.timestamp,
+                };
+                if let Err(e) = rollback_log.record(record) {
+                    error = Some(format!("{:?}", e));
+                }
+            }
+        }
+
+        replayed.push(ReplayedIteration {
+            timestamp: snapshot.timestamp,
+            recorded: snapshot.actions,
+            error: result.as_ref().err().map(|e| format!("{:?}", e)).or(error),
+            replayed: result.unwrap_or_default(),
+        });
+    }
+    replayed
+}
+
+/// Replays the recording of the arguments. The rollbacks of the replay are
+/// recorded in a rollback log, which must not be the one the controller
+/// reads, given as `controller_rollbacks_file`, since they would pause the
+/// live rollout.
+pub async fn run(args: &ReplayArgs, controller_rollbacks_file: &Path) -> anyhow::Result<()> {
+    let snapshots = Recorder::load(&args.recording)?;
+    let index = match &args.index_file {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read release index {}: {}", path.display(), e))?;
+            Some(serde_yaml::from_str::<Index>(&contents).map_err(|e| anyhow::anyhow!("Couldn't parse release index: {:?}", e))?)
+        }
+        None => None,
+    };
+    let temporary_rollbacks_file = std::env::temp_dir().join(format!("rollout-controller-replay-{}-rollbacks.yaml", std::process::id()));
+    let rollbacks_file = match &args.rollbacks_file {
+        Some(path) if same_file(path, controller_rollbacks_file) => {
+            return Err(anyhow::anyhow!(
+                "Refusing to replay with the rollback log of the controller {}, the replayed rollbacks would pause the rollout",
+                path.display()
+            ))
+        }
+        Some(path) => path.clone(),
+        None => temporary_rollbacks_file.clone(),
+    };
+    let _ = std::fs::remove_file(&temporary_rollbacks_file);
+
+    let replayed = replay(snapshots, index.as_ref(), &RollbackLog::new(rollbacks_file)).await;
+    let _ = std::fs::remove_file(&temporary_rollbacks_file);
+    for iteration in &replayed {
+        if args.json {
+            println!("{}", serde_json::to_string(iteration)?);
+            continue;
+        }
+        println!(
+            "{}  {}",
+            iteration.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            if iteration.changed() { "changed" } else { "unchanged" }
+        );
+        if let Some(error) = &iteration.error {
+            println!("    error: {}", error);
+        }
+        for action in &iteration.replayed {
+            let marker = if iteration.recorded.contains(action) { " " } else { "+" };
+            println!("  {} {}", marker, action.print());
+        }
+        for action in iteration.recorded.iter().filter(|a| !iteration.replayed.contains(a)) {
+            println!("  - {}", action.print());
+        }
+    }
+
+    let changed = replayed.iter().filter(|i| i.changed()).count();
+    if !args.json {
+        println!("{} of {} iterations changed", changed, replayed.len());
+    }
+    if args.check && changed > 0 {
+        return Err(anyhow::anyhow!("{} of {} iterations changed", changed, replayed.len()));
+    }
+    Ok(())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ic_base_types::PrincipalId;
+    use pretty_assertions::assert_eq;
+
+    use crate::calculation::{Rollout, Stage, Version};
+
+    use super::*;
+
+    fn principal(id: u64) -> PrincipalId {
+        PrincipalId::new_subnet_test_id(id)
+    }
+
+    fn index(bake_time: &str) -> Index {
+        let release = |name: &str, version: &str| Release {
+            rc_name: name.to_string(),
+            versions: vec![Version {
+                version: version.to_string(),
+                ..Default::default()
+            }],
+        };
+        Index {
+            rollout: Rollout {
+                stages: vec![Stage {
+                    subnets: vec![principal(1).to_string()],
+                    bake_time: humantime::parse_duration(bake_time).unwrap(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            releases: vec![release("rc--2", "b"), release("rc--1", "a")],
+            hostos_rollout: None,
+        }
+    }
+
+    fn snapshot(timestamp: &str, version: &str, seconds_since_upgrade: f64, actions: Vec<SubnetAction>) -> Snapshot {
+        let mut snapshot = Snapshot::new(timestamp.parse().unwrap(), index("8h"));
+        snapshot.subnets = vec![Subnet {
+            principal: principal(1),
+            replica_version: version.to_string(),
+            ..Default::default()
+        }];
+        snapshot.last_bake_status = BTreeMap::from([(principal(1).to_string(), seconds_since_upgrade)]);
+        snapshot.release_starts = BTreeMap::from([("rc--2".to_string(), "2024-03-12".parse().unwrap())]);
+        snapshot.unassigned_nodes_version = Some("b".to_string());
+        snapshot.actions = actions;
+        snapshot
+    }
+
+    #[tokio::test]
+    async fn replay_with_changed_index() {
+        let baking = |remaining: u64| SubnetAction::Baking {
+            subnet_short: principal(1).to_string(),
+            remaining: Duration::from_secs(remaining),
+        };
+        // 2024-03-13 is a Wednesday
+        let snapshots = vec![
+            snapshot("2024-03-13T10:00:00Z", "b", 3600., vec![baking(7 * 3600)]),
+            // The rollout is done once the last stage baked
+            snapshot("2024-03-13T18:00:00Z", "b", 9. * 3600., vec![]),
+        ];
+
+        let replayed = replay(snapshots.clone(), None, &RollbackLog::disabled()).await;
+        assert!(replayed.iter().all(|i| !i.changed()), "{:?}", replayed);
+
+        let replayed = replay(snapshots, Some(&index("12h")), &RollbackLog::disabled()).await;
+        assert_eq!(
+            replayed.iter().map(|i| i.replayed.clone()).collect::<Vec<_>>(),
+            vec![vec![baking(11 * 3600)], vec![baking(3 * 3600)]]
+        );
+        assert!(replayed.iter().all(|i| i.changed()));
+    }
+
+    #[tokio::test]
+    async fn paused_rollout_still_rolls_back_unhealthy_subnets() {
+        let mut snapshot = snapshot("2024-03-13T10:00:00Z", "b", 3600., vec![]);
+        snapshot.index.rollout.pause = true;
+        snapshot.index.rollout.health_checks = vec![HealthCheck {
+            name: "finalization_rate".to_string(),
+            min: Some(0.5),
+            ..Default::default()
+        }];
+        snapshot.health_samples = HealthSamples::from([("finalization_rate".to_string(), BTreeMap::from([(principal(1).to_string(), 0.1)]))]);
+
+        let replayed = replay(vec![snapshot.clone()], None, &RollbackLog::disabled()).await;
+        assert_eq!(
+            replayed[0].replayed,
+            vec![SubnetAction::Rollback {
+                subnet_principal: principal(1),
+                release: "rc--2".to_string(),
+                from_version: "b".to_string(),
+                to_version: "a".to_string(),
+                reason: "finalization_rate is 0.1 which is below 0.5".to_string(),
+            }]
+        );
+
+        // Without a breach the subnet keeps baking
+        snapshot.health_samples = HealthSamples::from([("finalization_rate".to_string(), BTreeMap::from([(principal(1).to_string(), 1.)]))]);
+        let replayed = replay(vec![snapshot], None, &RollbackLog::disabled()).await;
+        assert_eq!(
+            replayed[0].replayed,
+            vec![SubnetAction::Baking {
+                subnet_short: principal(1).to_string(),
+                remaining: Duration::from_secs(7 * 3600),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn recording_captures_inputs() {
+        let source = snapshot("2024-03-13T10:00:00Z", "b", 3600., vec![]);
+        let recorded = Mutex::new(Snapshot::new(source.timestamp, source.index.clone()));
+        let recording = Recording::new(&source, &recorded);
+
+        let actions = calculate_progress(
+            &Logger::root(slog::Discard, o!()),
+            source.index.clone(),
+            &recording,
+            &recording,
+            &RollbackLog::disabled(),
+            &source.holidays,
+            &mut Iteration::start(),
+            source.timestamp,
+        )
+        .await
+        .unwrap();
+
+        let recorded = recorded.into_inner().unwrap();
+        assert_eq!(recorded.last_bake_status, source.last_bake_status);
+        assert_eq!(recorded.release_starts, source.release_starts);
+        assert_eq!(recorded.unassigned_nodes_version, source.unassigned_nodes_version);
+
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = Recorder::new(dir.path().join("recording.jsonl"), Duration::from_secs(600));
+        recorder.record(&Snapshot { actions, ..recorded.clone() }).unwrap();
+        recorder
+            .record(&Snapshot {
+                timestamp: recorded.timestamp + chrono::TimeDelta::try_minutes(5).unwrap(),
+                ..recorded.clone()
+            })
+            .unwrap();
+        let loaded = Recorder::load(&dir.path().join("recording.jsonl")).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(
+            loaded[0].actions,
+            vec![SubnetAction::Baking {
+                subnet_short: principal(1).to_string(),
+                remaining: Duration::from_secs(7 * 3600),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn hostos_actions_are_recorded_and_replayed() {
+        let mut source = snapshot("2024-03-13T10:00:00Z", "b", 3600., vec![]);
+        source.index.hostos_rollout =
+            Some(serde_yaml::from_str("version: new\nstages:\n  - assignment: unassigned\n    percentage: 50\n    bake_time: 1h").unwrap());
+        let hostos_baking = SubnetAction::HostosBaking {
+            group: "GROUP { subnet: Unassigned, owner: All }".to_string(),
+            remaining: Duration::from_secs(1800),
+        };
+        source.hostos_actions = Some(vec![hostos_baking.clone()]);
+
+        let recorded = Mutex::new(Snapshot::new(source.timestamp, source.index.clone()));
+        let recording = Recording::new(&source, &recorded);
+        let actions = calculate_progress(
+            &Logger::root(slog::Discard, o!()),
+            source.index.clone(),
+            &recording,
+            &recording,
+            &RollbackLog::disabled(),
+            &source.holidays,
+            &mut Iteration::start(),
+            source.timestamp,
+        )
+        .await
+        .unwrap();
+        assert!(actions.contains(&hostos_baking));
+
+        let recorded = Snapshot {
+            actions,
+            ..recorded.into_inner().unwrap()
+        };
+        assert_eq!(recorded.hostos_actions, Some(vec![hostos_baking]));
+        let replayed = replay(vec![recorded.clone()], None, &RollbackLog::disabled()).await;
+        assert!(replayed.iter().all(|i| !i.changed()), "{:?}", replayed);
+
+        // Recordings made without the HostOS actions can't be replayed
+        let replayed = replay(
+            vec![Snapshot {
+                hostos_actions: None,
+                ..recorded
+            }],
+            None,
+            &RollbackLog::disabled(),
+        )
+        .await;
+        assert!(replayed[0].changed());
+    }
+
+    #[tokio::test]
+    async fn replay_refuses_the_rollback_log_of_the_controller() {
+        let dir = tempfile::tempdir().unwrap();
+        let recording = dir.path().join("recording.jsonl");
+        std::fs::write(&recording, "").unwrap();
+        let controller_rollbacks_file = dir.path().join("rollbacks.yaml");
+        let args = |rollbacks_file: Option<PathBuf>| ReplayArgs {
+            recording: recording.clone(),
+            index_file: None,
+            rollbacks_file,
+            check: true,
+            json: true,
+        };
+
+        assert!(run(&args(Some(controller_rollbacks_file.clone())), &controller_rollbacks_file)
+            .await
+            .is_err());
+        assert!(run(&args(Some(dir.path().join("replay-rollbacks.yaml"))), &controller_rollbacks_file)
+            .await
+            .is_ok());
+        assert!(run(&args(None), &controller_rollbacks_file).await.is_ok());
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A rollback of a subnet to the previous GuestOS version, placed because the
+/// subnet became unhealthy while baking the new version.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RollbackRecord {
+    /// Release whose rollout was paused by the rollback
+    pub release: String,
+    pub subnet: String,
+    pub from_version: String,
+    pub to_version: String,
+    pub reason: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Rollbacks placed by the controller, persisted so that the rollout of the
+/// affected release stays paused across restarts.
+///
+/// The rollout of a release resumes once its records are removed from the
+/// file, or once the index contains a newer release.
+pub struct RollbackLog {
+    path: Option<PathBuf>,
+}
+
+impl RollbackLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path: Some(path) }
+    }
+
+    /// A log that stays empty, so rollbacks never pause a rollout
+    pub fn disabled() -> Self {
+        Self { path: None }
+    }
+
+    pub fn load(&self) -> anyhow::Result<Vec<RollbackRecord>> {
+        let path = match &self.path {
+            Some(path) if path.exists() => path,
+            _ => return Ok(vec![]),
+        };
+        let contents = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read rollback log {}: {}", path.display(), e))?;
+        serde_yaml::from_str::<Option<Vec<RollbackRecord>>>(&contents)
+            .map(|records| records.unwrap_or_default())
+            .map_err(|e| anyhow::anyhow!("Failed to parse rollback log {}: {}", path.display(), e))
+    }
+
+    pub fn record(&self, record: RollbackRecord) -> anyhow::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut records = self.load()?;
+        records.push(record);
+        let contents = serde_yaml::to_string(&records)?;
+        std::fs::write(path, contents).map_err(|e| anyhow::anyhow!("Failed to write rollback log {}: {}", path.display(), e))
+    }
+
+    /// Rollbacks that paused the rollout of the given release
+    pub fn for_release(&self, release: &str) -> anyhow::Result<Vec<RollbackRecord>> {
+        Ok(self.load()?.into_iter().filter(|r| r.release == release).collect())
+    }
+}
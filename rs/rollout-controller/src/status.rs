@@ -0,0 +1,223 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use axum::{extract::State, routing::get, Json, Router};
+use axum_otel_metrics::HttpMetricsLayer;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use slog::{info, Logger};
+use tokio_util::sync::CancellationToken;
+
+use crate::{actions::SubnetAction, history::Iteration};
+
+const UNASSIGNED_NODES: &str = "unassigned-nodes";
+
+/// State of a subnet, or of the unassigned nodes, in the current rollout
+#[derive(Serialize, Clone, Debug, PartialEq, Default)]
+pub struct SubnetStatus {
+    pub desired_version: String,
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub bake_remaining: Option<Duration>,
+    pub pending_proposals: Vec<u64>,
+    /// Why no proposal is placed for the subnet even though it should be
+    /// upgraded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked_reason: Option<String>,
+}
+
+impl SubnetStatus {
+    fn new(target: &str, desired_version: &str, iteration: &Iteration) -> Self {
+        let concerns = |action: &SubnetAction| match action {
+            SubnetAction::PlaceProposal { is_unassigned: true, .. } => target == UNASSIGNED_NODES,
+            action => action.subnet().is_some_and(|subnet| target.starts_with(&subnet)),
+        };
+        Self {
+            desired_version: desired_version.to_string(),
+            bake_remaining: iteration.actions.iter().find_map(|a| match a {
+                SubnetAction::Baking { remaining, .. } if concerns(a) => Some(*remaining),
+                _ => None,
+            }),
+            pending_proposals: iteration.open_proposals.iter().filter(|p| p.target == target).map(|p| p.id).collect(),
+            blocked_reason: iteration.actions.iter().find_map(|a| match a {
+                SubnetAction::Blocked { action, reason } if concerns(action) => Some(reason.clone()),
+                SubnetAction::WaitForNextWeek { .. } if concerns(a) => Some("waiting for next week".to_string()),
+                _ => None,
+            }),
+        }
+    }
+}
+
+/// What the controller knows about the rollout, as of its last iteration
+#[derive(Serialize, Clone, Debug, PartialEq, Default)]
+pub struct RolloutStatus {
+    /// Time of the last iteration
+    pub updated: Option<DateTime<Utc>>,
+    /// Time of the last iteration that completed without errors
+    pub last_success: Option<DateTime<Utc>>,
+    /// Error of the last iteration, if it failed
+    pub error: Option<String>,
+    pub index_hash: Option<String>,
+    pub release: Option<String>,
+    /// Why the whole rollout is blocked, e.g. after a rollback
+    pub blocked_reason: Option<String>,
+    /// Status by subnet principal, and of `unassigned-nodes`
+    pub subnets: BTreeMap<String, SubnetStatus>,
+    /// Actions of the last iteration that calculated them
+    pub actions: Vec<SubnetAction>,
+}
+
+impl RolloutStatus {
+    pub fn update(&mut self, iteration: &Iteration) {
+        self.updated = Some(iteration.timestamp);
+        self.error.clone_from(&iteration.error);
+        if iteration.error.is_none() {
+            self.last_success = Some(iteration.timestamp);
+        }
+        // The iteration stopped before it got to the rollout, so the last
+        // known state of the rollout is kept
+        if iteration.release.is_none() {
+            return;
+        }
+
+        self.index_hash.clone_from(&iteration.index_hash);
+        self.release.clone_from(&iteration.release);
+        self.blocked_reason = iteration.actions.iter().find_map(|a| match a {
+            SubnetAction::RolloutPaused { reason, .. } => Some(reason.clone()),
+            _ => None,
+        });
+        self.subnets = iteration
+            .desired_versions
+            .iter()
+            .map(|(target, version)| (target.clone(), SubnetStatus::new(target, version, iteration)))
+            .collect();
+        self.actions.clone_from(&iteration.actions);
+    }
+}
+
+pub type SharedStatus = Arc<RwLock<RolloutStatus>>;
+
+async fn get_status(State(status): State<SharedStatus>) -> Json<RolloutStatus> {
+    Json(status.read().unwrap().clone())
+}
+
+/// Serves the rollout status as JSON on `/status`, and the metrics of the
+/// controller on `/metrics`, until the token is cancelled.
+pub async fn serve(
+    logger: Logger,
+    address: SocketAddr,
+    status: SharedStatus,
+    metrics_layer: HttpMetricsLayer,
+    token: CancellationToken,
+) -> anyhow::Result<()> {
+    let app = Router::new()
+        .merge(metrics_layer.routes())
+        .route("/status", get(get_status))
+        .layer(metrics_layer)
+        .with_state(status);
+
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", address, e))?;
+    info!(logger, "Status server started on {}", address);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { token.cancelled().await })
+        .await?;
+    info!(logger, "Status server stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ic_base_types::PrincipalId;
+    use pretty_assertions::assert_eq;
+
+    use crate::history::OpenProposal;
+
+    use super::*;
+
+    #[test]
+    fn status_of_subnets() {
+        let subnet = |id: u64| PrincipalId::new_subnet_test_id(id);
+        let mut iteration = Iteration::start();
+        iteration.release = Some("rc--2".to_string());
+        iteration.desired_versions = BTreeMap::from([
+            (subnet(1).to_string(), "b".to_string()),
+            (subnet(2).to_string(), "b".to_string()),
+            (subnet(3).to_string(), "b".to_string()),
+            (UNASSIGNED_NODES.to_string(), "b".to_string()),
+        ]);
+        iteration.open_proposals = vec![OpenProposal {
+            id: 7,
+            target: subnet(2).to_string(),
+            version: "b".to_string(),
+        }];
+        iteration.actions = vec![
+            SubnetAction::Baking {
+                subnet_short: subnet(1).to_string().split_once('-').unwrap().0.to_string(),
+                remaining: Duration::from_secs(60),
+            },
+            SubnetAction::PendingProposal {
+                subnet_short: subnet(2).to_string(),
+                proposal_id: 7,
+            },
+            SubnetAction::Blocked {
+                action: Box::new(SubnetAction::PlaceProposal {
+                    is_unassigned: true,
+                    subnet_principal: PrincipalId::new_anonymous(),
+                    version: "b".to_string(),
+                }),
+                reason: "holiday: Spring holiday".to_string(),
+            },
+        ];
+
+        let mut status = RolloutStatus::default();
+        status.update(&iteration);
+        let mut failed = Iteration::start();
+        failed.error = Some("registry sync failed".to_string());
+        status.update(&failed);
+
+        assert_eq!(status.error, failed.error);
+        assert_eq!(status.last_success, Some(iteration.timestamp));
+        assert_eq!(status.release, iteration.release);
+        assert_eq!(
+            status.subnets,
+            BTreeMap::from([
+                (
+                    subnet(1).to_string(),
+                    SubnetStatus {
+                        desired_version: "b".to_string(),
+                        bake_remaining: Some(Duration::from_secs(60)),
+                        ..Default::default()
+                    }
+                ),
+                (
+                    subnet(2).to_string(),
+                    SubnetStatus {
+                        desired_version: "b".to_string(),
+                        pending_proposals: vec![7],
+                        ..Default::default()
+                    }
+                ),
+                (
+                    subnet(3).to_string(),
+                    SubnetStatus {
+                        desired_version: "b".to_string(),
+                        ..Default::default()
+                    }
+                ),
+                (
+                    UNASSIGNED_NODES.to_string(),
+                    SubnetStatus {
+                        desired_version: "b".to_string(),
+                        blocked_reason: Some("holiday: Spring holiday".to_string()),
+                        ..Default::default()
+                    }
+                ),
+            ])
+        );
+    }
+}
@@ -9,8 +9,10 @@ use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::io::Write;
+use std::path::PathBuf;
 use std::time::SystemTime;
 use tokio::time::{sleep, Duration};
+mod routing;
 mod slack;
 use clap::Parser;
 use reqwest::Url;
@@ -35,8 +37,11 @@ async fn main() {
         .await
         .expect("Failed to create network");
 
-    let failed_proposals_handle = tokio::spawn(notify_for_failed_proposals(target_network.clone()));
-    let new_proposals_handle = tokio::spawn(notify_for_new_proposals(target_network));
+    // An invalid routing config stops the service before anything is sent
+    let routing = routing::RoutingConfigFile::new(args.routing_config).expect("failed to load the routing config");
+
+    let failed_proposals_handle = tokio::spawn(notify_for_failed_proposals(target_network.clone(), routing.clone()));
+    let new_proposals_handle = tokio::spawn(notify_for_new_proposals(target_network, routing));
 
     futures::future::join_all(vec![failed_proposals_handle, new_proposals_handle]).await;
 }
@@ -52,6 +57,11 @@ struct Cli {
     // The argument is mandatory for testnets, and is optional for mainnet and staging
     #[clap(long, env = "NNS_URLS", aliases = &["registry-url", "nns-url"], value_delimiter = ',')]
     pub nns_urls: Vec<Url>,
+
+    // Routing of the proposals to Slack channels, see `routing::RoutingConfig`.
+    // The file is reloaded when it changes.
+    #[clap(long, env = "ROUTING_CONFIG")]
+    pub routing_config: Option<PathBuf>,
 }
 
 #[derive(Default)]
@@ -156,7 +166,7 @@ impl ProposalPoller {
     }
 }
 
-async fn notify_for_new_proposals(target_network: Network) {
+async fn notify_for_new_proposals(target_network: Network, mut routing: routing::RoutingConfigFile) {
     let mut last_notified_proposal = ProposalCheckpointStore::new("new").expect("failed to initialize last notified proposal tracking");
     let proposal_poller = ProposalPoller::new(target_network);
     loop {
@@ -195,7 +205,8 @@ async fn notify_for_new_proposals(target_network: Network) {
                 continue;
             }
 
-            if let Ok(message_groups) = slack::MessageGroups::try_from(new_proposals.clone()) {
+            routing.reload_if_changed();
+            if let Ok(message_groups) = slack::MessageGroups::new(new_proposals.clone(), routing.config()) {
                 let slack_hook = slack::SlackHook::new(std::env::var(SLACK_URL_ENV).expect("SLACK_URL environment variable must be set"));
 
                 for slack_message in message_groups.message_groups.iter() {
@@ -228,7 +239,7 @@ async fn notify_for_new_proposals(target_network: Network) {
     }
 }
 
-async fn notify_for_failed_proposals(target_network: Network) {
+async fn notify_for_failed_proposals(target_network: Network, mut routing: routing::RoutingConfigFile) {
     let mut checkpoint = ProposalCheckpointStore::new("failed").expect("failed to initialize last notified proposal tracking");
     let proposal_poller = ProposalPoller::new(target_network);
     loop {
@@ -255,7 +266,8 @@ async fn notify_for_failed_proposals(target_network: Network) {
                 info!("new proposals: {:?}", &new_failed_proposals);
             }
 
-            if let Ok(message_groups) = slack::MessageGroups::try_from(new_failed_proposals.clone()) {
+            routing.reload_if_changed();
+            if let Ok(message_groups) = slack::MessageGroups::new(new_failed_proposals.clone(), routing.config()) {
                 let slack_hook = slack::SlackHook::new(std::env::var(SLACK_URL_ENV).expect("SLACK_URL environment variable must be set"));
 
                 for slack_message in message_groups.message_groups.iter() {
@@ -0,0 +1,330 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context};
+use ic_nns_governance::pb::v1::{proposal, NnsFunction, ProposalInfo, Topic};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NeuronSlackMapping {
+    pub neuron_id: u64,
+    pub slack_id: String,
+}
+
+/// Routing of the proposals to Slack channels, as configured in the file
+/// given with `--routing-config`.
+///
+/// A proposal is sent to the channels of every rule it matches. Proposals that
+/// do not match any rule are sent to the channels from the
+/// `SLACK_CHANNEL_PROPOSALS_INTERNAL` and `SLACK_CHANNEL_PROPOSALS_EXTERNAL`
+/// environment variables, as are all proposals without a configuration file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoutingConfig {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Slack users to mention for the proposers, replaces the built-in mapping
+    pub neurons: Option<Vec<NeuronSlackMapping>>,
+    /// Proposers whose proposals are not notified, replaces the built-in list
+    pub proposer_blacklist: Option<HashSet<u64>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(rename = "match", default)]
+    pub matcher: Matcher,
+    pub channels: Vec<String>,
+    /// Replaces the default mentions at the start of the message
+    pub mentions: Option<Vec<String>>,
+    /// Header of the message, see `SlackMessage::render_payload` for the
+    /// placeholders
+    pub template: Option<String>,
+}
+
+/// Conditions a proposal has to fulfill for a rule to apply. Every condition
+/// that is set has to match, and a condition matches if any of its values
+/// does.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Matcher {
+    /// Topic names, e.g. `SubnetManagement`
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// Ids of the proposer neurons
+    #[serde(default)]
+    pub proposers: Vec<u64>,
+    /// NNS function names, e.g. `ChangeSubnetMembership`
+    #[serde(default)]
+    pub nns_functions: Vec<String>,
+    /// Case insensitive keywords to look for in the title
+    #[serde(default)]
+    pub title_keywords: Vec<String>,
+}
+
+/// Where and how a proposal is sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub channel: String,
+    pub mentions: Option<String>,
+    pub template: Option<String>,
+}
+
+impl Matcher {
+    pub fn matches(&self, proposal: &ProposalInfo) -> bool {
+        let title = proposal
+            .proposal
+            .as_ref()
+            .and_then(|p| p.title.as_ref())
+            .map(|t| t.to_lowercase())
+            .unwrap_or_default();
+
+        (self.topics.is_empty() || self.topics.contains(&format!("{:?}", proposal.topic())))
+            && (self.proposers.is_empty() || proposal.proposer.map(|p| self.proposers.contains(&p.id)).unwrap_or(false))
+            && (self.nns_functions.is_empty() || nns_function(proposal).map(|f| self.nns_functions.contains(&f)).unwrap_or(false))
+            && (self.title_keywords.is_empty() || self.title_keywords.iter().any(|k| title.contains(&k.to_lowercase())))
+    }
+}
+
+/// The names of the values of a protobuf enum, as matched against the
+/// configuration. The values of these enums are small and non-negative.
+fn enum_names<E: TryFrom<i32> + std::fmt::Debug>() -> HashSet<String> {
+    (0..1000).filter_map(|v| E::try_from(v).ok()).map(|e| format!("{:?}", e)).collect()
+}
+
+fn nns_function(proposal: &ProposalInfo) -> Option<String> {
+    match proposal.proposal.as_ref()?.action.as_ref()? {
+        proposal::Action::ExecuteNnsFunction(action) => NnsFunction::try_from(action.nns_function).ok().map(|f| format!("{:?}", f)),
+        _ => None,
+    }
+}
+
+impl RoutingConfig {
+    /// Fails on topic or NNS function names that do not exist, as the rules
+    /// using them would silently never match
+    fn validate(&self) -> anyhow::Result<()> {
+        let topics = enum_names::<Topic>();
+        let nns_functions = enum_names::<NnsFunction>();
+        for matcher in self.rules.iter().map(|r| &r.matcher) {
+            if let Some(topic) = matcher.topics.iter().find(|t| !topics.contains(*t)) {
+                return Err(anyhow!("unknown topic {}", topic));
+            }
+            if let Some(nns_function) = matcher.nns_functions.iter().find(|f| !nns_functions.contains(*f)) {
+                return Err(anyhow!("unknown NNS function {}", nns_function));
+            }
+        }
+        Ok(())
+    }
+
+    /// Targets of the rules matching the proposal, at most one per channel.
+    /// Empty if no rule matches.
+    pub fn targets(&self, proposal: &ProposalInfo) -> Vec<Target> {
+        let mut targets: Vec<Target> = vec![];
+        for rule in self.rules.iter().filter(|r| r.matcher.matches(proposal)) {
+            for channel in &rule.channels {
+                if targets.iter().any(|t| &t.channel == channel) {
+                    continue;
+                }
+                targets.push(Target {
+                    channel: channel.clone(),
+                    mentions: rule.mentions.as_ref().map(|m| m.join(" ")),
+                    template: rule.template.clone(),
+                });
+            }
+        }
+        targets
+    }
+}
+
+/// The routing configuration file, reloaded when it changes
+#[derive(Clone, Default)]
+pub struct RoutingConfigFile {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    config: RoutingConfig,
+}
+
+impl RoutingConfigFile {
+    pub fn new(path: Option<PathBuf>) -> anyhow::Result<Self> {
+        let mut file = Self { path, ..Default::default() };
+        if let Some(path) = &file.path {
+            file.modified = Some(std::fs::metadata(path)?.modified()?);
+            file.config = Self::read(path)?;
+        }
+        Ok(file)
+    }
+
+    fn read(path: &Path) -> anyhow::Result<RoutingConfig> {
+        let config: RoutingConfig =
+            serde_yaml::from_str(&std::fs::read_to_string(path)?).with_context(|| format!("failed parsing routing config {}", path.display()))?;
+        config.validate().with_context(|| format!("invalid routing config {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Reloads the file if it was modified. An invalid file is reported and
+    /// the previous configuration is kept.
+    pub fn reload_if_changed(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let modified = match std::fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(e) => {
+                warn!("failed to check routing config {}: {}", path.display(), e);
+                return;
+            }
+        };
+        if self.modified == Some(modified) {
+            return;
+        }
+        self.modified = Some(modified);
+        match Self::read(path) {
+            Ok(config) => {
+                info!("reloaded routing config {}", path.display());
+                self.config = config;
+            }
+            Err(e) => warn!("keeping the previous routing config: {:?}", e),
+        }
+    }
+
+    pub fn config(&self) -> &RoutingConfig {
+        &self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_nns_common::pb::v1::NeuronId;
+    use ic_nns_governance::pb::v1::{ExecuteNnsFunction, Proposal};
+
+    fn gen_proposal(topic: Topic, proposer: u64, title: &str, nns_function: NnsFunction) -> ProposalInfo {
+        ProposalInfo {
+            proposer: Some(NeuronId { id: proposer }),
+            proposal: Some(Proposal {
+                title: Some(title.to_string()),
+                action: Some(proposal::Action::ExecuteNnsFunction(ExecuteNnsFunction {
+                    nns_function: nns_function as i32,
+                    payload: vec![],
+                })),
+                ..Default::default()
+            }),
+            topic: topic as i32,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rules_select_channels() {
+        let config: RoutingConfig = serde_yaml::from_str(
+            r##"
+rules:
+  - match:
+      topics: [SubnetManagement]
+      nns_functions: [ChangeSubnetMembership]
+    channels: ["#subnets"]
+    mentions: ["<!subteam^S1>"]
+    template: "{mentions} {count} subnet change(s)"
+  - match:
+      proposers: [40]
+      title_keywords: [bitcoin]
+    channels: ["#bitcoin", "#subnets"]
+neurons:
+  - neuron_id: 40
+    slack_id: U1
+"##,
+        )
+        .unwrap();
+
+        let subnet_change = gen_proposal(Topic::SubnetManagement, 40, "Replace nodes", NnsFunction::ChangeSubnetMembership);
+        assert_eq!(
+            config.targets(&subnet_change),
+            vec![Target {
+                channel: "#subnets".to_string(),
+                mentions: Some("<!subteam^S1>".to_string()),
+                template: Some("{mentions} {count} subnet change(s)".to_string()),
+            }]
+        );
+
+        // Only one message per channel, from the first matching rule
+        let bitcoin_change = gen_proposal(
+            Topic::SubnetManagement,
+            40,
+            "Update the Bitcoin canister",
+            NnsFunction::ChangeSubnetMembership,
+        );
+        let targets = config.targets(&bitcoin_change);
+        assert_eq!(
+            targets.iter().map(|t| t.channel.as_str()).collect::<Vec<_>>(),
+            vec!["#subnets", "#bitcoin"]
+        );
+
+        let other_proposer = gen_proposal(Topic::Governance, 41, "Update the Bitcoin canister", NnsFunction::CreateSubnet);
+        assert!(config.targets(&other_proposer).is_empty());
+    }
+
+    #[test]
+    fn reloads_changed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("routing.yaml");
+        std::fs::write(&path, "rules: []\n").unwrap();
+        let mut file = RoutingConfigFile::new(Some(path.clone())).unwrap();
+        assert!(file.config().rules.is_empty());
+
+        std::fs::write(&path, "rules:\n  - channels: [\"#all\"]\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + std::time::Duration::from_secs(1))
+            .unwrap();
+        file.reload_if_changed();
+        assert_eq!(file.config().rules.len(), 1);
+
+        // An invalid file keeps the previous configuration
+        std::fs::write(&path, "rules: nope\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + std::time::Duration::from_secs(2))
+            .unwrap();
+        file.reload_if_changed();
+        assert_eq!(file.config().rules.len(), 1);
+
+        // So does a file with names that do not exist
+        std::fs::write(&path, "rules:\n  - match:\n      topics: [SubnetManagment]\n    channels: [\"#all\"]\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + std::time::Duration::from_secs(3))
+            .unwrap();
+        file.reload_if_changed();
+        assert_eq!(file.config().rules.len(), 1);
+        assert!(file.config().rules[0].matcher.topics.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("routing.yaml");
+        for matcher in [
+            "topics: [SubnetManagment]",
+            "nns_functions: [ChangeSubnetMembers]",
+            "topics: [\"TOPIC_SUBNET_MANAGEMENT\"]",
+        ] {
+            std::fs::write(&path, format!("rules:\n  - match:\n      {}\n    channels: [\"#all\"]\n", matcher)).unwrap();
+            assert!(RoutingConfigFile::new(Some(path.clone())).is_err(), "{}", matcher);
+        }
+
+        std::fs::write(
+            &path,
+            "rules:\n  - match:\n      topics: [SubnetManagement]\n      nns_functions: [ChangeSubnetMembership]\n    channels: [\"#all\"]\n",
+        )
+        .unwrap();
+        assert!(RoutingConfigFile::new(Some(path)).is_ok());
+    }
+}
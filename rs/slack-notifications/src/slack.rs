@@ -1,4 +1,3 @@
-use candid::Deserialize;
 use ic_nns_common::pb::v1::NeuronId;
 use ic_nns_common::pb::v1::ProposalId;
 use ic_nns_governance::pb::v1::ProposalStatus;
@@ -13,6 +12,8 @@ use serde_json::{json, Value};
 use std::collections::HashSet;
 use std::convert::TryFrom;
 
+use crate::routing::{NeuronSlackMapping, RoutingConfig};
+
 const TRUSTED_NEURONS_TAG: &str = "<!subteam^S0200F4EYLF>";
 const DEVREL_TAG: &str = "<!subteam^S04AHQT37RQ>";
 const RELEASE_TEAM_TAG: &str = ""; // Can be changed to the following to mention @release-engs on each proposal:
@@ -22,12 +23,6 @@ const MAX_SUMMARY_LENGTH: usize = 2048;
 const SLACK_CHANNEL_ENV_INTERNAL: &str = "SLACK_CHANNEL_PROPOSALS_INTERNAL";
 const SLACK_CHANNEL_ENV_EXTERNAL: &str = "SLACK_CHANNEL_PROPOSALS_EXTERNAL";
 
-#[derive(Debug, Serialize, Deserialize)]
-struct NeuronSlackMapping {
-    pub neuron_id: u64,
-    pub slack_id: String,
-}
-
 pub struct SlackHook<T: IntoUrl> {
     client: reqwest::Client,
     url: T,
@@ -99,11 +94,15 @@ fn proposal_link_markdown(id: ProposalId) -> String {
     format!("<https://dashboard.internetcomputer.org/proposal/{}|*{}*>", id.id, id.id)
 }
 
-fn proposer_mention(proposer: NeuronId) -> Option<String> {
-    let neurons =
-        serde_yaml::from_str::<Vec<NeuronSlackMapping>>(include_str!("../conf/neurons-slack-mapping.yaml")).expect("failed parsing neurons config");
-    let neurons_blacklist =
-        serde_yaml::from_str::<HashSet<u64>>(include_str!("../conf/proposer-blacklist.yaml")).expect("failed parsing neurons config");
+fn proposer_mention(proposer: NeuronId, config: &RoutingConfig) -> Option<String> {
+    lazy_static! {
+        static ref NEURONS: Vec<NeuronSlackMapping> =
+            serde_yaml::from_str(include_str!("../conf/neurons-slack-mapping.yaml")).expect("failed parsing neurons config");
+        static ref PROPOSER_BLACKLIST: HashSet<u64> =
+            serde_yaml::from_str(include_str!("../conf/proposer-blacklist.yaml")).expect("failed parsing proposer blacklist");
+    }
+    let neurons = config.neurons.as_ref().unwrap_or(&NEURONS);
+    let neurons_blacklist = config.proposer_blacklist.as_ref().unwrap_or(&PROPOSER_BLACKLIST);
 
     if neurons_blacklist.contains(&proposer.id) {
         None
@@ -123,12 +122,22 @@ fn alert_mention(proposer: &NeuronId) -> String {
     }
 }
 
+fn default_mentions(proposal: &ProposalInfo) -> String {
+    alert_mention(proposal.proposer.as_ref().expect("No NeuronId in the proposal"))
+        + if proposal.topic() == Topic::SnsAndCommunityFund {
+            DEVREL_TAG
+        } else {
+            ""
+        }
+}
+
 #[derive(Clone)]
 pub struct SlackMessage {
     pub slack_channel: Option<String>,
     pub alert_mention: String,
     pub proposer_mention: String,
     pub motivation: String,
+    pub template: Option<String>,
     pub proposals: Vec<ProposalInfo>,
 }
 
@@ -142,17 +151,30 @@ impl Serialize for SlackMessage {
 }
 
 impl SlackMessage {
+    /// The template of the header can use the placeholders `{mentions}`,
+    /// `{proposer}`, `{topic}`, `{count}` and `{status}`, which is either
+    /// `failed` or `open`.
     pub fn render_payload(&self) -> Value {
-        let message = self.alert_mention.clone()
-            + if self
-                .proposals
-                .iter()
-                .all(|p| ProposalStatus::try_from(p.status).expect("failed to parse proposal status") == ProposalStatus::Failed)
-            {
-                "the following proposal(s) failed"
-            } else {
-                "please review the following proposal(s)"
-            };
+        let failed = self
+            .proposals
+            .iter()
+            .all(|p| ProposalStatus::try_from(p.status).expect("failed to parse proposal status") == ProposalStatus::Failed);
+        let message = match &self.template {
+            Some(template) => template
+                .replace("{mentions}", &self.alert_mention)
+                .replace("{proposer}", &self.proposer_mention)
+                .replace("{topic}", &self.proposals.first().map(|p| format!("{:?}", p.topic())).unwrap_or_default())
+                .replace("{count}", &self.proposals.len().to_string())
+                .replace("{status}", if failed { "failed" } else { "open" }),
+            None => {
+                self.alert_mention.clone()
+                    + if failed {
+                        "the following proposal(s) failed"
+                    } else {
+                        "please review the following proposal(s)"
+                    }
+            }
+        };
 
         // https://app.slack.com/block-kit-builder/T43F9UHS5#%7B%22blocks%22:%5B%5D%7D
         json!({
@@ -212,33 +234,65 @@ fn slack_channel_for_proposal(proposal: &ProposalInfo) -> Option<String> {
     None
 }
 
-impl TryFrom<Vec<ProposalInfo>> for MessageGroups {
-    type Error = anyhow::Error;
-    // https://app.slack.com/block-kit-builder/T43F9UHS5#%7B%22blocks%22:%5B%5D%7D
-    fn try_from(proposals: Vec<ProposalInfo>) -> Result<Self, anyhow::Error> {
-        let message_groups = proposals
-            .into_iter()
-            .chunk_by(|p| {
-                (
-                    slack_channel_for_proposal(p),
-                    alert_mention(p.proposer.as_ref().expect("No NeuronId in the proposal")),
-                    proposer_mention(p.proposer.expect("proposer not set")),
-                    proposal_motivation(p),
-                    p.topic(),
-                )
-            })
+/// A proposal routed to a channel, with the mentions and template of the
+/// routing rule, if any
+struct RoutedProposal {
+    mentions: Option<String>,
+    template: Option<String>,
+    proposal: ProposalInfo,
+}
+
+impl MessageGroups {
+    pub fn new(proposals: Vec<ProposalInfo>, config: &RoutingConfig) -> Result<Self, anyhow::Error> {
+        // Grouped by channel first, in the order the channels appear, so that
+        // proposals sent to several channels are still grouped in each of them
+        let mut by_channel: Vec<(Option<String>, Vec<RoutedProposal>)> = vec![];
+        for proposal in proposals {
+            let targets = config.targets(&proposal);
+            let targets = if targets.is_empty() {
+                vec![(slack_channel_for_proposal(&proposal), None, None)]
+            } else {
+                targets.into_iter().map(|t| (Some(t.channel), t.mentions, t.template)).collect()
+            };
+            for (channel, mentions, template) in targets {
+                let routed = RoutedProposal {
+                    mentions,
+                    template,
+                    proposal: proposal.clone(),
+                };
+                match by_channel.iter_mut().find(|(c, _)| *c == channel) {
+                    Some((_, routed_proposals)) => routed_proposals.push(routed),
+                    None => by_channel.push((channel, vec![routed])),
+                }
+            }
+        }
+
+        let message_groups = by_channel
             .into_iter()
-            .filter_map(|((slack_channel, alert_mention, proposer_mention, motivation, topic), group)| {
-                proposer_mention.map(|proposer_mention| {
-                    let proposals = group.collect::<Vec<_>>();
-                    SlackMessage {
-                        slack_channel,
-                        alert_mention: alert_mention + if topic == Topic::SnsAndCommunityFund { DEVREL_TAG } else { "" },
-                        proposer_mention,
-                        motivation,
-                        proposals,
-                    }
-                })
+            .flat_map(|(slack_channel, routed_proposals)| {
+                routed_proposals
+                    .into_iter()
+                    .chunk_by(|r| {
+                        (
+                            r.mentions.clone().unwrap_or_else(|| default_mentions(&r.proposal)),
+                            proposer_mention(r.proposal.proposer.expect("proposer not set"), config),
+                            proposal_motivation(&r.proposal),
+                            r.template.clone(),
+                            r.proposal.topic(),
+                        )
+                    })
+                    .into_iter()
+                    .filter_map(|((alert_mention, proposer_mention, motivation, template, _), group)| {
+                        proposer_mention.map(|proposer_mention| SlackMessage {
+                            slack_channel: slack_channel.clone(),
+                            alert_mention,
+                            proposer_mention,
+                            motivation,
+                            template,
+                            proposals: group.map(|r| r.proposal).collect(),
+                        })
+                    })
+                    .collect::<Vec<_>>()
             })
             .collect::<Vec<_>>();
 
@@ -250,6 +304,14 @@ impl TryFrom<Vec<ProposalInfo>> for MessageGroups {
     }
 }
 
+impl TryFrom<Vec<ProposalInfo>> for MessageGroups {
+    type Error = anyhow::Error;
+    // https://app.slack.com/block-kit-builder/T43F9UHS5#%7B%22blocks%22:%5B%5D%7D
+    fn try_from(proposals: Vec<ProposalInfo>) -> Result<Self, anyhow::Error> {
+        Self::new(proposals, &RoutingConfig::default())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -357,4 +419,45 @@ mod tests {
         assert_eq!(message_groups[0].proposer_mention, "<@URT5Z7VDZ>");
         assert_eq!(message_groups[0].motivation, "summary 1".to_string());
     }
+
+    #[test]
+    fn routing_rules() {
+        let config: RoutingConfig = serde_yaml::from_str(
+            r##"
+rules:
+  - match:
+      topics: [Governance]
+    channels: ["#governance", "#nns-proposals-test-internal"]
+    mentions: ["<!subteam^S1>"]
+    template: "{mentions} {count} {topic} proposal(s) by {proposer} are {status}"
+neurons:
+  - neuron_id: 40
+    slack_id: U1
+"##,
+        )
+        .unwrap();
+        let proposals = vec![
+            gen_test_proposal(1000, 40, "summary 1", Topic::Governance.into()),
+            gen_test_proposal(1001, 40, "summary 1", Topic::Governance.into()),
+            gen_test_proposal(1002, 40, "summary 1", 5),
+        ];
+        std::env::set_var("SLACK_CHANNEL_PROPOSALS_INTERNAL", "#nns-proposals-test-internal");
+        let message_groups = MessageGroups::new(proposals, &config).unwrap().message_groups;
+        assert_eq!(message_groups.len(), 3);
+
+        assert_eq!(message_groups[0].slack_channel.as_ref().unwrap(), "#governance");
+        assert_eq!(message_groups[0].proposals.len(), 2);
+        assert_eq!(
+            message_groups[0].render_payload()["text"],
+            "<!subteam^S1> 2 Governance proposal(s) by <@U1> are open"
+        );
+
+        // The proposal without a matching rule is sent with the default mentions
+        assert_eq!(message_groups[1].slack_channel.as_ref().unwrap(), "#nns-proposals-test-internal");
+        assert_eq!(message_groups[1].proposals.len(), 2);
+        assert_eq!(message_groups[1].alert_mention, "<!subteam^S1>");
+        assert_eq!(message_groups[2].slack_channel.as_ref().unwrap(), "#nns-proposals-test-internal");
+        assert_eq!(message_groups[2].alert_mention, TRUSTED_NEURONS_TAG);
+        assert_eq!(message_groups[2].proposals[0].id, Some(ProposalId { id: 1002 }));
+    }
 }